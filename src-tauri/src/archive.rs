@@ -0,0 +1,438 @@
+//! Tar archives: reading the streams the daemon produces and writing the ones it takes.
+//!
+//! Export and archive endpoints hand us a tar as a stream of byte chunks. Rather than
+//! spooling it to disk, [`TarStream`] parses headers as bytes arrive and keeps only the
+//! contents of the entries a caller asks for. [`TarWriter`] writes GNU-style archives,
+//! such as build contexts, to any writer.
+use crate::docker::{DockerError, DockerResult};
+use std::io::{self, Read, Write};
+
+const BLOCK: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    HardLink,
+    Other,
+}
+
+/// A tar entry whose contents were requested
+#[derive(Debug, Clone)]
+pub struct TarEntry {
+    /// Path inside the archive, without a leading `./` or `/`
+    pub path: String,
+    pub kind: EntryKind,
+    /// Target of a symlink or hard link
+    pub link: Option<String>,
+    pub data: Vec<u8>,
+}
+
+enum State {
+    Header,
+    Body {
+        path: String,
+        kind: EntryKind,
+        link: Option<String>,
+        remaining: usize,
+        padding: usize,
+        keep: bool,
+        data: Vec<u8>,
+    },
+    // GNU long name/link and pax extended headers carry data for the next entry
+    Meta {
+        typeflag: u8,
+        remaining: usize,
+        padding: usize,
+        data: Vec<u8>,
+    },
+    Padding(usize),
+    End,
+}
+
+pub struct TarStream<W> {
+    wanted: W,
+    /// Largest entry kept in memory; bigger wanted entries are skipped
+    max_entry: usize,
+    state: State,
+    buf: Vec<u8>,
+    long_name: Option<String>,
+    long_link: Option<String>,
+}
+
+impl<W: FnMut(&str, EntryKind) -> bool> TarStream<W> {
+    /// `wanted` decides per path whether an entry's contents should be collected
+    pub fn new(wanted: W, max_entry: usize) -> Self {
+        Self {
+            wanted,
+            max_entry,
+            state: State::Header,
+            buf: Vec::with_capacity(BLOCK),
+            long_name: None,
+            long_link: None,
+        }
+    }
+
+    /// Feed the next chunk of the archive, returning the wanted entries it completed
+    pub fn push(&mut self, mut chunk: &[u8]) -> DockerResult<Vec<TarEntry>> {
+        let mut done = Vec::new();
+
+        while !chunk.is_empty() {
+            match &mut self.state {
+                State::End => break,
+                State::Header => {
+                    let take = (BLOCK - self.buf.len()).min(chunk.len());
+                    self.buf.extend_from_slice(&chunk[..take]);
+                    chunk = &chunk[take..];
+                    if self.buf.len() == BLOCK {
+                        let header = std::mem::take(&mut self.buf);
+                        done.extend(self.start_entry(&header)?);
+                    }
+                }
+                State::Padding(remaining) => {
+                    let take = (*remaining).min(chunk.len());
+                    *remaining -= take;
+                    chunk = &chunk[take..];
+                    if *remaining == 0 {
+                        self.state = State::Header;
+                    }
+                }
+                State::Meta {
+                    typeflag,
+                    remaining,
+                    padding,
+                    data,
+                } => {
+                    let take = (*remaining).min(chunk.len());
+                    data.extend_from_slice(&chunk[..take]);
+                    *remaining -= take;
+                    chunk = &chunk[take..];
+                    if *remaining == 0 {
+                        let (typeflag, padding, data) = (*typeflag, *padding, std::mem::take(data));
+                        self.finish_meta(typeflag, &data);
+                        self.state = State::Padding(padding);
+                        if padding == 0 {
+                            self.state = State::Header;
+                        }
+                    }
+                }
+                State::Body {
+                    remaining,
+                    padding,
+                    keep,
+                    data,
+                    ..
+                } => {
+                    let take = (*remaining).min(chunk.len());
+                    if *keep {
+                        data.extend_from_slice(&chunk[..take]);
+                    }
+                    *remaining -= take;
+                    chunk = &chunk[take..];
+                    if *remaining == 0 {
+                        let padding = *padding;
+                        let finished = std::mem::replace(&mut self.state, State::Padding(padding));
+                        if padding == 0 {
+                            self.state = State::Header;
+                        }
+                        if let State::Body {
+                            path,
+                            kind,
+                            link,
+                            keep: true,
+                            data,
+                            ..
+                        } = finished
+                        {
+                            done.push(TarEntry {
+                                path,
+                                kind,
+                                link,
+                                data,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(done)
+    }
+
+    /// Parse a header block, returning the entry right away if it has no contents
+    fn start_entry(&mut self, header: &[u8]) -> DockerResult<Option<TarEntry>> {
+        // Two zero blocks end the archive; one is enough to stop reading
+        if header.iter().all(|&b| b == 0) {
+            self.state = State::End;
+            return Ok(None);
+        }
+        if !checksum_ok(header) {
+            return Err(DockerError::OperationError(
+                "Corrupt tar stream: bad header checksum".to_string(),
+            ));
+        }
+
+        let size = parse_size(&header[124..136])?;
+        let padding = (BLOCK - size % BLOCK) % BLOCK;
+        let typeflag = header[156];
+
+        if matches!(typeflag, b'L' | b'K' | b'x') {
+            self.state = State::Meta {
+                typeflag,
+                remaining: size,
+                padding,
+                data: Vec::with_capacity(size.min(64 * 1024)),
+            };
+            return Ok(None);
+        }
+
+        let path = match self.long_name.take() {
+            Some(name) => name,
+            None => {
+                let name = field(&header[0..100]);
+                // POSIX ustar splits long paths into a prefix; GNU keeps timestamps there
+                let prefix = if &header[257..263] == b"ustar\0" {
+                    field(&header[345..500])
+                } else {
+                    String::new()
+                };
+                if prefix.is_empty() {
+                    name
+                } else {
+                    format!("{}/{}", prefix, name)
+                }
+            }
+        };
+        let link = self
+            .long_link
+            .take()
+            .or_else(|| Some(field(&header[157..257])).filter(|l| !l.is_empty()));
+
+        let kind = match typeflag {
+            b'0' | 0 | b'7' => EntryKind::File,
+            b'5' => EntryKind::Directory,
+            b'2' => EntryKind::Symlink,
+            b'1' => EntryKind::HardLink,
+            _ => EntryKind::Other,
+        };
+        let path = normalize(&path);
+        let keep = (self.wanted)(&path, kind) && size <= self.max_entry;
+
+        if size == 0 {
+            self.state = State::Header;
+            return Ok(keep.then(|| TarEntry {
+                path,
+                kind,
+                link,
+                data: Vec::new(),
+            }));
+        }
+
+        self.state = State::Body {
+            path,
+            kind,
+            link,
+            remaining: size,
+            padding,
+            keep,
+            data: Vec::with_capacity(if keep { size } else { 0 }),
+        };
+        Ok(None)
+    }
+
+    fn finish_meta(&mut self, typeflag: u8, data: &[u8]) {
+        match typeflag {
+            b'L' => self.long_name = Some(field(data)),
+            b'K' => self.long_link = Some(field(data)),
+            _ => {
+                // pax records: "<len> key=value\n"
+                for record in String::from_utf8_lossy(data).split('\n') {
+                    let Some((_, pair)) = record.split_once(' ') else {
+                        continue;
+                    };
+                    match pair.split_once('=') {
+                        Some(("path", path)) => self.long_name = Some(path.to_string()),
+                        Some(("linkpath", link)) => self.long_link = Some(link.to_string()),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn field(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+fn normalize(path: &str) -> String {
+    path.trim_start_matches("./")
+        .trim_start_matches('/')
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn parse_size(bytes: &[u8]) -> DockerResult<usize> {
+    // GNU base-256 encoding for sizes over 8 GiB
+    if bytes[0] & 0x80 != 0 {
+        let mut size: u64 = (bytes[0] & 0x7f) as u64;
+        for &b in &bytes[1..] {
+            size = size.checked_shl(8).unwrap_or(u64::MAX) | b as u64;
+        }
+        return Ok(size as usize);
+    }
+
+    let text = field(bytes);
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(text, 8).map_err(|_| {
+        DockerError::OperationError(format!("Corrupt tar stream: bad size {:?}", text))
+    })
+}
+
+fn checksum_ok(header: &[u8]) -> bool {
+    let expected = match u32::from_str_radix(field(&header[148..156]).trim(), 8) {
+        Ok(sum) => sum,
+        Err(_) => return false,
+    };
+    let sum: u32 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if (148..156).contains(&i) {
+                b' ' as u32
+            } else {
+                b as u32
+            }
+        })
+        .sum();
+    sum == expected
+}
+
+/// Writes a tar archive to any writer, copying file contents from their readers so
+/// no entry is held in memory. Paths longer than the header allows get a GNU
+/// long-name entry, which the daemon understands.
+pub struct TarWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    /// Append a file of `size` bytes read from `data`. Fails if `data` ends early, as
+    /// it does when the file shrinks while it's being packed.
+    pub fn append_file(
+        &mut self,
+        path: &str,
+        mode: u32,
+        mtime: u64,
+        size: u64,
+        data: &mut impl Read,
+    ) -> io::Result<()> {
+        self.append_header(path, b'0', mode, mtime, None, size)?;
+        let copied = io::copy(&mut data.take(size), &mut self.out)?;
+        if copied != size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} changed while it was being archived", path),
+            ));
+        }
+        self.pad(size)
+    }
+
+    pub fn append_dir(&mut self, path: &str, mode: u32, mtime: u64) -> io::Result<()> {
+        self.append_header(&format!("{}/", path), b'5', mode, mtime, None, 0)
+    }
+
+    pub fn append_symlink(&mut self, path: &str, target: &str, mtime: u64) -> io::Result<()> {
+        self.append_header(path, b'2', 0o777, mtime, Some(target), 0)
+    }
+
+    /// Write the end-of-archive marker and hand back the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[0; 2 * BLOCK])?;
+        Ok(self.out)
+    }
+
+    fn append_header(
+        &mut self,
+        path: &str,
+        typeflag: u8,
+        mode: u32,
+        mtime: u64,
+        link: Option<&str>,
+        size: u64,
+    ) -> io::Result<()> {
+        if path.len() > 100 {
+            self.long_entry(b'L', path)?;
+        }
+        if let Some(link) = link.filter(|l| l.len() > 100) {
+            self.long_entry(b'K', link)?;
+        }
+        let header = header(path, typeflag, mode, mtime, link.unwrap_or_default(), size);
+        self.out.write_all(&header)
+    }
+
+    fn long_entry(&mut self, typeflag: u8, value: &str) -> io::Result<()> {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+        let size = data.len() as u64;
+        self.out
+            .write_all(&header("././@LongLink", typeflag, 0o644, 0, "", size))?;
+        self.out.write_all(&data)?;
+        self.pad(size)
+    }
+
+    /// Fill the last block of an entry's contents
+    fn pad(&mut self, size: u64) -> io::Result<()> {
+        let padding = (BLOCK - size as usize % BLOCK) % BLOCK;
+        self.out.write_all(&[0; BLOCK][..padding])
+    }
+}
+
+fn header(path: &str, typeflag: u8, mode: u32, mtime: u64, link: &str, size: u64) -> [u8; BLOCK] {
+    let mut header = [0u8; BLOCK];
+    let put = |header: &mut [u8; BLOCK], at: usize, len: usize, value: &[u8]| {
+        let n = value.len().min(len);
+        header[at..at + n].copy_from_slice(&value[..n]);
+    };
+    // Octal numbers are zero-padded and NUL-terminated
+    let octal = |value: u64, width: usize| format!("{:0width$o}", value, width = width - 1);
+
+    put(&mut header, 0, 100, path.as_bytes());
+    put(
+        &mut header,
+        100,
+        8,
+        octal(mode as u64 & 0o7777, 8).as_bytes(),
+    );
+    put(&mut header, 108, 8, octal(0, 8).as_bytes());
+    put(&mut header, 116, 8, octal(0, 8).as_bytes());
+    if size >= 0o77777777777 {
+        // GNU base-256 for sizes that don't fit 11 octal digits
+        header[124] = 0x80;
+        header[128..136].copy_from_slice(&size.to_be_bytes());
+    } else {
+        put(&mut header, 124, 12, octal(size, 12).as_bytes());
+    }
+    put(
+        &mut header,
+        136,
+        12,
+        octal(mtime.min(0o77777777777), 12).as_bytes(),
+    );
+    header[156] = typeflag;
+    put(&mut header, 157, 100, link.as_bytes());
+    put(&mut header, 257, 8, b"ustar  \0");
+
+    header[148..156].fill(b' ');
+    let sum: u32 = header.iter().map(|&b| b as u32).sum();
+    put(&mut header, 148, 8, format!("{:06o}\0 ", sum).as_bytes());
+    header
+}
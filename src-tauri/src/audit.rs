@@ -0,0 +1,318 @@
+//! Audit trail of everything rykard changes on a Docker daemon.
+//!
+//! Records are appended one JSON object per line, so writing never rewrites the file
+//! and a crash can at worst tear the last line, which reading skips. Environment
+//! variable and build arg values are masked before they are written; only their names
+//! are kept.
+use crate::docker::{DockerError, DockerResult};
+use crate::jobs::{Job, JobKind, JobRequest, JobState};
+use crate::schedule::{ActionRecord, ScheduleAction};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const AUDIT_FILE: &str = "audit.jsonl";
+
+/// Parameter keys whose values hold `KEY=value` environment variables
+const ENV_KEYS: [&str; 2] = ["env_vars", "env"];
+/// Parameter keys whose values are maps of build args, which often carry tokens
+const BUILD_ARG_KEYS: [&str; 1] = ["build_args"];
+const MASK: &str = "***";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Unix time the action finished
+    pub time: i64,
+    /// Daemon the action went to, e.g. `unix:///var/run/docker.sock`
+    pub endpoint: String,
+    /// Command that made the change, e.g. `remove_container`
+    pub action: String,
+    /// Container, image or project acted on
+    pub target: String,
+    #[serde(default)]
+    pub parameters: Value,
+    pub outcome: AuditOutcome,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Which records to return; every field left out matches everything
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AuditFilter {
+    /// Exact action names
+    pub actions: Vec<String>,
+    /// Case-insensitive substring of the target
+    pub target: Option<String>,
+    pub endpoint: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    /// Unix time bounds, both inclusive
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// Keep only the newest records
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        (self.actions.is_empty() || self.actions.contains(&record.action))
+            && self.target.as_ref().is_none_or(|target| {
+                record
+                    .target
+                    .to_lowercase()
+                    .contains(&target.to_lowercase())
+            })
+            && self
+                .endpoint
+                .as_ref()
+                .is_none_or(|endpoint| *endpoint == record.endpoint)
+            && self.outcome.is_none_or(|outcome| outcome == record.outcome)
+            && self.since.is_none_or(|since| record.time >= since)
+            && self.until.is_none_or(|until| record.time <= until)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditExportFormat {
+    /// Newline-delimited JSON `AuditRecord`s
+    Json,
+    /// One row per record with an RFC 3339 `time` and the parameters as JSON
+    Csv,
+}
+
+/// Mask the values of `KEY=value` environment variables and of build args, wherever
+/// they are nested
+pub fn redact(parameters: &mut Value) {
+    match parameters {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    Value::Array(vars) if ENV_KEYS.contains(&key.as_str()) => {
+                        for var in vars.iter_mut() {
+                            if let Some((name, _)) = var.as_str().and_then(|v| v.split_once('=')) {
+                                *var = Value::String(format!("{}={}", name, MASK));
+                            }
+                        }
+                    }
+                    Value::Object(args) if BUILD_ARG_KEYS.contains(&key.as_str()) => {
+                        for arg in args.values_mut() {
+                            *arg = Value::String(MASK.to_string());
+                        }
+                    }
+                    _ => redact(value),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The audit file. Cloning shares it, and appends from clones don't interleave.
+#[derive(Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    write: Arc<Mutex<()>>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            write: Arc::default(),
+        }
+    }
+
+    pub fn append(&self, record: &AuditRecord) -> DockerResult<()> {
+        let line =
+            serde_json::to_string(record).map_err(|e| DockerError::Unknown(e.to_string()))?;
+
+        let _guard = self.write.lock().unwrap();
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+
+    /// Record the outcome of a command. A failed write is logged rather than failing
+    /// the command, whose change has already happened.
+    pub fn record<T>(
+        &self,
+        endpoint: &str,
+        action: &str,
+        target: &str,
+        mut parameters: Value,
+        result: &DockerResult<T>,
+    ) {
+        redact(&mut parameters);
+        let (outcome, error) = match result {
+            Ok(_) => (AuditOutcome::Success, None),
+            Err(e) => (AuditOutcome::Failure, Some(e.to_string())),
+        };
+        self.write_record(AuditRecord {
+            time: Utc::now().timestamp(),
+            endpoint: endpoint.to_string(),
+            action: action.to_string(),
+            target: target.to_string(),
+            parameters,
+            outcome,
+            error,
+        });
+    }
+
+    /// Record a background job once it has finished
+    pub fn record_job(&self, endpoint: &str, request: &JobRequest, job: &Job) {
+        let action = match job.kind {
+            JobKind::Pull => "pull_image",
+            JobKind::Build => "build_image",
+            JobKind::Push => "push_image",
+            JobKind::Prune => "prune",
+        };
+        let outcome = match job.state {
+            JobState::Cancelled => AuditOutcome::Cancelled,
+            JobState::Failed => AuditOutcome::Failure,
+            _ => AuditOutcome::Success,
+        };
+        let mut parameters = serde_json::to_value(request).unwrap_or_default();
+        redact(&mut parameters);
+        parameters["job"] = json!(job.id);
+        self.write_record(AuditRecord {
+            time: job.finished.unwrap_or_else(|| Utc::now().timestamp()),
+            endpoint: endpoint.to_string(),
+            action: action.to_string(),
+            target: job.target.clone(),
+            parameters,
+            outcome,
+            error: job.error.clone(),
+        });
+    }
+
+    /// Record a run of a schedule, timed or by hand
+    pub fn record_schedule(&self, endpoint: &str, run: &ActionRecord) {
+        let action = match run.action {
+            ScheduleAction::Start => "start_container",
+            ScheduleAction::Stop => "stop_container",
+            ScheduleAction::Restart => "restart_container",
+        };
+        self.write_record(AuditRecord {
+            time: run.time,
+            endpoint: endpoint.to_string(),
+            action: action.to_string(),
+            target: run.target.to_string(),
+            parameters: json!({
+                "schedule": run.schedule,
+                "manual": run.manual,
+                "containers": run.containers,
+            }),
+            outcome: match run.error {
+                Some(_) => AuditOutcome::Failure,
+                None => AuditOutcome::Success,
+            },
+            error: run.error.clone(),
+        });
+    }
+
+    fn write_record(&self, record: AuditRecord) {
+        if let Err(e) = self.append(&record) {
+            eprintln!("Failed to write audit record: {}", e);
+        }
+    }
+
+    /// Matching records, newest first
+    pub fn read(&self, filter: &AuditFilter) -> DockerResult<Vec<AuditRecord>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut records: Vec<AuditRecord> = contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .filter(|record| filter.matches(record))
+            .collect();
+        records.reverse();
+        if let Some(limit) = filter.limit {
+            records.truncate(limit);
+        }
+        Ok(records)
+    }
+
+    /// Write the matching records to `path` oldest first, returning how many there were
+    pub fn export(
+        &self,
+        filter: &AuditFilter,
+        path: &Path,
+        format: AuditExportFormat,
+    ) -> DockerResult<usize> {
+        let mut records = self.read(filter)?;
+        records.reverse();
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        if format == AuditExportFormat::Csv {
+            writeln!(
+                writer,
+                "time,endpoint,action,target,parameters,outcome,error"
+            )?;
+        }
+        for record in &records {
+            match format {
+                AuditExportFormat::Json => {
+                    let json = serde_json::to_string(record)
+                        .map_err(|e| DockerError::Unknown(e.to_string()))?;
+                    writeln!(writer, "{}", json)?;
+                }
+                AuditExportFormat::Csv => {
+                    let time = Utc
+                        .timestamp_opt(record.time, 0)
+                        .single()
+                        .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+                        .unwrap_or_default();
+                    let outcome = serde_json::to_value(record.outcome)
+                        .ok()
+                        .and_then(|v| v.as_str().map(str::to_string))
+                        .unwrap_or_default();
+                    let fields = [
+                        time,
+                        record.endpoint.clone(),
+                        record.action.clone(),
+                        record.target.clone(),
+                        record.parameters.to_string(),
+                        outcome,
+                        record.error.clone().unwrap_or_default(),
+                    ];
+                    let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                    writeln!(writer, "{}", row.join(","))?;
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(records.len())
+    }
+}
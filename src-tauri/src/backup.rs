@@ -0,0 +1,387 @@
+//! Back up named volumes to gzipped tar files on the host, and restore them.
+//!
+//! The daemon can't read a volume directly, so a helper container mounting it at
+//! `/volume` is created (never started) and its archive endpoint streams the files
+//! out, or takes them back in. Archives hold a single `volume/` directory, compressed
+//! as they stream through. Next to each archive a `<archive>.sha256` file in
+//! `sha256sum` format records its checksum, and a restore refuses to touch a volume
+//! until the archive matches it.
+use crate::docker::{self, DockerError, DockerResult};
+use bollard::container::{
+    Config, DownloadFromContainerOptions, RemoveContainerOptions, UploadToContainerOptions,
+};
+use bollard::models::HostConfig;
+use bollard::volume::CreateVolumeOptions;
+use bollard::Docker;
+use bytes::Bytes;
+use chrono::Utc;
+use flate2::write::{GzDecoder, GzEncoder};
+use flate2::Compression;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+/// Small image the helper containers are created from, pulled when missing
+pub const HELPER_IMAGE: &str = "busybox:stable";
+/// Marks the helper containers, so leftovers of a crash can be found
+pub const BACKUP_LABEL: &str = "rykard.volume-backup";
+
+const MOUNT_POINT: &str = "/volume";
+const CHUNK_SIZE: usize = 64 * 1024;
+// Report progress at most once per this many bytes
+const PROGRESS_STEP: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeOperation {
+    Backup,
+    Restore,
+}
+
+/// Progress of a backup or restore
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VolumeProgress {
+    pub volume: String,
+    pub operation: VolumeOperation,
+    /// Archive bytes written so far, or read so far when restoring
+    pub bytes: u64,
+    /// Size of the archive being restored; unknown while backing up
+    pub total: Option<u64>,
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VolumeBackup {
+    pub volume: String,
+    pub path: PathBuf,
+    /// Size of the compressed archive
+    pub size: u64,
+    /// Hex SHA-256 of the archive, as written to the `.sha256` file
+    pub sha256: String,
+    pub created: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestoreOptions {
+    /// Restore into a volume that already exists. Files from the archive replace
+    /// those with the same path; the rest of the volume is left alone.
+    pub overwrite: bool,
+    /// Expected hex SHA-256 of the archive; defaults to the one in its `.sha256` file
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VolumeRestore {
+    pub volume: String,
+    /// The volume didn't exist and was created for the restore
+    pub created: bool,
+    pub sha256: String,
+}
+
+/// Where the checksum of `archive` is kept
+pub fn checksum_path(archive: &Path) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push(".sha256");
+    PathBuf::from(path)
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+async fn volume_exists(docker: &Docker, volume: &str) -> DockerResult<bool> {
+    match docker.inspect_volume(volume).await {
+        Ok(_) => Ok(true),
+        Err(e) => match DockerError::from(e) {
+            DockerError::NotFound(_) => Ok(false),
+            e => Err(e),
+        },
+    }
+}
+
+/// Create a stopped container with `volume` mounted, pulling the helper image if needed
+async fn create_helper(docker: &Docker, volume: &str, read_only: bool) -> DockerResult<String> {
+    docker::ensure_image(docker, HELPER_IMAGE, |_| {}).await?;
+
+    let mode = if read_only { "ro" } else { "rw" };
+    let config = Config {
+        image: Some(HELPER_IMAGE.to_string()),
+        cmd: Some(vec!["true".to_string()]),
+        network_disabled: Some(true),
+        labels: Some(HashMap::from([(
+            BACKUP_LABEL.to_string(),
+            volume.to_string(),
+        )])),
+        host_config: Some(HostConfig {
+            binds: Some(vec![format!("{}:{}:{}", volume, MOUNT_POINT, mode)]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    Ok(docker
+        .create_container::<String, String>(None, config)
+        .await?
+        .id)
+}
+
+/// Remove a helper container. The volume it mounted is named, so it stays.
+async fn remove_helper(docker: &Docker, container: &str) {
+    let options = RemoveContainerOptions {
+        force: true,
+        ..Default::default()
+    };
+    if let Err(e) = docker.remove_container(container, Some(options)).await {
+        eprintln!("Failed to remove volume helper {}: {}", container, e);
+    }
+}
+
+/// Write `volume` to a gzipped tar at `path` and its checksum next to it.
+///
+/// Like a container export, the archive is written to a `.part` file first and only
+/// renamed into place once complete. Containers writing to the volume during the
+/// backup may leave it inconsistent, so stop databases first.
+pub async fn backup_volume<F>(
+    docker: &Docker,
+    volume: &str,
+    path: &Path,
+    mut on_progress: F,
+) -> DockerResult<VolumeBackup>
+where
+    F: FnMut(&VolumeProgress),
+{
+    if !volume_exists(docker, volume).await? {
+        return Err(DockerError::NotFound(format!("No such volume: {}", volume)));
+    }
+    let helper = create_helper(docker, volume, true).await?;
+
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    let mut progress = VolumeProgress {
+        volume: volume.to_string(),
+        operation: VolumeOperation::Backup,
+        bytes: 0,
+        total: None,
+        done: false,
+    };
+
+    let result = async {
+        let mut file = tokio::io::BufWriter::new(tokio::fs::File::create(&part).await?);
+        let mut hasher = Sha256::new();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let options = DownloadFromContainerOptions { path: MOUNT_POINT };
+        let stream = docker.download_from_container(&helper, Some(options));
+        tokio::pin!(stream);
+
+        let mut reported = 0;
+        while let Some(chunk) = stream.next().await {
+            encoder.write_all(&chunk?)?;
+            // Whatever the encoder has produced so far goes straight to the file
+            let compressed = std::mem::take(encoder.get_mut());
+            hasher.update(&compressed);
+            file.write_all(&compressed).await?;
+            progress.bytes += compressed.len() as u64;
+            if progress.bytes - reported >= PROGRESS_STEP {
+                reported = progress.bytes;
+                on_progress(&progress);
+            }
+        }
+        let compressed = encoder.finish()?;
+        hasher.update(&compressed);
+        file.write_all(&compressed).await?;
+        progress.bytes += compressed.len() as u64;
+        file.flush().await?;
+        tokio::fs::rename(&part, path).await?;
+
+        let sha256 = hex(&hasher.finalize());
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        tokio::fs::write(checksum_path(path), format!("{}  {}\n", sha256, file_name)).await?;
+        Ok::<_, DockerError>(sha256)
+    }
+    .await;
+    remove_helper(docker, &helper).await;
+
+    let sha256 = match result {
+        Ok(sha256) => sha256,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&part).await;
+            return Err(e);
+        }
+    };
+    progress.done = true;
+    on_progress(&progress);
+    Ok(VolumeBackup {
+        volume: volume.to_string(),
+        path: path.to_path_buf(),
+        size: progress.bytes,
+        sha256,
+        created: Utc::now().timestamp(),
+    })
+}
+
+/// The checksum a restore must match: the one given, or the archive's `.sha256` file
+async fn expected_checksum(path: &Path, given: Option<&str>) -> DockerResult<String> {
+    if let Some(sha256) = given {
+        return Ok(sha256.trim().to_lowercase());
+    }
+    let checksum_file = checksum_path(path);
+    let contents = tokio::fs::read_to_string(&checksum_file)
+        .await
+        .map_err(|e| {
+            DockerError::OperationError(format!(
+                "No checksum to verify {} against: {}: {}",
+                path.display(),
+                checksum_file.display(),
+                e
+            ))
+        })?;
+    contents
+        .split_whitespace()
+        .next()
+        .map(str::to_lowercase)
+        .ok_or_else(|| DockerError::OperationError(format!("{} is empty", checksum_file.display())))
+}
+
+async fn file_checksum(path: &Path) -> DockerResult<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Restore an archive written by [`backup_volume`] into `volume`, creating the volume
+/// when it doesn't exist. The archive is checked against its checksum before anything
+/// is written, and an existing volume is only written to with `overwrite`.
+pub async fn restore_volume<F>(
+    docker: &Docker,
+    path: &Path,
+    volume: &str,
+    options: &RestoreOptions,
+    mut on_progress: F,
+) -> DockerResult<VolumeRestore>
+where
+    F: FnMut(&VolumeProgress),
+{
+    let expected = expected_checksum(path, options.sha256.as_deref()).await?;
+    let sha256 = file_checksum(path).await?;
+    if sha256 != expected {
+        return Err(DockerError::OperationError(format!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            path.display(),
+            expected,
+            sha256
+        )));
+    }
+
+    let mut progress = VolumeProgress {
+        volume: volume.to_string(),
+        operation: VolumeOperation::Restore,
+        bytes: 0,
+        total: Some(tokio::fs::metadata(path).await?.len()),
+        done: false,
+    };
+
+    let exists = volume_exists(docker, volume).await?;
+    if exists && !options.overwrite {
+        return Err(DockerError::OperationError(format!(
+            "Volume {} already exists; restore into a new volume or allow overwriting it",
+            volume
+        )));
+    }
+    if !exists {
+        docker
+            .create_volume(CreateVolumeOptions {
+                name: volume,
+                ..Default::default()
+            })
+            .await?;
+    }
+
+    let restored = async {
+        let helper = create_helper(docker, volume, false).await?;
+
+        // Decompress as the daemon reads, rather than holding the whole tar in memory
+        let (sender, receiver) = mpsc::channel::<Bytes>(8);
+        let tar = futures_util::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|chunk| (chunk, receiver))
+        });
+        let upload = async {
+            let options = UploadToContainerOptions {
+                path: "/",
+                ..Default::default()
+            };
+            docker
+                .upload_to_container_streaming(&helper, Some(options), tar)
+                .await
+                .map_err(DockerError::from)
+        };
+        let decompress = async {
+            let mut file = tokio::fs::File::open(path).await?;
+            let mut decoder = GzDecoder::new(Vec::new());
+            let mut buffer = vec![0; CHUNK_SIZE];
+            let mut reported = 0;
+            loop {
+                let read = file.read(&mut buffer).await?;
+                if read == 0 {
+                    break;
+                }
+                decoder.write_all(&buffer[..read])?;
+                let tar = std::mem::take(decoder.get_mut());
+                if !tar.is_empty() && sender.send(tar.into()).await.is_err() {
+                    // The upload failed and stopped reading; its error is the one to report
+                    return Ok(());
+                }
+                progress.bytes += read as u64;
+                if progress.bytes - reported >= PROGRESS_STEP {
+                    reported = progress.bytes;
+                    on_progress(&progress);
+                }
+            }
+            // Checks the gzip trailer, so a truncated archive fails here
+            let tar = decoder.finish()?;
+            if !tar.is_empty() {
+                let _ = sender.send(tar.into()).await;
+            }
+            drop(sender);
+            Ok::<_, DockerError>(())
+        };
+        let (uploaded, decompressed) = tokio::join!(upload, decompress);
+        remove_helper(docker, &helper).await;
+        decompressed?;
+        uploaded
+    }
+    .await;
+    if let Err(e) = restored {
+        // Don't leave a half-filled volume behind under the name the user asked for
+        if !exists {
+            if let Err(remove) = docker.remove_volume(volume, None).await {
+                eprintln!("Failed to remove volume {}: {}", volume, remove);
+            }
+        }
+        return Err(e);
+    }
+
+    progress.done = true;
+    on_progress(&progress);
+    Ok(VolumeRestore {
+        volume: volume.to_string(),
+        created: !exists,
+        sha256,
+    })
+}
@@ -0,0 +1,357 @@
+//! Image builds from a local context directory.
+//!
+//! The context is packed into a gzipped tar, honouring `.dockerignore` the way the
+//! docker CLI does, and the Dockerfile can be linted before anything is sent.
+use crate::archive::TarWriter;
+use crate::docker::{DockerError, DockerResult};
+use crate::dockerfile::{self, Diagnostic, Severity};
+use crate::settings::DockerfileLint;
+use bollard::image::BuildImageOptions;
+use bollard::models::BuildInfo;
+use bollard::Docker;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+pub const DOCKERIGNORE_FILE: &str = ".dockerignore";
+
+fn default_dockerfile() -> String {
+    "Dockerfile".to_string()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildOptions {
+    /// Directory sent to the daemon as the build context
+    pub context: PathBuf,
+    /// Dockerfile path relative to the context
+    #[serde(default = "default_dockerfile")]
+    pub dockerfile: String,
+    /// `repo:tag` to apply to the result, if any
+    #[serde(default)]
+    pub tag: String,
+    #[serde(default)]
+    pub build_args: HashMap<String, String>,
+    /// Stage to stop at in a multi-stage build
+    #[serde(default)]
+    pub target: String,
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Always pull newer versions of the base images
+    #[serde(default)]
+    pub pull: bool,
+}
+
+impl BuildOptions {
+    /// Dockerfile path inside the context, with `/` separators
+    fn dockerfile_in_context(&self) -> DockerResult<String> {
+        let relative = Path::new(&self.dockerfile);
+        let escapes = relative.is_absolute()
+            || relative
+                .components()
+                .any(|c| matches!(c, Component::ParentDir | Component::Prefix(_)));
+        if escapes {
+            return Err(DockerError::OperationError(format!(
+                "Dockerfile {} must be inside the build context",
+                self.dockerfile
+            )));
+        }
+        Ok(relative
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/"))
+    }
+
+    pub fn dockerfile_path(&self) -> PathBuf {
+        self.context.join(&self.dockerfile)
+    }
+}
+
+/// Compiled `.dockerignore` patterns; the last matching pattern decides
+#[derive(Debug, Default)]
+pub struct DockerIgnore {
+    patterns: Vec<(bool, Vec<String>)>,
+}
+
+impl DockerIgnore {
+    pub fn parse(text: &str) -> Self {
+        let patterns = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (negated, pattern) = match line.strip_prefix('!') {
+                    Some(pattern) => (true, pattern.trim()),
+                    None => (false, line),
+                };
+                let segments = pattern
+                    .split('/')
+                    .filter(|s| !s.is_empty() && *s != ".")
+                    .map(str::to_string)
+                    .collect();
+                (negated, segments)
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    fn load(context: &Path) -> DockerResult<Self> {
+        match fs::read_to_string(context.join(DOCKERIGNORE_FILE)) {
+            Ok(text) => Ok(Self::parse(&text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn has_negations(&self) -> bool {
+        self.patterns.iter().any(|(negated, _)| *negated)
+    }
+
+    /// Whether a `/`-separated context path is excluded. A pattern that matches a
+    /// directory excludes everything below it.
+    pub fn is_ignored(&self, path: &str) -> bool {
+        let segments: Vec<&str> = path.split('/').collect();
+        let mut ignored = false;
+        for (negated, pattern) in &self.patterns {
+            let matched = (1..=segments.len()).any(|n| match_segments(pattern, &segments[..n]));
+            if matched {
+                ignored = !negated;
+            }
+        }
+        ignored
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path_rest)) => {
+                glob(first.as_bytes(), segment.as_bytes()) && match_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// `*` and `?` wildcards within a single path segment, `\` escaping the next character
+pub(crate) fn glob(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob(&pattern[1..], name) || (!name.is_empty() && glob(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob(&pattern[1..], &name[1..]),
+        (Some(b'\\'), Some(&c)) if pattern.get(1) == Some(&c) => glob(&pattern[2..], &name[1..]),
+        (Some(&p), Some(&c)) if p == c => glob(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.is_dir() {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+fn mtime(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Pack a build context into a gzipped tar. The Dockerfile and `.dockerignore` are
+/// always included, even when ignored, because the daemon needs them.
+///
+/// Files are streamed from disk into the compressor, so only the compressed context
+/// is held in memory; the daemon's build endpoint takes the context as one body.
+pub fn build_context(options: &BuildOptions) -> DockerResult<Vec<u8>> {
+    let dockerfile = options.dockerfile_in_context()?;
+    if !options.dockerfile_path().is_file() {
+        return Err(DockerError::NotFound(format!(
+            "No Dockerfile at {}",
+            options.dockerfile_path().display()
+        )));
+    }
+
+    let ignore = DockerIgnore::load(&options.context)?;
+    let mut tar = TarWriter::new(GzEncoder::new(Vec::new(), Compression::fast()));
+    let mut pending = vec![(options.context.clone(), String::new())];
+
+    while let Some((dir, prefix)) = pending.pop() {
+        let mut entries: Vec<_> = fs::read_dir(&dir)?.collect::<Result<_, _>>()?;
+        // Stable order keeps the context tar, and so the build cache, reproducible
+        entries.sort_by_key(|e| e.file_name());
+
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = if prefix.is_empty() {
+                name
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            let metadata = fs::symlink_metadata(entry.path())?;
+            let keep = path == dockerfile || path == DOCKERIGNORE_FILE || !ignore.is_ignored(&path);
+
+            if metadata.is_dir() {
+                // A negated pattern may re-include something below an ignored directory
+                if keep || ignore.has_negations() {
+                    if keep {
+                        tar.append_dir(&path, mode(&metadata), mtime(&metadata))?;
+                    }
+                    pending.push((entry.path(), path));
+                }
+            } else if !keep {
+                continue;
+            } else if metadata.file_type().is_symlink() {
+                let target = fs::read_link(entry.path())?;
+                tar.append_symlink(&path, &target.to_string_lossy(), mtime(&metadata))?;
+            } else if metadata.is_file() {
+                let mut file = fs::File::open(entry.path())?;
+                tar.append_file(
+                    &path,
+                    mode(&metadata),
+                    mtime(&metadata),
+                    metadata.len(),
+                    &mut file,
+                )?;
+            }
+        }
+    }
+
+    Ok(tar.finish()?.finish()?)
+}
+
+/// A build message as the frontend sees it. bollard's [`BuildInfo`] can only be
+/// deserialized, so the fields worth showing are copied out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BuildProgress {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// A line of build output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<String>,
+    /// Set on the message that reports the finished image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_id: Option<String>,
+}
+
+impl From<&BuildInfo> for BuildProgress {
+    fn from(info: &BuildInfo) -> Self {
+        Self {
+            id: info.id.clone(),
+            stream: info.stream.clone(),
+            status: info.status.clone(),
+            progress: info.progress.clone(),
+            image_id: info.aux.as_ref().and_then(|aux| aux.id.clone()),
+        }
+    }
+}
+
+/// Lint the Dockerfile of a build according to the settings.
+///
+/// Errors out instead of returning the diagnostics when they include errors and
+/// `block_on_errors` is set.
+pub fn preflight(options: &BuildOptions, lint: &DockerfileLint) -> DockerResult<Vec<Diagnostic>> {
+    if !lint.before_build {
+        return Ok(Vec::new());
+    }
+
+    let diagnostics = dockerfile::lint_file(&options.dockerfile_path(), &lint.disabled_rules)?;
+    let errors: Vec<String> = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| format!("line {}: {} [{}]", d.line, d.message, d.rule))
+        .collect();
+    if lint.block_on_errors && !errors.is_empty() {
+        return Err(DockerError::OperationError(format!(
+            "Dockerfile has lint errors: {}",
+            errors.join("; ")
+        )));
+    }
+    Ok(diagnostics)
+}
+
+/// Build an image, handing every progress message to `on_progress`, and return its ID
+pub async fn build_image<F>(
+    docker: &Docker,
+    options: &BuildOptions,
+    mut on_progress: F,
+) -> DockerResult<String>
+where
+    F: FnMut(&BuildInfo),
+{
+    // Walking and compressing a large context takes a while; keep it off the runtime
+    let context_options = options.clone();
+    let context = tokio::task::spawn_blocking(move || build_context(&context_options))
+        .await
+        .map_err(|e| DockerError::Unknown(e.to_string()))??;
+    let build_options = BuildImageOptions {
+        dockerfile: options.dockerfile_in_context()?,
+        t: options.tag.clone(),
+        target: options.target.clone(),
+        buildargs: options.build_args.clone(),
+        nocache: options.no_cache,
+        pull: options.pull,
+        rm: true,
+        ..Default::default()
+    };
+
+    let stream = docker.build_image(build_options, None, Some(context.into()));
+    tokio::pin!(stream);
+
+    let mut image_id = None;
+    while let Some(result) = stream.next().await {
+        let info = match result {
+            Ok(info) => info,
+            Err(bollard::errors::Error::DockerStreamError { error }) => {
+                return Err(DockerError::OperationError(format!(
+                    "Build failed: {}",
+                    error
+                )))
+            }
+            Err(e) => return Err(DockerError::OperationError(format!("Build failed: {}", e))),
+        };
+        if let Some(error) = &info.error {
+            return Err(DockerError::OperationError(format!(
+                "Build failed: {}",
+                error
+            )));
+        }
+        if let Some(id) = info.aux.as_ref().and_then(|aux| aux.id.clone()) {
+            image_id = Some(id);
+        }
+        on_progress(&info);
+    }
+
+    image_id.ok_or_else(|| {
+        DockerError::OperationError("Build finished without reporting an image ID".to_string())
+    })
+}
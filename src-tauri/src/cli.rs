@@ -0,0 +1,559 @@
+//! Headless `rykard` subcommands (`rykard ps`, `rykard logs -f`, ...).
+//!
+//! These call into [`crate::docker`] just like the Tauri commands do, so a script
+//! on a CI box sees exactly what the GUI would show. Commands that change the daemon
+//! are recorded in the same audit log.
+use crate::audit::{AuditLog, AUDIT_FILE};
+use crate::docker::{self, ContainerStats, DockerError, DockerResult, DockerStatus};
+use crate::dockerfile::{self, Severity};
+use crate::logs;
+use crate::settings::{Settings, SettingsStore};
+use crate::store;
+use bollard::Docker;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use futures_util::StreamExt;
+use serde::Serialize;
+use serde_json::json;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, Parser)]
+#[command(name = "rykard", version, about = "Docker desktop manager")]
+struct Cli {
+    /// Output format for listings
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+enum CliCommand {
+    /// List containers
+    Ps {
+        /// Show all containers (default shows just running)
+        #[arg(short, long)]
+        all: bool,
+    },
+    /// List images
+    Images,
+    /// Print the logs of a container
+    Logs {
+        container: String,
+        /// Follow log output
+        #[arg(short, long)]
+        follow: bool,
+        /// Number of lines to show from the end of the logs [default: from settings]
+        #[arg(short = 'n', long)]
+        tail: Option<u64>,
+    },
+    /// Show resource usage of containers (all running ones if none are given)
+    Stats {
+        containers: Vec<String>,
+        /// Print a single sample and exit
+        #[arg(long)]
+        no_stream: bool,
+    },
+    /// Check a Dockerfile for common mistakes; exits non-zero on errors
+    Lint {
+        #[arg(default_value = "Dockerfile")]
+        dockerfile: PathBuf,
+    },
+    /// Docker Compose project commands
+    Compose {
+        #[command(subcommand)]
+        command: ComposeCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ComposeCommand {
+    /// Create and start the project's containers
+    Up {
+        /// Compose files to use
+        #[arg(short, long = "file")]
+        files: Vec<PathBuf>,
+        /// Project directory
+        #[arg(long)]
+        project_directory: Option<PathBuf>,
+        /// Run containers in the background
+        #[arg(short, long)]
+        detach: bool,
+    },
+    /// Print the interleaved logs of every container in a project
+    Logs {
+        /// Compose project name
+        project: String,
+        /// Only show these services
+        services: Vec<String>,
+        /// Follow log output
+        #[arg(short, long)]
+        follow: bool,
+        /// Number of lines to show from the end of each container's logs [default: from settings]
+        #[arg(short = 'n', long)]
+        tail: Option<u64>,
+    },
+}
+
+/// Run the CLI if a subcommand was given on the command line.
+///
+/// Returns `None` when there is nothing for the CLI to do and the GUI should start,
+/// otherwise the process exit code.
+pub fn run() -> Option<i32> {
+    run_with(std::env::args_os())
+}
+
+/// [`run`] for the given arguments, the first being the program name.
+///
+/// Arguments that don't parse only count as a CLI error once a subcommand is named;
+/// otherwise they're left to the GUI, which e.g. macOS launches with `-psn_…`.
+pub fn run_with<I, T>(args: I) -> Option<i32>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let args: Vec<std::ffi::OsString> = args.into_iter().map(Into::into).collect();
+    let cli = match Cli::try_parse_from(&args) {
+        Ok(cli) => cli,
+        Err(e) if names_subcommand(&args) => {
+            attach_console();
+            let _ = e.print();
+            return Some(e.exit_code());
+        }
+        Err(_) => return None,
+    };
+    let command = cli.command?;
+    attach_console();
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("rykard: failed to start runtime: {}", e);
+            return Some(1);
+        }
+    };
+
+    match runtime.block_on(execute(command, cli.format)) {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("rykard: {}", e);
+            Some(1)
+        }
+    }
+}
+
+/// Write to the console `rykard` was started from. Release builds on Windows use the
+/// GUI subsystem and start without one, so CLI output would otherwise go nowhere.
+#[cfg(windows)]
+fn attach_console() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    // Fails harmlessly when there's no parent console or one is attached already
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
+
+/// Whether any argument is one of the CLI's subcommands, or a request for help or the
+/// version, which clap answers through its error path
+fn names_subcommand(args: &[std::ffi::OsString]) -> bool {
+    let command = Cli::command();
+    args.iter()
+        .skip(1)
+        .filter_map(|arg| arg.to_str())
+        .any(|arg| {
+            matches!(arg, "help" | "-h" | "--help" | "-V" | "--version")
+                || command.find_subcommand(arg).is_some()
+        })
+}
+
+async fn execute(command: CliCommand, format: OutputFormat) -> DockerResult<()> {
+    let settings = match SettingsStore::load_default() {
+        Ok(store) => store.settings().clone(),
+        Err(e) => {
+            eprintln!("rykard: {}; using default settings", e);
+            Settings::default()
+        }
+    };
+
+    // Compose shells out to the docker CLI and doesn't need a client
+    if let CliCommand::Compose {
+        command:
+            ComposeCommand::Up {
+                files,
+                project_directory,
+                detach,
+            },
+    } = command
+    {
+        let result = docker::compose_up(project_directory.as_deref(), &files, detach);
+        let target = project_directory
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default())
+            .display()
+            .to_string();
+        audit(
+            &settings,
+            "compose_up",
+            &target,
+            json!({ "files": files, "detach": detach }),
+            &result,
+        );
+        return result;
+    }
+
+    if let CliCommand::Lint { dockerfile } = &command {
+        return lint(dockerfile, &settings, format);
+    }
+
+    let docker = connect(&settings)?;
+
+    match command {
+        CliCommand::Ps { all } => {
+            let containers = docker::list_containers(&docker, all).await?;
+            if format == OutputFormat::Json {
+                return print_json(&containers);
+            }
+
+            let rows = containers
+                .iter()
+                .map(|c| {
+                    vec![
+                        short_id(&c.id),
+                        c.image.clone(),
+                        c.status.clone(),
+                        c.ports
+                            .iter()
+                            .map(|p| {
+                                if p.public_port > 0 {
+                                    format!(
+                                        "{}:{}->{}/{}",
+                                        p.ip, p.public_port, p.private_port, p.type_
+                                    )
+                                } else {
+                                    format!("{}/{}", p.private_port, p.type_)
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        c.names.join(", "),
+                    ]
+                })
+                .collect();
+            print_table(&["CONTAINER ID", "IMAGE", "STATUS", "PORTS", "NAMES"], rows);
+            Ok(())
+        }
+        CliCommand::Images => {
+            let images = docker::list_images(&docker).await?;
+            if format == OutputFormat::Json {
+                return print_json(&images);
+            }
+
+            let rows = images
+                .iter()
+                .map(|i| {
+                    vec![
+                        i.repo_tags.join(", "),
+                        short_id(&i.id),
+                        i.platform.to_string(),
+                        format_bytes(i.size),
+                    ]
+                })
+                .collect();
+            print_table(&["TAGS", "IMAGE ID", "PLATFORM", "SIZE"], rows);
+            Ok(())
+        }
+        CliCommand::Logs {
+            container,
+            follow,
+            tail,
+        } => {
+            let tail = tail.unwrap_or(settings.log_tail_lines);
+            let stream = docker::container_logs_stream(&docker, &container, tail, follow);
+            tokio::pin!(stream);
+
+            let mut stdout = std::io::stdout();
+            while let Some(chunk) = stream.next().await {
+                stdout.write_all(chunk?.as_bytes())?;
+                stdout.flush()?;
+            }
+            Ok(())
+        }
+        CliCommand::Stats {
+            containers,
+            no_stream,
+        } => {
+            let containers = if containers.is_empty() {
+                docker::list_containers(&docker, false)
+                    .await?
+                    .into_iter()
+                    .map(|c| c.names.first().cloned().unwrap_or(c.id))
+                    .collect()
+            } else {
+                containers
+            };
+
+            loop {
+                let mut samples = Vec::new();
+                for container in &containers {
+                    let stats = docker::container_stats(&docker, container).await?;
+                    samples.push(StatsSample {
+                        container: container.clone(),
+                        stats,
+                    });
+                }
+                print_stats(&samples, format)?;
+
+                if no_stream {
+                    return Ok(());
+                }
+                tokio::time::sleep(Duration::from_millis(settings.stats_interval_ms)).await;
+            }
+        }
+        CliCommand::Compose {
+            command:
+                ComposeCommand::Logs {
+                    project,
+                    services,
+                    follow,
+                    tail,
+                },
+        } => {
+            let mut sources = logs::log_sources(&docker, Some(&project), &[]).await?;
+            if !services.is_empty() {
+                sources.retain(|s| services.contains(&s.service));
+            }
+            let width = sources.iter().map(|s| s.service.len()).max().unwrap_or(0);
+            let tail = tail.unwrap_or(settings.log_tail_lines);
+
+            logs::aggregate_logs(&docker, &sources, tail, follow, |batch| {
+                for line in batch.lines {
+                    if format == OutputFormat::Json {
+                        if let Ok(json) = to_json(&line, false) {
+                            println!("{}", json);
+                        }
+                    } else {
+                        println!(
+                            "{:width$} | {}",
+                            line.service,
+                            line.line.message,
+                            width = width
+                        );
+                    }
+                }
+                if batch.skipped > 0 {
+                    eprintln!("... {} lines skipped", batch.skipped);
+                }
+            })
+            .await
+        }
+        CliCommand::Compose {
+            command: ComposeCommand::Up { .. },
+        }
+        | CliCommand::Lint { .. } => unreachable!("handled above"),
+    }
+}
+
+fn lint(path: &std::path::Path, settings: &Settings, format: OutputFormat) -> DockerResult<()> {
+    let diagnostics = dockerfile::lint_file(path, &settings.dockerfile_lint.disabled_rules)?;
+    if format == OutputFormat::Json {
+        print_json(&diagnostics)?;
+    } else {
+        for d in &diagnostics {
+            let severity = match d.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Info => "info",
+            };
+            println!(
+                "{}:{}: {}: {} [{}]",
+                path.display(),
+                d.line,
+                severity,
+                d.message,
+                d.rule
+            );
+        }
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(DockerError::OperationError(format!(
+            "{} has {} lint error(s)",
+            path.display(),
+            errors
+        )));
+    }
+    Ok(())
+}
+
+/// Record a change made from the command line in the GUI's audit log
+fn audit<T>(
+    settings: &Settings,
+    action: &str,
+    target: &str,
+    parameters: serde_json::Value,
+    result: &DockerResult<T>,
+) {
+    match store::app_config_dir() {
+        Ok(dir) => AuditLog::new(dir.join(AUDIT_FILE)).record(
+            &settings.docker_state().endpoint(),
+            action,
+            target,
+            parameters,
+            result,
+        ),
+        Err(e) => eprintln!("rykard: not audited: {}", e),
+    }
+}
+
+fn connect(settings: &Settings) -> DockerResult<Docker> {
+    let mut state = settings.docker_state();
+    match state.initialize() {
+        DockerStatus::Connected => state.get_client(),
+        DockerStatus::Error(e) => Err(DockerError::ConnectionError(e)),
+        DockerStatus::Disconnected => Err(DockerError::ConnectionError(
+            "Docker client not initialized".to_string(),
+        )),
+    }
+}
+
+#[derive(Serialize)]
+struct StatsSample {
+    container: String,
+    #[serde(flatten)]
+    stats: ContainerStats,
+}
+
+fn print_stats(samples: &[StatsSample], format: OutputFormat) -> DockerResult<()> {
+    if format == OutputFormat::Json {
+        // One JSON document per line so followers can parse samples as they arrive
+        for sample in samples {
+            println!("{}", to_json(sample, false)?);
+        }
+        return Ok(());
+    }
+
+    let rows = samples
+        .iter()
+        .map(|s| {
+            vec![
+                s.container.clone(),
+                format!("{:.2}%", s.stats.cpu_usage_percent),
+                format!(
+                    "{} / {}",
+                    format_bytes(s.stats.memory_usage),
+                    format_bytes(s.stats.memory_limit)
+                ),
+                format!("{:.2}%", s.stats.memory_usage_percent),
+                format!(
+                    "{} / {}",
+                    format_bytes(s.stats.network_rx_bytes),
+                    format_bytes(s.stats.network_tx_bytes)
+                ),
+                format!(
+                    "{} / {}",
+                    format_bytes(s.stats.block_read_bytes),
+                    format_bytes(s.stats.block_write_bytes)
+                ),
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            "CONTAINER",
+            "CPU %",
+            "MEM USAGE / LIMIT",
+            "MEM %",
+            "NET I/O",
+            "BLOCK I/O",
+        ],
+        rows,
+    );
+    Ok(())
+}
+
+fn to_json<T: Serialize>(value: &T, pretty: bool) -> DockerResult<String> {
+    let json = if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    };
+    json.map_err(|e| DockerError::Unknown(format!("Failed to serialize output: {}", e)))
+}
+
+fn print_json<T: Serialize>(value: &T) -> DockerResult<()> {
+    println!("{}", to_json(value, true)?);
+    Ok(())
+}
+
+/// Print rows as left-aligned columns separated by three spaces, like the docker CLI
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<String>| {
+        let last = cells.len().saturating_sub(1);
+        cells
+            .into_iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                if i == last {
+                    cell
+                } else {
+                    format!("{:width$}", cell, width = widths[i])
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("   ")
+    };
+
+    println!(
+        "{}",
+        format_row(headers.iter().map(|h| h.to_string()).collect())
+    );
+    for row in rows {
+        println!("{}", format_row(row));
+    }
+}
+
+fn short_id(id: &str) -> String {
+    id.trim_start_matches("sha256:").chars().take(12).collect()
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[0])
+    } else {
+        format!("{:.2}{}", value, UNITS[unit])
+    }
+}
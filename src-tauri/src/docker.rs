@@ -0,0 +1,2071 @@
+//! Docker backend core shared by the Tauri commands and the `rykard` CLI.
+//!
+//! Everything in here works on a plain [`Docker`] client and returns
+//! [`DockerResult`], so the GUI and the CLI go through exactly the same code.
+use crate::guards;
+use crate::reference::ImageReference;
+use crate::settings::DeleteGuards;
+use bollard::container::Config as BollardConfig;
+use bollard::container::CreateContainerOptions as BollardCreateOptions;
+use bollard::container::{
+    InspectContainerOptions, ListContainersOptions, LogsOptions, RestartContainerOptions,
+    StartContainerOptions, Stats, StopContainerOptions, UpdateContainerOptions,
+};
+use bollard::image::{
+    CommitContainerOptions, CreateImageOptions, PruneImagesOptions, PushImageOptions,
+};
+use bollard::models::{
+    ChangeType, CreateImageInfo, EventMessage, HostConfig, ImageInspect, PortBinding,
+    PushImageInfo, RestartPolicy, RestartPolicyNameEnum,
+};
+use bollard::network::PruneNetworksOptions;
+use bollard::volume::PruneVolumesOptions;
+use bollard::Docker;
+use bytes::Bytes;
+use chrono::{NaiveDateTime, Utc};
+use futures_util::{Stream, StreamExt};
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::Frame;
+use hyper::client::conn::http1;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE, HOST};
+use hyper::{Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+/// Label docker compose puts on every container of a project
+pub const COMPOSE_PROJECT_LABEL: &str = "com.docker.compose.project";
+/// Label holding the compose service a container belongs to
+pub const COMPOSE_SERVICE_LABEL: &str = "com.docker.compose.service";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContainerInfo {
+    pub id: String,
+    pub names: Vec<String>,
+    pub image: String,
+    pub state: String,
+    pub status: String,
+    pub labels: HashMap<String, String>,
+    pub ports: Vec<PortInfo>,
+    pub created: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PortInfo {
+    pub ip: String,
+    pub private_port: u16,
+    pub public_port: u16,
+    pub type_: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageInfo {
+    pub id: String,
+    pub repo_tags: Vec<String>,
+    /// `repository@digest` of every registry the image was pulled from or pushed to
+    pub repo_digests: Vec<String>,
+    pub size: u64,
+    pub created: u64,
+    /// Platform the image's binaries were built for, which may not be the host's
+    #[serde(flatten)]
+    pub platform: ImagePlatform,
+}
+
+/// An `os/architecture[/variant]` triple such as `linux/arm64` or `linux/arm/v7`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImagePlatform {
+    pub os: String,
+    pub architecture: String,
+    pub variant: Option<String>,
+}
+
+impl ImagePlatform {
+    /// Parse the `os/arch[/variant]` form the daemon takes for `--platform`
+    pub fn parse(platform: &str) -> DockerResult<Self> {
+        let parts: Vec<&str> = platform.split('/').collect();
+        match parts.as_slice() {
+            [os, architecture] | [os, architecture, _]
+                if !os.is_empty() && !architecture.is_empty() =>
+            {
+                Ok(Self {
+                    os: os.to_string(),
+                    architecture: architecture.to_string(),
+                    variant: parts
+                        .get(2)
+                        .filter(|v| !v.is_empty())
+                        .map(|v| v.to_string()),
+                })
+            }
+            _ => Err(DockerError::OperationError(format!(
+                "Invalid platform '{}', expected os/arch[/variant] such as linux/arm64",
+                platform
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for ImagePlatform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a reference resolves to in its registry: a manifest list (image index) for
+/// multi-arch images, or a single manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestList {
+    pub digest: String,
+    pub media_type: Option<String>,
+    /// Every platform the registry has an image for
+    pub platforms: Vec<ImagePlatform>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum DockerStatus {
+    Connected,
+    Disconnected,
+    Error(String),
+}
+
+/// Structured error type for Docker operations
+#[derive(Debug, Clone, Serialize)]
+pub enum DockerError {
+    ConnectionError(String),
+    OperationError(String),
+    NotFound(String),
+    PermissionDenied(String),
+    Unknown(String),
+}
+
+impl std::fmt::Display for DockerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DockerError::ConnectionError(msg) => write!(f, "Connection error: {}", msg),
+            DockerError::OperationError(msg) => write!(f, "Operation error: {}", msg),
+            DockerError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            DockerError::PermissionDenied(msg) => write!(f, "Permission denied: {}", msg),
+            DockerError::Unknown(msg) => write!(f, "Unknown error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DockerError {}
+
+impl From<bollard::errors::Error> for DockerError {
+    fn from(err: bollard::errors::Error) -> Self {
+        match err {
+            bollard::errors::Error::DockerResponseServerError {
+                status_code,
+                message,
+            } => match status_code {
+                404 => DockerError::NotFound(message),
+                403 => DockerError::PermissionDenied(message),
+                _ => DockerError::OperationError(format!(
+                    "Server error ({}): {}",
+                    status_code, message
+                )),
+            },
+            // The Display impl drops the daemon's message, so keep it ourselves
+            bollard::errors::Error::DockerStreamError { error } => {
+                DockerError::OperationError(error)
+            }
+            err => {
+                if err.to_string().contains("connection") {
+                    DockerError::ConnectionError(err.to_string())
+                } else {
+                    DockerError::Unknown(err.to_string())
+                }
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for DockerError {
+    fn from(err: std::io::Error) -> Self {
+        DockerError::OperationError(err.to_string())
+    }
+}
+
+// Type alias for results with DockerError
+pub type DockerResult<T> = Result<T, DockerError>;
+
+// Request timeout in seconds, matching bollard's local defaults
+const DOCKER_TIMEOUT: u64 = 120;
+
+// Where bollard's local defaults connect when DOCKER_HOST isn't set
+#[cfg(unix)]
+const DEFAULT_DOCKER_HOST: &str = "unix:///var/run/docker.sock";
+#[cfg(windows)]
+const DEFAULT_DOCKER_HOST: &str = "npipe:////./pipe/docker_engine";
+
+pub struct DockerState {
+    client: Option<Docker>,
+    status: DockerStatus,
+    // Unix socket or named pipe to connect to instead of the platform default
+    socket_path: Option<String>,
+}
+
+impl Default for DockerState {
+    fn default() -> Self {
+        Self {
+            client: None,
+            status: DockerStatus::Disconnected,
+            socket_path: None,
+        }
+    }
+}
+
+impl DockerState {
+    /// Create a state that connects to the daemon listening on `socket_path`
+    pub fn with_socket(socket_path: impl Into<String>) -> Self {
+        Self {
+            socket_path: Some(socket_path.into()),
+            ..Self::default()
+        }
+    }
+
+    fn connect(&self) -> Result<Docker, bollard::errors::Error> {
+        match &self.socket_path {
+            Some(path) => {
+                Docker::connect_with_socket(path, DOCKER_TIMEOUT, bollard::API_DEFAULT_VERSION)
+            }
+            None => Docker::connect_with_local_defaults(),
+        }
+    }
+
+    pub fn initialize(&mut self) -> DockerStatus {
+        if self.client.is_some() {
+            return self.status.clone();
+        }
+
+        match self.connect() {
+            Ok(client) => {
+                self.client = Some(client);
+                self.status = DockerStatus::Connected;
+                self.status.clone()
+            }
+            Err(e) => {
+                self.status = DockerStatus::Error(format!("Failed to connect to Docker: {}", e));
+                self.status.clone()
+            }
+        }
+    }
+
+    /// The daemon address this state connects to, for display and the audit log
+    pub fn endpoint(&self) -> String {
+        match &self.socket_path {
+            Some(path) => path.clone(),
+            None => {
+                std::env::var("DOCKER_HOST").unwrap_or_else(|_| DEFAULT_DOCKER_HOST.to_string())
+            }
+        }
+    }
+
+    /// The socket or named pipe the client talks to, for requests bollard can't make
+    pub fn socket(&self) -> String {
+        match &self.socket_path {
+            Some(path) => path.clone(),
+            #[cfg(unix)]
+            None => std::env::var("DOCKER_HOST")
+                .ok()
+                .filter(|host| host.starts_with("unix://"))
+                .unwrap_or_else(|| DEFAULT_DOCKER_HOST.to_string()),
+            #[cfg(windows)]
+            None => DEFAULT_DOCKER_HOST.to_string(),
+        }
+    }
+
+    pub fn get_client(&self) -> DockerResult<Docker> {
+        match &self.client {
+            Some(client) => Ok(client.clone()),
+            None => Err(DockerError::ConnectionError(
+                "Docker client not initialized".to_string(),
+            )),
+        }
+    }
+
+    pub async fn check_status(&mut self) -> DockerStatus {
+        if let Some(client) = &self.client {
+            match client.ping().await {
+                Ok(_) => {
+                    self.status = DockerStatus::Connected;
+                }
+                Err(e) => {
+                    self.status = DockerStatus::Error(format!("Docker is not responding: {}", e));
+                }
+            }
+        } else {
+            // Try to initialize if not already initialized
+            self.initialize();
+        }
+
+        self.status.clone()
+    }
+
+    pub fn reset(&mut self) -> DockerStatus {
+        self.client = None;
+        self.status = DockerStatus::Disconnected;
+        self.initialize()
+    }
+}
+
+/// List containers, including stopped ones when `all` is set
+pub async fn list_containers(docker: &Docker, all: bool) -> DockerResult<Vec<ContainerInfo>> {
+    let options = Some(ListContainersOptions::<String> {
+        all,
+        ..Default::default()
+    });
+
+    let containers = docker.list_containers(options).await?;
+
+    let container_info = containers
+        .iter()
+        .map(|container| {
+            let names = container
+                .names
+                .clone()
+                .unwrap_or_default()
+                .iter()
+                .map(|name| name.trim_start_matches('/').to_string())
+                .collect();
+
+            // Extract labels
+            let labels = container
+                .labels
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .collect();
+
+            // Extract ports
+            let ports = container
+                .ports
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|port| {
+                    let port_type = match &port.typ {
+                        Some(t) => format!("{}", t),
+                        None => "tcp".to_string(),
+                    };
+
+                    PortInfo {
+                        ip: port.ip.unwrap_or_default(),
+                        private_port: port.private_port,
+                        public_port: port.public_port.unwrap_or_default(),
+                        type_: port_type,
+                    }
+                })
+                .collect();
+
+            ContainerInfo {
+                id: container.id.clone().unwrap_or_default(),
+                names,
+                image: container.image.clone().unwrap_or_default(),
+                state: container.state.clone().unwrap_or_default(),
+                status: container.status.clone().unwrap_or_default(),
+                labels,
+                ports,
+                created: container.created.unwrap_or_default() as u64,
+            }
+        })
+        .collect();
+
+    Ok(container_info)
+}
+
+// Image inspections in flight at once while listing
+const IMAGE_INSPECT_CONCURRENCY: usize = 8;
+
+pub async fn list_images(docker: &Docker) -> DockerResult<Vec<ImageInfo>> {
+    // Use Bollard's list_images API
+    let options = Some(bollard::image::ListImagesOptions::<String> {
+        all: false, // Only show available images
+        ..Default::default()
+    });
+
+    let images = docker.list_images(options).await?;
+
+    // The listing leaves out the platform, so ask for each image's config, a few at a
+    // time so a large image store doesn't flood the daemon
+    let ids: Vec<String> = images.iter().map(|image| image.id.clone()).collect();
+    let platforms: Vec<ImagePlatform> = futures_util::stream::iter(ids)
+        .map(|id| {
+            let docker = docker.clone();
+            async move {
+                docker
+                    .inspect_image(&id)
+                    .await
+                    .map(|details| image_platform(&details))
+                    .unwrap_or_default()
+            }
+        })
+        .buffered(IMAGE_INSPECT_CONCURRENCY)
+        .collect()
+        .await;
+
+    let image_info = images
+        .iter()
+        .zip(platforms)
+        .map(|(image, platform)| {
+            // Extract repo tags
+            let repo_tags = image.repo_tags.clone();
+
+            // Extract image ID (remove "sha256:" prefix if present)
+            let id = image.id.trim_start_matches("sha256:").to_string();
+
+            // Extract size and created timestamp
+            let size = image.size as u64;
+            let created = image.created as u64;
+
+            ImageInfo {
+                id,
+                repo_tags,
+                repo_digests: image.repo_digests.clone(),
+                size,
+                created,
+                platform,
+            }
+        })
+        .collect();
+
+    Ok(image_info)
+}
+
+fn image_platform(details: &ImageInspect) -> ImagePlatform {
+    ImagePlatform {
+        os: details.os.clone().unwrap_or_default(),
+        architecture: details.architecture.clone().unwrap_or_default(),
+        variant: details.variant.clone().filter(|v| !v.is_empty()),
+    }
+}
+
+/// Look up a single image by id or reference
+pub async fn inspect_image(docker: &Docker, image: &str) -> DockerResult<ImageInfo> {
+    let details = docker.inspect_image(image).await?;
+    let platform = image_platform(&details);
+    let created = details
+        .created
+        .as_deref()
+        .and_then(|c| chrono::DateTime::parse_from_rfc3339(c).ok())
+        .map(|c| c.timestamp() as u64)
+        .unwrap_or_default();
+
+    Ok(ImageInfo {
+        id: details
+            .id
+            .unwrap_or_default()
+            .trim_start_matches("sha256:")
+            .to_string(),
+        repo_tags: details.repo_tags.unwrap_or_default(),
+        repo_digests: details.repo_digests.unwrap_or_default(),
+        size: details.size.unwrap_or_default() as u64,
+        created,
+        platform,
+    })
+}
+
+/// Ask the registry which platforms `reference` is available for
+pub async fn manifest_list(docker: &Docker, reference: &str) -> DockerResult<ManifestList> {
+    let inspect = docker.inspect_registry_image(reference, None).await?;
+    let platforms = inspect
+        .platforms
+        .into_iter()
+        .map(|p| ImagePlatform {
+            os: p.os.unwrap_or_default(),
+            architecture: p.architecture.unwrap_or_default(),
+            variant: p.variant.filter(|v| !v.is_empty()),
+        })
+        .collect();
+
+    Ok(ManifestList {
+        digest: inspect.descriptor.digest.unwrap_or_default(),
+        media_type: inspect.descriptor.media_type,
+        platforms,
+    })
+}
+
+pub async fn start_container(docker: &Docker, container_id: &str) -> DockerResult<()> {
+    docker
+        .start_container(container_id, None::<StartContainerOptions<String>>)
+        .await?;
+    Ok(())
+}
+
+pub async fn stop_container(docker: &Docker, container_id: &str) -> DockerResult<()> {
+    docker
+        .stop_container(container_id, None::<StopContainerOptions>)
+        .await?;
+    Ok(())
+}
+
+pub async fn restart_container(docker: &Docker, container_id: &str) -> DockerResult<()> {
+    docker
+        .restart_container(container_id, None::<RestartContainerOptions>)
+        .await?;
+    Ok(())
+}
+
+pub async fn remove_container(docker: &Docker, container_id: &str) -> DockerResult<()> {
+    docker.remove_container(container_id, None).await?;
+    Ok(())
+}
+
+pub async fn remove_image(docker: &Docker, image_id: &str) -> DockerResult<()> {
+    docker.remove_image(image_id, None, None).await?;
+    Ok(())
+}
+
+/// Pull an image for the host's platform, handing every progress message to `on_progress`
+pub async fn pull_image<F>(docker: &Docker, image_name: &str, on_progress: F) -> DockerResult<()>
+where
+    F: FnMut(&CreateImageInfo),
+{
+    pull_image_for_platform(docker, image_name, None, on_progress).await
+}
+
+/// Pull an image unless it's already present, returning whether it was pulled
+pub async fn ensure_image<F>(
+    docker: &Docker,
+    image_name: &str,
+    on_progress: F,
+) -> DockerResult<bool>
+where
+    F: FnMut(&CreateImageInfo),
+{
+    match docker
+        .inspect_image(image_name)
+        .await
+        .map_err(DockerError::from)
+    {
+        Ok(_) => Ok(false),
+        Err(DockerError::NotFound(_)) => {
+            pull_image(docker, image_name, on_progress).await?;
+            Ok(true)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Pull an image, optionally for another platform such as `linux/arm64`
+pub async fn pull_image_for_platform<F>(
+    docker: &Docker,
+    image_name: &str,
+    platform: Option<&str>,
+    mut on_progress: F,
+) -> DockerResult<()>
+where
+    F: FnMut(&CreateImageInfo),
+{
+    let reference = ImageReference::parse(image_name)?;
+    let repository = reference.familiar_name();
+    let platform = match platform {
+        Some(platform) => ImagePlatform::parse(platform)?.to_string(),
+        None => String::new(),
+    };
+
+    // Create image returns a Stream, not a Future, so we need to collect the results
+    let create_image_options = CreateImageOptions {
+        from_image: repository.as_str(),
+        tag: reference.pull_tag(),
+        platform: &platform,
+        ..Default::default()
+    };
+
+    // Create a stream of pull progress events
+    let pull_stream = docker.create_image(Some(create_image_options), None, None);
+
+    tokio::pin!(pull_stream);
+
+    while let Some(pull_result) = pull_stream.next().await {
+        match pull_result {
+            Ok(progress) => on_progress(&progress),
+            Err(bollard::errors::Error::DockerStreamError { error }) => {
+                return Err(DockerError::OperationError(format!(
+                    "Failed to pull image: {}",
+                    error
+                )));
+            }
+            Err(e) => {
+                return Err(DockerError::OperationError(format!(
+                    "Failed to pull image: {}",
+                    e
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Push an image to its registry, handing every progress message to `on_progress`
+pub async fn push_image<F>(
+    docker: &Docker,
+    image_name: &str,
+    mut on_progress: F,
+) -> DockerResult<()>
+where
+    F: FnMut(&PushImageInfo),
+{
+    let reference = ImageReference::parse(image_name)?;
+    // The daemon pushes tags; a digest alone names no tag to push
+    let tag = reference.tag_or_default().ok_or_else(|| {
+        DockerError::OperationError(format!("Can't push {} without a tag", image_name))
+    })?;
+    let push_stream = docker.push_image(
+        &reference.familiar_name(),
+        Some(PushImageOptions { tag }),
+        None,
+    );
+    tokio::pin!(push_stream);
+
+    while let Some(push_result) = push_stream.next().await {
+        let progress = match push_result {
+            Ok(progress) => progress,
+            Err(bollard::errors::Error::DockerStreamError { error }) => {
+                return Err(DockerError::OperationError(format!(
+                    "Failed to push image: {}",
+                    error
+                )));
+            }
+            Err(e) => {
+                return Err(DockerError::OperationError(format!(
+                    "Failed to push image: {}",
+                    e
+                )));
+            }
+        };
+        // Push failures arrive as a message in an otherwise successful stream
+        if let Some(error) = &progress.error {
+            return Err(DockerError::OperationError(format!(
+                "Failed to push image: {}",
+                error
+            )));
+        }
+        on_progress(&progress);
+    }
+
+    Ok(())
+}
+
+/// Unused resources a prune removes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PruneTarget {
+    /// Stopped containers
+    Containers,
+    /// Dangling images
+    Images,
+    /// Volumes no container uses
+    Volumes,
+    /// Networks no container uses
+    Networks,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PruneReport {
+    pub deleted: Vec<String>,
+    /// Bytes freed; always 0 for networks
+    pub space_reclaimed: u64,
+}
+
+/// Remove unused resources. Containers go through [`guards::prune_containers`], so the
+/// trash and protected containers are left alone.
+pub async fn prune(
+    docker: &Docker,
+    target: PruneTarget,
+    delete_guards: &DeleteGuards,
+) -> DockerResult<PruneReport> {
+    let report = match target {
+        PruneTarget::Containers => guards::prune_containers(docker, delete_guards).await?,
+        PruneTarget::Images => {
+            let pruned = docker
+                .prune_images(None::<PruneImagesOptions<String>>)
+                .await?;
+            PruneReport {
+                deleted: pruned
+                    .images_deleted
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|item| item.deleted.or(item.untagged))
+                    .collect(),
+                space_reclaimed: pruned.space_reclaimed.unwrap_or_default() as u64,
+            }
+        }
+        PruneTarget::Volumes => {
+            let pruned = docker
+                .prune_volumes(None::<PruneVolumesOptions<String>>)
+                .await?;
+            PruneReport {
+                deleted: pruned.volumes_deleted.unwrap_or_default(),
+                space_reclaimed: pruned.space_reclaimed.unwrap_or_default() as u64,
+            }
+        }
+        PruneTarget::Networks => {
+            let pruned = docker
+                .prune_networks(None::<PruneNetworksOptions<String>>)
+                .await?;
+            PruneReport {
+                deleted: pruned.networks_deleted.unwrap_or_default(),
+                space_reclaimed: 0,
+            }
+        }
+    };
+    Ok(report)
+}
+
+/// Stream a container's stdout and stderr, optionally following new output
+pub fn container_logs_stream(
+    docker: &Docker,
+    container_id: &str,
+    tail_lines: u64,
+    follow: bool,
+) -> impl Stream<Item = DockerResult<String>> {
+    let options = Some(LogsOptions::<String> {
+        follow,
+        stdout: true,
+        stderr: true,
+        tail: tail_lines.to_string(),
+        ..Default::default()
+    });
+
+    docker.logs(container_id, options).map(|chunk| {
+        chunk
+            .map(|output| output.to_string())
+            .map_err(DockerError::from)
+    })
+}
+
+/// Fetch the last `tail_lines` lines of a container's logs
+pub async fn container_logs(
+    docker: &Docker,
+    container_id: &str,
+    tail_lines: u64,
+) -> DockerResult<String> {
+    let stream = container_logs_stream(docker, container_id, tail_lines, false);
+    tokio::pin!(stream);
+
+    let mut logs = String::new();
+    while let Some(chunk) = stream.next().await {
+        logs.push_str(&chunk?);
+    }
+
+    Ok(logs)
+}
+
+/// Options for creating a new container, received from the frontend
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CreateContainerOptions {
+    pub image: String,
+    pub name: String,
+    #[serde(default)]
+    pub ports: Vec<PortMapping>,
+    #[serde(default)]
+    pub volumes: Vec<VolumeMapping>,
+    /// `KEY=value` pairs
+    #[serde(default)]
+    pub env_vars: Vec<String>,
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// `os/arch[/variant]` to run the image as; `None` uses the host's
+    #[serde(default)]
+    pub platform: Option<String>,
+}
+
+/// CPU and memory limits applied to a container
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// Memory limit in bytes
+    pub memory: Option<i64>,
+    /// Number of CPUs, fractions allowed
+    pub cpus: Option<f64>,
+}
+
+impl CreateContainerOptions {
+    fn host_config(&self) -> HostConfig {
+        let mut port_bindings: HashMap<String, Vec<PortBinding>> = HashMap::new();
+        for port in &self.ports {
+            port_bindings
+                .entry(port.key())
+                .or_default()
+                .push(PortBinding {
+                    host_ip: Some(port.host_ip.clone()).filter(|ip| !ip.is_empty()),
+                    host_port: Some(port.host_port.clone()).filter(|p| !p.is_empty()),
+                });
+        }
+        let port_bindings: HashMap<_, _> = port_bindings
+            .into_iter()
+            .map(|(key, bindings)| (key, Some(bindings)))
+            .collect();
+
+        let binds: Vec<String> = self
+            .volumes
+            .iter()
+            .map(|v| match v.mode.as_str() {
+                "" => format!("{}:{}", v.host_path, v.container_path),
+                mode => format!("{}:{}:{}", v.host_path, v.container_path, mode),
+            })
+            .collect();
+
+        HostConfig {
+            port_bindings: Some(port_bindings).filter(|p| !p.is_empty()),
+            binds: Some(binds).filter(|b| !b.is_empty()),
+            memory: self.limits.memory,
+            nano_cpus: self.limits.cpus.map(|cpus| (cpus * 1e9) as i64),
+            ..Default::default()
+        }
+    }
+}
+
+/// Create a container and start it, returning the new container's ID
+pub async fn create_container(
+    docker: &Docker,
+    options: &CreateContainerOptions,
+) -> DockerResult<String> {
+    // Catch taken host ports up front instead of failing with "port is already allocated"
+    let conflicts = crate::ports::check_port_conflicts(docker, &options.ports).await?;
+    if !conflicts.is_empty() {
+        let messages: Vec<String> = conflicts.iter().map(|c| c.describe()).collect();
+        return Err(DockerError::OperationError(messages.join("\n")));
+    }
+
+    let exposed_ports: HashMap<String, HashMap<(), ()>> = options
+        .ports
+        .iter()
+        .map(|port| (port.key(), HashMap::new()))
+        .collect();
+
+    // Prepare Bollard's CreateContainerOptions and Config
+    let config = BollardConfig {
+        image: Some(options.image.clone()),
+        env: Some(options.env_vars.clone()).filter(|env| !env.is_empty()),
+        exposed_ports: Some(exposed_ports).filter(|ports| !ports.is_empty()),
+        host_config: Some(options.host_config()),
+        ..Default::default()
+    };
+
+    let platform = match &options.platform {
+        Some(platform) => Some(ImagePlatform::parse(platform)?.to_string()),
+        None => None,
+    };
+    let create_options = Some(BollardCreateOptions {
+        name: options.name.clone(),
+        platform,
+    });
+
+    let response = docker.create_container(create_options, config).await?;
+
+    // Attempt to start the container
+    match docker
+        .start_container(&response.id, None::<StartContainerOptions<String>>)
+        .await
+    {
+        Ok(_) => Ok(response.id),
+        // Even if starting fails, creation was successful, so we could argue about the return.
+        // For now, let's return an error that it failed to start.
+        Err(e) => Err(DockerError::OperationError(format!(
+            "Container created (ID: {}), but failed to start: {}",
+            response.id,
+            DockerError::from(e)
+        ))),
+    }
+}
+
+/// Stream events from the Docker daemon
+pub fn events_stream(docker: &Docker) -> impl Stream<Item = DockerResult<EventMessage>> {
+    docker
+        .events(None::<bollard::system::EventsOptions<String>>)
+        .map(|event| event.map_err(DockerError::from))
+}
+
+/// Run `docker compose up` for a project, streaming compose's own output to our stdio
+pub fn compose_up(project_dir: Option<&Path>, files: &[PathBuf], detach: bool) -> DockerResult<()> {
+    let mut command = Command::new("docker");
+    command.arg("compose");
+
+    for file in files {
+        command.arg("-f").arg(file);
+    }
+
+    command.arg("up");
+    if detach {
+        command.arg("-d");
+    }
+
+    if let Some(dir) = project_dir {
+        command.current_dir(dir);
+    }
+
+    let status = command.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(DockerError::OperationError(format!(
+            "docker compose up exited with {}",
+            status
+        )))
+    }
+}
+
+// Helper function to parse human-readable size to bytes
+pub fn parse_size(size_str: &str) -> u64 {
+    if size_str.is_empty() {
+        return 0;
+    }
+
+    let size_str = size_str.trim();
+    let mut numeric_part = String::new();
+    let mut unit_part = String::new();
+
+    for c in size_str.chars() {
+        if c.is_ascii_digit() || c == '.' {
+            numeric_part.push(c);
+        } else if !c.is_whitespace() {
+            unit_part.push(c);
+        }
+    }
+
+    let numeric_value: f64 = numeric_part.parse().unwrap_or(0.0);
+
+    match unit_part.to_uppercase().as_str() {
+        "B" => numeric_value as u64,
+        "KB" | "K" => (numeric_value * 1_024.0) as u64,
+        "MB" | "M" => (numeric_value * 1_024.0 * 1_024.0) as u64,
+        "GB" | "G" => (numeric_value * 1_024.0 * 1_024.0 * 1_024.0) as u64,
+        "TB" | "T" => (numeric_value * 1_024.0 * 1_024.0 * 1_024.0 * 1_024.0) as u64,
+        _ => numeric_value as u64,
+    }
+}
+
+// Helper function to parse timestamp to Unix timestamp
+pub fn parse_timestamp(timestamp_str: &str) -> u64 {
+    // Docker timestamps can be in different formats
+    // Try a few common formats
+    let formats = [
+        "%Y-%m-%d %H:%M:%S %z",
+        "%Y-%m-%d %H:%M:%S",
+        "%a %b %d %H:%M:%S %Y",
+        "%Y-%m-%dT%H:%M:%S",
+    ];
+
+    for format in formats {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(timestamp_str, format) {
+            // Use the non-deprecated approach
+            return dt.and_utc().timestamp() as u64;
+        }
+    }
+
+    // Fallback to current time if parsing fails
+    Utc::now().timestamp() as u64
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContainerStats {
+    pub cpu_usage_percent: f64,
+    pub memory_usage: u64,
+    pub memory_limit: u64,
+    pub memory_usage_percent: f64,
+    pub network_rx_bytes: u64,
+    pub network_tx_bytes: u64,
+    pub block_read_bytes: u64,
+    pub block_write_bytes: u64,
+}
+
+/// Get container stats (CPU, memory, network)
+pub async fn container_stats(docker: &Docker, container_id: &str) -> DockerResult<ContainerStats> {
+    // Use Bollard's stats API to get container stats
+    // We need to create a stream and get the first result
+    let stats_options = bollard::container::StatsOptions {
+        stream: false, // We only want one stats snapshot
+        ..Default::default()
+    };
+
+    let mut stats_stream = docker.stats(container_id, Some(stats_options));
+
+    // Get the first (and only) stats result
+    match stats_stream.next().await {
+        Some(Ok(stats)) => Ok(summarize_stats(&stats)),
+        Some(Err(e)) => Err(DockerError::from(e)),
+        None => Err(DockerError::NotFound(format!(
+            "No stats found for container {}",
+            container_id
+        ))),
+    }
+}
+
+/// Reduce a raw stats sample to the numbers we display
+pub fn summarize_stats(stats: &Stats) -> ContainerStats {
+    // Calculate CPU usage percentage
+    let cpu_usage_percent = calculate_cpu_percentage(stats);
+
+    // Get memory usage and limit
+    let memory_usage = stats.memory_stats.usage.unwrap_or(0);
+    let memory_limit = stats.memory_stats.limit.unwrap_or(0);
+
+    // Calculate memory usage percentage
+    let memory_usage_percent = if memory_limit > 0 {
+        (memory_usage as f64 / memory_limit as f64) * 100.0
+    } else {
+        0.0
+    };
+
+    // Get network I/O
+    let (network_rx_bytes, network_tx_bytes) = get_network_stats(stats);
+
+    // Get block I/O
+    let (block_read_bytes, block_write_bytes) = get_block_io_stats(stats);
+
+    ContainerStats {
+        cpu_usage_percent,
+        memory_usage,
+        memory_limit,
+        memory_usage_percent,
+        network_rx_bytes,
+        network_tx_bytes,
+        block_read_bytes,
+        block_write_bytes,
+    }
+}
+
+/// Calculate CPU usage percentage from stats
+pub fn calculate_cpu_percentage(stats: &Stats) -> f64 {
+    // Extract CPU usage data
+    let cpu_usage = stats.cpu_stats.cpu_usage.total_usage;
+    let precpu_usage = stats.precpu_stats.cpu_usage.total_usage;
+    let cpu_delta = if cpu_usage > precpu_usage {
+        (cpu_usage - precpu_usage) as i64
+    } else {
+        0
+    };
+
+    let system_cpu_usage = stats.cpu_stats.system_cpu_usage.unwrap_or_default();
+    let system_precpu_usage = stats.precpu_stats.system_cpu_usage.unwrap_or_default();
+
+    let system_delta = if system_cpu_usage > system_precpu_usage {
+        (system_cpu_usage - system_precpu_usage) as i64
+    } else {
+        0
+    };
+
+    let online_cpus = match stats.cpu_stats.online_cpus {
+        Some(cpus) => cpus as f64,
+        None => 1.0,
+    };
+
+    // Calculate percentage
+    if system_delta > 0 && cpu_delta > 0 {
+        ((cpu_delta as f64 / system_delta as f64) * online_cpus) * 100.0
+    } else {
+        0.0
+    }
+}
+
+/// Extract network stats from container stats
+pub fn get_network_stats(stats: &Stats) -> (u64, u64) {
+    if let Some(networks) = &stats.networks {
+        let mut rx_bytes = 0;
+        let mut tx_bytes = 0;
+
+        for network in networks.values() {
+            rx_bytes += network.rx_bytes;
+            tx_bytes += network.tx_bytes;
+        }
+
+        (rx_bytes, tx_bytes)
+    } else {
+        (0, 0)
+    }
+}
+
+/// Extract block I/O stats from container stats
+pub fn get_block_io_stats(stats: &Stats) -> (u64, u64) {
+    let blkio_stats = &stats.blkio_stats;
+
+    if let Some(io_service_bytes_recursive) = &blkio_stats.io_service_bytes_recursive {
+        let mut read_bytes = 0;
+        let mut write_bytes = 0;
+
+        for stat in io_service_bytes_recursive {
+            // op is a String, not an Option<String>
+            if stat.op == "Read" {
+                read_bytes += stat.value;
+            } else if stat.op == "Write" {
+                write_bytes += stat.value;
+            }
+        }
+
+        return (read_bytes, write_bytes);
+    }
+
+    (0, 0)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortMapping {
+    pub host_ip: String,
+    pub host_port: String,
+    pub container_port: String,
+    pub protocol: String,
+}
+
+impl PortMapping {
+    /// The `port/protocol` key Docker uses, defaulting to tcp
+    fn key(&self) -> String {
+        match self.protocol.as_str() {
+            "" => format!("{}/tcp", self.container_port),
+            protocol => format!("{}/{}", self.container_port, protocol),
+        }
+    }
+}
+
+/// A bind mount or named volume; `host_path` without a slash names a volume
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VolumeMapping {
+    pub host_path: String,
+    pub container_path: String,
+    pub mode: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContainerConfig {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub command: String,
+    pub created: String,
+    pub status: String,
+    pub ports: Vec<PortMapping>,
+    pub volumes: Vec<VolumeMapping>,
+    pub env_vars: Vec<String>,
+    pub labels: HashMap<String, String>,
+    pub network_mode: String,
+    pub restart_policy: String,
+    /// Limits currently in force, unset ones left out
+    pub resources: ContainerResources,
+    pub entrypoint: Vec<String>,
+    pub working_dir: String,
+    pub user: String,
+    pub hostname: String,
+    pub healthcheck: Option<Healthcheck>,
+    pub health: Option<HealthState>,
+    pub privileged: bool,
+    pub cap_add: Vec<String>,
+    pub cap_drop: Vec<String>,
+    pub security_opts: Vec<String>,
+    pub devices: Vec<DeviceInfo>,
+    /// Mount point to tmpfs options
+    pub tmpfs: HashMap<String, String>,
+    pub ulimits: Vec<UlimitInfo>,
+    pub log_driver: String,
+    pub log_options: HashMap<String, String>,
+    pub dns: Vec<String>,
+    pub dns_search: Vec<String>,
+    /// `host:ip` entries added to /etc/hosts
+    pub extra_hosts: Vec<String>,
+    pub networks: Vec<NetworkAttachment>,
+    pub exit_code: i64,
+    pub oom_killed: bool,
+    pub restart_count: i64,
+    /// Process id on the host, 0 when not running
+    pub pid: i64,
+    /// Why the daemon failed to start the container, if it did
+    pub error: String,
+    pub started_at: String,
+    pub finished_at: String,
+}
+
+/// The image's or container's HEALTHCHECK, with durations in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Healthcheck {
+    /// `["CMD", ...]`, `["CMD-SHELL", "..."]` or `["NONE"]`
+    pub test: Vec<String>,
+    pub interval_secs: f64,
+    pub timeout_secs: f64,
+    pub start_period_secs: f64,
+    pub retries: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthState {
+    /// `starting`, `healthy` or `unhealthy`
+    pub status: String,
+    pub failing_streak: i64,
+    /// Output of the most recent check
+    pub last_output: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub host_path: String,
+    pub container_path: String,
+    /// Cgroup permissions, e.g. `rwm`
+    pub permissions: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UlimitInfo {
+    pub name: String,
+    pub soft: i64,
+    pub hard: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkAttachment {
+    pub name: String,
+    pub network_id: String,
+    pub ip_address: String,
+    pub ipv6_address: String,
+    pub gateway: String,
+    pub mac_address: String,
+    pub aliases: Vec<String>,
+}
+
+/// Docker reports durations in nanoseconds
+fn nanos_to_secs(nanos: Option<i64>) -> f64 {
+    nanos.unwrap_or_default() as f64 / 1e9
+}
+
+/// Limits and restart policy that can be changed while a container runs.
+///
+/// Read back from a container, `None` means the setting isn't limited. In an update,
+/// `None` leaves the setting as it is. The daemon ignores `0` for every limit but the
+/// pids limit, so a limit can only be lifted on a running container by setting
+/// `pids_limit` to `0` or `-1`, or `memory_swap` to `-1`; `0` is rejected elsewhere.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerResources {
+    /// Memory limit in bytes
+    pub memory: Option<i64>,
+    /// Memory plus swap in bytes; `-1` allows unlimited swap
+    pub memory_swap: Option<i64>,
+    /// Relative CPU weight against other containers, 1024 by default
+    pub cpu_shares: Option<i64>,
+    /// CFS period in microseconds that `cpu_quota` applies to
+    pub cpu_period: Option<i64>,
+    /// Microseconds of CPU time per `cpu_period`
+    pub cpu_quota: Option<i64>,
+    /// Number of CPUs, fractions allowed; can't be combined with a quota
+    pub cpus: Option<f64>,
+    /// CPUs the container may run on, e.g. `0-3,6`
+    pub cpuset_cpus: Option<String>,
+    pub pids_limit: Option<i64>,
+    /// Relative block IO weight, 10 to 1000
+    pub blkio_weight: Option<u16>,
+    pub restart_policy: Option<RestartPolicySetting>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestartPolicySetting {
+    /// `no`, `always`, `unless-stopped` or `on-failure`
+    pub name: String,
+    /// Only used by `on-failure`; `0` retries forever
+    #[serde(default)]
+    pub maximum_retry_count: i64,
+}
+
+/// Smallest memory limit the daemon accepts
+const MIN_MEMORY: i64 = 6 * 1024 * 1024;
+
+impl ContainerResources {
+    fn from_host_config(config: &HostConfig) -> Self {
+        let set = |value: Option<i64>| value.filter(|v| *v != 0);
+        Self {
+            memory: set(config.memory),
+            memory_swap: set(config.memory_swap),
+            cpu_shares: set(config.cpu_shares),
+            cpu_period: set(config.cpu_period),
+            cpu_quota: set(config.cpu_quota),
+            cpus: set(config.nano_cpus).map(|nano| nano as f64 / 1e9),
+            cpuset_cpus: config.cpuset_cpus.clone().filter(|c| !c.is_empty()),
+            pids_limit: set(config.pids_limit).filter(|p| *p > 0),
+            blkio_weight: config.blkio_weight.filter(|w| *w != 0),
+            restart_policy: config
+                .restart_policy
+                .as_ref()
+                .and_then(|policy| {
+                    Some(RestartPolicySetting {
+                        name: policy.name?.to_string(),
+                        maximum_retry_count: policy.maximum_retry_count.unwrap_or_default(),
+                    })
+                })
+                .filter(|policy| !policy.name.is_empty()),
+        }
+    }
+
+    /// Catch what the daemon would reject or silently ignore, with messages that name
+    /// our fields
+    fn validate(&self) -> DockerResult<()> {
+        let invalid = |msg: String| Err(DockerError::OperationError(msg));
+
+        let ignored = [
+            ("memory", self.memory == Some(0)),
+            ("memory_swap", self.memory_swap == Some(0)),
+            ("cpu_shares", self.cpu_shares == Some(0)),
+            ("cpu_period", self.cpu_period == Some(0)),
+            ("cpu_quota", self.cpu_quota == Some(0)),
+            ("cpus", self.cpus == Some(0.0)),
+            ("blkio_weight", self.blkio_weight == Some(0)),
+        ];
+        if let Some((field, _)) = ignored.iter().find(|(_, zero)| *zero) {
+            return invalid(format!(
+                "Docker can't lift {} on a running container; recreate it without the limit",
+                field
+            ));
+        }
+
+        if let Some(memory) = self.memory {
+            if memory < MIN_MEMORY {
+                return invalid("Memory limit must be at least 6MB".to_string());
+            }
+        }
+        if let Some(swap) = self.memory_swap.filter(|s| *s > 0) {
+            match self.memory {
+                Some(memory) if memory > 0 && swap < memory => {
+                    return invalid(
+                        "Memory plus swap must be at least the memory limit".to_string(),
+                    )
+                }
+                _ => {}
+            }
+        }
+        if self.memory_swap.is_some_and(|s| s < -1) {
+            return invalid("Memory plus swap must be -1 or a size".to_string());
+        }
+        if self
+            .cpu_period
+            .is_some_and(|p| !(1_000..=1_000_000).contains(&p))
+        {
+            return invalid("CPU period must be between 1000 and 1000000 microseconds".to_string());
+        }
+        if self.cpu_quota.is_some_and(|q| q > 0 && q < 1_000) {
+            return invalid("CPU quota must be at least 1000 microseconds".to_string());
+        }
+        if let Some(cpus) = self.cpus {
+            if cpus < 0.0 || !cpus.is_finite() {
+                return invalid(format!("Invalid number of CPUs: {}", cpus));
+            }
+            let quota =
+                self.cpu_quota.is_some_and(|q| q > 0) || self.cpu_period.is_some_and(|p| p > 0);
+            if cpus > 0.0 && quota {
+                return invalid("Set either cpus or a CPU quota and period, not both".to_string());
+            }
+        }
+        if self.cpu_shares.is_some_and(|s| s < 0) {
+            return invalid("CPU shares can't be negative".to_string());
+        }
+        if let Some(cpuset) = self.cpuset_cpus.as_deref().filter(|c| !c.is_empty()) {
+            if !is_cpu_list(cpuset) {
+                return invalid(format!(
+                    "Invalid cpuset '{}', expected a list like 0-3,6",
+                    cpuset
+                ));
+            }
+        }
+        if self.pids_limit.is_some_and(|p| p < -1) {
+            return invalid("Pids limit must be -1, 0 or a positive number".to_string());
+        }
+        if self.blkio_weight.is_some_and(|w| !(10..=1000).contains(&w)) {
+            return invalid("Block IO weight must be between 10 and 1000".to_string());
+        }
+        if let Some(policy) = &self.restart_policy {
+            if !matches!(
+                policy.name.as_str(),
+                "no" | "always" | "unless-stopped" | "on-failure"
+            ) {
+                return invalid(format!("Unknown restart policy '{}'", policy.name));
+            }
+            if policy.maximum_retry_count < 0 {
+                return invalid("Maximum retry count can't be negative".to_string());
+            }
+            if policy.maximum_retry_count > 0 && policy.name != "on-failure" {
+                return invalid(
+                    "A maximum retry count only applies to the on-failure policy".to_string(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `0-3,6`: CPU numbers and inclusive ranges separated by commas
+fn is_cpu_list(list: &str) -> bool {
+    list.split(',').all(|part| {
+        let (start, end) = part.split_once('-').unwrap_or((part, part));
+        match (start.parse::<u32>(), end.parse::<u32>()) {
+            (Ok(start), Ok(end)) => start <= end,
+            _ => false,
+        }
+    })
+}
+
+/// Change a container's limits and restart policy without restarting it, returning
+/// the limits now in force
+pub async fn update_container_resources(
+    docker: &Docker,
+    container_id: &str,
+    resources: &ContainerResources,
+) -> DockerResult<ContainerResources> {
+    resources.validate()?;
+
+    let restart_policy = resources
+        .restart_policy
+        .as_ref()
+        .map(|policy| RestartPolicy {
+            name: Some(
+                policy
+                    .name
+                    .parse::<RestartPolicyNameEnum>()
+                    .unwrap_or(RestartPolicyNameEnum::EMPTY),
+            ),
+            maximum_retry_count: Some(policy.maximum_retry_count),
+        });
+    let options = UpdateContainerOptions::<String> {
+        memory: resources.memory,
+        memory_swap: resources.memory_swap,
+        cpu_shares: resources.cpu_shares.map(|s| s as isize),
+        cpu_period: resources.cpu_period,
+        cpu_quota: resources.cpu_quota,
+        nano_cpus: resources.cpus.map(|cpus| (cpus * 1e9) as i64),
+        cpuset_cpus: resources.cpuset_cpus.clone(),
+        pids_limit: resources.pids_limit,
+        blkio_weight: resources.blkio_weight,
+        restart_policy,
+        ..Default::default()
+    };
+    docker.update_container(container_id, options).await?;
+
+    let details = docker.inspect_container(container_id, None).await?;
+    Ok(ContainerResources::from_host_config(
+        &details.host_config.unwrap_or_default(),
+    ))
+}
+
+/// Get detailed container configuration, runtime state and network attachments
+pub async fn container_config(
+    docker: &Docker,
+    container_id: &str,
+) -> DockerResult<ContainerConfig> {
+    // Inspect the container to get its configuration
+    let details = docker.inspect_container(container_id, None).await?;
+
+    // Extract container name (remove leading slash)
+    let name = details
+        .name
+        .unwrap_or_default()
+        .trim_start_matches('/')
+        .to_string();
+
+    // Extract image name
+    let image = details
+        .config
+        .as_ref()
+        .and_then(|config| config.image.clone())
+        .unwrap_or_default();
+
+    // Extract command
+    let command = details
+        .config
+        .as_ref()
+        .and_then(|config| config.cmd.clone())
+        .map(|cmd| cmd.join(" "))
+        .unwrap_or_default();
+
+    // Extract created time
+    let created = details.created.unwrap_or_default();
+
+    // Extract status - fix the Default trait issue
+    let status = details
+        .state
+        .as_ref()
+        .and_then(|state| state.status)
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let config = details.config.clone().unwrap_or_default();
+    let host_config = details.host_config.clone().unwrap_or_default();
+    let state = details.state.clone().unwrap_or_default();
+
+    let healthcheck = config.healthcheck.map(|check| Healthcheck {
+        test: check.test.unwrap_or_default(),
+        interval_secs: nanos_to_secs(check.interval),
+        timeout_secs: nanos_to_secs(check.timeout),
+        start_period_secs: nanos_to_secs(check.start_period),
+        retries: check.retries.unwrap_or_default(),
+    });
+    let health = state.health.map(|health| HealthState {
+        status: health.status.map(|s| s.to_string()).unwrap_or_default(),
+        failing_streak: health.failing_streak.unwrap_or_default(),
+        last_output: health
+            .log
+            .and_then(|log| log.last().and_then(|result| result.output.clone()))
+            .unwrap_or_default(),
+    });
+
+    let devices = host_config
+        .devices
+        .unwrap_or_default()
+        .into_iter()
+        .map(|device| DeviceInfo {
+            host_path: device.path_on_host.unwrap_or_default(),
+            container_path: device.path_in_container.unwrap_or_default(),
+            permissions: device.cgroup_permissions.unwrap_or_default(),
+        })
+        .collect();
+    let ulimits = host_config
+        .ulimits
+        .unwrap_or_default()
+        .into_iter()
+        .map(|ulimit| UlimitInfo {
+            name: ulimit.name.unwrap_or_default(),
+            soft: ulimit.soft.unwrap_or_default(),
+            hard: ulimit.hard.unwrap_or_default(),
+        })
+        .collect();
+    let log_config = host_config.log_config.unwrap_or_default();
+
+    let mut networks: Vec<NetworkAttachment> = details
+        .network_settings
+        .as_ref()
+        .and_then(|settings| settings.networks.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, endpoint)| NetworkAttachment {
+            name,
+            network_id: endpoint.network_id.unwrap_or_default(),
+            ip_address: endpoint.ip_address.unwrap_or_default(),
+            ipv6_address: endpoint.global_ipv6_address.unwrap_or_default(),
+            gateway: endpoint.gateway.unwrap_or_default(),
+            mac_address: endpoint.mac_address.unwrap_or_default(),
+            aliases: endpoint.aliases.unwrap_or_default(),
+        })
+        .collect();
+    networks.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Extract port mappings
+    let mut ports = Vec::new();
+    if let Some(network_settings) = details.network_settings {
+        if let Some(port_map) = network_settings.ports {
+            for (container_port, host_bindings) in port_map {
+                if let Some(bindings) = host_bindings {
+                    for binding in bindings {
+                        let parts: Vec<&str> = container_port.split('/').collect();
+                        let port_number = parts.first().unwrap_or(&"");
+                        let protocol = parts.get(1).unwrap_or(&"tcp");
+
+                        ports.push(PortMapping {
+                            host_ip: binding.host_ip.unwrap_or_default(),
+                            host_port: binding.host_port.unwrap_or_default(),
+                            container_port: port_number.to_string(),
+                            protocol: protocol.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Extract volume mappings
+    let mut volumes = Vec::new();
+    if let Some(mounts) = details.mounts {
+        for mount in mounts {
+            volumes.push(VolumeMapping {
+                host_path: mount.source.unwrap_or_default(),
+                container_path: mount.destination.unwrap_or_default(),
+                mode: mount.mode.unwrap_or_default(),
+            });
+        }
+    }
+
+    // Extract environment variables
+    let env_vars = details
+        .config
+        .as_ref()
+        .and_then(|config| config.env.clone())
+        .unwrap_or_default();
+
+    // Extract labels
+    let labels = details
+        .config
+        .as_ref()
+        .and_then(|config| config.labels.clone())
+        .unwrap_or_default();
+
+    let resources = details
+        .host_config
+        .as_ref()
+        .map(ContainerResources::from_host_config)
+        .unwrap_or_default();
+
+    // Extract network mode
+    let network_mode = details
+        .host_config
+        .as_ref()
+        .and_then(|config| config.network_mode.clone())
+        .unwrap_or_default();
+
+    // Extract restart policy - fix the Default trait issue
+    let restart_policy = details
+        .host_config
+        .as_ref()
+        .and_then(|config| config.restart_policy.as_ref())
+        .and_then(|policy| policy.name)
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| "no".to_string());
+
+    Ok(ContainerConfig {
+        id: container_id.to_string(),
+        name,
+        image,
+        command,
+        created,
+        status,
+        ports,
+        volumes,
+        env_vars,
+        labels,
+        network_mode,
+        restart_policy,
+        resources,
+        entrypoint: config.entrypoint.unwrap_or_default(),
+        working_dir: config.working_dir.unwrap_or_default(),
+        user: config.user.unwrap_or_default(),
+        hostname: config.hostname.unwrap_or_default(),
+        healthcheck,
+        health,
+        privileged: host_config.privileged.unwrap_or(false),
+        cap_add: host_config.cap_add.unwrap_or_default(),
+        cap_drop: host_config.cap_drop.unwrap_or_default(),
+        security_opts: host_config.security_opt.unwrap_or_default(),
+        devices,
+        tmpfs: host_config.tmpfs.unwrap_or_default(),
+        ulimits,
+        log_driver: log_config.typ.unwrap_or_default(),
+        log_options: log_config.config.unwrap_or_default(),
+        dns: host_config.dns.unwrap_or_default(),
+        dns_search: host_config.dns_search.unwrap_or_default(),
+        extra_hosts: host_config.extra_hosts.unwrap_or_default(),
+        networks,
+        exit_code: state.exit_code.unwrap_or_default(),
+        oom_killed: state.oom_killed.unwrap_or(false),
+        restart_count: details.restart_count.unwrap_or_default(),
+        pid: state.pid.unwrap_or_default(),
+        error: state.error.unwrap_or_default(),
+        started_at: state.started_at.unwrap_or_default(),
+        finished_at: state.finished_at.unwrap_or_default(),
+    })
+}
+
+/// Options for snapshotting a container into an image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommitOptions {
+    /// Repository to tag the image with; empty leaves the image untagged
+    pub repo: String,
+    pub tag: String,
+    pub author: String,
+    /// Commit message
+    pub message: String,
+    /// Pause the container while committing
+    pub pause: bool,
+    /// Replaces the image's `CMD`
+    pub cmd: Option<Vec<String>>,
+    /// `KEY=value` pairs added to the image's `ENV`
+    pub env: Vec<String>,
+}
+
+impl Default for CommitOptions {
+    fn default() -> Self {
+        Self {
+            repo: String::new(),
+            tag: String::new(),
+            author: String::new(),
+            message: String::new(),
+            pause: true,
+            cmd: None,
+            env: Vec::new(),
+        }
+    }
+}
+
+/// `CMD` and `ENV` Dockerfile instructions applied when committing or importing
+fn config_changes(cmd: Option<&[String]>, env: &[String]) -> DockerResult<Vec<String>> {
+    let mut changes = Vec::new();
+
+    if let Some(cmd) = cmd {
+        let cmd = serde_json::to_string(cmd).map_err(|e| DockerError::Unknown(e.to_string()))?;
+        changes.push(format!("CMD {}", cmd));
+    }
+    for pair in env {
+        let (key, value) = env_pair(pair)?;
+        // A JSON string is also a valid double-quoted Dockerfile value
+        let value =
+            serde_json::to_string(value).map_err(|e| DockerError::Unknown(e.to_string()))?;
+        changes.push(format!("ENV {}={}", key, value));
+    }
+
+    Ok(changes)
+}
+
+/// Split a `KEY=value` environment variable
+fn env_pair(pair: &str) -> DockerResult<(&str, &str)> {
+    pair.split_once('=')
+        .filter(|(key, _)| !key.is_empty())
+        .ok_or_else(|| {
+            DockerError::OperationError(format!("Invalid environment variable: {}", pair))
+        })
+}
+
+/// Create an image from a container's current state, returning the new image ID
+pub async fn commit_container(
+    docker: &Docker,
+    container_id: &str,
+    options: &CommitOptions,
+) -> DockerResult<String> {
+    if options.repo.is_empty() && !options.tag.is_empty() {
+        return Err(DockerError::OperationError(
+            "A tag needs a repository".to_string(),
+        ));
+    }
+
+    let commit_options = CommitContainerOptions {
+        container: container_id.to_string(),
+        repo: options.repo.clone(),
+        tag: options.tag.clone(),
+        comment: options.message.clone(),
+        author: options.author.clone(),
+        pause: options.pause,
+        changes: Some(config_changes(options.cmd.as_deref(), &options.env)?.join("\n"))
+            .filter(|c| !c.is_empty()),
+    };
+
+    let commit = docker
+        .commit_container(commit_options, BollardConfig::<String>::default())
+        .await?;
+    if let Some(id) = commit.id {
+        return Ok(id);
+    }
+
+    // bollard reads the response as `{"ID": ..}` but the daemon answers `{"Id": ..}`,
+    // so look the new image up by the reference it was given instead
+    if options.repo.is_empty() {
+        return Err(DockerError::OperationError(
+            "Container committed, but the untagged image ID is unknown".to_string(),
+        ));
+    }
+    let reference = match options.tag.as_str() {
+        "" => options.repo.clone(),
+        tag => format!("{}:{}", options.repo, tag),
+    };
+    let image = docker.inspect_image(&reference).await?;
+    Ok(image.id.unwrap_or_default())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Modified,
+    Added,
+    Deleted,
+}
+
+/// A path that changed in a container's filesystem relative to its image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContainerChange {
+    pub path: String,
+    pub kind: ChangeKind,
+}
+
+/// Filesystem changes in a container since it was created, sorted by path
+pub async fn container_diff(
+    docker: &Docker,
+    container_id: &str,
+) -> DockerResult<Vec<ContainerChange>> {
+    let mut changes: Vec<ContainerChange> = docker
+        .container_changes(container_id)
+        .await?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|change| ContainerChange {
+            path: change.path,
+            kind: match change.kind {
+                ChangeType::_0 => ChangeKind::Modified,
+                ChangeType::_1 => ChangeKind::Added,
+                ChangeType::_2 => ChangeKind::Deleted,
+            },
+        })
+        .collect();
+
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+/// Progress of a container export
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportProgress {
+    pub container_id: String,
+    pub bytes_written: u64,
+    /// Size of the container's filesystem; the tar ends up slightly larger
+    pub estimated_total: Option<u64>,
+    pub done: bool,
+}
+
+// Report export and import progress at most once per this many bytes
+const EXPORT_PROGRESS_STEP: u64 = 1024 * 1024;
+
+/// Write a container's filesystem to a tar file, returning the number of bytes written.
+///
+/// The tar is streamed to a `.part` file next to `path` and only renamed into place
+/// once complete, so a failed export never leaves a truncated archive behind.
+pub async fn export_container<F>(
+    docker: &Docker,
+    container_id: &str,
+    path: &Path,
+    mut on_progress: F,
+) -> DockerResult<u64>
+where
+    F: FnMut(&ExportProgress),
+{
+    let details = docker
+        .inspect_container(container_id, Some(InspectContainerOptions { size: true }))
+        .await?;
+    let mut progress = ExportProgress {
+        container_id: details.id.unwrap_or_else(|| container_id.to_string()),
+        bytes_written: 0,
+        estimated_total: details.size_root_fs.map(|size| size as u64),
+        done: false,
+    };
+
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+
+    let result = async {
+        let mut file = tokio::io::BufWriter::new(tokio::fs::File::create(&part).await?);
+        let stream = docker.export_container(container_id);
+        tokio::pin!(stream);
+
+        let mut reported = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            file.write_all(&chunk).await?;
+            progress.bytes_written += chunk.len() as u64;
+            if progress.bytes_written - reported >= EXPORT_PROGRESS_STEP {
+                reported = progress.bytes_written;
+                on_progress(&progress);
+            }
+        }
+
+        file.flush().await?;
+        tokio::fs::rename(&part, path).await?;
+        Ok::<_, DockerError>(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&part).await;
+        return Err(e);
+    }
+
+    progress.done = true;
+    on_progress(&progress);
+    Ok(progress.bytes_written)
+}
+
+/// Options for creating an image from a filesystem tarball
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportOptions {
+    /// Repository to tag the image with; empty leaves the image untagged
+    pub repo: String,
+    pub tag: String,
+    pub cmd: Option<Vec<String>>,
+    /// `KEY=value` pairs for the image's `ENV`
+    pub env: Vec<String>,
+    /// Further Dockerfile instructions such as `WORKDIR /app` or `EXPOSE 80`
+    pub changes: Vec<String>,
+}
+
+/// Progress of an image import
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportProgress {
+    pub bytes_sent: u64,
+    pub total: u64,
+    pub done: bool,
+}
+
+// Size of the chunks an import is streamed to the daemon in
+const IMPORT_CHUNK: usize = 64 * 1024;
+
+/// Create an image from a tarball such as one written by `export_container`, returning its ID.
+///
+/// bollard only sends an import as a single in-memory body, so the tarball is streamed
+/// to the daemon's `socket` directly instead.
+pub async fn import_image<F>(
+    docker: &Docker,
+    socket: &str,
+    path: &Path,
+    options: &ImportOptions,
+    mut on_progress: F,
+) -> DockerResult<String>
+where
+    F: FnMut(&ImportProgress),
+{
+    if options.repo.is_empty() && !options.tag.is_empty() {
+        return Err(DockerError::OperationError(
+            "A tag needs a repository".to_string(),
+        ));
+    }
+    let mut changes = config_changes(options.cmd.as_deref(), &options.env)?;
+    changes.extend(options.changes.iter().cloned());
+
+    let read_error = |e: io::Error| {
+        DockerError::OperationError(format!("Failed to read {}: {}", path.display(), e))
+    };
+    let mut file = tokio::fs::File::open(path).await.map_err(read_error)?;
+    let total = file.metadata().await.map_err(read_error)?.len();
+
+    let mut query = vec![("fromSrc", "-")];
+    if !options.repo.is_empty() {
+        query.push(("repo", options.repo.as_str()));
+    }
+    if !options.tag.is_empty() {
+        query.push(("tag", options.tag.as_str()));
+    }
+    query.extend(changes.iter().map(|change| ("changes", change.as_str())));
+    let query =
+        serde_urlencoded::to_string(&query).map_err(|e| DockerError::Unknown(e.to_string()))?;
+    let uri = format!("/v{}/images/create?{}", docker.client_version(), query);
+
+    let (body_sender, body) = mpsc::channel::<Bytes>(8);
+    let mut progress = ImportProgress {
+        bytes_sent: 0,
+        total,
+        done: false,
+    };
+    let send = async {
+        let body_sender = body_sender;
+        let mut reported = 0;
+        let mut buffer = vec![0; IMPORT_CHUNK];
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            if body_sender
+                .send(Bytes::copy_from_slice(&buffer[..read]))
+                .await
+                .is_err()
+            {
+                // The daemon answered before reading everything; its answer says why
+                return Ok(());
+            }
+            progress.bytes_sent += read as u64;
+            if progress.bytes_sent - reported >= EXPORT_PROGRESS_STEP {
+                reported = progress.bytes_sent;
+                on_progress(&progress);
+            }
+        }
+    };
+    let (sent, response) = tokio::join!(send, post_stream(socket, &uri, total, body));
+    // A read error cuts the body short, which is what the daemon will have complained about
+    sent.map_err(read_error)?;
+    let (status, body) = response?;
+
+    let messages = serde_json::Deserializer::from_slice(&body)
+        .into_iter::<serde_json::Value>()
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+    let failure = messages.iter().find_map(|message| {
+        message["message"]
+            .as_str()
+            .or_else(|| message["error"].as_str())
+    });
+    if !status.is_success() || failure.is_some() {
+        let message = failure
+            .map(str::to_string)
+            .unwrap_or_else(|| status.to_string());
+        return Err(DockerError::OperationError(format!(
+            "Failed to import image: {}",
+            message
+        )));
+    }
+    // The daemon's last word is the new image's ID
+    let image_id = messages
+        .iter()
+        .rev()
+        .find_map(|message| message["status"].as_str())
+        .filter(|status| status.starts_with("sha256:"))
+        .ok_or_else(|| {
+            DockerError::OperationError("The daemon didn't report the imported image".to_string())
+        })?
+        .to_string();
+
+    progress.done = true;
+    on_progress(&progress);
+    Ok(image_id)
+}
+
+/// POST a streamed body of `length` bytes to the daemon listening on `socket`, returning
+/// the response status and body
+async fn post_stream(
+    socket: &str,
+    uri: &str,
+    length: u64,
+    body: mpsc::Receiver<Bytes>,
+) -> DockerResult<(StatusCode, Bytes)> {
+    let connection_error = |e: &dyn std::fmt::Display| {
+        DockerError::ConnectionError(format!("Failed to reach {}: {}", socket, e))
+    };
+    let body = futures_util::stream::unfold(body, |mut body| async move {
+        body.recv()
+            .await
+            .map(|chunk| (Ok::<_, io::Error>(Frame::data(chunk)), body))
+    });
+    let request = Request::post(uri)
+        .header(HOST, "localhost")
+        .header(CONTENT_TYPE, "application/x-tar")
+        .header(CONTENT_LENGTH, length)
+        .body(StreamBody::new(body))
+        .map_err(|e| DockerError::Unknown(e.to_string()))?;
+
+    let address = socket
+        .trim_start_matches("unix://")
+        .trim_start_matches("npipe://");
+    #[cfg(unix)]
+    let stream = tokio::net::UnixStream::connect(address)
+        .await
+        .map_err(|e| connection_error(&e))?;
+    #[cfg(windows)]
+    let stream = tokio::net::windows::named_pipe::ClientOptions::new()
+        .open(address.replace('/', "\\"))
+        .map_err(|e| connection_error(&e))?;
+
+    let (mut sender, connection) = http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| connection_error(&e))?;
+    tokio::spawn(connection);
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|e| connection_error(&e))?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| connection_error(&e))?
+        .to_bytes();
+    Ok((status, body))
+}
@@ -0,0 +1,642 @@
+//! Dockerfile parsing and linting.
+//!
+//! The parser follows the BuildKit frontend closely enough for linting: parser
+//! directives, escaped line continuations, comments inside continuations, heredocs
+//! and JSON (exec) form arguments. Every rule has a stable id so it can be turned
+//! off in settings.
+use crate::docker::{DockerError, DockerResult};
+use crate::reference::ImageReference;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rule {
+    pub id: &'static str,
+    pub severity: Severity,
+    pub description: &'static str,
+}
+
+const fn rule(id: &'static str, severity: Severity, description: &'static str) -> Rule {
+    Rule {
+        id,
+        severity,
+        description,
+    }
+}
+
+/// Every rule the linter knows, in the order they're checked
+pub const RULES: &[Rule] = &[
+    rule(
+        "unknown-instruction",
+        Severity::Error,
+        "Instruction isn't a Dockerfile keyword",
+    ),
+    rule(
+        "from-first",
+        Severity::Error,
+        "Only ARG may come before the first FROM",
+    ),
+    rule(
+        "unpinned-base-image",
+        Severity::Warning,
+        "Base image has no tag or digest, so it floats to whatever `latest` is",
+    ),
+    rule(
+        "latest-tag",
+        Severity::Warning,
+        "Base image uses the `latest` tag",
+    ),
+    rule(
+        "apt-get-no-cleanup",
+        Severity::Warning,
+        "`apt-get install` without removing /var/lib/apt/lists in the same RUN",
+    ),
+    rule(
+        "apt-get-missing-yes",
+        Severity::Warning,
+        "`apt-get install` without `-y` waits for confirmation and fails the build",
+    ),
+    rule(
+        "apk-no-cache",
+        Severity::Info,
+        "`apk add` without `--no-cache` leaves the package index in the layer",
+    ),
+    rule(
+        "add-instead-of-copy",
+        Severity::Warning,
+        "ADD used for local files; COPY is more predictable",
+    ),
+    rule(
+        "missing-user",
+        Severity::Warning,
+        "Final stage never sets USER, so the container runs as root",
+    ),
+    rule(
+        "root-user",
+        Severity::Warning,
+        "Final stage explicitly runs as root",
+    ),
+    rule(
+        "secret-in-env",
+        Severity::Error,
+        "ENV or ARG bakes something that looks like a secret into the image",
+    ),
+    rule(
+        "cd-in-run",
+        Severity::Info,
+        "`cd` in RUN; use WORKDIR instead",
+    ),
+    rule(
+        "relative-workdir",
+        Severity::Warning,
+        "WORKDIR should be an absolute path",
+    ),
+    rule(
+        "multiple-cmd",
+        Severity::Warning,
+        "Only the last CMD or ENTRYPOINT of a stage takes effect",
+    ),
+    rule(
+        "shell-form-cmd",
+        Severity::Info,
+        "Shell form CMD/ENTRYPOINT runs under `sh -c`, which doesn't forward signals",
+    ),
+    rule(
+        "curl-pipe-shell",
+        Severity::Warning,
+        "Piping a download straight into a shell",
+    ),
+    rule(
+        "sudo-in-run",
+        Severity::Warning,
+        "RUN already runs as the current USER; sudo adds nothing but a dependency",
+    ),
+    rule(
+        "maintainer-deprecated",
+        Severity::Info,
+        "MAINTAINER is deprecated; use a LABEL",
+    ),
+];
+
+const KEYWORDS: [&str; 18] = [
+    "ADD",
+    "ARG",
+    "CMD",
+    "COPY",
+    "ENTRYPOINT",
+    "ENV",
+    "EXPOSE",
+    "FROM",
+    "HEALTHCHECK",
+    "LABEL",
+    "MAINTAINER",
+    "ONBUILD",
+    "RUN",
+    "SHELL",
+    "STOPSIGNAL",
+    "USER",
+    "VOLUME",
+    "WORKDIR",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub rule: String,
+    pub severity: Severity,
+    /// 1-based line the instruction starts on
+    pub line: usize,
+    pub message: String,
+}
+
+/// One instruction, with continuations joined and heredoc bodies appended
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    /// Upper-cased keyword
+    pub keyword: String,
+    /// Leading `--flag=value` options, e.g. `--from=builder`
+    pub flags: Vec<String>,
+    /// Everything after the keyword and flags
+    pub args: String,
+    /// Arguments in exec form, when given as a JSON array
+    pub json: Option<Vec<String>>,
+    pub line: usize,
+}
+
+/// Split a Dockerfile into instructions
+pub fn parse(text: &str) -> Vec<Instruction> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut escape = '\\';
+    let mut instructions = Vec::new();
+    let mut i = 0;
+
+    // Parser directives are only recognised at the very top
+    while i < lines.len() {
+        let Some(directive) = lines[i].trim().strip_prefix('#') else {
+            break;
+        };
+        match directive.split_once('=') {
+            Some((key, value)) if !key.trim().contains(' ') => {
+                if key.trim().eq_ignore_ascii_case("escape") {
+                    escape = value.trim().chars().next().unwrap_or('\\');
+                }
+                i += 1;
+            }
+            _ => break,
+        }
+    }
+
+    while i < lines.len() {
+        let trimmed = lines[i].trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        let mut logical = String::new();
+        loop {
+            let line = lines[i].trim_end();
+            i += 1;
+            match line.strip_suffix(escape) {
+                Some(rest) => {
+                    logical.push_str(rest);
+                    logical.push(' ');
+                    // Comment lines inside a continuation are dropped, blank ones ignored
+                    while i < lines.len() {
+                        let next = lines[i].trim();
+                        if next.is_empty() || next.starts_with('#') {
+                            i += 1;
+                        } else {
+                            break;
+                        }
+                    }
+                    if i >= lines.len() {
+                        break;
+                    }
+                }
+                None => {
+                    logical.push_str(line);
+                    break;
+                }
+            }
+        }
+
+        let logical = logical.trim();
+        let (keyword, rest) = logical
+            .split_once(char::is_whitespace)
+            .unwrap_or((logical, ""));
+        let mut rest = rest.trim_start();
+        let mut flags = Vec::new();
+        while rest.starts_with("--") {
+            let (flag, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            flags.push(flag.to_string());
+            rest = tail.trim_start();
+        }
+        let mut args = rest.to_string();
+
+        // Heredocs: `RUN <<EOF` takes the following lines up to the terminator
+        for caps in HEREDOC.captures_iter(rest) {
+            let terminator = &caps[1];
+            let strip_tabs = caps.get(0).map(|m| m.as_str().starts_with("<<-")) == Some(true);
+            while i < lines.len() {
+                let line = if strip_tabs {
+                    lines[i].trim_start_matches('\t')
+                } else {
+                    lines[i]
+                };
+                i += 1;
+                if line == terminator {
+                    break;
+                }
+                args.push('\n');
+                args.push_str(line);
+            }
+        }
+
+        let json = if rest.starts_with('[') {
+            serde_json::from_str(rest).ok()
+        } else {
+            None
+        };
+
+        instructions.push(Instruction {
+            keyword: keyword.to_ascii_uppercase(),
+            flags,
+            args,
+            json,
+            line: start + 1,
+        });
+    }
+
+    instructions
+}
+
+static HEREDOC: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"<<-?["']?([A-Za-z_][A-Za-z0-9_]*)["']?"#).unwrap());
+static SECRET_NAME: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(passw(or)?d|secret|token|api_?key|private_?key|access_?key|credential)")
+        .unwrap()
+});
+static CURL_PIPE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b(curl|wget)\b[^|;&]*\|\s*(sudo\s+)?(ba|z|da)?sh\b").unwrap());
+static CD: Lazy<Regex> = Lazy::new(|| Regex::new(r"(^|&&|;|\|\|)\s*cd\s").unwrap());
+static SUDO: Lazy<Regex> = Lazy::new(|| Regex::new(r"(^|&&|;|\|\|)\s*sudo\s").unwrap());
+
+/// Shell commands of a RUN, split on `&&`, `;` and `||`
+fn commands(args: &str) -> Vec<&str> {
+    static SEPARATOR: Lazy<Regex> = Lazy::new(|| Regex::new(r"&&|\|\||;|\n").unwrap());
+    SEPARATOR
+        .split(args)
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+/// `KEY=value` pairs of ENV/ARG, also accepting the legacy `ENV KEY value` form
+fn assignments(instruction: &Instruction) -> Vec<(String, Option<String>)> {
+    let args = instruction.args.trim();
+    let first = args.split_whitespace().next().unwrap_or_default();
+    if instruction.keyword == "ENV" && !first.contains('=') {
+        let value = args[first.len()..].trim();
+        return vec![(first.to_string(), Some(value.to_string()))];
+    }
+
+    let mut pairs = Vec::new();
+    let mut token = String::new();
+    let mut quote = None;
+    for c in args.chars().chain(std::iter::once(' ')) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => token.push(c),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, c) if c.is_whitespace() => {
+                if !token.is_empty() {
+                    let pair = match token.split_once('=') {
+                        Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                        None => (token.clone(), None),
+                    };
+                    pairs.push(pair);
+                    token.clear();
+                }
+            }
+            (None, c) => token.push(c),
+        }
+    }
+    pairs
+}
+
+fn is_secret(key: &str, value: Option<&str>) -> bool {
+    // `DB_PASSWORD_FILE=/run/secrets/db` points at a secret rather than holding one
+    let key = key.to_ascii_uppercase();
+    if key.ends_with("_FILE") || key.ends_with("_PATH") {
+        return false;
+    }
+    let value = value.unwrap_or_default();
+    // Values that only reference a build arg are checked at the ARG instead
+    let is_reference = value.starts_with('$') && !value[1..].contains(char::is_whitespace);
+    SECRET_NAME.is_match(&key) && !value.is_empty() && !is_reference
+}
+
+struct Linter<'a> {
+    disabled: HashSet<&'a str>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, id: &str, line: usize, message: String) {
+        if self.disabled.contains(id) {
+            return;
+        }
+        // Every check reports a registered rule; an unknown id is dropped, not a crash
+        let Some(rule) = RULES.iter().find(|r| r.id == id) else {
+            return;
+        };
+        self.diagnostics.push(Diagnostic {
+            rule: id.to_string(),
+            severity: rule.severity,
+            line,
+            message,
+        });
+    }
+}
+
+/// Lint a Dockerfile, skipping the rules in `disabled`.
+///
+/// Diagnostics come back ordered by line.
+pub fn lint(text: &str, disabled: &[String]) -> Vec<Diagnostic> {
+    let instructions = parse(text);
+    let mut linter = Linter {
+        disabled: disabled.iter().map(String::as_str).collect(),
+        diagnostics: Vec::new(),
+    };
+
+    let mut stages: Vec<String> = Vec::new();
+    let mut seen_from = false;
+    // Per stage: last USER and its line, CMD/ENTRYPOINT count
+    let mut user: Option<(String, usize)> = None;
+    let mut cmds = (0, 0);
+
+    for ins in &instructions {
+        let line = ins.line;
+        if !KEYWORDS.contains(&ins.keyword.as_str()) {
+            linter.report(
+                "unknown-instruction",
+                line,
+                format!("Unknown instruction {}", ins.keyword),
+            );
+            continue;
+        }
+        if !seen_from && ins.keyword != "FROM" && ins.keyword != "ARG" {
+            linter.report(
+                "from-first",
+                line,
+                format!("{} before the first FROM", ins.keyword),
+            );
+        }
+
+        match ins.keyword.as_str() {
+            "FROM" => {
+                seen_from = true;
+                user = None;
+                cmds = (0, 0);
+
+                let mut words = ins.args.split_whitespace();
+                let image = words.next().unwrap_or_default();
+                let is_stage = stages.iter().any(|s| s.eq_ignore_ascii_case(image));
+                if let (Some(as_), Some(name)) = (words.next(), words.next()) {
+                    if as_.eq_ignore_ascii_case("as") {
+                        stages.push(name.to_ascii_lowercase());
+                    }
+                }
+
+                if image == "scratch" || image.contains('$') || is_stage {
+                    continue;
+                }
+                let Ok(reference) = ImageReference::parse(image) else {
+                    continue;
+                };
+                if reference.digest.is_some() {
+                    continue;
+                }
+                match reference.tag.as_deref() {
+                    None => linter.report(
+                        "unpinned-base-image",
+                        line,
+                        format!("Pin {} to a tag or digest", image),
+                    ),
+                    Some("latest") => linter.report(
+                        "latest-tag",
+                        line,
+                        format!("{} uses the latest tag; pin a version", image),
+                    ),
+                    Some(_) => {}
+                }
+            }
+            "RUN" => {
+                let args = ins
+                    .json
+                    .as_ref()
+                    .map(|j| j.join(" "))
+                    .unwrap_or(ins.args.clone());
+                let commands = commands(&args);
+                let installs: Vec<&&str> = commands
+                    .iter()
+                    .filter(|c| {
+                        c.contains("apt-get") && c.split_whitespace().any(|w| w == "install")
+                    })
+                    .collect();
+                if !installs.is_empty() {
+                    let cleans = args.contains("/var/lib/apt/lists")
+                        || ins.flags.iter().any(|f| f.contains("/var/lib/apt"));
+                    if !cleans {
+                        linter.report(
+                            "apt-get-no-cleanup",
+                            line,
+                            "Add `&& rm -rf /var/lib/apt/lists/*` to the same RUN".to_string(),
+                        );
+                    }
+                    let unattended = installs.iter().all(|c| {
+                        c.split_whitespace().any(|w| {
+                            matches!(w, "-y" | "--yes" | "--assume-yes" | "-qq")
+                                || (w.starts_with('-') && !w.starts_with("--") && w.contains('y'))
+                        })
+                    });
+                    if !unattended {
+                        linter.report(
+                            "apt-get-missing-yes",
+                            line,
+                            "Pass -y to apt-get install".to_string(),
+                        );
+                    }
+                }
+                let apk_adds = commands
+                    .iter()
+                    .any(|c| c.contains("apk") && c.split_whitespace().any(|w| w == "add"));
+                if apk_adds && !args.contains("--no-cache") {
+                    linter.report("apk-no-cache", line, "Use `apk add --no-cache`".to_string());
+                }
+                if CD.is_match(&args) {
+                    linter.report(
+                        "cd-in-run",
+                        line,
+                        "Use WORKDIR to change directories".to_string(),
+                    );
+                }
+                if CURL_PIPE.is_match(&args) {
+                    linter.report(
+                        "curl-pipe-shell",
+                        line,
+                        "Download to a file and verify its checksum before running it".to_string(),
+                    );
+                }
+                if SUDO.is_match(&args) {
+                    linter.report(
+                        "sudo-in-run",
+                        line,
+                        "Drop sudo; switch USER instead".to_string(),
+                    );
+                }
+            }
+            "ADD" => {
+                let sources: Vec<String> = match &ins.json {
+                    Some(json) => json[..json.len().saturating_sub(1)].to_vec(),
+                    None => {
+                        let words: Vec<&str> = ins.args.split_whitespace().collect();
+                        words[..words.len().saturating_sub(1)]
+                            .iter()
+                            .map(|w| w.to_string())
+                            .collect()
+                    }
+                };
+                // ADD earns its keep for URLs, git repos and archives it unpacks
+                let needs_add = |s: &String| {
+                    s.contains("://")
+                        || s.starts_with("git@")
+                        || [".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tar.xz", ".txz"]
+                            .iter()
+                            .any(|ext| s.ends_with(ext))
+                };
+                if !sources.is_empty() && !sources.iter().any(needs_add) {
+                    linter.report(
+                        "add-instead-of-copy",
+                        line,
+                        "Use COPY for local files".to_string(),
+                    );
+                }
+            }
+            "ENV" | "ARG" => {
+                for (key, value) in assignments(ins) {
+                    // An ARG without a default is only a declaration
+                    if ins.keyword == "ARG" && value.is_none() {
+                        continue;
+                    }
+                    if is_secret(&key, value.as_deref()) {
+                        linter.report(
+                            "secret-in-env",
+                            line,
+                            format!(
+                                "{} {} ends up in the image history; use a build secret",
+                                ins.keyword, key
+                            ),
+                        );
+                    }
+                }
+            }
+            "WORKDIR" => {
+                let dir = ins.args.trim().trim_matches('"');
+                let absolute = dir.starts_with('/')
+                    || dir.starts_with('$')
+                    // Windows containers
+                    || dir.get(1..3) == Some(":\\")
+                    || dir.get(1..3) == Some(":/");
+                if !absolute {
+                    linter.report(
+                        "relative-workdir",
+                        line,
+                        format!("WORKDIR {} is relative", dir),
+                    );
+                }
+            }
+            "USER" => user = Some((ins.args.trim().to_string(), line)),
+            "CMD" | "ENTRYPOINT" => {
+                let count = if ins.keyword == "CMD" {
+                    &mut cmds.0
+                } else {
+                    &mut cmds.1
+                };
+                *count += 1;
+                if *count > 1 {
+                    linter.report(
+                        "multiple-cmd",
+                        line,
+                        format!("Earlier {} in this stage is overridden", ins.keyword),
+                    );
+                }
+                if ins.json.is_none() {
+                    linter.report(
+                        "shell-form-cmd",
+                        line,
+                        format!(
+                            "Use the JSON form: {} [\"executable\", \"arg\"]",
+                            ins.keyword
+                        ),
+                    );
+                }
+            }
+            "MAINTAINER" => linter.report(
+                "maintainer-deprecated",
+                line,
+                "Use LABEL org.opencontainers.image.authors instead".to_string(),
+            ),
+            _ => {}
+        }
+    }
+
+    if seen_from {
+        let last_from = instructions
+            .iter()
+            .rev()
+            .find(|i| i.keyword == "FROM")
+            .map(|i| i.line)
+            .unwrap_or(1);
+        match &user {
+            None => linter.report(
+                "missing-user",
+                last_from,
+                "Add a USER for an unprivileged account".to_string(),
+            ),
+            Some((name, line)) => {
+                let name = name.split(':').next().unwrap_or_default();
+                if name == "root" || name == "0" {
+                    linter.report(
+                        "root-user",
+                        *line,
+                        "Switch to an unprivileged USER at the end".to_string(),
+                    );
+                }
+            }
+        }
+    }
+
+    linter.diagnostics.sort_by_key(|d| d.line);
+    linter.diagnostics
+}
+
+/// Lint a Dockerfile on disk
+pub fn lint_file(path: &Path, disabled: &[String]) -> DockerResult<Vec<Diagnostic>> {
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        DockerError::OperationError(format!("Unable to read {}: {}", path.display(), e))
+    })?;
+    Ok(lint(&text, disabled))
+}
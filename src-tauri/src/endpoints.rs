@@ -0,0 +1,277 @@
+//! Service URLs for published container ports, plus a reachability probe.
+use crate::docker::{self, ContainerInfo, DockerError, DockerResult};
+use bollard::Docker;
+use serde::Serialize;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Label overriding the guessed scheme for every port, e.g. `rykard.scheme=https`
+pub const SCHEME_LABEL: &str = "rykard.scheme";
+
+// Ports that speak TLS more often than not
+const HTTPS_PORTS: [u16; 4] = [443, 4443, 8443, 9443];
+// Well-known ports of services that don't speak HTTP, so there is nothing to open
+const NON_HTTP_PORTS: [u16; 14] = [
+    21, 22, 25, 53, 1433, 1521, 2181, 3306, 5432, 5672, 6379, 9092, 11211, 27017,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    Http,
+    Https,
+    /// Plain TCP or UDP service with no browser URL
+    Tcp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ServiceEndpoint {
+    pub private_port: u16,
+    pub public_port: u16,
+    /// `tcp` or `udp`, as published
+    pub protocol: String,
+    pub scheme: Scheme,
+    pub host: String,
+    /// Browser URL, only for http and https endpoints
+    pub url: Option<String>,
+    pub reachability: Option<Reachability>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Reachability {
+    /// A TCP connection could be opened
+    pub reachable: bool,
+    /// Status code of a `HEAD /` request, for plain http endpoints that answered
+    pub http_status: Option<u16>,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+fn scheme_label(container: &ContainerInfo, private_port: u16) -> Option<Scheme> {
+    let value = container
+        .labels
+        .get(&format!("{}.{}", SCHEME_LABEL, private_port))
+        .or_else(|| container.labels.get(SCHEME_LABEL))?;
+    match value.to_ascii_lowercase().as_str() {
+        "http" => Some(Scheme::Http),
+        "https" => Some(Scheme::Https),
+        "tcp" => Some(Scheme::Tcp),
+        _ => None,
+    }
+}
+
+/// Guess how to talk to a published port from its labels and port numbers
+pub fn guess_scheme(container: &ContainerInfo, private_port: u16, protocol: &str) -> Scheme {
+    if protocol != "tcp" {
+        return Scheme::Tcp;
+    }
+    if let Some(scheme) = scheme_label(container, private_port) {
+        return scheme;
+    }
+    if HTTPS_PORTS.contains(&private_port) {
+        Scheme::Https
+    } else if NON_HTTP_PORTS.contains(&private_port) {
+        Scheme::Tcp
+    } else {
+        Scheme::Http
+    }
+}
+
+fn display_host(ip: &str) -> String {
+    match ip {
+        "" | "0.0.0.0" | "::" => "localhost".to_string(),
+        ip if ip.contains(':') => format!("[{}]", ip),
+        ip => ip.to_string(),
+    }
+}
+
+/// One endpoint per published port; the IPv4 and IPv6 bindings of a port are merged
+pub fn container_endpoints(container: &ContainerInfo) -> Vec<ServiceEndpoint> {
+    let mut endpoints: Vec<ServiceEndpoint> = Vec::new();
+
+    for port in container.ports.iter().filter(|p| p.public_port > 0) {
+        let duplicate = endpoints
+            .iter()
+            .any(|e| e.public_port == port.public_port && e.protocol == port.type_);
+        if duplicate {
+            continue;
+        }
+
+        let scheme = guess_scheme(container, port.private_port, &port.type_);
+        let host = display_host(&port.ip);
+        let url = match scheme {
+            Scheme::Http => Some(format!("http://{}:{}", host, port.public_port)),
+            Scheme::Https => Some(format!("https://{}:{}", host, port.public_port)),
+            Scheme::Tcp => None,
+        };
+
+        endpoints.push(ServiceEndpoint {
+            private_port: port.private_port,
+            public_port: port.public_port,
+            protocol: port.type_.clone(),
+            scheme,
+            host,
+            url,
+            reachability: None,
+        });
+    }
+
+    endpoints
+}
+
+/// Connect to an endpoint and, for plain http, check that something answers HTTP
+pub async fn probe(host: &str, port: u16, scheme: Scheme) -> Reachability {
+    let started = Instant::now();
+    let address = format!(
+        "{}:{}",
+        host.trim_start_matches('[').trim_end_matches(']'),
+        port
+    );
+    let elapsed = |started: Instant| started.elapsed().as_millis() as u64;
+
+    let mut stream = match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(&address)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            return Reachability {
+                reachable: false,
+                http_status: None,
+                latency_ms: elapsed(started),
+                error: Some(e.to_string()),
+            }
+        }
+        Err(_) => {
+            return Reachability {
+                reachable: false,
+                http_status: None,
+                latency_ms: elapsed(started),
+                error: Some("Timed out".to_string()),
+            }
+        }
+    };
+    let latency_ms = elapsed(started);
+
+    let mut reachability = Reachability {
+        reachable: true,
+        http_status: None,
+        latency_ms,
+        error: None,
+    };
+    if scheme != Scheme::Http {
+        return reachability;
+    }
+
+    let request = format!(
+        "HEAD / HTTP/1.0\r\nHost: {}\r\nUser-Agent: rykard\r\n\r\n",
+        host
+    );
+    let exchange = async {
+        stream.write_all(request.as_bytes()).await?;
+        let mut buf = [0u8; 64];
+        let read = stream.read(&mut buf).await?;
+        Ok::<_, std::io::Error>(String::from_utf8_lossy(&buf[..read]).to_string())
+    };
+
+    match tokio::time::timeout(PROBE_TIMEOUT, exchange).await {
+        Ok(Ok(response)) => {
+            // "HTTP/1.1 200 OK"
+            reachability.http_status = response
+                .strip_prefix("HTTP/")
+                .and_then(|rest| rest.split_whitespace().nth(1))
+                .and_then(|code| code.parse().ok());
+            if reachability.http_status.is_none() {
+                reachability.error = Some("Port is open but did not answer HTTP".to_string());
+            }
+        }
+        Ok(Err(e)) => reachability.error = Some(e.to_string()),
+        Err(_) => reachability.error = Some("Timed out waiting for HTTP response".to_string()),
+    }
+    reachability
+}
+
+async fn find_container(docker: &Docker, container: &str) -> DockerResult<ContainerInfo> {
+    docker::list_containers(docker, true)
+        .await?
+        .into_iter()
+        .find(|c| c.id.starts_with(container) || c.names.iter().any(|n| n == container))
+        .ok_or_else(|| DockerError::NotFound(format!("No such container: {}", container)))
+}
+
+/// Service endpoints of a container, optionally probed concurrently
+pub async fn endpoints(
+    docker: &Docker,
+    container: &str,
+    check: bool,
+) -> DockerResult<Vec<ServiceEndpoint>> {
+    let mut endpoints = container_endpoints(&find_container(docker, container).await?);
+
+    if check {
+        let probes = endpoints
+            .iter()
+            .filter(|e| e.protocol == "tcp")
+            .map(|e| probe(&e.host, e.public_port, e.scheme));
+        let results = futures_util::future::join_all(probes).await;
+        for (endpoint, result) in endpoints
+            .iter_mut()
+            .filter(|e| e.protocol == "tcp")
+            .zip(results)
+        {
+            endpoint.reachability = Some(result);
+        }
+    }
+
+    Ok(endpoints)
+}
+
+/// The URL to open for a container: the given public port, or its first web endpoint
+pub async fn endpoint_url(
+    docker: &Docker,
+    container: &str,
+    public_port: Option<u16>,
+) -> DockerResult<String> {
+    let endpoints = endpoints(docker, container, false).await?;
+    let endpoint = match public_port {
+        Some(port) => endpoints.iter().find(|e| e.public_port == port),
+        None => endpoints.iter().find(|e| e.url.is_some()),
+    };
+
+    match endpoint {
+        Some(ServiceEndpoint { url: Some(url), .. }) => Ok(url.clone()),
+        Some(endpoint) => Err(DockerError::OperationError(format!(
+            "Port {} doesn't serve http",
+            endpoint.public_port
+        ))),
+        None => Err(DockerError::NotFound(match public_port {
+            Some(port) => format!("Container {} doesn't publish port {}", container, port),
+            None => format!("Container {} doesn't publish any web ports", container),
+        })),
+    }
+}
+
+/// Open a URL in the default browser.
+///
+/// The launcher is started without waiting for it, since some stay around until the
+/// browser exits; only a failure to start it is reported.
+pub fn open_url(url: &str) -> DockerResult<()> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        // The empty argument is the window title `start` expects first
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+
+    let mut child = command
+        .arg(url)
+        .spawn()
+        .map_err(|e| DockerError::OperationError(format!("Failed to open {}: {}", url, e)))?;
+    // Reap it off the async runtime so it doesn't linger as a zombie
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
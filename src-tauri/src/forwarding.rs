@@ -0,0 +1,368 @@
+//! Local TCP forwards to container ports that were never published.
+//!
+//! Each forward listens on `127.0.0.1` and proxies connections straight to the
+//! container's network IP when the host can route to it (Linux). Otherwise, as on
+//! Docker Desktop, every connection is tunnelled through an exec of `socat` or `nc`
+//! inside the container. Forwards go away on their own when the container stops.
+use crate::docker::{DockerError, DockerResult};
+use bollard::container::LogOutput;
+use bollard::exec::{CreateExecOptions, StartExecResults};
+use bollard::system::EventsOptions;
+use bollard::Docker;
+use chrono::Utc;
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::{JoinHandle, JoinSet};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+// First wait before re-subscribing to a dropped event stream; it doubles up to the max
+const RESUBSCRIBE_BACKOFF: Duration = Duration::from_secs(1);
+const RESUBSCRIBE_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+static NEXT_FORWARD: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForwardMode {
+    /// Connections go straight to the container's IP
+    Direct,
+    /// Connections are tunnelled through `docker exec`
+    Exec,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PortForward {
+    pub id: String,
+    pub container_id: String,
+    pub container_name: String,
+    pub container_port: u16,
+    pub local_port: u16,
+    pub mode: ForwardMode,
+    /// Unix timestamp the forward was opened at
+    pub created: i64,
+}
+
+#[derive(Clone)]
+enum Target {
+    Direct(SocketAddr),
+    Exec { container_id: String, port: u16 },
+}
+
+struct Forward {
+    info: PortForward,
+    task: JoinHandle<()>,
+}
+
+/// The running forwards. Cloning shares the same set.
+#[derive(Clone, Default)]
+pub struct PortForwards {
+    forwards: Arc<Mutex<HashMap<String, Forward>>>,
+}
+
+impl PortForwards {
+    /// Forward `local_port` (any free port if `None`) to `container_port` of a running container
+    pub async fn start(
+        &self,
+        docker: &Docker,
+        container: &str,
+        container_port: u16,
+        local_port: Option<u16>,
+    ) -> DockerResult<PortForward> {
+        let details = docker.inspect_container(container, None).await?;
+        let running = details
+            .state
+            .as_ref()
+            .and_then(|state| state.running)
+            .unwrap_or(false);
+        if !running {
+            return Err(DockerError::OperationError(format!(
+                "Container {} is not running",
+                container
+            )));
+        }
+
+        let container_id = details.id.clone().unwrap_or_else(|| container.to_string());
+        let container_name = details
+            .name
+            .clone()
+            .unwrap_or_default()
+            .trim_start_matches('/')
+            .to_string();
+        let (target, mode) = choose_target(&details, &container_id, container_port).await;
+
+        let listener = TcpListener::bind(("127.0.0.1", local_port.unwrap_or(0)))
+            .await
+            .map_err(|e| {
+                DockerError::OperationError(format!(
+                    "Unable to listen on local port {}: {}",
+                    local_port.unwrap_or(0),
+                    e
+                ))
+            })?;
+        let local_port = listener.local_addr()?.port();
+
+        let info = PortForward {
+            id: format!("forward-{}", NEXT_FORWARD.fetch_add(1, Ordering::Relaxed)),
+            container_id: container_id.clone(),
+            container_name,
+            container_port,
+            local_port,
+            mode,
+            created: Utc::now().timestamp(),
+        };
+
+        // Hold the lock until the forward is registered so a container that stops
+        // right away can't unregister it first
+        let mut forwards = self.forwards.lock().unwrap();
+        let task = tokio::spawn(serve(
+            docker.clone(),
+            listener,
+            target,
+            info.id.clone(),
+            container_id,
+            self.clone(),
+        ));
+        forwards.insert(
+            info.id.clone(),
+            Forward {
+                info: info.clone(),
+                task,
+            },
+        );
+
+        Ok(info)
+    }
+
+    pub fn list(&self) -> Vec<PortForward> {
+        let mut forwards: Vec<PortForward> = self
+            .forwards
+            .lock()
+            .unwrap()
+            .values()
+            .map(|f| f.info.clone())
+            .collect();
+        forwards.sort_by(|a, b| a.created.cmp(&b.created).then(a.id.cmp(&b.id)));
+        forwards
+    }
+
+    /// Close the listener and every open connection of a forward
+    pub fn stop(&self, id: &str) -> DockerResult<()> {
+        match self.forwards.lock().unwrap().remove(id) {
+            Some(forward) => {
+                forward.task.abort();
+                Ok(())
+            }
+            None => Err(DockerError::NotFound(format!(
+                "No such port forward: {}",
+                id
+            ))),
+        }
+    }
+
+    fn remove(&self, id: &str) {
+        self.forwards.lock().unwrap().remove(id);
+    }
+}
+
+/// Go direct if the container's IP answers (a refused connection still proves it's routable)
+async fn choose_target(
+    details: &bollard::models::ContainerInspectResponse,
+    container_id: &str,
+    port: u16,
+) -> (Target, ForwardMode) {
+    let settings = details.network_settings.as_ref();
+    let ip = settings
+        .and_then(|s| s.networks.as_ref())
+        .and_then(|networks| {
+            networks
+                .values()
+                .filter_map(|n| n.ip_address.clone())
+                .find(|ip| !ip.is_empty())
+        })
+        .or_else(|| settings.and_then(|s| s.ip_address.clone()))
+        .and_then(|ip| ip.parse().ok());
+
+    if let Some(ip) = ip {
+        let addr = SocketAddr::new(ip, port);
+        match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => return (Target::Direct(addr), ForwardMode::Direct),
+            Ok(Err(e)) if e.kind() == ErrorKind::ConnectionRefused => {
+                return (Target::Direct(addr), ForwardMode::Direct)
+            }
+            _ => {}
+        }
+    }
+
+    (
+        Target::Exec {
+            container_id: container_id.to_string(),
+            port,
+        },
+        ForwardMode::Exec,
+    )
+}
+
+async fn serve(
+    docker: Docker,
+    listener: TcpListener,
+    target: Target,
+    id: String,
+    container_id: String,
+    forwards: PortForwards,
+) {
+    // Dropping the set when the forward ends closes its open connections too
+    let mut connections = JoinSet::new();
+    let stopped = wait_for_stop(&docker, &container_id);
+    tokio::pin!(stopped);
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((socket, _)) => {
+                    let (docker, target) = (docker.clone(), target.clone());
+                    connections.spawn(async move {
+                        if let Err(e) = proxy(&docker, socket, &target).await {
+                            eprintln!("Port forward connection failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    eprintln!("Port forward {} stopped accepting: {}", id, e);
+                    break;
+                }
+            },
+            Some(_) = connections.join_next() => {}
+            _ = &mut stopped => break,
+        }
+    }
+
+    forwards.remove(&id);
+}
+
+/// Resolves once the container dies or is removed
+async fn wait_for_stop(docker: &Docker, container_id: &str) {
+    let mut filters = HashMap::new();
+    filters.insert("type".to_string(), vec!["container".to_string()]);
+    filters.insert("container".to_string(), vec![container_id.to_string()]);
+    filters.insert(
+        "event".to_string(),
+        vec!["die".to_string(), "destroy".to_string()],
+    );
+    let mut backoff = RESUBSCRIBE_BACKOFF;
+
+    loop {
+        let mut events = docker.events(Some(EventsOptions {
+            filters: filters.clone(),
+            ..Default::default()
+        }));
+        while let Some(event) = events.next().await {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("Event stream for {} broke: {}", container_id, e);
+                    break;
+                }
+            };
+            backoff = RESUBSCRIBE_BACKOFF;
+            let actor = event.actor.and_then(|a| a.id).unwrap_or_default();
+            let action = event.action.unwrap_or_default();
+            if actor == container_id && (action == "die" || action == "destroy") {
+                return;
+            }
+        }
+
+        // Events may have been missed while the stream was down, so ask directly. A broken
+        // stream alone shouldn't tear down a forward that still works.
+        if has_stopped(docker, container_id).await == Some(true) {
+            return;
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(RESUBSCRIBE_BACKOFF_MAX);
+    }
+}
+
+/// Whether the container has exited or is gone; `None` when the daemon can't be asked
+async fn has_stopped(docker: &Docker, container_id: &str) -> Option<bool> {
+    match docker.inspect_container(container_id, None).await {
+        Ok(details) => Some(!details.state.and_then(|s| s.running).unwrap_or(false)),
+        Err(bollard::errors::Error::DockerResponseServerError {
+            status_code: 404, ..
+        }) => Some(true),
+        Err(_) => None,
+    }
+}
+
+async fn proxy(docker: &Docker, mut socket: TcpStream, target: &Target) -> DockerResult<()> {
+    match target {
+        Target::Direct(addr) => {
+            let mut upstream = TcpStream::connect(addr).await?;
+            tokio::io::copy_bidirectional(&mut socket, &mut upstream).await?;
+            Ok(())
+        }
+        Target::Exec { container_id, port } => {
+            let script = format!(
+                "if command -v socat >/dev/null 2>&1; then exec socat - TCP:127.0.0.1:{port}; \
+                 else exec nc 127.0.0.1 {port}; fi",
+                port = port
+            );
+            let exec = docker
+                .create_exec(
+                    container_id,
+                    CreateExecOptions {
+                        attach_stdin: Some(true),
+                        attach_stdout: Some(true),
+                        attach_stderr: Some(true),
+                        cmd: Some(vec!["sh".to_string(), "-c".to_string(), script]),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+            let (mut output, mut input) = match docker.start_exec(&exec.id, None).await? {
+                StartExecResults::Attached { output, input } => (output, input),
+                StartExecResults::Detached => {
+                    return Err(DockerError::OperationError(
+                        "Exec tunnel started detached".to_string(),
+                    ))
+                }
+            };
+
+            let (reader, mut writer) = socket.split();
+            let upstream = async {
+                tokio::io::copy(&mut BufReader::new(reader), &mut input).await?;
+                input.shutdown().await
+            };
+            let downstream = async {
+                while let Some(chunk) = output.next().await {
+                    match chunk? {
+                        LogOutput::StdOut { message } => writer.write_all(&message).await?,
+                        LogOutput::StdErr { message } => {
+                            eprintln!("Port forward tunnel: {}", String::from_utf8_lossy(&message))
+                        }
+                        _ => {}
+                    }
+                }
+                writer.shutdown().await?;
+                Ok::<_, DockerError>(())
+            };
+
+            let (up, down) = tokio::join!(upstream, downstream);
+            down?;
+            // The client hanging up first is normal
+            if let Err(e) = up {
+                if e.kind() != ErrorKind::BrokenPipe {
+                    return Err(e.into());
+                }
+            }
+            Ok(())
+        }
+    }
+}
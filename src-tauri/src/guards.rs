@@ -0,0 +1,471 @@
+//! Guarded removal of containers and images.
+//!
+//! Containers and images can be protected by name pattern or label, and then refuse
+//! removal unless it is forced. Removing an image that containers still use reports
+//! those containers instead of the daemon's conflict error. With the trash enabled a
+//! removed container is stopped and renamed to
+//! `rykard-trash.<unix time>.<restart policy>.<name>`, so the trash needs no bookkeeping
+//! of its own, and survives restarts of the app, until it is purged once the grace
+//! period is over. Its restart policy is switched off meanwhile, so the daemon doesn't
+//! bring it back, and the one in the name is restored with the container.
+use crate::build;
+use crate::docker::{
+    self, ContainerResources, DockerError, DockerResult, DockerState, PruneReport,
+    RestartPolicySetting,
+};
+use crate::settings::{DeleteGuards, SettingsStore};
+use bollard::container::{ListContainersOptions, RemoveContainerOptions, RenameContainerOptions};
+use bollard::image::RemoveImageOptions;
+use bollard::Docker;
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+pub const TRASH_PREFIX: &str = "rykard-trash.";
+
+/// How often the trash is checked for containers past their grace period
+const PURGE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// What became of a container that was asked to be removed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum ContainerRemoval {
+    Removed,
+    /// Stopped and renamed; purged for good at `purge_at` unless restored first
+    Trashed {
+        name: String,
+        purge_at: i64,
+    },
+}
+
+/// What became of an image that was asked to be removed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ImageRemoval {
+    Removed,
+    /// Nothing was removed because these containers still use the image
+    InUse {
+        dependents: Vec<Dependent>,
+    },
+}
+
+/// A container created from an image
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Dependent {
+    pub id: String,
+    pub name: String,
+    pub state: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrashedContainer {
+    pub id: String,
+    /// The name it had, and gets back when restored
+    pub name: String,
+    /// The name it has in the trash
+    pub trash_name: String,
+    pub image: String,
+    pub trashed_at: i64,
+    pub purge_at: i64,
+}
+
+/// The rule protecting a resource with these names and labels, if any
+pub fn protection(
+    guards: &DeleteGuards,
+    names: &[String],
+    labels: &HashMap<String, String>,
+) -> Option<String> {
+    let by_name = guards.protected_names.iter().find(|pattern| {
+        names
+            .iter()
+            .any(|name| build::glob(pattern.as_bytes(), name.as_bytes()))
+    });
+    if let Some(pattern) = by_name {
+        return Some(format!("name pattern {}", pattern));
+    }
+    guards
+        .protected_labels
+        .iter()
+        .find(|label| match label.split_once('=') {
+            Some((key, value)) => labels.get(key).is_some_and(|v| v == value),
+            None => labels.contains_key(label.as_str()),
+        })
+        .map(|label| format!("label {}", label))
+}
+
+/// Whether a container with these names is in the trash. Trashed containers keep
+/// their compose labels, so anything resolving a project's containers skips them.
+pub fn is_trashed(names: &[String]) -> bool {
+    names.iter().any(|name| name.starts_with(TRASH_PREFIX))
+}
+
+fn no_restart() -> RestartPolicySetting {
+    RestartPolicySetting {
+        name: "no".to_string(),
+        maximum_retry_count: 0,
+    }
+}
+
+// Container names can't hold `:`, so `on-failure:3` becomes `on-failure-3`
+fn policy_token(policy: &RestartPolicySetting) -> String {
+    if policy.name == "on-failure" && policy.maximum_retry_count > 0 {
+        format!("on-failure-{}", policy.maximum_retry_count)
+    } else {
+        policy.name.clone()
+    }
+}
+
+fn parse_policy_token(token: &str) -> Option<RestartPolicySetting> {
+    let (name, maximum_retry_count) = match token {
+        "no" | "always" | "unless-stopped" | "on-failure" => (token, 0),
+        _ => (
+            "on-failure",
+            token.strip_prefix("on-failure-")?.parse().ok()?,
+        ),
+    };
+    Some(RestartPolicySetting {
+        name: name.to_string(),
+        maximum_retry_count,
+    })
+}
+
+fn trash_name(name: &str, policy: &RestartPolicySetting, trashed_at: i64) -> String {
+    format!(
+        "{}{}.{}.{}",
+        TRASH_PREFIX,
+        trashed_at,
+        policy_token(policy),
+        name
+    )
+}
+
+/// When a container went to the trash, the restart policy it had and its old name
+fn parse_trash_name(name: &str) -> Option<(i64, RestartPolicySetting, &str)> {
+    let (time, rest) = name.strip_prefix(TRASH_PREFIX)?.split_once('.')?;
+    let (policy, original) = rest.split_once('.')?;
+    if original.is_empty() {
+        return None;
+    }
+    Some((time.parse().ok()?, parse_policy_token(policy)?, original))
+}
+
+async fn restart_policy(docker: &Docker, container: &str) -> DockerResult<RestartPolicySetting> {
+    let details = docker.inspect_container(container, None).await?;
+    let resources = details.host_config.unwrap_or_default();
+    Ok(resources
+        .restart_policy
+        .and_then(|policy| {
+            Some(RestartPolicySetting {
+                name: policy.name?.to_string(),
+                maximum_retry_count: policy.maximum_retry_count.unwrap_or_default(),
+            })
+        })
+        .filter(|policy| !policy.name.is_empty())
+        .unwrap_or_else(no_restart))
+}
+
+async fn set_restart_policy(
+    docker: &Docker,
+    container: &str,
+    policy: RestartPolicySetting,
+) -> DockerResult<()> {
+    let resources = ContainerResources {
+        restart_policy: Some(policy),
+        ..Default::default()
+    };
+    docker::update_container_resources(docker, container, &resources).await?;
+    Ok(())
+}
+
+/// Find a container by id, id prefix or name
+async fn find_container(docker: &Docker, container: &str) -> DockerResult<docker::ContainerInfo> {
+    let name = container.trim_start_matches('/');
+    docker::list_containers(docker, true)
+        .await?
+        .into_iter()
+        .find(|c| c.id.starts_with(container) || c.names.iter().any(|n| n == name))
+        .ok_or_else(|| DockerError::NotFound(format!("No such container: {}", container)))
+}
+
+/// Remove a container, or move it to the trash when that's enabled. Forcing skips
+/// both the protection check and the trash, and kills the container if it's running.
+pub async fn remove_container(
+    docker: &Docker,
+    guards: &DeleteGuards,
+    container: &str,
+    force: bool,
+) -> DockerResult<ContainerRemoval> {
+    let info = find_container(docker, container).await?;
+    let name = info
+        .names
+        .first()
+        .cloned()
+        .unwrap_or_else(|| info.id.clone());
+
+    if !force {
+        if let Some(rule) = protection(guards, &info.names, &info.labels) {
+            return Err(DockerError::PermissionDenied(format!(
+                "{} is protected by {}; force the removal to delete it",
+                name, rule
+            )));
+        }
+    }
+
+    // Removing a container that's already in the trash deletes it
+    if guards.trash && !force && parse_trash_name(&name).is_none() {
+        // Switched off first, so the daemon can't restart it once it's stopped
+        let policy = restart_policy(docker, &info.id).await?;
+        if policy.name != "no" {
+            set_restart_policy(docker, &info.id, no_restart()).await?;
+        }
+        if info.state == "running" {
+            docker::stop_container(docker, &info.id).await?;
+        }
+        let trashed_at = Utc::now().timestamp();
+        let trashed = trash_name(&name, &policy, trashed_at);
+        docker
+            .rename_container(
+                &info.id,
+                RenameContainerOptions {
+                    name: trashed.clone(),
+                },
+            )
+            .await?;
+        return Ok(ContainerRemoval::Trashed {
+            name: trashed,
+            purge_at: trashed_at + guards.trash_grace_minutes as i64 * 60,
+        });
+    }
+
+    docker
+        .remove_container(
+            &info.id,
+            Some(RemoveContainerOptions {
+                force,
+                ..Default::default()
+            }),
+        )
+        .await?;
+    Ok(ContainerRemoval::Removed)
+}
+
+/// Containers created from `image`, running or not
+pub async fn dependents(docker: &Docker, image: &str) -> DockerResult<Vec<Dependent>> {
+    let options = ListContainersOptions {
+        all: true,
+        filters: HashMap::from([("ancestor".to_string(), vec![image.to_string()])]),
+        ..Default::default()
+    };
+    let containers = docker.list_containers(Some(options)).await?;
+    Ok(containers
+        .into_iter()
+        .map(|c| Dependent {
+            id: c.id.unwrap_or_default(),
+            name: c
+                .names
+                .unwrap_or_default()
+                .first()
+                .map(|n| n.trim_start_matches('/').to_string())
+                .unwrap_or_default(),
+            state: c.state.unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Remove an image unless it's protected or still used by containers. Forcing skips
+/// the protection check and untags images only stopped containers use; the daemon
+/// still refuses to remove an image a running container uses.
+pub async fn remove_image(
+    docker: &Docker,
+    guards: &DeleteGuards,
+    image: &str,
+    force: bool,
+) -> DockerResult<ImageRemoval> {
+    let details = docker.inspect_image(image).await?;
+    if !force {
+        let names = details.repo_tags.clone().unwrap_or_default();
+        let labels = details
+            .config
+            .as_ref()
+            .and_then(|config| config.labels.clone())
+            .unwrap_or_default();
+        if let Some(rule) = protection(guards, &names, &labels) {
+            return Err(DockerError::PermissionDenied(format!(
+                "{} is protected by {}; force the removal to delete it",
+                image, rule
+            )));
+        }
+    }
+
+    let options = RemoveImageOptions {
+        force,
+        ..Default::default()
+    };
+    match docker.remove_image(image, Some(options), None).await {
+        Ok(_) => Ok(ImageRemoval::Removed),
+        Err(bollard::errors::Error::DockerResponseServerError {
+            status_code: 409,
+            message,
+        }) => {
+            let id = details.id.unwrap_or_else(|| image.to_string());
+            let dependents = dependents(docker, &id).await?;
+            if dependents.is_empty() {
+                // A conflict for another reason, such as a tag shared by several repositories
+                return Err(DockerError::OperationError(message));
+            }
+            Ok(ImageRemoval::InUse { dependents })
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Containers in the trash, the oldest first
+pub async fn list_trash(
+    docker: &Docker,
+    grace_minutes: u64,
+) -> DockerResult<Vec<TrashedContainer>> {
+    let mut trashed: Vec<TrashedContainer> = docker::list_containers(docker, true)
+        .await?
+        .into_iter()
+        .filter_map(|c| {
+            let trash_name = c.names.first()?.clone();
+            let (trashed_at, _, name) = parse_trash_name(&trash_name)?;
+            Some(TrashedContainer {
+                id: c.id.clone(),
+                name: name.to_string(),
+                trashed_at,
+                purge_at: trashed_at + grace_minutes as i64 * 60,
+                image: c.image.clone(),
+                trash_name,
+            })
+        })
+        .collect();
+    trashed.sort_by_key(|c| c.trashed_at);
+    Ok(trashed)
+}
+
+/// Take a container out of the trash under its old name and with its old restart
+/// policy. It stays stopped.
+pub async fn restore_container(docker: &Docker, container: &str) -> DockerResult<String> {
+    let info = find_container(docker, container).await?;
+    let name = info.names.first().cloned().unwrap_or_default();
+    let Some((_, policy, original)) = parse_trash_name(&name) else {
+        return Err(DockerError::OperationError(format!(
+            "{} is not in the trash",
+            container
+        )));
+    };
+    docker
+        .rename_container(
+            &info.id,
+            RenameContainerOptions {
+                name: original.to_string(),
+            },
+        )
+        .await?;
+    if policy.name != "no" {
+        set_restart_policy(docker, &info.id, policy).await?;
+    }
+    Ok(original.to_string())
+}
+
+/// Delete the containers trashed at or before `cutoff`, returning their old names.
+/// A container that can't be removed stays in the trash for the next purge.
+pub async fn purge_trash(docker: &Docker, cutoff: i64) -> DockerResult<Vec<String>> {
+    let mut purged = Vec::new();
+    for container in list_trash(docker, 0).await? {
+        if container.trashed_at > cutoff {
+            continue;
+        }
+        let options = RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+        };
+        match docker.remove_container(&container.id, Some(options)).await {
+            Ok(()) => purged.push(container.name),
+            Err(e) => eprintln!(
+                "Failed to purge {} from the trash: {}",
+                container.trash_name,
+                DockerError::from(e)
+            ),
+        }
+    }
+    Ok(purged)
+}
+
+/// Remove stopped containers like `docker container prune`, except those in the trash,
+/// which keep their grace period, and protected ones. The daemon's own prune can't be
+/// told to skip them.
+pub async fn prune_containers(docker: &Docker, guards: &DeleteGuards) -> DockerResult<PruneReport> {
+    let options = ListContainersOptions::<String> {
+        all: true,
+        size: true,
+        ..Default::default()
+    };
+    let mut report = PruneReport::default();
+    for container in docker.list_containers(Some(options)).await? {
+        let names: Vec<String> = container
+            .names
+            .unwrap_or_default()
+            .iter()
+            .map(|name| name.trim_start_matches('/').to_string())
+            .collect();
+        let labels = container.labels.unwrap_or_default();
+        let stopped = matches!(
+            container.state.as_deref(),
+            Some("created" | "exited" | "dead")
+        );
+        if !stopped || is_trashed(&names) || protection(guards, &names, &labels).is_some() {
+            continue;
+        }
+        let Some(id) = container.id else {
+            continue;
+        };
+        match docker.remove_container(&id, None).await {
+            Ok(()) => {
+                report.deleted.push(id);
+                report.space_reclaimed += container.size_rw.unwrap_or_default().max(0) as u64;
+            }
+            // Most likely started again since it was listed
+            Err(e) => eprintln!("Failed to prune {}: {}", id, DockerError::from(e)),
+        }
+    }
+    Ok(report)
+}
+
+/// Purge expired containers from the trash every few minutes, calling `on_purged`
+/// with the endpoint and old names of the containers that were deleted
+pub async fn run_trash_collector<F>(
+    state: Arc<Mutex<DockerState>>,
+    settings: Arc<Mutex<SettingsStore>>,
+    mut on_purged: F,
+) where
+    F: FnMut(&str, &[String]),
+{
+    loop {
+        tokio::time::sleep(PURGE_INTERVAL).await;
+        let grace = settings
+            .lock()
+            .await
+            .settings()
+            .delete_guards
+            .trash_grace_minutes;
+        let (docker, endpoint) = {
+            let state = state.lock().await;
+            (state.get_client(), state.endpoint())
+        };
+        // Not connected yet; nothing can have been trashed on this endpoint either
+        let Ok(docker) = docker else {
+            continue;
+        };
+        let cutoff = Utc::now().timestamp() - grace as i64 * 60;
+        match purge_trash(&docker, cutoff).await {
+            Ok(purged) if !purged.is_empty() => on_purged(&endpoint, &purged),
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to purge the trash: {}", e),
+        }
+    }
+}
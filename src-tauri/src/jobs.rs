@@ -0,0 +1,545 @@
+//! Background jobs for long Docker operations: pulls, builds, pushes and prunes.
+//!
+//! Every job gets an id and runs on its own task, so the command that started it
+//! returns right away. Jobs of the same kind queue up behind a concurrency limit and
+//! start in submission order. Cancelling aborts the task, which drops the request to
+//! the daemon; the daemon then stops the pull or build on its side.
+use crate::build::{self, BuildOptions};
+use crate::docker::{self, DockerError, DockerResult, PruneTarget};
+use crate::settings::{DeleteGuards, JobLimits};
+use bollard::models::CreateImageInfo;
+use bollard::Docker;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Finished jobs kept for `list`, oldest dropped first
+pub const FINISHED_JOB_RETENTION: usize = 50;
+/// Progress-only updates of one job are reported at most this often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+static NEXT_JOB: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Pull,
+    Build,
+    Push,
+    Prune,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// Waiting for a free slot
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// What to run, as sent by the frontend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum JobRequest {
+    Pull {
+        image: String,
+        /// `os/arch[/variant]`; the host's platform when left out
+        #[serde(default)]
+        platform: Option<String>,
+    },
+    Build(BuildOptions),
+    Push {
+        image: String,
+    },
+    Prune {
+        target: PruneTarget,
+    },
+}
+
+impl JobRequest {
+    pub fn kind(&self) -> JobKind {
+        match self {
+            Self::Pull { .. } => JobKind::Pull,
+            Self::Build(_) => JobKind::Build,
+            Self::Push { .. } => JobKind::Push,
+            Self::Prune { .. } => JobKind::Prune,
+        }
+    }
+
+    /// What the job works on, for display
+    fn target(&self) -> String {
+        match self {
+            Self::Pull {
+                image,
+                platform: Some(platform),
+            } => format!("{} ({})", image, platform),
+            Self::Pull { image, .. } | Self::Push { image } => image.clone(),
+            Self::Build(options) if !options.tag.is_empty() => options.tag.clone(),
+            Self::Build(options) => options.context.display().to_string(),
+            Self::Prune { target } => format!("{:?}", target).to_lowercase(),
+        }
+    }
+}
+
+/// Progress of one image layer. For pushes `downloaded` counts bytes uploaded.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LayerProgress {
+    pub id: String,
+    pub status: String,
+    pub downloaded: u64,
+    pub extracted: u64,
+    /// Size of the layer, 0 until the daemon reports it
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct JobProgress {
+    /// Per-layer progress in the order layers first appeared
+    pub layers: Vec<LayerProgress>,
+    pub downloaded: u64,
+    pub extracted: u64,
+    pub total: u64,
+    /// Latest status or build output line
+    pub message: String,
+}
+
+impl JobProgress {
+    /// Fold one pull status message into the per-layer totals
+    pub fn apply_pull(&mut self, info: &CreateImageInfo) {
+        let status = info.status.clone().unwrap_or_default();
+        let Some(id) = info.id.clone().filter(|_| is_layer_status(&status)) else {
+            if !status.is_empty() {
+                self.message = status;
+            }
+            return;
+        };
+
+        let index = match self.layers.iter().position(|l| l.id == id) {
+            Some(index) => index,
+            None => {
+                self.layers.push(LayerProgress {
+                    id,
+                    ..Default::default()
+                });
+                self.layers.len() - 1
+            }
+        };
+        let layer = &mut self.layers[index];
+        let detail = info.progress_detail.as_ref();
+        let current = detail.and_then(|d| d.current).unwrap_or_default().max(0) as u64;
+        let total = detail.and_then(|d| d.total).unwrap_or_default().max(0) as u64;
+
+        match status.as_str() {
+            "Downloading" => {
+                layer.downloaded = current;
+                layer.total = layer.total.max(total);
+            }
+            "Extracting" => {
+                layer.downloaded = layer.total.max(total);
+                layer.extracted = current;
+                layer.total = layer.total.max(total);
+            }
+            "Download complete" | "Verifying Checksum" => layer.downloaded = layer.total,
+            "Pull complete" => {
+                layer.downloaded = layer.total;
+                layer.extracted = layer.total;
+            }
+            _ => {}
+        }
+        layer.status = status;
+        self.sum_layers();
+    }
+
+    fn sum_layers(&mut self) {
+        self.downloaded = self.layers.iter().map(|l| l.downloaded).sum();
+        self.extracted = self.layers.iter().map(|l| l.extracted).sum();
+        self.total = self.layers.iter().map(|l| l.total).sum();
+    }
+}
+
+/// Statuses that describe a single layer rather than the whole pull, whose `id` is a tag
+fn is_layer_status(status: &str) -> bool {
+    matches!(
+        status,
+        "Pulling fs layer"
+            | "Waiting"
+            | "Downloading"
+            | "Verifying Checksum"
+            | "Download complete"
+            | "Extracting"
+            | "Pull complete"
+            | "Already exists"
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Job {
+    pub id: String,
+    pub kind: JobKind,
+    pub target: String,
+    pub state: JobState,
+    pub progress: JobProgress,
+    /// Image id of a build, or a summary of what a prune removed
+    pub result: Option<String>,
+    pub error: Option<String>,
+    /// Unix timestamps
+    pub created: i64,
+    pub started: Option<i64>,
+    pub finished: Option<i64>,
+}
+
+struct Entry {
+    job: Job,
+    /// Submission order, so queued jobs start first come first served
+    seq: u64,
+    task: Option<JoinHandle<()>>,
+    last_report: Option<Instant>,
+}
+
+type Listener = Arc<dyn Fn(&Job) + Send + Sync>;
+
+struct Shared {
+    entries: Mutex<HashMap<String, Entry>>,
+    limits: Mutex<JobLimits>,
+    /// What container prunes leave alone
+    delete_guards: Mutex<DeleteGuards>,
+    /// Woken whenever a job changes state
+    changed: Notify,
+    listener: Mutex<Option<Listener>>,
+}
+
+/// The job queue. Cloning shares the same queue.
+#[derive(Clone)]
+pub struct JobManager {
+    shared: Arc<Shared>,
+}
+
+impl Default for JobManager {
+    fn default() -> Self {
+        Self::new(JobLimits::default())
+    }
+}
+
+impl JobManager {
+    pub fn new(limits: JobLimits) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                entries: Mutex::new(HashMap::new()),
+                limits: Mutex::new(limits),
+                delete_guards: Mutex::new(DeleteGuards::default()),
+                changed: Notify::new(),
+                listener: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Call `listener` with a snapshot whenever a job changes
+    pub fn on_update<F>(&self, listener: F)
+    where
+        F: Fn(&Job) + Send + Sync + 'static,
+    {
+        *self.shared.listener.lock().unwrap() = Some(Arc::new(listener));
+    }
+
+    /// Apply new limits; raising one lets queued jobs start right away
+    pub fn set_limits(&self, limits: JobLimits) {
+        *self.shared.limits.lock().unwrap() = limits;
+        self.shared.changed.notify_waiters();
+    }
+
+    /// Apply new delete guards to container prunes that start from now on
+    pub fn set_delete_guards(&self, guards: DeleteGuards) {
+        *self.shared.delete_guards.lock().unwrap() = guards;
+    }
+
+    /// Queue a job and return it as submitted
+    pub fn submit(&self, docker: Docker, request: JobRequest) -> Job {
+        let seq = NEXT_JOB.fetch_add(1, Ordering::Relaxed);
+        let job = Job {
+            id: format!("job-{}", seq),
+            kind: request.kind(),
+            target: request.target(),
+            state: JobState::Queued,
+            progress: JobProgress::default(),
+            result: None,
+            error: None,
+            created: Utc::now().timestamp(),
+            started: None,
+            finished: None,
+        };
+
+        // Register before spawning so the task always finds its entry
+        let mut entries = self.shared.entries.lock().unwrap();
+        entries.insert(
+            job.id.clone(),
+            Entry {
+                job: job.clone(),
+                seq,
+                task: None,
+                last_report: None,
+            },
+        );
+        let task = tokio::spawn(run(self.clone(), job.id.clone(), docker, request));
+        if let Some(entry) = entries.get_mut(&job.id) {
+            entry.task = Some(task);
+        }
+        drop(entries);
+
+        self.report(&job);
+        job
+    }
+
+    /// Every known job, oldest first
+    pub fn list(&self) -> Vec<Job> {
+        let entries = self.shared.entries.lock().unwrap();
+        let mut jobs: Vec<(&u64, &Job)> = entries.values().map(|e| (&e.seq, &e.job)).collect();
+        jobs.sort_by_key(|(seq, _)| **seq);
+        jobs.into_iter().map(|(_, job)| job.clone()).collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        let entries = self.shared.entries.lock().unwrap();
+        entries.get(id).map(|e| e.job.clone())
+    }
+
+    /// Stop a queued or running job
+    pub fn cancel(&self, id: &str) -> DockerResult<Job> {
+        let job = {
+            let mut entries = self.shared.entries.lock().unwrap();
+            let entry = entries
+                .get_mut(id)
+                .ok_or_else(|| DockerError::NotFound(format!("No such job: {}", id)))?;
+            if entry.job.state.is_finished() {
+                return Err(DockerError::OperationError(format!(
+                    "Job {} has already finished",
+                    id
+                )));
+            }
+            if let Some(task) = entry.task.take() {
+                task.abort();
+            }
+            entry.job.state = JobState::Cancelled;
+            entry.job.finished = Some(Utc::now().timestamp());
+            let job = entry.job.clone();
+            prune_finished(&mut entries);
+            job
+        };
+
+        self.shared.changed.notify_waiters();
+        self.report(&job);
+        Ok(job)
+    }
+
+    /// Wait for a job to finish and return its final state
+    pub async fn wait(&self, id: &str) -> Option<Job> {
+        loop {
+            let changed = self.shared.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let job = self.get(id)?;
+            if job.state.is_finished() {
+                return Some(job);
+            }
+            changed.await;
+        }
+    }
+
+    /// Wait for a free slot of `id`'s kind, then mark the job running. Returns `false`
+    /// when the job went away or was cancelled meanwhile.
+    async fn acquire(&self, id: &str) -> bool {
+        loop {
+            let changed = self.shared.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let started = {
+                let limits = self.shared.limits.lock().unwrap().clone();
+                let mut entries = self.shared.entries.lock().unwrap();
+                let Some(entry) = entries.get(id) else {
+                    return false;
+                };
+                if entry.job.state != JobState::Queued {
+                    return false;
+                }
+                let (kind, seq) = (entry.job.kind, entry.seq);
+                let running = entries
+                    .values()
+                    .filter(|e| e.job.kind == kind && e.job.state == JobState::Running)
+                    .count();
+                let ahead = entries
+                    .values()
+                    .any(|e| e.job.kind == kind && e.job.state == JobState::Queued && e.seq < seq);
+
+                if running < limits.limit(kind) && !ahead {
+                    let entry = entries.get_mut(id).unwrap();
+                    entry.job.state = JobState::Running;
+                    entry.job.started = Some(Utc::now().timestamp());
+                    Some(entry.job.clone())
+                } else {
+                    None
+                }
+            };
+
+            if let Some(job) = started {
+                self.report(&job);
+                return true;
+            }
+            changed.await;
+        }
+    }
+
+    /// Record progress, reporting it unless the last report was very recent
+    fn progress<F>(&self, id: &str, update: F)
+    where
+        F: FnOnce(&mut JobProgress),
+    {
+        let job = {
+            let mut entries = self.shared.entries.lock().unwrap();
+            let Some(entry) = entries.get_mut(id) else {
+                return;
+            };
+            update(&mut entry.job.progress);
+            let due = entry
+                .last_report
+                .map(|last| last.elapsed() >= PROGRESS_INTERVAL)
+                .unwrap_or(true);
+            if !due {
+                return;
+            }
+            entry.last_report = Some(Instant::now());
+            entry.job.clone()
+        };
+        self.report(&job);
+    }
+
+    fn finish(&self, id: &str, result: DockerResult<Option<String>>) {
+        let job = {
+            let mut entries = self.shared.entries.lock().unwrap();
+            let Some(entry) = entries.get_mut(id) else {
+                return;
+            };
+            // A cancel that raced the last message wins
+            if entry.job.state.is_finished() {
+                return;
+            }
+            match result {
+                Ok(output) => {
+                    entry.job.state = JobState::Completed;
+                    entry.job.result = output;
+                }
+                Err(e) => {
+                    entry.job.state = JobState::Failed;
+                    entry.job.error = Some(e.to_string());
+                }
+            }
+            entry.job.finished = Some(Utc::now().timestamp());
+            entry.task = None;
+            let job = entry.job.clone();
+            prune_finished(&mut entries);
+            job
+        };
+
+        self.shared.changed.notify_waiters();
+        self.report(&job);
+    }
+
+    fn report(&self, job: &Job) {
+        let listener = self.shared.listener.lock().unwrap().clone();
+        if let Some(listener) = listener {
+            listener(job);
+        }
+    }
+}
+
+fn prune_finished(entries: &mut HashMap<String, Entry>) {
+    let mut finished: Vec<(u64, String)> = entries
+        .values()
+        .filter(|e| e.job.state.is_finished())
+        .map(|e| (e.seq, e.job.id.clone()))
+        .collect();
+    if finished.len() <= FINISHED_JOB_RETENTION {
+        return;
+    }
+    finished.sort();
+    let excess = finished.len() - FINISHED_JOB_RETENTION;
+    for (_, id) in finished.into_iter().take(excess) {
+        entries.remove(&id);
+    }
+}
+
+async fn run(jobs: JobManager, id: String, docker: Docker, request: JobRequest) {
+    if !jobs.acquire(&id).await {
+        return;
+    }
+    let result = execute(&jobs, &id, &docker, request).await;
+    jobs.finish(&id, result);
+}
+
+async fn execute(
+    jobs: &JobManager,
+    id: &str,
+    docker: &Docker,
+    request: JobRequest,
+) -> DockerResult<Option<String>> {
+    match request {
+        JobRequest::Pull { image, platform } => {
+            docker::pull_image_for_platform(docker, &image, platform.as_deref(), |info| {
+                jobs.progress(id, |p| p.apply_pull(info))
+            })
+            .await?;
+            Ok(None)
+        }
+        JobRequest::Build(options) => {
+            let image_id = build::build_image(docker, &options, |info| {
+                let line = info.stream.as_deref().or(info.status.as_deref());
+                if let Some(line) = line.map(str::trim).filter(|l| !l.is_empty()) {
+                    jobs.progress(id, |p| p.message = line.to_string());
+                }
+            })
+            .await?;
+            Ok(Some(image_id))
+        }
+        // bollard's push messages carry no layer id, so only the status line is kept
+        JobRequest::Push { image } => {
+            docker::push_image(docker, &image, |info| {
+                if let Some(status) = &info.status {
+                    jobs.progress(id, |p| p.message = status.clone());
+                }
+            })
+            .await?;
+            Ok(None)
+        }
+        JobRequest::Prune { target } => {
+            let guards = jobs.shared.delete_guards.lock().unwrap().clone();
+            let report = docker::prune(docker, target, &guards).await?;
+            Ok(Some(format!(
+                "Removed {} and reclaimed {} bytes",
+                plural(report.deleted.len(), "item"),
+                report.space_reclaimed
+            )))
+        }
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
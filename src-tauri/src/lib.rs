@@ -1,191 +1,142 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-use bollard::container::Config as BollardConfig; // Add import for Config
-use bollard::container::CreateContainerOptions as BollardCreateOptions; // Add import for CreateContainerOptions
-use bollard::container::{
-    ListContainersOptions, StartContainerOptions, Stats, StopContainerOptions,
-};
 use bollard::Docker;
-use chrono::{NaiveDateTime, Utc};
 use futures_util::StreamExt;
-use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::collections::HashMap;
-use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tauri::{Emitter, Manager, State, Window};
+use tauri::{AppHandle, Emitter, Manager, State, Window, WindowEvent};
 use tokio::sync::Mutex;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ContainerInfo {
-    id: String,
-    names: Vec<String>,
-    image: String,
-    state: String,
-    status: String,
-    labels: HashMap<String, String>,
-    ports: Vec<PortInfo>,
-    created: u64,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PortInfo {
-    ip: String,
-    private_port: u16,
-    public_port: u16,
-    type_: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ImageInfo {
-    id: String,
-    repo_tags: Vec<String>,
-    size: u64,
-    created: u64,
-}
-
-#[derive(Debug, Clone, Serialize)]
-pub enum DockerStatus {
-    Connected,
-    Disconnected,
-    Error(String),
-}
-
-/// Structured error type for Docker operations
-#[derive(Debug, Clone, Serialize)]
-pub enum DockerError {
-    ConnectionError(String),
-    OperationError(String),
-    NotFound(String),
-    PermissionDenied(String),
-    Unknown(String),
-}
-
-impl std::fmt::Display for DockerError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            DockerError::ConnectionError(msg) => write!(f, "Connection error: {}", msg),
-            DockerError::OperationError(msg) => write!(f, "Operation error: {}", msg),
-            DockerError::NotFound(msg) => write!(f, "Not found: {}", msg),
-            DockerError::PermissionDenied(msg) => write!(f, "Permission denied: {}", msg),
-            DockerError::Unknown(msg) => write!(f, "Unknown error: {}", msg),
-        }
-    }
-}
-
-impl From<bollard::errors::Error> for DockerError {
-    fn from(err: bollard::errors::Error) -> Self {
-        match err {
-            bollard::errors::Error::DockerResponseServerError {
-                status_code,
-                message,
-            } => match status_code {
-                404 => DockerError::NotFound(message),
-                403 => DockerError::PermissionDenied(message),
-                _ => DockerError::OperationError(format!(
-                    "Server error ({}): {}",
-                    status_code, message
-                )),
-            },
-            err => {
-                if err.to_string().contains("connection") {
-                    DockerError::ConnectionError(err.to_string())
-                } else {
-                    DockerError::Unknown(err.to_string())
-                }
-            }
-        }
-    }
-}
-
-impl From<std::io::Error> for DockerError {
-    fn from(err: std::io::Error) -> Self {
-        DockerError::OperationError(err.to_string())
-    }
-}
-
-// Type alias for results with DockerError
-type DockerResult<T> = Result<T, DockerError>;
+pub mod archive;
+pub mod audit;
+pub mod backup;
+pub mod build;
+pub mod cli;
+pub mod docker;
+pub mod dockerfile;
+pub mod endpoints;
+pub mod forwarding;
+pub mod guards;
+pub mod jobs;
+pub mod logs;
+pub mod monitor;
+pub mod ports;
+pub mod profiles;
+pub mod recreate;
+pub mod reference;
+pub mod rpmdb;
+pub mod sbom;
+pub mod schedule;
+pub mod settings;
+pub mod store;
+pub mod updates;
+pub mod vulndb;
+
+use audit::{AuditExportFormat, AuditFilter, AuditLog, AuditRecord, AUDIT_FILE};
+use backup::{RestoreOptions, VolumeBackup, VolumeRestore};
+use build::{BuildOptions, BuildProgress};
+use docker::{
+    CommitOptions, ContainerChange, ContainerConfig, ContainerInfo, ContainerResources,
+    ContainerStats, CreateContainerOptions, DockerResult, DockerState, DockerStatus, ImageInfo,
+    ImportOptions, PortMapping,
+};
+use dockerfile::{Diagnostic, Rule};
+use endpoints::ServiceEndpoint;
+use forwarding::{PortForward, PortForwards};
+use guards::{ContainerRemoval, ImageRemoval, TrashedContainer};
+use jobs::{Job, JobManager, JobRequest, JobState};
+use logs::{LogBatch, LogExportFormat, LogQuery, LogSearchResult};
+use monitor::CrashMonitor;
+use ports::PortConflict;
+use profiles::{ContainerProfile, ProfileStore, PROFILES_FILE};
+use recreate::RecreateOptions;
+use sbom::{SbomFormat, SbomReport};
+use schedule::{ActionRecord, Schedule, ScheduleInfo, ScheduleStore, SCHEDULES_FILE};
+use settings::{Settings, SettingsStore, SETTINGS_FILE};
+use updates::{ImageUpdate, RecreateOutcome};
+use vulndb::{VulnerabilityDbStore, VULNDB_FILE};
 
 // Convert DockerResult to Result<T, String> for Tauri commands
 fn to_string_error<T>(result: DockerResult<T>) -> Result<T, String> {
     result.map_err(|e| e.to_string())
 }
 
-pub struct DockerState {
-    client: Option<Docker>,
-    status: DockerStatus,
+// Define a type alias for our state to make it easier to use
+type DockerStateManager = Arc<Mutex<DockerState>>;
+type SettingsManager = Arc<Mutex<SettingsStore>>;
+type ProfileManager = Arc<Mutex<ProfileStore>>;
+type ScheduleManager = Arc<Mutex<ScheduleStore>>;
+type VulnerabilityDbManager = Arc<Mutex<VulnerabilityDbStore>>;
+// Running aggregated log subscriptions, keyed by subscription id
+type LogSubscriptions = Arc<Mutex<HashMap<String, tokio::task::JoinHandle<()>>>>;
+
+static NEXT_LOG_SUBSCRIPTION: AtomicU64 = AtomicU64::new(1);
+
+// Get the Docker client, releasing the state lock before the caller awaits anything
+async fn docker_client(state: &DockerStateManager) -> Result<Docker, String> {
+    let docker_state = state.lock().await;
+    to_string_error(docker_state.get_client())
 }
 
-impl Default for DockerState {
-    fn default() -> Self {
-        Self {
-            client: None,
-            status: DockerStatus::Disconnected,
-        }
-    }
+// The client plus the endpoint it talks to, for commands that write to the audit log
+async fn audited_client(state: &DockerStateManager) -> Result<(Docker, String), String> {
+    let docker_state = state.lock().await;
+    Ok((
+        to_string_error(docker_state.get_client())?,
+        docker_state.endpoint(),
+    ))
 }
 
-impl DockerState {
-    pub fn initialize(&mut self) -> DockerStatus {
-        if self.client.is_some() {
-            return self.status.clone();
-        }
-
-        match Docker::connect_with_local_defaults() {
-            Ok(client) => {
-                self.client = Some(client);
-                self.status = DockerStatus::Connected;
-                self.status.clone()
-            }
-            Err(e) => {
-                self.status = DockerStatus::Error(format!("Failed to connect to Docker: {}", e));
-                self.status.clone()
-            }
-        }
-    }
-
-    pub fn get_client(&self) -> DockerResult<Docker> {
-        match &self.client {
-            Some(client) => Ok(client.clone()),
-            None => Err(DockerError::ConnectionError(
-                "Docker client not initialized".to_string(),
-            )),
-        }
-    }
-
-    pub async fn check_status(&mut self) -> DockerStatus {
-        if let Some(client) = &self.client {
-            match client.ping().await {
-                Ok(_) => {
-                    self.status = DockerStatus::Connected;
-                }
-                Err(e) => {
-                    self.status = DockerStatus::Error(format!("Docker is not responding: {}", e));
-                }
-            }
-        } else {
-            // Try to initialize if not already initialized
-            self.initialize();
-        }
+/// Watch for crashes with `docker`, emitting `container-alert` for each one
+fn watch_crashes(app: &AppHandle, monitor: &CrashMonitor, docker: Docker) {
+    let app = app.clone();
+    monitor.start(docker, move |alert| {
+        let _ = app.emit("container-alert", alert);
+    });
+}
 
-        self.status.clone()
+/// Point an enabled crash monitor at the current client after a reconnect
+async fn rewatch_crashes(app: &AppHandle, monitor: &CrashMonitor, state: &DockerStateManager) {
+    if !monitor.is_enabled() {
+        return;
     }
-
-    pub fn reset(&mut self) -> DockerStatus {
-        self.client = None;
-        self.status = DockerStatus::Disconnected;
-        self.initialize()
+    match state.lock().await.get_client() {
+        Ok(docker) => watch_crashes(app, monitor, docker),
+        Err(_) => monitor.suspend(),
     }
 }
 
-// Define a type alias for our state to make it easier to use
-type DockerStateManager = Arc<Mutex<DockerState>>;
-
 #[tauri::command]
 async fn initialize_docker_client(
+    app: AppHandle,
     state: State<'_, DockerStateManager>,
+    monitor: State<'_, CrashMonitor>,
 ) -> Result<DockerStatus, String> {
-    let mut docker_state = state.lock().await;
-    Ok(docker_state.initialize())
+    let status = state.lock().await.initialize();
+    // A watch that ended with a lost connection picks up again
+    if !monitor.is_running() {
+        rewatch_crashes(&app, &monitor, &state).await;
+    }
+    Ok(status)
+}
+
+/// Watch the event stream for crashes, emitting `container-alert` for each one
+#[tauri::command]
+async fn start_crash_monitor(
+    app: AppHandle,
+    state: State<'_, DockerStateManager>,
+    monitor: State<'_, CrashMonitor>,
+) -> Result<(), String> {
+    let docker = docker_client(&state).await?;
+    watch_crashes(&app, &monitor, docker);
+    Ok(())
+}
+
+#[tauri::command]
+async fn stop_crash_monitor(monitor: State<'_, CrashMonitor>) -> Result<(), String> {
+    monitor.stop();
+    Ok(())
 }
 
 #[tauri::command]
@@ -198,359 +149,464 @@ async fn get_docker_status(state: State<'_, DockerStateManager>) -> Result<Docke
 async fn list_containers(
     state: State<'_, DockerStateManager>,
 ) -> Result<Vec<ContainerInfo>, String> {
-    // Get the Docker client first, then release the lock before the await
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
-        }
-    };
-
-    let options = Some(ListContainersOptions::<String> {
-        all: true,
-        ..Default::default()
-    });
-
-    // Use our new error handling
-    match docker.list_containers(options).await {
-        Ok(containers) => {
-            let container_info = containers
-                .iter()
-                .map(|container| {
-                    let names = container
-                        .names
-                        .clone()
-                        .unwrap_or_default()
-                        .iter()
-                        .map(|name| name.trim_start_matches('/').to_string())
-                        .collect();
-
-                    // Extract labels
-                    let labels = container
-                        .labels
-                        .clone()
-                        .unwrap_or_default()
-                        .into_iter()
-                        .collect();
-
-                    // Extract ports
-                    let ports = container
-                        .ports
-                        .clone()
-                        .unwrap_or_default()
-                        .into_iter()
-                        .map(|port| {
-                            let port_type = match &port.typ {
-                                Some(t) => format!("{}", t),
-                                None => "tcp".to_string(),
-                            };
-
-                            PortInfo {
-                                ip: port.ip.unwrap_or_default(),
-                                private_port: port.private_port,
-                                public_port: port.public_port.unwrap_or_default(),
-                                type_: port_type,
-                            }
-                        })
-                        .collect();
-
-                    ContainerInfo {
-                        id: container.id.clone().unwrap_or_default(),
-                        names,
-                        image: container.image.clone().unwrap_or_default(),
-                        state: container.state.clone().unwrap_or_default(),
-                        status: container.status.clone().unwrap_or_default(),
-                        labels,
-                        ports,
-                        created: container.created.unwrap_or_default() as u64,
-                    }
-                })
-                .collect();
-
-            Ok(container_info)
-        }
-        Err(e) => Err(DockerError::from(e).to_string()),
-    }
+    let docker = docker_client(&state).await?;
+    to_string_error(docker::list_containers(&docker, true).await)
 }
 
 #[tauri::command]
 async fn list_images(state: State<'_, DockerStateManager>) -> Result<Vec<ImageInfo>, String> {
-    // Get the Docker client
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
-        }
-    };
-
-    // Use Bollard's list_images API
-    let options = Some(bollard::image::ListImagesOptions::<String> {
-        all: false, // Only show available images
-        ..Default::default()
-    });
-
-    match docker.list_images(options).await {
-        Ok(images) => {
-            let image_info = images
-                .iter()
-                .map(|image| {
-                    // Extract repo tags
-                    let repo_tags = image.repo_tags.clone();
-
-                    // Extract image ID (remove "sha256:" prefix if present)
-                    let id = image.id.trim_start_matches("sha256:").to_string();
-
-                    // Extract size and created timestamp
-                    let size = image.size as u64;
-                    let created = image.created as u64;
-
-                    ImageInfo {
-                        id,
-                        repo_tags,
-                        size,
-                        created,
-                    }
-                })
-                .collect();
-
-            Ok(image_info)
-        }
-        Err(e) => Err(DockerError::from(e).to_string()),
-    }
+    let docker = docker_client(&state).await?;
+    to_string_error(docker::list_images(&docker).await)
 }
 
 #[tauri::command]
 async fn start_container(
     container_id: &str,
     state: State<'_, DockerStateManager>,
+    audit: State<'_, AuditLog>,
 ) -> Result<(), String> {
-    // Get the Docker client first, then release the lock before the await
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
-        }
-    };
-
-    match docker
-        .start_container(container_id, None::<StartContainerOptions<String>>)
-        .await
-    {
-        Ok(_) => Ok(()),
-        Err(e) => Err(DockerError::from(e).to_string()),
-    }
+    let (docker, endpoint) = audited_client(&state).await?;
+    let result = docker::start_container(&docker, container_id).await;
+    audit.record(
+        &endpoint,
+        "start_container",
+        container_id,
+        json!({}),
+        &result,
+    );
+    to_string_error(result)
 }
 
 #[tauri::command]
 async fn stop_container(
     container_id: &str,
     state: State<'_, DockerStateManager>,
+    audit: State<'_, AuditLog>,
 ) -> Result<(), String> {
-    // Get the Docker client first, then release the lock before the await
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
-        }
-    };
-
-    match docker
-        .stop_container(container_id, None::<StopContainerOptions>)
-        .await
-    {
-        Ok(_) => Ok(()),
-        Err(e) => Err(DockerError::from(e).to_string()),
-    }
+    let (docker, endpoint) = audited_client(&state).await?;
+    let result = docker::stop_container(&docker, container_id).await;
+    audit.record(
+        &endpoint,
+        "stop_container",
+        container_id,
+        json!({}),
+        &result,
+    );
+    to_string_error(result)
 }
 
+/// Remove a container unless it's protected, or move it to the trash when that's on
 #[tauri::command]
 async fn remove_container(
     container_id: &str,
+    force: Option<bool>,
     state: State<'_, DockerStateManager>,
-) -> Result<(), String> {
-    // Get the Docker client first, then release the lock before the await
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
-        }
-    };
-
-    match docker.remove_container(container_id, None).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(DockerError::from(e).to_string()),
-    }
+    settings: State<'_, SettingsManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<ContainerRemoval, String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let guards = settings.lock().await.settings().delete_guards.clone();
+    let force = force.unwrap_or(false);
+    let result = guards::remove_container(&docker, &guards, container_id, force).await;
+    let parameters = json!({ "force": force, "result": result.as_ref().ok() });
+    audit.record(
+        &endpoint,
+        "remove_container",
+        container_id,
+        parameters,
+        &result,
+    );
+    to_string_error(result)
 }
 
 #[tauri::command]
-async fn pull_image(image_name: &str, state: State<'_, DockerStateManager>) -> Result<(), String> {
-    // Get the Docker client first, then release the lock before the await
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
-        }
-    };
-
-    // Split the image name into repository and tag
-    let parts: Vec<&str> = image_name.split(':').collect();
-    let repository = parts[0];
-    let tag = if parts.len() > 1 { parts[1] } else { "latest" };
+async fn pull_image(
+    image_name: &str,
+    platform: Option<String>,
+    state: State<'_, DockerStateManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<(), String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let result =
+        docker::pull_image_for_platform(&docker, image_name, platform.as_deref(), |_| {}).await;
+    let parameters = json!({ "platform": platform });
+    audit.record(&endpoint, "pull_image", image_name, parameters, &result);
+    to_string_error(result)
+}
 
-    // Create image returns a Stream, not a Future, so we need to collect the results
-    let create_image_options = bollard::image::CreateImageOptions {
-        from_image: repository,
-        tag,
-        ..Default::default()
-    };
+/// Platforms a reference is published for in its registry
+#[tauri::command]
+async fn get_manifest_list(
+    image: &str,
+    state: State<'_, DockerStateManager>,
+) -> Result<docker::ManifestList, String> {
+    let docker = docker_client(&state).await?;
+    to_string_error(docker::manifest_list(&docker, image).await)
+}
 
-    // Create a stream of pull progress events
-    let pull_stream = docker.create_image(Some(create_image_options), None, None);
+/// Remove an image unless it's protected; one still in use reports its containers
+#[tauri::command]
+async fn remove_image(
+    image_id: &str,
+    force: Option<bool>,
+    state: State<'_, DockerStateManager>,
+    settings: State<'_, SettingsManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<ImageRemoval, String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let guards = settings.lock().await.settings().delete_guards.clone();
+    let force = force.unwrap_or(false);
+    let result = guards::remove_image(&docker, &guards, image_id, force).await;
+    let parameters = json!({ "force": force, "result": result.as_ref().ok() });
+    audit.record(&endpoint, "remove_image", image_id, parameters, &result);
+    to_string_error(result)
+}
 
-    // Collect all events from the stream
-    let mut result = Ok(());
+#[tauri::command]
+async fn list_trash(
+    state: State<'_, DockerStateManager>,
+    settings: State<'_, SettingsManager>,
+) -> Result<Vec<TrashedContainer>, String> {
+    let docker = docker_client(&state).await?;
+    let grace = settings
+        .lock()
+        .await
+        .settings()
+        .delete_guards
+        .trash_grace_minutes;
+    to_string_error(guards::list_trash(&docker, grace).await)
+}
 
-    tokio::pin!(pull_stream);
+/// Take a container out of the trash, returning the name it got back
+#[tauri::command]
+async fn restore_container(
+    container_id: &str,
+    state: State<'_, DockerStateManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<String, String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let result = guards::restore_container(&docker, container_id).await;
+    audit.record(
+        &endpoint,
+        "restore_container",
+        container_id,
+        json!({}),
+        &result,
+    );
+    to_string_error(result)
+}
 
-    while let Some(pull_result) = pull_stream.next().await {
-        if let Err(e) = pull_result {
-            result = Err(DockerError::OperationError(format!(
-                "Failed to pull image: {}",
-                e
-            )));
-            break;
+/// Delete everything in the trash now, returning the old names of what was deleted
+#[tauri::command]
+async fn empty_trash(
+    state: State<'_, DockerStateManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<Vec<String>, String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let result = guards::purge_trash(&docker, i64::MAX).await;
+    if let Ok(purged) = &result {
+        for name in purged {
+            audit.record(&endpoint, "purge_trash", name, json!({}), &result);
         }
     }
+    to_string_error(result)
+}
 
-    result.map_err(|e| e.to_string())
+#[tauri::command]
+fn greet(name: &str) -> String {
+    format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
 #[tauri::command]
-async fn remove_image(image_id: &str, state: State<'_, DockerStateManager>) -> Result<(), String> {
-    // Get the Docker client first, then release the lock before the await
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
-        }
+async fn get_container_logs(
+    container_id: &str,
+    tail_lines: Option<u64>,
+    state: State<'_, DockerStateManager>,
+    settings: State<'_, SettingsManager>,
+) -> Result<String, String> {
+    let docker = docker_client(&state).await?;
+    let tail = match tail_lines {
+        Some(tail) => tail,
+        None => settings.lock().await.settings().log_tail_lines,
     };
-
-    match docker.remove_image(image_id, None, None).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(DockerError::from(e).to_string()),
-    }
+    docker::container_logs(&docker, container_id, tail)
+        .await
+        .map_err(|e| format!("Failed to get logs: {}", e))
 }
 
+/// Fetch a window of a container's logs and filter it by pattern and level
 #[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
+async fn search_container_logs(
+    container_id: &str,
+    query: LogQuery,
+    state: State<'_, DockerStateManager>,
+) -> Result<LogSearchResult, String> {
+    let docker = docker_client(&state).await?;
+    to_string_error(logs::search_logs(&docker, container_id, &query).await)
 }
 
+/// Write the filtered logs to a file, returning the number of lines written
 #[tauri::command]
-async fn get_container_logs(container_id: &str, tail_lines: Option<u64>) -> Result<String, String> {
-    // Use a simpler approach with a command execution
-    let tail = tail_lines.unwrap_or(100);
+async fn export_container_logs(
+    container_id: &str,
+    query: LogQuery,
+    path: std::path::PathBuf,
+    format: LogExportFormat,
+    state: State<'_, DockerStateManager>,
+) -> Result<usize, String> {
+    let docker = docker_client(&state).await?;
+    to_string_error(logs::export_logs(&docker, container_id, &query, &path, format).await)
+}
 
-    // Use std::process::Command to run the docker logs command
-    let output = Command::new("docker")
-        .args(&["logs", "--tail", &tail.to_string(), container_id])
-        .output();
+#[derive(Clone, serde::Serialize)]
+struct AggregatedLogsPayload {
+    subscription_id: String,
+    #[serde(flatten)]
+    batch: LogBatch,
+}
 
-    match output {
-        Ok(output) => {
-            if output.status.success() {
-                let logs = String::from_utf8_lossy(&output.stdout).to_string();
+/// Stream the interleaved logs of a compose project and/or a set of containers.
+///
+/// Batches arrive as `aggregated-logs` events tagged with the returned subscription
+/// id; `aggregated-logs-end` is emitted once every stream has finished. Closing the
+/// window ends the subscription.
+#[tauri::command]
+async fn subscribe_to_aggregated_logs(
+    project: Option<String>,
+    container_ids: Option<Vec<String>>,
+    tail_lines: Option<u64>,
+    window: Window,
+    state: State<'_, DockerStateManager>,
+    settings: State<'_, SettingsManager>,
+    subscriptions: State<'_, LogSubscriptions>,
+) -> Result<String, String> {
+    let docker = docker_client(&state).await?;
+    let tail = match tail_lines {
+        Some(tail) => tail,
+        None => settings.lock().await.settings().log_tail_lines,
+    };
+    let sources = to_string_error(
+        logs::log_sources(
+            &docker,
+            project.as_deref(),
+            &container_ids.unwrap_or_default(),
+        )
+        .await,
+    )?;
+
+    let subscription_id = format!(
+        "logs-{}",
+        NEXT_LOG_SUBSCRIPTION.fetch_add(1, Ordering::Relaxed)
+    );
+    let id = subscription_id.clone();
+    let registry = subscriptions.inner().clone();
+    // Hold the lock until the handle is stored so a stream that ends at once can't
+    // try to remove itself before it was added
+    let mut running = subscriptions.lock().await;
+
+    let closed = (registry.clone(), subscription_id.clone());
+    window.on_window_event(move |event| {
+        if let WindowEvent::Destroyed = event {
+            let (registry, id) = closed.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Some(task) = registry.lock().await.remove(&id) {
+                    task.abort();
+                }
+            });
+        }
+    });
 
-                return Ok(logs);
-            } else {
-                let error = String::from_utf8_lossy(&output.stderr).to_string();
+    let task = tokio::spawn(async move {
+        let result = logs::aggregate_logs(&docker, &sources, tail, true, |batch| {
+            let _ = window.emit(
+                "aggregated-logs",
+                AggregatedLogsPayload {
+                    subscription_id: id.clone(),
+                    batch,
+                },
+            );
+        })
+        .await;
 
-                return Err(format!("Failed to get logs: {}", error));
-            }
+        if let Err(e) = result {
+            eprintln!("Aggregated log stream {} failed: {}", id, e);
         }
-        Err(e) => Err(format!("Failed to execute command: {}", e)),
-    }
-}
+        let _ = window.emit("aggregated-logs-end", &id);
+        registry.lock().await.remove(&id);
+    });
 
-// --- Start: Add create_container command ---
+    running.insert(subscription_id.clone(), task);
+    Ok(subscription_id)
+}
 
-/// Options for creating a new container, received from the frontend
-#[derive(Debug, Deserialize)]
-struct CreateContainerOptions {
-    image: String,
-    name: String,
-    // TODO: Add ports, volumes, env vars later
+/// Stop an aggregated log subscription
+#[tauri::command]
+async fn unsubscribe_from_aggregated_logs(
+    subscription_id: &str,
+    subscriptions: State<'_, LogSubscriptions>,
+) -> Result<(), String> {
+    match subscriptions.lock().await.remove(subscription_id) {
+        Some(task) => {
+            task.abort();
+            Ok(())
+        }
+        None => Err(format!("No log subscription {}", subscription_id)),
+    }
 }
 
 #[tauri::command]
 async fn create_container(
     options: CreateContainerOptions,
     state: State<'_, DockerStateManager>,
+    audit: State<'_, AuditLog>,
 ) -> Result<(), String> {
-    // Get the Docker client
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
-        }
-    };
-
-    // Prepare Bollard's CreateContainerOptions and Config
-    let config = BollardConfig {
-        image: Some(options.image.clone()),
-        // TODO: Add HostConfig for ports, volumes etc.
-        ..Default::default()
-    };
-
-    let create_options = Some(BollardCreateOptions {
-        name: options.name.clone(),
-        platform: None, // Specifying platform for broader compatibility
-    });
-
-    // Call Bollard's create_container
-    match docker.create_container(create_options, config).await {
-        Ok(response) => {
-            println!("Container created successfully: ID {}", response.id);
-            // Attempt to start the container
-            match docker
-                .start_container(&response.id, None::<StartContainerOptions<String>>)
-                .await
-            {
-                Ok(_) => {
-                    println!("Container started successfully: ID {}", response.id);
-                    Ok(())
-                }
-                Err(e) => {
-                    eprintln!(
-                        "Container {} created, but failed to start: {}",
-                        response.id, e
-                    );
-                    // Even if starting fails, creation was successful, so we could argue about the return.
-                    // For now, let's return an error that it failed to start.
-                    Err(format!(
-                        "Container created (ID: {}), but failed to start: {}",
-                        response.id,
-                        DockerError::from(e).to_string()
-                    ))
-                }
-            }
+    let (docker, endpoint) = audited_client(&state).await?;
+
+    let result = docker::create_container(&docker, &options).await;
+    let parameters = serde_json::to_value(&options).unwrap_or_default();
+    audit.record(
+        &endpoint,
+        "create_container",
+        &options.name,
+        parameters,
+        &result,
+    );
+    match result {
+        Ok(id) => {
+            println!("Container created and started successfully: ID {}", id);
+            Ok(())
         }
         Err(e) => {
             eprintln!("Failed to create container: {}", e);
-            Err(DockerError::from(e).to_string())
+            Err(e.to_string())
         }
     }
 }
 
-// --- End: Add create_container command ---
+/// Check which of the given host ports are taken, with a free alternative for each
+#[tauri::command]
+async fn check_port_conflicts(
+    ports: Vec<PortMapping>,
+    state: State<'_, DockerStateManager>,
+) -> Result<Vec<PortConflict>, String> {
+    let docker = docker_client(&state).await?;
+    to_string_error(ports::check_port_conflicts(&docker, &ports).await)
+}
+
+/// Saved run profiles followed by the built-in catalog
+#[tauri::command]
+async fn list_profiles(
+    profiles: State<'_, ProfileManager>,
+) -> Result<Vec<ContainerProfile>, String> {
+    Ok(profiles.lock().await.list())
+}
+
+#[tauri::command]
+async fn save_profile(
+    profile: ContainerProfile,
+    profiles: State<'_, ProfileManager>,
+) -> Result<ContainerProfile, String> {
+    to_string_error(profiles.lock().await.save(profile))
+}
+
+#[tauri::command]
+async fn delete_profile(name: &str, profiles: State<'_, ProfileManager>) -> Result<(), String> {
+    to_string_error(profiles.lock().await.delete(name))
+}
+
+/// Schedules with their next run and the outcome of their last one
+#[tauri::command]
+async fn list_schedules(
+    schedules: State<'_, ScheduleManager>,
+) -> Result<Vec<ScheduleInfo>, String> {
+    Ok(schedules.lock().await.list(&chrono::Local::now()))
+}
+
+#[tauri::command]
+async fn save_schedule(
+    schedule: Schedule,
+    schedules: State<'_, ScheduleManager>,
+) -> Result<Schedule, String> {
+    to_string_error(schedules.lock().await.save(schedule))
+}
+
+#[tauri::command]
+async fn delete_schedule(name: &str, schedules: State<'_, ScheduleManager>) -> Result<(), String> {
+    to_string_error(schedules.lock().await.delete(name))
+}
+
+/// Run a schedule's action right away, recording and emitting it like a timed run
+#[tauri::command]
+async fn run_schedule_now(
+    name: &str,
+    app: AppHandle,
+    state: State<'_, DockerStateManager>,
+    schedules: State<'_, ScheduleManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<ActionRecord, String> {
+    let schedule = to_string_error(schedules.lock().await.get(name))?;
+    let (docker, endpoint) = audited_client(&state).await?;
+    let record = schedule::run(&docker, &schedule, true).await;
+    audit.record_schedule(&endpoint, &record);
+    let _ = app.emit("schedule-run", &record);
+    to_string_error(schedules.lock().await.record(record.clone()))?;
+    Ok(record)
+}
+
+/// Past scheduled runs, newest first
+#[tauri::command]
+async fn get_schedule_history(
+    schedule: Option<String>,
+    schedules: State<'_, ScheduleManager>,
+) -> Result<Vec<ActionRecord>, String> {
+    Ok(schedules.lock().await.history(schedule.as_deref()))
+}
+
+/// Fill in a profile's placeholders and create and start the container, returning its ID.
+/// A missing image is pulled first, emitting `pull-progress`.
+#[tauri::command]
+async fn launch_profile(
+    name: &str,
+    variables: Option<HashMap<String, String>>,
+    window: Window,
+    state: State<'_, DockerStateManager>,
+    profiles: State<'_, ProfileManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<String, String> {
+    let profile = to_string_error(profiles.lock().await.get(name))?;
+    let options = to_string_error(profile.resolve(&variables.unwrap_or_default()))?;
+
+    let (docker, endpoint) = audited_client(&state).await?;
+    let pulled = docker::ensure_image(&docker, &options.image, |progress| {
+        if let Ok(progress_json) = serde_json::to_string(progress) {
+            let _ = window.emit("pull-progress", progress_json);
+        }
+    })
+    .await;
+    match &pulled {
+        Ok(false) => {}
+        _ => audit.record(
+            &endpoint,
+            "pull_image",
+            &options.image,
+            json!({ "profile": name }),
+            &pulled,
+        ),
+    }
+    to_string_error(pulled)?;
+
+    let result = docker::create_container(&docker, &options).await;
+    let mut parameters = serde_json::to_value(&options).unwrap_or_default();
+    parameters["profile"] = json!(name);
+    audit.record(
+        &endpoint,
+        "create_container",
+        &options.name,
+        parameters,
+        &result,
+    );
+    to_string_error(result)
+}
 
 /// Subscribe to Docker events and forward them to the frontend
 /// This replaces polling with real-time event notifications
@@ -559,17 +615,10 @@ async fn subscribe_to_docker_events(
     window: Window,
     state: State<'_, DockerStateManager>,
 ) -> Result<(), String> {
-    // Get the Docker client first, then release the lock before the await
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
-        }
-    };
+    let docker = docker_client(&state).await?;
 
     // Create a stream of Docker events
-    let events = docker.events(None::<bollard::system::EventsOptions<String>>);
+    let events = docker::events_stream(&docker);
 
     // Spawn a task to process events
     tokio::spawn(async move {
@@ -596,305 +645,401 @@ async fn subscribe_to_docker_events(
     Ok(())
 }
 
-/// Pull an image with progress reporting to the frontend
+/// Pull an image as a job and wait for it, returning the finished job. Progress arrives
+/// as `job-update` events for that job; `start_job` does the same without waiting.
 #[tauri::command]
 async fn pull_image_with_progress(
     image_name: &str,
-    window: Window,
+    platform: Option<String>,
     state: State<'_, DockerStateManager>,
-) -> Result<(), String> {
-    // Get the Docker client first, then release the lock before the await
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
-        }
+    jobs: State<'_, JobManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<Job, String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let request = JobRequest::Pull {
+        image: image_name.to_string(),
+        platform,
     };
-
-    // Split the image name into repository and tag
-    let parts: Vec<&str> = image_name.split(':').collect();
-    let repository = parts[0];
-    let tag = if parts.len() > 1 { parts[1] } else { "latest" };
-
-    // Create image returns a Stream, not a Future, so we need to collect the results
-    let create_image_options = bollard::image::CreateImageOptions {
-        from_image: repository,
-        tag,
-        ..Default::default()
-    };
-
-    // Create a stream of pull progress events
-    let pull_stream = docker.create_image(Some(create_image_options), None, None);
-
-    tokio::pin!(pull_stream);
-
-    while let Some(pull_result) = pull_stream.next().await {
-        match pull_result {
-            Ok(progress) => {
-                // Emit progress event to frontend
-                if let Ok(progress_json) = serde_json::to_string(&progress) {
-                    let _ = window.emit("pull-progress", progress_json);
-                }
-            }
-            Err(e) => {
-                return Err(
-                    DockerError::OperationError(format!("Failed to pull image: {}", e)).to_string(),
-                );
-            }
-        }
+    let job = submit_audited(&jobs, &audit, docker, endpoint, request);
+
+    match jobs.wait(&job.id).await {
+        Some(done) if done.state == JobState::Completed => Ok(done),
+        Some(done) => Err(done
+            .error
+            .unwrap_or_else(|| format!("Pull of {} was cancelled", image_name))),
+        None => Err(format!("Job {} is no longer tracked", job.id)),
     }
+}
 
-    Ok(())
+/// Lint a Dockerfile, honouring the rules disabled in settings
+#[tauri::command]
+async fn lint_dockerfile(
+    path: std::path::PathBuf,
+    settings: State<'_, SettingsManager>,
+) -> Result<Vec<Diagnostic>, String> {
+    let disabled = settings
+        .lock()
+        .await
+        .settings()
+        .dockerfile_lint
+        .disabled_rules
+        .clone();
+    to_string_error(dockerfile::lint_file(&path, &disabled))
 }
 
-// Helper function to parse human-readable size to bytes
-fn parse_size(size_str: &str) -> u64 {
-    if size_str.is_empty() {
-        return 0;
-    }
+#[tauri::command]
+fn list_lint_rules() -> Vec<Rule> {
+    dockerfile::RULES.to_vec()
+}
 
-    let size_str = size_str.trim();
-    let mut numeric_part = String::new();
-    let mut unit_part = String::new();
+/// Build an image from a local context, returning its ID.
+///
+/// The Dockerfile is linted first when settings ask for it; the diagnostics are
+/// emitted as `build-lint` and build output as `build-progress`.
+#[tauri::command]
+async fn build_image(
+    options: BuildOptions,
+    window: Window,
+    state: State<'_, DockerStateManager>,
+    settings: State<'_, SettingsManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<String, String> {
+    let lint = settings.lock().await.settings().dockerfile_lint.clone();
+    let diagnostics = to_string_error(build::preflight(&options, &lint))?;
+    if !diagnostics.is_empty() {
+        let _ = window.emit("build-lint", &diagnostics);
+    }
 
-    for c in size_str.chars() {
-        if c.is_digit(10) || c == '.' {
-            numeric_part.push(c);
-        } else if !c.is_whitespace() {
-            unit_part.push(c);
+    let (docker, endpoint) = audited_client(&state).await?;
+    let result = build::build_image(&docker, &options, |progress| {
+        if let Ok(progress_json) = serde_json::to_string(&BuildProgress::from(progress)) {
+            let _ = window.emit("build-progress", progress_json);
         }
-    }
+    })
+    .await;
 
-    let numeric_value: f64 = numeric_part.parse().unwrap_or(0.0);
+    let parameters = serde_json::to_value(&options).unwrap_or_default();
+    audit.record(&endpoint, "build_image", &options.tag, parameters, &result);
+    to_string_error(result)
+}
 
-    match unit_part.to_uppercase().as_str() {
-        "B" => numeric_value as u64,
-        "KB" | "K" => (numeric_value * 1_024.0) as u64,
-        "MB" | "M" => (numeric_value * 1_024.0 * 1_024.0) as u64,
-        "GB" | "G" => (numeric_value * 1_024.0 * 1_024.0 * 1_024.0) as u64,
-        "TB" | "T" => (numeric_value * 1_024.0 * 1_024.0 * 1_024.0 * 1_024.0) as u64,
-        _ => numeric_value as u64,
-    }
+/// Compare the tags of one image, or of every image, with what their registries serve
+#[tauri::command]
+async fn check_image_updates(
+    image: Option<String>,
+    state: State<'_, DockerStateManager>,
+) -> Result<Vec<ImageUpdate>, String> {
+    let docker = docker_client(&state).await?;
+    to_string_error(updates::check_updates(&docker, image.as_deref()).await)
 }
 
-// Helper function to parse timestamp to Unix timestamp
-fn parse_timestamp(timestamp_str: &str) -> u64 {
-    // Docker timestamps can be in different formats
-    // Try a few common formats
-    let formats = [
-        "%Y-%m-%d %H:%M:%S %z",
-        "%Y-%m-%d %H:%M:%S",
-        "%a %b %d %H:%M:%S %Y",
-        "%Y-%m-%dT%H:%M:%S",
-    ];
-
-    for format in formats {
-        if let Ok(dt) = NaiveDateTime::parse_from_str(timestamp_str, format) {
-            // Use the non-deprecated approach
-            return dt.and_utc().timestamp() as u64;
+/// Pull a newer `tag` and move the containers using the old image onto it, emitting
+/// `pull-progress` while pulling. Returns how recreating each container went.
+#[tauri::command]
+async fn pull_and_recreate(
+    tag: &str,
+    options: Option<RecreateOptions>,
+    window: Window,
+    state: State<'_, DockerStateManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<Vec<RecreateOutcome>, String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let options = options.unwrap_or_default();
+
+    let result = updates::pull_and_recreate(&docker, tag, &options, |progress| {
+        if let Ok(progress_json) = serde_json::to_string(progress) {
+            let _ = window.emit("pull-progress", progress_json);
         }
-    }
+    })
+    .await;
 
-    // Fallback to current time if parsing fails
-    Utc::now().timestamp() as u64
+    let parameters = json!({ "options": options, "outcomes": result.as_ref().ok() });
+    audit.record(&endpoint, "pull_and_recreate", tag, parameters, &result);
+    to_string_error(result)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ContainerStats {
-    cpu_usage_percent: f64,
-    memory_usage: u64,
-    memory_limit: u64,
-    memory_usage_percent: f64,
-    network_rx_bytes: u64,
-    network_tx_bytes: u64,
-    block_read_bytes: u64,
-    block_write_bytes: u64,
-}
-
-/// Get container stats (CPU, memory, network)
+/// Replace a container with an identical one, optionally on a freshly pulled image,
+/// emitting `pull-progress` while pulling. The old container is restored if the new
+/// one fails to come up.
 #[tauri::command]
-async fn get_container_stats(
+async fn recreate_container(
     container_id: &str,
+    options: Option<RecreateOptions>,
+    window: Window,
     state: State<'_, DockerStateManager>,
-) -> Result<ContainerStats, String> {
-    // Get the Docker client
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
+    audit: State<'_, AuditLog>,
+) -> Result<String, String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let options = options.unwrap_or_default();
+
+    let result = recreate::recreate_container(&docker, container_id, &options, |progress| {
+        if let Ok(progress_json) = serde_json::to_string(progress) {
+            let _ = window.emit("pull-progress", progress_json);
         }
-    };
-
-    // Use Bollard's stats API to get container stats
-    // We need to create a stream and get the first result
-    let stats_options = bollard::container::StatsOptions {
-        stream: false, // We only want one stats snapshot
-        ..Default::default()
-    };
+    })
+    .await;
+
+    let parameters = json!({ "options": options, "new_id": result.as_ref().ok() });
+    audit.record(
+        &endpoint,
+        "recreate_container",
+        container_id,
+        parameters,
+        &result,
+    );
+    to_string_error(result)
+}
 
-    let mut stats_stream = docker.stats(container_id, Some(stats_options));
-
-    // Get the first (and only) stats result
-    match stats_stream.next().await {
-        Some(Ok(stats)) => {
-            // Calculate CPU usage percentage
-            let cpu_usage_percent = calculate_cpu_percentage(&stats);
-
-            // Get memory usage and limit
-            let memory_usage = match &stats.memory_stats.usage {
-                Some(usage) => *usage,
-                None => 0,
-            };
-
-            let memory_limit = match &stats.memory_stats.limit {
-                Some(limit) => *limit,
-                None => 0,
-            };
-
-            // Calculate memory usage percentage
-            let memory_usage_percent = if memory_limit > 0 {
-                (memory_usage as f64 / memory_limit as f64) * 100.0
-            } else {
-                0.0
-            };
-
-            // Get network I/O
-            let (network_rx_bytes, network_tx_bytes) = get_network_stats(&stats);
-
-            // Get block I/O
-            let (block_read_bytes, block_write_bytes) = get_block_io_stats(&stats);
-
-            Ok(ContainerStats {
-                cpu_usage_percent,
-                memory_usage,
-                memory_limit,
-                memory_usage_percent,
-                network_rx_bytes,
-                network_tx_bytes,
-                block_read_bytes,
-                block_write_bytes,
-            })
+/// Queue a pull, build, push or prune and return the job right away; progress arrives
+/// as `job-update` events. Builds are linted first, like `build_image`.
+#[tauri::command]
+async fn start_job(
+    request: JobRequest,
+    window: Window,
+    state: State<'_, DockerStateManager>,
+    settings: State<'_, SettingsManager>,
+    jobs: State<'_, JobManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<Job, String> {
+    if let JobRequest::Build(options) = &request {
+        let lint = settings.lock().await.settings().dockerfile_lint.clone();
+        let diagnostics = to_string_error(build::preflight(options, &lint))?;
+        if !diagnostics.is_empty() {
+            let _ = window.emit("build-lint", &diagnostics);
         }
-        Some(Err(e)) => Err(DockerError::from(e).to_string()),
-        None => Err(DockerError::NotFound(format!(
-            "No stats found for container {}",
-            container_id
-        ))
-        .to_string()),
     }
+    let (docker, endpoint) = audited_client(&state).await?;
+    Ok(submit_audited(&jobs, &audit, docker, endpoint, request))
 }
 
-/// Calculate CPU usage percentage from stats
-fn calculate_cpu_percentage(stats: &Stats) -> f64 {
-    // Extract CPU usage data
-    let cpu_usage = stats.cpu_stats.cpu_usage.total_usage;
-    let precpu_usage = stats.precpu_stats.cpu_usage.total_usage;
-    let cpu_delta = if cpu_usage > precpu_usage {
-        (cpu_usage - precpu_usage) as i64
-    } else {
-        0
-    };
+/// Queue a job and audit it once it has finished, however long it queues and runs
+fn submit_audited(
+    jobs: &JobManager,
+    audit: &AuditLog,
+    docker: Docker,
+    endpoint: String,
+    request: JobRequest,
+) -> Job {
+    let job = jobs.submit(docker, request.clone());
+    let (jobs, audit, id) = (jobs.clone(), audit.clone(), job.id.clone());
+    tokio::spawn(async move {
+        if let Some(done) = jobs.wait(&id).await {
+            audit.record_job(&endpoint, &request, &done);
+        }
+    });
+    job
+}
 
-    let system_cpu_usage = match stats.cpu_stats.system_cpu_usage {
-        Some(usage) => usage,
-        None => 0,
-    };
+/// Queued, running and recently finished jobs, for the UI to reattach to after a reload
+#[tauri::command]
+fn list_jobs(jobs: State<'_, JobManager>) -> Vec<Job> {
+    jobs.list()
+}
 
-    let system_precpu_usage = match stats.precpu_stats.system_cpu_usage {
-        Some(usage) => usage,
-        None => 0,
-    };
+#[tauri::command]
+fn cancel_job(job_id: &str, jobs: State<'_, JobManager>) -> Result<Job, String> {
+    to_string_error(jobs.cancel(job_id))
+}
 
-    let system_delta = if system_cpu_usage > system_precpu_usage {
-        (system_cpu_usage - system_precpu_usage) as i64
-    } else {
-        0
-    };
+/// Save a container's filesystem as a tar file, emitting `export-progress` as it goes
+#[tauri::command]
+async fn export_container(
+    container_id: &str,
+    path: std::path::PathBuf,
+    window: Window,
+    state: State<'_, DockerStateManager>,
+) -> Result<u64, String> {
+    let docker = docker_client(&state).await?;
 
-    let online_cpus = match stats.cpu_stats.online_cpus {
-        Some(cpus) => cpus as f64,
-        None => 1.0,
-    };
+    let result = docker::export_container(&docker, container_id, &path, |progress| {
+        let _ = window.emit("export-progress", progress);
+    })
+    .await;
 
-    // Calculate percentage
-    if system_delta > 0 && cpu_delta > 0 {
-        ((cpu_delta as f64 / system_delta as f64) * online_cpus) * 100.0
-    } else {
-        0.0
-    }
+    to_string_error(result)
 }
 
-/// Extract network stats from container stats
-fn get_network_stats(stats: &Stats) -> (u64, u64) {
-    if let Some(networks) = &stats.networks {
-        let mut rx_bytes = 0;
-        let mut tx_bytes = 0;
+/// Save a named volume as a gzipped tar, emitting `volume-progress` as it goes
+#[tauri::command]
+async fn backup_volume(
+    volume: &str,
+    path: std::path::PathBuf,
+    window: Window,
+    state: State<'_, DockerStateManager>,
+) -> Result<VolumeBackup, String> {
+    let docker = docker_client(&state).await?;
+    let result = backup::backup_volume(&docker, volume, &path, |progress| {
+        let _ = window.emit("volume-progress", progress);
+    })
+    .await;
+    to_string_error(result)
+}
 
-        for (_interface, network) in networks {
-            rx_bytes += network.rx_bytes;
-            tx_bytes += network.tx_bytes;
-        }
+/// Restore a volume backup, into a new volume unless overwriting is allowed
+#[tauri::command]
+async fn restore_volume(
+    path: std::path::PathBuf,
+    volume: &str,
+    options: Option<RestoreOptions>,
+    window: Window,
+    state: State<'_, DockerStateManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<VolumeRestore, String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let options = options.unwrap_or_default();
+    let result = backup::restore_volume(&docker, &path, volume, &options, |progress| {
+        let _ = window.emit("volume-progress", progress);
+    })
+    .await;
+    let parameters = json!({ "path": path, "options": options, "result": result.as_ref().ok() });
+    audit.record(&endpoint, "restore_volume", volume, parameters, &result);
+    to_string_error(result)
+}
 
-        (rx_bytes, tx_bytes)
-    } else {
-        (0, 0)
+/// Create an image from a filesystem tarball, emitting `import-progress` as it goes
+#[tauri::command]
+async fn import_image(
+    path: std::path::PathBuf,
+    options: Option<ImportOptions>,
+    window: Window,
+    state: State<'_, DockerStateManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<String, String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let socket = state.lock().await.socket();
+
+    let options = options.unwrap_or_default();
+    let result = docker::import_image(&docker, &socket, &path, &options, |progress| {
+        let _ = window.emit("import-progress", progress);
+    })
+    .await;
+
+    let parameters = json!({ "options": options, "image_id": result.as_ref().ok() });
+    let target = path.display().to_string();
+    audit.record(&endpoint, "import_image", &target, parameters, &result);
+    to_string_error(result)
+}
+
+/// Inventory an image's packages, matched against the imported vulnerability database if
+/// there is one. The CycloneDX or SPDX document is also written to `path` when given.
+#[tauri::command]
+async fn generate_sbom(
+    image: &str,
+    format: SbomFormat,
+    path: Option<std::path::PathBuf>,
+    state: State<'_, DockerStateManager>,
+    vulndb: State<'_, VulnerabilityDbManager>,
+) -> Result<SbomReport, String> {
+    let docker = docker_client(&state).await?;
+    let db = to_string_error(vulndb.lock().await.get())?;
+
+    let sbom = to_string_error(sbom::generate_sbom(&docker, image, db.as_deref()).await)?;
+    let document = sbom.document(format);
+    if let Some(path) = path {
+        to_string_error(store::write_json(&path, &document))?;
     }
+    Ok(SbomReport { sbom, document })
 }
 
-/// Extract block I/O stats from container stats
-fn get_block_io_stats(stats: &Stats) -> (u64, u64) {
-    let blkio_stats = &stats.blkio_stats;
+/// Replace the vulnerability database with an OSV JSON file, returning its record count
+#[tauri::command]
+async fn import_vulnerability_db(
+    path: std::path::PathBuf,
+    vulndb: State<'_, VulnerabilityDbManager>,
+) -> Result<usize, String> {
+    to_string_error(vulndb.lock().await.import(&path))
+}
 
-    if let Some(io_service_bytes_recursive) = &blkio_stats.io_service_bytes_recursive {
-        let mut read_bytes = 0;
-        let mut write_bytes = 0;
+/// Get container stats (CPU, memory, network)
+#[tauri::command]
+async fn get_container_stats(
+    container_id: &str,
+    state: State<'_, DockerStateManager>,
+) -> Result<ContainerStats, String> {
+    let docker = docker_client(&state).await?;
+    to_string_error(docker::container_stats(&docker, container_id).await)
+}
 
-        for stat in io_service_bytes_recursive {
-            // op is a String, not an Option<String>
-            if stat.op == "Read" {
-                read_bytes += stat.value;
-            } else if stat.op == "Write" {
-                write_bytes += stat.value;
-            }
-        }
+/// Snapshot a container into a new image, returning the image ID
+#[tauri::command]
+async fn commit_container(
+    container_id: &str,
+    options: CommitOptions,
+    state: State<'_, DockerStateManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<String, String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let result = docker::commit_container(&docker, container_id, &options).await;
+    let parameters = serde_json::to_value(&options).unwrap_or_default();
+    audit.record(
+        &endpoint,
+        "commit_container",
+        container_id,
+        parameters,
+        &result,
+    );
+    to_string_error(result)
+}
 
-        return (read_bytes, write_bytes);
-    }
+/// Files added, changed or deleted in a container relative to its image
+#[tauri::command]
+async fn container_diff(
+    container_id: &str,
+    state: State<'_, DockerStateManager>,
+) -> Result<Vec<ContainerChange>, String> {
+    let docker = docker_client(&state).await?;
+    to_string_error(docker::container_diff(&docker, container_id).await)
+}
 
-    (0, 0)
+/// URLs for a container's published ports, optionally probed for reachability
+#[tauri::command]
+async fn get_container_endpoints(
+    container_id: &str,
+    probe: Option<bool>,
+    state: State<'_, DockerStateManager>,
+) -> Result<Vec<ServiceEndpoint>, String> {
+    let docker = docker_client(&state).await?;
+    to_string_error(endpoints::endpoints(&docker, container_id, probe.unwrap_or(true)).await)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PortMapping {
-    host_ip: String,
-    host_port: String,
-    container_port: String,
-    protocol: String,
+/// Open a published port in the default browser, returning the URL that was opened
+#[tauri::command]
+async fn open_container_port(
+    container_id: &str,
+    public_port: Option<u16>,
+    state: State<'_, DockerStateManager>,
+) -> Result<String, String> {
+    let docker = docker_client(&state).await?;
+    let url = to_string_error(endpoints::endpoint_url(&docker, container_id, public_port).await)?;
+    to_string_error(endpoints::open_url(&url))?;
+    Ok(url)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct VolumeMapping {
-    host_path: String,
-    container_path: String,
-    mode: String,
+/// Open a local listener proxying to a container port that isn't published
+#[tauri::command]
+async fn forward_port(
+    container_id: &str,
+    container_port: u16,
+    local_port: Option<u16>,
+    state: State<'_, DockerStateManager>,
+    forwards: State<'_, PortForwards>,
+) -> Result<PortForward, String> {
+    let docker = docker_client(&state).await?;
+    to_string_error(
+        forwards
+            .start(&docker, container_id, container_port, local_port)
+            .await,
+    )
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ContainerConfig {
-    id: String,
-    name: String,
-    image: String,
-    command: String,
-    created: String,
-    status: String,
-    ports: Vec<PortMapping>,
-    volumes: Vec<VolumeMapping>,
-    env_vars: Vec<String>,
-    labels: HashMap<String, String>,
-    network_mode: String,
-    restart_policy: String,
+#[tauri::command]
+fn list_port_forwards(forwards: State<'_, PortForwards>) -> Vec<PortForward> {
+    forwards.list()
+}
+
+#[tauri::command]
+fn stop_port_forward(forward_id: &str, forwards: State<'_, PortForwards>) -> Result<(), String> {
+    to_string_error(forwards.stop(forward_id))
 }
 
 /// Get detailed container configuration
@@ -903,133 +1048,88 @@ async fn get_container_config(
     container_id: &str,
     state: State<'_, DockerStateManager>,
 ) -> Result<ContainerConfig, String> {
-    // Get the Docker client first, then release the lock before the await
-    let docker = {
-        let docker_state = state.lock().await;
-        match docker_state.get_client() {
-            Ok(client) => client,
-            Err(e) => return Err(e.to_string()),
-        }
-    };
+    let docker = docker_client(&state).await?;
+    to_string_error(docker::container_config(&docker, container_id).await)
+}
 
-    // Inspect the container to get its configuration
-    match docker.inspect_container(container_id, None).await {
-        Ok(details) => {
-            // Extract container name (remove leading slash)
-            let name = details
-                .name
-                .unwrap_or_default()
-                .trim_start_matches('/')
-                .to_string();
-
-            // Extract image name
-            let image = details
-                .config
-                .as_ref()
-                .and_then(|config| config.image.clone())
-                .unwrap_or_default();
-
-            // Extract command
-            let command = details
-                .config
-                .as_ref()
-                .and_then(|config| config.cmd.clone())
-                .map(|cmd| cmd.join(" "))
-                .unwrap_or_default();
-
-            // Extract created time
-            let created = details.created.unwrap_or_default();
-
-            // Extract status - fix the Default trait issue
-            let status = details
-                .state
-                .as_ref()
-                .and_then(|state| state.status.clone())
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| "unknown".to_string());
-
-            // Extract port mappings
-            let mut ports = Vec::new();
-            if let Some(network_settings) = details.network_settings {
-                if let Some(port_map) = network_settings.ports {
-                    for (container_port, host_bindings) in port_map {
-                        if let Some(bindings) = host_bindings {
-                            for binding in bindings {
-                                let parts: Vec<&str> = container_port.split('/').collect();
-                                let port_number = parts.get(0).unwrap_or(&"");
-                                let protocol = parts.get(1).unwrap_or(&"tcp");
-
-                                ports.push(PortMapping {
-                                    host_ip: binding.host_ip.unwrap_or_default(),
-                                    host_port: binding.host_port.unwrap_or_default(),
-                                    container_port: port_number.to_string(),
-                                    protocol: protocol.to_string(),
-                                });
-                            }
-                        }
-                    }
-                }
-            }
+/// Change a running container's limits and restart policy in place
+#[tauri::command]
+async fn update_container_resources(
+    container_id: &str,
+    resources: ContainerResources,
+    state: State<'_, DockerStateManager>,
+    audit: State<'_, AuditLog>,
+) -> Result<ContainerResources, String> {
+    let (docker, endpoint) = audited_client(&state).await?;
+    let result = docker::update_container_resources(&docker, container_id, &resources).await;
+    let parameters = serde_json::to_value(&resources).unwrap_or_default();
+    audit.record(
+        &endpoint,
+        "update_container_resources",
+        container_id,
+        parameters,
+        &result,
+    );
+    to_string_error(result)
+}
 
-            // Extract volume mappings
-            let mut volumes = Vec::new();
-            if let Some(mounts) = details.mounts {
-                for mount in mounts {
-                    volumes.push(VolumeMapping {
-                        host_path: mount.source.unwrap_or_default(),
-                        container_path: mount.destination.unwrap_or_default(),
-                        mode: mount.mode.unwrap_or_default(),
-                    });
-                }
-            }
+/// Audit records matching `filter`, newest first
+#[tauri::command]
+fn get_audit_log(
+    filter: Option<AuditFilter>,
+    audit: State<'_, AuditLog>,
+) -> Result<Vec<AuditRecord>, String> {
+    to_string_error(audit.read(&filter.unwrap_or_default()))
+}
 
-            // Extract environment variables
-            let env_vars = details
-                .config
-                .as_ref()
-                .and_then(|config| config.env.clone())
-                .unwrap_or_default();
-
-            // Extract labels
-            let labels = details
-                .config
-                .as_ref()
-                .and_then(|config| config.labels.clone())
-                .unwrap_or_default();
-
-            // Extract network mode
-            let network_mode = details
-                .host_config
-                .as_ref()
-                .and_then(|config| config.network_mode.clone())
-                .unwrap_or_default();
-
-            // Extract restart policy - fix the Default trait issue
-            let restart_policy = details
-                .host_config
-                .as_ref()
-                .and_then(|config| config.restart_policy.as_ref())
-                .and_then(|policy| policy.name.clone())
-                .map(|name| name.to_string())
-                .unwrap_or_else(|| "no".to_string());
-
-            Ok(ContainerConfig {
-                id: container_id.to_string(),
-                name,
-                image,
-                command,
-                created,
-                status,
-                ports,
-                volumes,
-                env_vars,
-                labels,
-                network_mode,
-                restart_policy,
-            })
-        }
-        Err(e) => Err(DockerError::from(e).to_string()),
+/// Write the matching audit records to a file, returning how many were written
+#[tauri::command]
+fn export_audit_log(
+    path: std::path::PathBuf,
+    format: AuditExportFormat,
+    filter: Option<AuditFilter>,
+    audit: State<'_, AuditLog>,
+) -> Result<usize, String> {
+    to_string_error(audit.export(&filter.unwrap_or_default(), &path, format))
+}
+
+#[tauri::command]
+async fn get_settings(settings: State<'_, SettingsManager>) -> Result<Settings, String> {
+    Ok(settings.lock().await.settings().clone())
+}
+
+/// Apply a partial settings update, persist it and broadcast `settings-changed`
+#[tauri::command]
+async fn update_settings(
+    changes: serde_json::Value,
+    app: AppHandle,
+    settings: State<'_, SettingsManager>,
+    state: State<'_, DockerStateManager>,
+    jobs: State<'_, JobManager>,
+    monitor: State<'_, CrashMonitor>,
+) -> Result<Settings, String> {
+    let (updated, endpoint_changed) = {
+        let mut store = settings.lock().await;
+        let previous_endpoint = store.settings().default_endpoint.clone();
+        let updated = to_string_error(store.update(changes))?;
+        let endpoint_changed = previous_endpoint != updated.default_endpoint;
+        (updated, endpoint_changed)
+    };
+
+    // Reconnect so the new endpoint takes effect immediately
+    if endpoint_changed {
+        let mut docker_state = state.lock().await;
+        *docker_state = updated.docker_state();
+        docker_state.initialize();
+        drop(docker_state);
+        rewatch_crashes(&app, &monitor, &state).await;
     }
+
+    jobs.set_limits(updated.job_limits.clone());
+    jobs.set_delete_guards(updated.delete_guards.clone());
+    monitor.set_settings(updated.crash_alerts.clone());
+    let _ = app.emit("settings-changed", &updated);
+    Ok(updated)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -1037,8 +1137,71 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_window_state::Builder::new().build())
         .setup(|app| {
+            let config_dir = app.path().app_config_dir()?;
+            let settings_path = config_dir.join(SETTINGS_FILE);
+            let settings = SettingsStore::load(settings_path.clone()).unwrap_or_else(|e| {
+                eprintln!("Failed to load settings, using defaults: {}", e);
+                SettingsStore::recover(settings_path)
+            });
+
             // Initialize Docker state with tokio Mutex
-            app.manage(Arc::new(Mutex::new(DockerState::default())));
+            let docker_state = settings.settings().docker_state();
+            let jobs = JobManager::new(settings.settings().job_limits.clone());
+            jobs.set_delete_guards(settings.settings().delete_guards.clone());
+            let handle = app.handle().clone();
+            jobs.on_update(move |job| {
+                let _ = handle.emit("job-update", job);
+            });
+            app.manage(jobs);
+            app.manage(CrashMonitor::new(settings.settings().crash_alerts.clone()));
+            let docker_state = Arc::new(Mutex::new(docker_state));
+            app.manage(docker_state.clone());
+            let settings = Arc::new(Mutex::new(settings));
+            app.manage(settings.clone());
+            app.manage(LogSubscriptions::default());
+            app.manage(PortForwards::default());
+
+            let profiles_path = config_dir.join(PROFILES_FILE);
+            let profiles = ProfileStore::load(profiles_path.clone()).unwrap_or_else(|e| {
+                eprintln!("Failed to load profiles: {}", e);
+                ProfileStore::recover(profiles_path)
+            });
+            app.manage(Arc::new(Mutex::new(profiles)));
+            app.manage(VulnerabilityDbManager::new(Mutex::new(
+                VulnerabilityDbStore::new(config_dir.join(VULNDB_FILE)),
+            )));
+
+            let audit = AuditLog::new(config_dir.join(AUDIT_FILE));
+            app.manage(audit.clone());
+            let trash_audit = audit.clone();
+
+            let schedules_path = config_dir.join(SCHEDULES_FILE);
+            let schedules = ScheduleStore::load(schedules_path.clone()).unwrap_or_else(|e| {
+                eprintln!("Failed to load schedules: {}", e);
+                ScheduleStore::recover(schedules_path)
+            });
+            let schedules = Arc::new(Mutex::new(schedules));
+            app.manage(schedules.clone());
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(schedule::run_scheduler(
+                docker_state.clone(),
+                schedules,
+                move |endpoint, record| {
+                    audit.record_schedule(endpoint, record);
+                    let _ = handle.emit("schedule-run", record);
+                },
+            ));
+
+            tauri::async_runtime::spawn(guards::run_trash_collector(
+                docker_state,
+                settings,
+                move |endpoint, names| {
+                    let purged: DockerResult<()> = Ok(());
+                    for name in names {
+                        trash_audit.record(endpoint, "purge_trash", name, json!({}), &purged);
+                    }
+                },
+            ));
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -1048,16 +1211,63 @@ pub fn run() {
             start_container,
             stop_container,
             remove_container,
+            list_trash,
+            restore_container,
+            empty_trash,
             pull_image,
             pull_image_with_progress,
+            get_manifest_list,
             remove_image,
+            export_container,
+            backup_volume,
+            restore_volume,
+            import_image,
+            build_image,
+            lint_dockerfile,
+            list_lint_rules,
+            generate_sbom,
+            import_vulnerability_db,
+            check_image_updates,
+            pull_and_recreate,
+            recreate_container,
+            start_job,
+            list_jobs,
+            cancel_job,
             get_container_logs,
+            search_container_logs,
+            export_container_logs,
+            subscribe_to_aggregated_logs,
+            unsubscribe_from_aggregated_logs,
             get_container_stats,
             get_container_config,
+            update_container_resources,
+            commit_container,
+            container_diff,
+            get_container_endpoints,
+            open_container_port,
+            forward_port,
+            list_port_forwards,
+            stop_port_forward,
             initialize_docker_client,
             get_docker_status,
             subscribe_to_docker_events,
-            create_container // Register the new command
+            start_crash_monitor,
+            stop_crash_monitor,
+            create_container, // Register the new command
+            check_port_conflicts,
+            list_profiles,
+            save_profile,
+            delete_profile,
+            list_schedules,
+            save_schedule,
+            delete_schedule,
+            run_schedule_now,
+            get_schedule_history,
+            get_audit_log,
+            export_audit_log,
+            launch_profile,
+            get_settings,
+            update_settings
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
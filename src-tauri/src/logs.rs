@@ -0,0 +1,519 @@
+//! Log search: fetch a time window of a container's logs, classify each line by
+//! level and filter it in Rust instead of the webview.
+use crate::docker::{
+    self, DockerError, DockerResult, COMPOSE_PROJECT_LABEL, COMPOSE_SERVICE_LABEL,
+};
+use crate::guards;
+use bollard::container::{LogOutput, LogsOptions};
+use bollard::Docker;
+use chrono::{DateTime, FixedOffset, Utc};
+use futures_util::StreamExt;
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogLine {
+    /// RFC 3339 timestamp as reported by the daemon
+    pub timestamp: Option<String>,
+    pub stream: LogStream,
+    pub level: Option<LogLevel>,
+    pub message: String,
+}
+
+/// Which part of a container's logs to fetch and how to filter it
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LogQuery {
+    /// Only lines at or after this Unix timestamp
+    pub since: Option<i64>,
+    /// Only lines before this Unix timestamp
+    pub until: Option<i64>,
+    /// Only the last N lines of the window (before filtering)
+    pub tail: Option<u64>,
+    /// Substring, or regular expression when `regex` is set
+    pub pattern: Option<String>,
+    pub regex: bool,
+    pub case_sensitive: bool,
+    /// Keep only these levels; empty keeps everything, including unclassified lines
+    pub levels: Vec<LogLevel>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogSearchResult {
+    pub lines: Vec<LogLine>,
+    /// Lines fetched from the daemon before filtering
+    pub scanned: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogExportFormat {
+    /// Plain text, one `timestamp message` line per entry
+    Text,
+    /// Newline-delimited JSON `LogLine`s
+    Json,
+}
+
+// Bare level tokens as most loggers print them, e.g. `2024-01-01 ERROR boom`
+static LEVEL_TOKEN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\b(TRACE|DEBUG|INFO|WARN|WARNING|ERROR|ERR|FATAL|PANIC|CRITICAL)\b").unwrap()
+});
+
+// logfmt style `level=warn`
+static LEVEL_PAIR: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)\b(?:level|lvl|severity)=["']?([a-z]+)"#).unwrap());
+
+fn level_from_name(name: &str) -> Option<LogLevel> {
+    match name.to_ascii_lowercase().as_str() {
+        "trace" => Some(LogLevel::Trace),
+        "debug" => Some(LogLevel::Debug),
+        "info" | "notice" => Some(LogLevel::Info),
+        "warn" | "warning" => Some(LogLevel::Warn),
+        "error" | "err" | "fatal" | "panic" | "critical" | "crit" | "alert" | "emerg" => {
+            Some(LogLevel::Error)
+        }
+        _ => None,
+    }
+}
+
+// pino/bunyan numeric levels
+fn level_from_number(level: u64) -> Option<LogLevel> {
+    match level {
+        0..=10 => Some(LogLevel::Trace),
+        11..=20 => Some(LogLevel::Debug),
+        21..=30 => Some(LogLevel::Info),
+        31..=40 => Some(LogLevel::Warn),
+        _ => Some(LogLevel::Error),
+    }
+}
+
+/// Work out a line's level from a JSON `level` field, a logfmt pair or a bare token
+pub fn detect_level(message: &str) -> Option<LogLevel> {
+    let trimmed = message.trim();
+
+    if trimmed.starts_with('{') {
+        if let Ok(Value::Object(fields)) = serde_json::from_str::<Value>(trimmed) {
+            for key in ["level", "lvl", "severity", "log.level"] {
+                match fields.get(key) {
+                    Some(Value::String(name)) => return level_from_name(name),
+                    Some(Value::Number(number)) => {
+                        return number.as_u64().and_then(level_from_number)
+                    }
+                    _ => {}
+                }
+            }
+            return None;
+        }
+    }
+
+    if let Some(captures) = LEVEL_PAIR.captures(trimmed) {
+        if let Some(level) = level_from_name(&captures[1]) {
+            return Some(level);
+        }
+    }
+
+    LEVEL_TOKEN
+        .captures(trimmed)
+        .and_then(|captures| level_from_name(&captures[1]))
+}
+
+/// Split one chunk of daemon output into log lines, peeling off timestamps if present
+pub fn parse_log_output(output: &LogOutput) -> Vec<LogLine> {
+    let stream = match output {
+        LogOutput::StdErr { .. } => LogStream::Stderr,
+        _ => LogStream::Stdout,
+    };
+
+    output
+        .to_string()
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (timestamp, message) = match line.split_once(' ') {
+                Some((ts, rest)) if DateTime::parse_from_rfc3339(ts).is_ok() => {
+                    (Some(ts.to_string()), rest)
+                }
+                _ => (None, line),
+            };
+
+            LogLine {
+                timestamp,
+                stream,
+                level: detect_level(message),
+                message: message.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// A compiled `LogQuery` filter
+pub struct LogFilter {
+    pattern: Option<Regex>,
+    levels: Vec<LogLevel>,
+}
+
+impl LogFilter {
+    pub fn new(query: &LogQuery) -> DockerResult<Self> {
+        let pattern = match query.pattern.as_deref() {
+            Some(pattern) if !pattern.is_empty() => {
+                let source = if query.regex {
+                    pattern.to_string()
+                } else {
+                    regex::escape(pattern)
+                };
+                let compiled = RegexBuilder::new(&source)
+                    .case_insensitive(!query.case_sensitive)
+                    .build()
+                    .map_err(|e| {
+                        DockerError::OperationError(format!("Invalid log filter: {}", e))
+                    })?;
+                Some(compiled)
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            pattern,
+            levels: query.levels.clone(),
+        })
+    }
+
+    pub fn matches(&self, line: &LogLine) -> bool {
+        if !self.levels.is_empty() {
+            match line.level {
+                Some(level) if self.levels.contains(&level) => {}
+                _ => return false,
+            }
+        }
+
+        match &self.pattern {
+            Some(pattern) => pattern.is_match(&line.message),
+            None => true,
+        }
+    }
+}
+
+/// Fetch the query's window from the daemon and return the matching lines
+pub async fn search_logs(
+    docker: &Docker,
+    container_id: &str,
+    query: &LogQuery,
+) -> DockerResult<LogSearchResult> {
+    let filter = LogFilter::new(query)?;
+
+    let options = Some(LogsOptions::<String> {
+        stdout: true,
+        stderr: true,
+        timestamps: true,
+        since: query.since.unwrap_or(0),
+        until: query.until.unwrap_or(0),
+        tail: query
+            .tail
+            .map(|tail| tail.to_string())
+            .unwrap_or_else(|| "all".to_string()),
+        ..Default::default()
+    });
+
+    let mut stream = docker.logs(container_id, options);
+    let mut lines = Vec::new();
+    let mut scanned = 0;
+
+    while let Some(chunk) = stream.next().await {
+        for line in parse_log_output(&chunk?) {
+            scanned += 1;
+            if filter.matches(&line) {
+                lines.push(line);
+            }
+        }
+    }
+
+    Ok(LogSearchResult { lines, scanned })
+}
+
+/// Write the lines matching `query` to `path`, returning how many were written
+pub async fn export_logs(
+    docker: &Docker,
+    container_id: &str,
+    query: &LogQuery,
+    path: &Path,
+    format: LogExportFormat,
+) -> DockerResult<usize> {
+    let result = search_logs(docker, container_id, query).await?;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    for line in &result.lines {
+        match format {
+            LogExportFormat::Text => match &line.timestamp {
+                Some(timestamp) => writeln!(writer, "{} {}", timestamp, line.message)?,
+                None => writeln!(writer, "{}", line.message)?,
+            },
+            LogExportFormat::Json => {
+                let json =
+                    serde_json::to_string(line).map_err(|e| DockerError::Unknown(e.to_string()))?;
+                writeln!(writer, "{}", json)?;
+            }
+        }
+    }
+    writer.flush()?;
+
+    Ok(result.lines.len())
+}
+
+// Lines buffered between the per-container readers and the batcher. Once it is
+// full the readers stop pulling from the daemon until the batcher catches up.
+const AGGREGATE_BUFFER: usize = 1024;
+// Largest batch handed to the consumer at once; lines beyond it are skipped
+const AGGREGATE_BATCH: usize = 500;
+// Batches are handed over at most this often, so a flood can't swamp the consumer
+const AGGREGATE_FLUSH: Duration = Duration::from_millis(100);
+
+/// A container whose logs are part of an aggregated view
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LogSource {
+    pub container_id: String,
+    /// Compose service name, or the container name outside compose
+    pub service: String,
+}
+
+/// A log line tagged with the service it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregatedLogLine {
+    pub service: String,
+    pub container_id: String,
+    #[serde(flatten)]
+    pub line: LogLine,
+}
+
+/// Lines delivered together by [`aggregate_logs`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LogBatch {
+    pub lines: Vec<AggregatedLogLine>,
+    /// Lines dropped after these because they arrived faster than batches are delivered
+    pub skipped: usize,
+}
+
+/// Resolve the containers of a compose project plus any explicitly listed containers
+pub async fn log_sources(
+    docker: &Docker,
+    project: Option<&str>,
+    containers: &[String],
+) -> DockerResult<Vec<LogSource>> {
+    let sources: Vec<LogSource> = docker::list_containers(docker, true)
+        .await?
+        .into_iter()
+        .filter(|c| {
+            let in_project = project.is_some()
+                && c.labels.get(COMPOSE_PROJECT_LABEL).map(String::as_str) == project
+                && !guards::is_trashed(&c.names);
+            let listed = containers
+                .iter()
+                .any(|wanted| c.id.starts_with(wanted.as_str()) || c.names.contains(wanted));
+            in_project || listed
+        })
+        .map(|c| LogSource {
+            service: c
+                .labels
+                .get(COMPOSE_SERVICE_LABEL)
+                .cloned()
+                .or_else(|| c.names.first().cloned())
+                .unwrap_or_else(|| c.id.chars().take(12).collect()),
+            container_id: c.id,
+        })
+        .collect();
+
+    if sources.is_empty() {
+        return Err(DockerError::NotFound(match project {
+            Some(project) => format!("No containers found for project {}", project),
+            None => "No containers found".to_string(),
+        }));
+    }
+
+    Ok(sources)
+}
+
+fn parse_timestamp(line: &LogLine) -> Option<DateTime<FixedOffset>> {
+    line.timestamp
+        .as_deref()
+        .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+}
+
+/// Order lines by timestamp, keeping the original order for ties. A line without a
+/// timestamp, such as the rest of a multi-line message, stays behind the last timed
+/// line of its container.
+pub fn merge_by_timestamp(lines: &mut Vec<AggregatedLogLine>) {
+    merge_carrying(lines, &mut HashMap::new());
+}
+
+/// [`merge_by_timestamp`], with the last timestamp of each container carried over from
+/// earlier batches
+fn merge_carrying(
+    lines: &mut Vec<AggregatedLogLine>,
+    carried: &mut HashMap<String, DateTime<FixedOffset>>,
+) {
+    let mut keyed: Vec<_> = lines
+        .drain(..)
+        .map(|line| {
+            if let Some(timestamp) = parse_timestamp(&line.line) {
+                carried.insert(line.container_id.clone(), timestamp);
+            }
+            (carried.get(&line.container_id).copied(), line)
+        })
+        .collect();
+    keyed.sort_by_key(|(timestamp, _)| *timestamp);
+    lines.extend(keyed.into_iter().map(|(_, line)| line));
+}
+
+fn tag(source: &LogSource, line: LogLine) -> AggregatedLogLine {
+    AggregatedLogLine {
+        service: source.service.clone(),
+        container_id: source.container_id.clone(),
+        line,
+    }
+}
+
+/// Interleave the logs of several containers, like `docker compose logs`.
+///
+/// The last `tail` lines of every container are merged and delivered first; with
+/// `follow` new lines keep arriving in timestamp-sorted batches until every
+/// container's stream ends. Batches come at most every 100ms with at most 500 lines;
+/// a batch counts what didn't fit in `skipped`. Returning early or dropping the future
+/// stops all readers.
+pub async fn aggregate_logs<F>(
+    docker: &Docker,
+    sources: &[LogSource],
+    tail: u64,
+    follow: bool,
+    mut on_batch: F,
+) -> DockerResult<()>
+where
+    F: FnMut(LogBatch),
+{
+    let started = Utc::now().timestamp();
+
+    let mut backlog = Vec::new();
+    let mut last_seen = Vec::with_capacity(sources.len());
+    for source in sources {
+        let query = LogQuery {
+            tail: Some(tail),
+            ..Default::default()
+        };
+        let lines = search_logs(docker, &source.container_id, &query)
+            .await?
+            .lines;
+        last_seen.push(lines.last().and_then(parse_timestamp));
+        backlog.extend(lines.into_iter().map(|line| tag(source, line)));
+    }
+
+    let mut carried = HashMap::new();
+    merge_carrying(&mut backlog, &mut carried);
+    for (i, lines) in backlog.chunks(AGGREGATE_BATCH).enumerate() {
+        if i > 0 {
+            tokio::time::sleep(AGGREGATE_FLUSH).await;
+        }
+        on_batch(LogBatch {
+            lines: lines.to_vec(),
+            skipped: 0,
+        });
+    }
+
+    if !follow {
+        return Ok(());
+    }
+
+    let (tx, mut rx) = mpsc::channel(AGGREGATE_BUFFER);
+    // Dropping the set aborts every reader
+    let mut readers = JoinSet::new();
+
+    for (source, last_seen) in sources.iter().cloned().zip(last_seen) {
+        let tx = tx.clone();
+        let options = Some(LogsOptions::<String> {
+            follow: true,
+            stdout: true,
+            stderr: true,
+            timestamps: true,
+            since: started,
+            ..Default::default()
+        });
+        let mut stream = docker.logs(&source.container_id, options);
+
+        readers.spawn(async move {
+            let mut skipping = false;
+            while let Some(chunk) = stream.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        eprintln!("Log stream for {} ended: {}", source.service, e);
+                        return;
+                    }
+                };
+
+                for line in parse_log_output(&chunk) {
+                    // `since` has one-second resolution, so skip what the backlog already
+                    // had; an untimed line goes with the timed line before it
+                    if let Some(timestamp) = parse_timestamp(&line) {
+                        skipping = Some(timestamp) <= last_seen;
+                    }
+                    if skipping {
+                        continue;
+                    }
+                    if tx.send(tag(&source, line)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    while let Some(first) = rx.recv().await {
+        let mut batch = LogBatch {
+            lines: vec![first],
+            skipped: 0,
+        };
+        let flush = tokio::time::sleep(AGGREGATE_FLUSH);
+        tokio::pin!(flush);
+
+        // Keep reading for the whole interval; what doesn't fit in the batch is only counted
+        loop {
+            tokio::select! {
+                line = rx.recv() => match line {
+                    Some(line) if batch.lines.len() < AGGREGATE_BATCH => batch.lines.push(line),
+                    Some(_) => batch.skipped += 1,
+                    None => break,
+                },
+                _ = &mut flush => break,
+            }
+        }
+
+        merge_carrying(&mut batch.lines, &mut carried);
+        on_batch(batch);
+    }
+
+    Ok(())
+}
@@ -2,5 +2,10 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // `rykard <subcommand>` runs headless; no subcommand starts the GUI
+    if let Some(code) = rykard_lib::cli::run() {
+        std::process::exit(code);
+    }
+
     rykard_lib::run()
 }
@@ -0,0 +1,269 @@
+//! Crash detection from the Docker event stream.
+//!
+//! Every `die` is classified: killed for running out of memory, crash-looping (too
+//! many restarts within a time window) or exiting with a non-zero code. Deaths
+//! preceded by a `kill` event were asked for, by `docker stop` or a restart, so they
+//! neither alert nor count as restarts. The last log lines are fetched as soon as the
+//! container dies, before a restart policy brings it back and buries them.
+use crate::docker::{self, DockerResult};
+use crate::settings::CrashAlerts;
+use bollard::models::{EventMessage, EventMessageTypeEnum};
+use bollard::Docker;
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    CrashLoop,
+    OomKilled,
+    NonZeroExit,
+}
+
+/// A death worth telling the user about, before its logs are fetched
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Crash {
+    pub container_id: String,
+    pub container_name: String,
+    pub kind: AlertKind,
+    pub exit_code: i64,
+    /// Restarts after a crash within the configured window
+    pub restarts: usize,
+    /// Unix time of the death
+    pub time: i64,
+}
+
+impl Crash {
+    fn message(&self, window_minutes: u64) -> String {
+        match self.kind {
+            AlertKind::CrashLoop => format!(
+                "{} restarted {} times in {} minutes (last exit code {})",
+                self.container_name, self.restarts, window_minutes, self.exit_code
+            ),
+            AlertKind::OomKilled => format!(
+                "{} was killed for running out of memory",
+                self.container_name
+            ),
+            AlertKind::NonZeroExit => format!(
+                "{} exited with code {}",
+                self.container_name, self.exit_code
+            ),
+        }
+    }
+}
+
+/// Payload of the `container-alert` event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContainerAlert {
+    #[serde(flatten)]
+    pub crash: Crash,
+    pub message: String,
+    /// The container's last log lines at the time it died
+    pub last_logs: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct History {
+    /// Times of starts that followed an unrequested death
+    restarts: VecDeque<i64>,
+    /// Last stopped by a death nobody asked for
+    died: bool,
+    oom: bool,
+    killed: bool,
+    /// When the current crash loop was reported, so it's reported once per window
+    loop_reported: Option<i64>,
+}
+
+/// Per-container restart and exit bookkeeping, fed one event at a time
+#[derive(Debug)]
+pub struct CrashTracker {
+    settings: CrashAlerts,
+    containers: HashMap<String, History>,
+}
+
+impl CrashTracker {
+    pub fn new(settings: CrashAlerts) -> Self {
+        Self {
+            settings,
+            containers: HashMap::new(),
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: CrashAlerts) {
+        self.settings = settings;
+    }
+
+    /// Record a container event, returning the crash it reveals, if any
+    pub fn observe(&mut self, event: &EventMessage) -> Option<Crash> {
+        if event.typ != Some(EventMessageTypeEnum::CONTAINER) {
+            return None;
+        }
+        let actor = event.actor.as_ref()?;
+        let id = actor.id.clone()?;
+        let attributes = actor.attributes.clone().unwrap_or_default();
+        let time = event
+            .time_nano
+            .map(|nanos| nanos / 1_000_000_000)
+            .or(event.time)
+            .unwrap_or_default();
+        let window = self.settings.window_minutes as i64 * 60;
+
+        if event.action.as_deref() == Some("destroy") {
+            self.containers.remove(&id);
+            return None;
+        }
+        let history = self.containers.entry(id.clone()).or_default();
+        match event.action.as_deref()? {
+            "oom" => history.oom = true,
+            "kill" => history.killed = true,
+            "start" => {
+                if history.died {
+                    history.restarts.push_back(time);
+                }
+                history.died = false;
+            }
+            "die" => {
+                let exit_code = attributes
+                    .get("exitCode")
+                    .and_then(|code| code.parse().ok())
+                    .unwrap_or_default();
+                let (oom, killed) = (history.oom, history.killed);
+                history.died = !killed;
+                history.oom = false;
+                history.killed = false;
+                while history.restarts.front().is_some_and(|t| time - t >= window) {
+                    history.restarts.pop_front();
+                }
+
+                let restarts = history.restarts.len();
+                let in_reported_loop = history
+                    .loop_reported
+                    .is_some_and(|reported| time - reported < window);
+                let kind = if oom {
+                    AlertKind::OomKilled
+                } else if in_reported_loop {
+                    return None;
+                } else if restarts >= self.settings.restart_threshold {
+                    history.loop_reported = Some(time);
+                    AlertKind::CrashLoop
+                } else if exit_code != 0 && !killed {
+                    AlertKind::NonZeroExit
+                } else {
+                    return None;
+                };
+
+                return Some(Crash {
+                    container_id: id,
+                    container_name: attributes.get("name").cloned().unwrap_or_default(),
+                    kind,
+                    exit_code,
+                    restarts,
+                    time,
+                });
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Follow the event stream until it ends, calling `on_alert` for every crash
+pub async fn watch<F>(
+    docker: &Docker,
+    tracker: &Mutex<CrashTracker>,
+    mut on_alert: F,
+) -> DockerResult<()>
+where
+    F: FnMut(ContainerAlert),
+{
+    let events = docker::events_stream(docker);
+    tokio::pin!(events);
+
+    while let Some(event) = events.next().await {
+        let event = event?;
+        let (crash, settings) = {
+            let mut tracker = tracker.lock().unwrap();
+            (tracker.observe(&event), tracker.settings.clone())
+        };
+        let Some(crash) = crash else {
+            continue;
+        };
+
+        // A removed container has no logs left; the alert still goes out
+        let logs = docker::container_logs(docker, &crash.container_id, settings.log_lines)
+            .await
+            .unwrap_or_default();
+        on_alert(ContainerAlert {
+            message: crash.message(settings.window_minutes),
+            crash,
+            last_logs: logs.lines().map(str::to_string).collect(),
+        });
+    }
+    Ok(())
+}
+
+/// The app's crash monitor: at most one watch task, restartable with new settings
+#[derive(Clone)]
+pub struct CrashMonitor {
+    tracker: Arc<Mutex<CrashTracker>>,
+    task: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl CrashMonitor {
+    pub fn new(settings: CrashAlerts) -> Self {
+        Self {
+            tracker: Arc::new(Mutex::new(CrashTracker::new(settings))),
+            task: Arc::default(),
+        }
+    }
+
+    /// Start watching, replacing a watch that is already running
+    pub fn start<F>(&self, docker: Docker, on_alert: F)
+    where
+        F: FnMut(ContainerAlert) + Send + 'static,
+    {
+        let tracker = self.tracker.clone();
+        let task = tokio::spawn(async move {
+            if let Err(e) = watch(&docker, &tracker, on_alert).await {
+                eprintln!("Crash monitor stopped: {}", e);
+            }
+        });
+        if let Some(previous) = self.task.lock().unwrap().replace(task) {
+            previous.abort();
+        }
+    }
+
+    pub fn stop(&self) {
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+    }
+
+    /// End the watch but stay enabled, so the next reconnect resumes it
+    pub fn suspend(&self) {
+        if let Some(task) = self.task.lock().unwrap().as_ref() {
+            task.abort();
+        }
+    }
+
+    /// Started and not stopped since, even if the watch ended with its connection
+    pub fn is_enabled(&self) -> bool {
+        self.task.lock().unwrap().is_some()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.task
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+    }
+
+    /// New thresholds apply from the next event on
+    pub fn set_settings(&self, settings: CrashAlerts) {
+        self.tracker.lock().unwrap().set_settings(settings);
+    }
+}
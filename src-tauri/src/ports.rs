@@ -0,0 +1,185 @@
+//! Host port conflict detection for containers about to be created.
+//!
+//! A host port counts as taken if a running container publishes it or if binding
+//! it locally fails, which also catches ports held by non-Docker processes.
+use crate::docker::{self, DockerError, DockerResult, PortMapping};
+use bollard::Docker;
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, UdpSocket};
+
+// How far either side of a taken port to look for a free one
+const SUGGESTION_RANGE: u16 = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum PortOwner {
+    Container {
+        id: String,
+        name: String,
+    },
+    /// Something outside Docker is listening on the port
+    HostProcess,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PortConflict {
+    pub host_ip: String,
+    pub host_port: u16,
+    pub protocol: String,
+    pub owner: PortOwner,
+    /// Closest port that is free for the same address and protocol
+    pub suggested_port: Option<u16>,
+}
+
+impl PortConflict {
+    pub fn describe(&self) -> String {
+        let owner = match &self.owner {
+            PortOwner::Container { name, .. } => format!("container {}", name),
+            PortOwner::HostProcess => "another process".to_string(),
+        };
+        let suggestion = match self.suggested_port {
+            Some(port) => format!("; port {} is free", port),
+            None => String::new(),
+        };
+        format!(
+            "Host port {}/{} is already in use by {}{}",
+            self.host_port, self.protocol, owner, suggestion
+        )
+    }
+}
+
+// A host port already published by a running container
+struct Published {
+    ip: String,
+    port: u16,
+    protocol: String,
+    owner: PortOwner,
+}
+
+fn is_wildcard(ip: &str) -> bool {
+    matches!(ip, "" | "0.0.0.0" | "::")
+}
+
+fn same_address(a: &str, b: &str) -> bool {
+    is_wildcard(a) || is_wildcard(b) || a == b
+}
+
+fn protocol(mapping: &PortMapping) -> &str {
+    match mapping.protocol.as_str() {
+        "" => "tcp",
+        protocol => protocol,
+    }
+}
+
+/// The fixed host port a mapping asks for, or `None` if Docker picks one.
+///
+/// Ranges like `8000-8010` are left for the daemon to allocate.
+fn requested_port(mapping: &PortMapping) -> DockerResult<Option<u16>> {
+    let port = mapping.host_port.trim();
+    if port.is_empty() || port.contains('-') {
+        return Ok(None);
+    }
+    match port.parse::<u16>() {
+        Ok(0) => Ok(None),
+        Ok(port) => Ok(Some(port)),
+        Err(_) => Err(DockerError::OperationError(format!(
+            "Invalid host port: {}",
+            mapping.host_port
+        ))),
+    }
+}
+
+/// Whether binding `ip:port` on this machine succeeds right now
+pub fn is_port_free(ip: &str, port: u16, protocol: &str) -> bool {
+    let ip = match ip {
+        "" => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        ip => match ip.parse() {
+            Ok(ip) => ip,
+            Err(_) => return false,
+        },
+    };
+    let addr = SocketAddr::new(ip, port);
+
+    match protocol {
+        "udp" => UdpSocket::bind(addr).is_ok(),
+        _ => TcpListener::bind(addr).is_ok(),
+    }
+}
+
+async fn published_ports(docker: &Docker) -> DockerResult<Vec<Published>> {
+    let containers = docker::list_containers(docker, false).await?;
+    Ok(containers
+        .into_iter()
+        .flat_map(|c| {
+            let owner = PortOwner::Container {
+                name: c.names.first().cloned().unwrap_or_else(|| c.id.clone()),
+                id: c.id,
+            };
+            c.ports
+                .into_iter()
+                .filter(|p| p.public_port > 0)
+                .map(move |p| Published {
+                    ip: p.ip,
+                    port: p.public_port,
+                    protocol: p.type_,
+                    owner: owner.clone(),
+                })
+        })
+        .collect())
+}
+
+/// Check the host ports a container would bind, returning one entry per port that is taken
+pub async fn check_port_conflicts(
+    docker: &Docker,
+    mappings: &[PortMapping],
+) -> DockerResult<Vec<PortConflict>> {
+    let mut requested = Vec::new();
+    for mapping in mappings {
+        if let Some(port) = requested_port(mapping)? {
+            requested.push((mapping.host_ip.as_str(), port, protocol(mapping)));
+        }
+    }
+    if requested.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let published = published_ports(docker).await?;
+    let owner_of = |ip: &str, port: u16, protocol: &str| {
+        published
+            .iter()
+            .find(|p| p.port == port && p.protocol == protocol && same_address(&p.ip, ip))
+            .map(|p| p.owner.clone())
+    };
+
+    let mut conflicts = Vec::new();
+    for &(ip, port, protocol) in &requested {
+        let owner = match owner_of(ip, port, protocol) {
+            Some(owner) => owner,
+            None if !is_port_free(ip, port, protocol) => PortOwner::HostProcess,
+            None => continue,
+        };
+
+        // Don't suggest a port another mapping of the same container asks for
+        let is_free = |candidate: u16| {
+            owner_of(ip, candidate, protocol).is_none()
+                && !requested
+                    .iter()
+                    .any(|&(_, p, proto)| p == candidate && proto == protocol)
+                && is_port_free(ip, candidate, protocol)
+        };
+        let suggested_port = (1..=SUGGESTION_RANGE)
+            .flat_map(|offset| [port.checked_add(offset), port.checked_sub(offset)])
+            .flatten()
+            .filter(|&candidate| candidate >= 1024)
+            .find(|&candidate| is_free(candidate));
+
+        conflicts.push(PortConflict {
+            host_ip: ip.to_string(),
+            host_port: port,
+            protocol: protocol.to_string(),
+            owner,
+            suggested_port,
+        });
+    }
+
+    Ok(conflicts)
+}
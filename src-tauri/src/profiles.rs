@@ -0,0 +1,388 @@
+//! Saved run profiles: named container definitions with `${VAR}` placeholders.
+//!
+//! A profile is a [`CreateContainerOptions`] whose strings may reference variables.
+//! `${NAME}` must be supplied at launch or have a default in the profile, and
+//! `${NAME:-fallback}` carries its own fallback. `$$` produces a literal `$`.
+use crate::docker::{
+    CreateContainerOptions, DockerError, DockerResult, PortMapping, ResourceLimits, VolumeMapping,
+};
+use crate::store;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+pub const PROFILES_FILE: &str = "profiles.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContainerProfile {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Container definition; any string field may contain placeholders
+    pub container: CreateContainerOptions,
+    /// Default values for placeholders, used when launch doesn't provide one
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+    /// Shipped with rykard rather than saved by the user
+    #[serde(default, skip_deserializing)]
+    pub builtin: bool,
+}
+
+impl ContainerProfile {
+    /// Every variable referenced by the profile, so the UI can ask for values
+    pub fn placeholders(&self) -> DockerResult<BTreeSet<String>> {
+        let mut names = BTreeSet::new();
+        for value in self.strings() {
+            for token in tokenize(value)? {
+                if let Token::Var { name, .. } = token {
+                    names.insert(name.to_string());
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    /// Fill in the placeholders, producing options ready for `create_container`
+    pub fn resolve(
+        &self,
+        values: &HashMap<String, String>,
+    ) -> DockerResult<CreateContainerOptions> {
+        let mut missing = BTreeSet::new();
+        let mut fill = |value: &str| -> DockerResult<String> {
+            let mut out = String::new();
+            for token in tokenize(value)? {
+                match token {
+                    Token::Text(text) => out.push_str(text),
+                    Token::Var { name, fallback } => {
+                        let value = values
+                            .get(name)
+                            .or_else(|| self.variables.get(name))
+                            .map(String::as_str)
+                            .or(fallback);
+                        match value {
+                            Some(value) => out.push_str(value),
+                            None => {
+                                missing.insert(name.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(out)
+        };
+
+        let c = &self.container;
+        let mut resolved = CreateContainerOptions {
+            image: fill(&c.image)?,
+            name: fill(&c.name)?,
+            ports: Vec::with_capacity(c.ports.len()),
+            volumes: Vec::with_capacity(c.volumes.len()),
+            env_vars: Vec::with_capacity(c.env_vars.len()),
+            limits: c.limits.clone(),
+            platform: c.platform.as_deref().map(&mut fill).transpose()?,
+        };
+        for port in &c.ports {
+            resolved.ports.push(PortMapping {
+                host_ip: fill(&port.host_ip)?,
+                host_port: fill(&port.host_port)?,
+                container_port: fill(&port.container_port)?,
+                protocol: fill(&port.protocol)?,
+            });
+        }
+        for volume in &c.volumes {
+            resolved.volumes.push(VolumeMapping {
+                host_path: fill(&volume.host_path)?,
+                container_path: fill(&volume.container_path)?,
+                mode: fill(&volume.mode)?,
+            });
+        }
+        for env in &c.env_vars {
+            resolved.env_vars.push(fill(env)?);
+        }
+
+        if !missing.is_empty() {
+            return Err(DockerError::OperationError(format!(
+                "Profile {} needs values for: {}",
+                self.name,
+                missing.into_iter().collect::<Vec<_>>().join(", ")
+            )));
+        }
+        if resolved.image.trim().is_empty() {
+            return Err(DockerError::OperationError(format!(
+                "Profile {} has no image",
+                self.name
+            )));
+        }
+        Ok(resolved)
+    }
+
+    fn strings(&self) -> Vec<&str> {
+        let c = &self.container;
+        let mut strings = vec![c.image.as_str(), c.name.as_str()];
+        for port in &c.ports {
+            strings.extend([
+                port.host_ip.as_str(),
+                port.host_port.as_str(),
+                port.container_port.as_str(),
+                port.protocol.as_str(),
+            ]);
+        }
+        for volume in &c.volumes {
+            strings.extend([
+                volume.host_path.as_str(),
+                volume.container_path.as_str(),
+                volume.mode.as_str(),
+            ]);
+        }
+        strings.extend(c.env_vars.iter().map(String::as_str));
+        strings
+    }
+}
+
+enum Token<'a> {
+    Text(&'a str),
+    Var {
+        name: &'a str,
+        fallback: Option<&'a str>,
+    },
+}
+
+fn tokenize(value: &str) -> DockerResult<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find('$') {
+        tokens.push(Token::Text(&rest[..start]));
+        let after = &rest[start + 1..];
+
+        if let Some(after) = after.strip_prefix('$') {
+            tokens.push(Token::Text("$"));
+            rest = after;
+        } else if let Some(body) = after.strip_prefix('{') {
+            let end = body.find('}').ok_or_else(|| {
+                DockerError::OperationError(format!("Unclosed placeholder in {:?}", value))
+            })?;
+            let (name, fallback) = match body[..end].split_once(":-") {
+                Some((name, fallback)) => (name, Some(fallback)),
+                None => (&body[..end], None),
+            };
+            let valid = !name.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !name.starts_with(|c: char| c.is_ascii_digit());
+            if !valid {
+                return Err(DockerError::OperationError(format!(
+                    "Invalid placeholder name {:?} in {:?}",
+                    name, value
+                )));
+            }
+            tokens.push(Token::Var { name, fallback });
+            rest = &body[end + 1..];
+        } else {
+            // A lone `$` is kept as-is, e.g. in a password
+            tokens.push(Token::Text("$"));
+            rest = after;
+        }
+    }
+
+    tokens.push(Token::Text(rest));
+    Ok(tokens)
+}
+
+fn port(host_port: &str, container_port: &str) -> PortMapping {
+    PortMapping {
+        host_port: host_port.to_string(),
+        container_port: container_port.to_string(),
+        protocol: "tcp".to_string(),
+        ..Default::default()
+    }
+}
+
+fn volume(host_path: &str, container_path: &str) -> VolumeMapping {
+    VolumeMapping {
+        host_path: host_path.to_string(),
+        container_path: container_path.to_string(),
+        mode: "rw".to_string(),
+    }
+}
+
+fn variables(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Profiles shipped with rykard for the databases people start most often
+pub fn builtin_profiles() -> Vec<ContainerProfile> {
+    const MIB: i64 = 1024 * 1024;
+
+    vec![
+        ContainerProfile {
+            name: "postgres".to_string(),
+            description: "PostgreSQL with a persistent data volume".to_string(),
+            container: CreateContainerOptions {
+                image: "postgres:${VERSION}".to_string(),
+                name: "${NAME}".to_string(),
+                ports: vec![port("${PORT}", "5432")],
+                volumes: vec![volume("${NAME}-data", "/var/lib/postgresql/data")],
+                env_vars: vec![
+                    "POSTGRES_USER=${USER}".to_string(),
+                    "POSTGRES_PASSWORD=${PASSWORD}".to_string(),
+                    "POSTGRES_DB=${DATABASE}".to_string(),
+                ],
+                limits: ResourceLimits {
+                    memory: Some(1024 * MIB),
+                    cpus: None,
+                },
+                platform: None,
+            },
+            variables: variables(&[
+                ("VERSION", "16"),
+                ("NAME", "postgres"),
+                ("PORT", "5432"),
+                ("USER", "postgres"),
+                ("PASSWORD", "postgres"),
+                ("DATABASE", "postgres"),
+            ]),
+            builtin: true,
+        },
+        ContainerProfile {
+            name: "redis".to_string(),
+            description: "Redis with a persistent data volume".to_string(),
+            container: CreateContainerOptions {
+                image: "redis:${VERSION}".to_string(),
+                name: "${NAME}".to_string(),
+                ports: vec![port("${PORT}", "6379")],
+                volumes: vec![volume("${NAME}-data", "/data")],
+                env_vars: Vec::new(),
+                limits: ResourceLimits {
+                    memory: Some(256 * MIB),
+                    cpus: None,
+                },
+                platform: None,
+            },
+            variables: variables(&[("VERSION", "7"), ("NAME", "redis"), ("PORT", "6379")]),
+            builtin: true,
+        },
+        ContainerProfile {
+            name: "mysql".to_string(),
+            description: "MySQL with a persistent data volume".to_string(),
+            container: CreateContainerOptions {
+                image: "mysql:${VERSION}".to_string(),
+                name: "${NAME}".to_string(),
+                ports: vec![port("${PORT}", "3306")],
+                volumes: vec![volume("${NAME}-data", "/var/lib/mysql")],
+                env_vars: vec![
+                    "MYSQL_ROOT_PASSWORD=${ROOT_PASSWORD}".to_string(),
+                    "MYSQL_DATABASE=${DATABASE}".to_string(),
+                ],
+                limits: ResourceLimits {
+                    memory: Some(1024 * MIB),
+                    cpus: None,
+                },
+                platform: None,
+            },
+            variables: variables(&[
+                ("VERSION", "8"),
+                ("NAME", "mysql"),
+                ("PORT", "3306"),
+                ("ROOT_PASSWORD", "mysql"),
+                ("DATABASE", "app"),
+            ]),
+            builtin: true,
+        },
+    ]
+}
+
+/// User profiles persisted in the app config directory, layered over the built-ins
+pub struct ProfileStore {
+    path: PathBuf,
+    profiles: Vec<ContainerProfile>,
+    writable: bool,
+}
+
+impl ProfileStore {
+    pub fn load(path: PathBuf) -> DockerResult<Self> {
+        let profiles = store::read_json(&path)?.unwrap_or_default();
+        Ok(Self {
+            path,
+            profiles,
+            writable: true,
+        })
+    }
+
+    /// A store with no user profiles, for when [`load`](Self::load) fails. The file is
+    /// moved to `profiles.json.bak` first; if that fails, saves and deletes are refused.
+    pub fn recover(path: PathBuf) -> Self {
+        let writable = store::set_aside(&path);
+        Self {
+            path,
+            profiles: Vec::new(),
+            writable,
+        }
+    }
+
+    /// User profiles followed by the built-ins they don't shadow
+    pub fn list(&self) -> Vec<ContainerProfile> {
+        let mut profiles = self.profiles.clone();
+        profiles.extend(
+            builtin_profiles()
+                .into_iter()
+                .filter(|builtin| self.profiles.iter().all(|p| p.name != builtin.name)),
+        );
+        profiles
+    }
+
+    pub fn get(&self, name: &str) -> DockerResult<ContainerProfile> {
+        self.list()
+            .into_iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| DockerError::NotFound(format!("No such profile: {}", name)))
+    }
+
+    /// Add or replace a user profile. Saving under a built-in's name shadows it.
+    pub fn save(&mut self, mut profile: ContainerProfile) -> DockerResult<ContainerProfile> {
+        if profile.name.trim().is_empty() {
+            return Err(DockerError::OperationError(
+                "Profile name must not be empty".to_string(),
+            ));
+        }
+        // Reject malformed placeholders now rather than at launch time
+        profile.placeholders()?;
+        profile.builtin = false;
+
+        let mut profiles = self.profiles.clone();
+        match profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile.clone(),
+            None => profiles.push(profile.clone()),
+        }
+
+        self.write(&profiles)?;
+        self.profiles = profiles;
+        Ok(profile)
+    }
+
+    /// Delete a user profile; built-ins can't be deleted
+    pub fn delete(&mut self, name: &str) -> DockerResult<()> {
+        let mut profiles = self.profiles.clone();
+        let before = profiles.len();
+        profiles.retain(|p| p.name != name);
+        if profiles.len() == before {
+            return Err(DockerError::NotFound(format!(
+                "No saved profile named {}",
+                name
+            )));
+        }
+
+        self.write(&profiles)?;
+        self.profiles = profiles;
+        Ok(())
+    }
+
+    fn write(&self, profiles: &[ContainerProfile]) -> DockerResult<()> {
+        if !self.writable {
+            return Err(store::not_writable(&self.path));
+        }
+        store::write_json(&self.path, profiles)
+    }
+}
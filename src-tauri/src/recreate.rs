@@ -0,0 +1,429 @@
+//! Recreate a container with the same configuration, usually on a newer image.
+//!
+//! The old container is stopped and renamed out of the way rather than removed, so
+//! when the replacement fails to start or never turns healthy the old one can be
+//! renamed back and started again.
+//!
+//! Only the configuration the container was given carries over: whatever it merely
+//! inherited from its image, such as the command or the image's environment and
+//! labels, is left for the new image to supply, as `docker compose up` does.
+use crate::docker::{self, DockerError, DockerResult};
+use bollard::container::{
+    Config as BollardConfig, CreateContainerOptions as BollardCreateOptions, NetworkingConfig,
+    RemoveContainerOptions, RenameContainerOptions,
+};
+use bollard::models::{
+    ContainerConfig, ContainerInspectResponse, CreateImageInfo, EndpointSettings, HealthStatusEnum,
+    HostConfig, ImageConfig, MountPoint, MountPointTypeEnum,
+};
+use bollard::network::ConnectNetworkOptions;
+use bollard::Docker;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecreateOptions {
+    /// Image for the new container; defaults to the one the old container was created from
+    pub image: Option<String>,
+    /// Pull the image before touching the old container
+    pub pull: bool,
+    /// How long a container with a healthcheck gets to turn healthy
+    pub health_timeout_secs: u64,
+    /// How long a container without a healthcheck must stay up to count as started
+    pub settle_secs: u64,
+}
+
+impl Default for RecreateOptions {
+    fn default() -> Self {
+        Self {
+            image: None,
+            pull: false,
+            health_timeout_secs: 60,
+            settle_secs: 5,
+        }
+    }
+}
+
+/// Everything needed to create the replacement, taken from the old container
+struct Blueprint {
+    name: String,
+    running: bool,
+    config: BollardConfig<String>,
+    /// Networks joined after creation; only one can be given at create time
+    extra_networks: Vec<(String, EndpointSettings)>,
+}
+
+/// The part of a container's config that didn't come from its image
+fn without_image_defaults(
+    mut config: ContainerConfig,
+    image: &ImageConfig,
+    container_id: &str,
+) -> ContainerConfig {
+    fn differs<T: PartialEq>(value: Option<T>, default: &Option<T>) -> Option<T> {
+        value.filter(|v| default.as_ref() != Some(v))
+    }
+
+    config.cmd = differs(config.cmd, &image.cmd);
+    config.entrypoint = differs(config.entrypoint, &image.entrypoint);
+    config.working_dir = differs(config.working_dir, &image.working_dir);
+    config.user = differs(config.user, &image.user);
+    config.healthcheck = differs(config.healthcheck, &image.healthcheck);
+    // The daemon names the host after the container's short id unless told otherwise
+    config.hostname = config
+        .hostname
+        .filter(|hostname| !container_id.starts_with(hostname.as_str()));
+
+    let image_env = image.env.clone().unwrap_or_default();
+    config.env = config
+        .env
+        .map(|env| {
+            env.into_iter()
+                .filter(|var| !image_env.contains(var))
+                .collect::<Vec<_>>()
+        })
+        .filter(|env| !env.is_empty());
+    let image_labels = image.labels.clone().unwrap_or_default();
+    config.labels = config
+        .labels
+        .map(|labels| {
+            labels
+                .into_iter()
+                .filter(|(key, value)| image_labels.get(key) != Some(value))
+                .collect::<HashMap<_, _>>()
+        })
+        .filter(|labels| !labels.is_empty());
+    let image_ports = image.exposed_ports.clone().unwrap_or_default();
+    config.exposed_ports = config
+        .exposed_ports
+        .map(|ports| {
+            ports
+                .into_iter()
+                .filter(|(port, _)| !image_ports.contains_key(port))
+                .collect::<HashMap<_, _>>()
+        })
+        .filter(|ports| !ports.is_empty());
+    let image_volumes = image.volumes.clone().unwrap_or_default();
+    config.volumes = config
+        .volumes
+        .map(|volumes| {
+            volumes
+                .into_iter()
+                .filter(|(path, _)| !image_volumes.contains_key(path))
+                .collect::<HashMap<_, _>>()
+        })
+        .filter(|volumes| !volumes.is_empty());
+    config
+}
+
+/// `old_image` is the config of the image the container was created from, when that
+/// image is still around
+fn blueprint(
+    details: ContainerInspectResponse,
+    old_image: Option<&ImageConfig>,
+    image: &str,
+) -> Blueprint {
+    let id = details.id.unwrap_or_default();
+    let name = details
+        .name
+        .unwrap_or_default()
+        .trim_start_matches('/')
+        .to_string();
+    let running = details
+        .state
+        .as_ref()
+        .and_then(|s| s.running)
+        .unwrap_or(false);
+
+    let mut host_config = details.host_config.unwrap_or_default();
+    keep_anonymous_volumes(&mut host_config, &details.mounts.unwrap_or_default());
+
+    let mut networks: Vec<(String, EndpointSettings)> = details
+        .network_settings
+        .and_then(|n| n.networks)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(network, endpoint)| (network, endpoint_settings(endpoint, &id)))
+        .collect();
+    networks.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // `host`, `none` and `container:<id>` take no endpoint configuration at all
+    let mode = host_config.network_mode.clone().unwrap_or_default();
+    let primary = match mode.as_str() {
+        "" | "default" => "bridge".to_string(),
+        mode => mode.to_string(),
+    };
+    let mut endpoints = HashMap::new();
+    if let Some(i) = networks.iter().position(|(network, _)| *network == primary) {
+        let (network, settings) = networks.remove(i);
+        endpoints.insert(network, settings);
+    }
+    let extra_networks =
+        if matches!(primary.as_str(), "host" | "none") || primary.starts_with("container:") {
+            Vec::new()
+        } else {
+            networks
+        };
+
+    let mut config = details.config.unwrap_or_default();
+    if let Some(old_image) = old_image {
+        config = without_image_defaults(config, old_image, &id);
+    }
+    let mut config: BollardConfig<String> = config.into();
+    config.image = Some(image.to_string());
+    config.host_config = Some(host_config);
+    config.networking_config = Some(NetworkingConfig {
+        endpoints_config: endpoints,
+    });
+
+    Blueprint {
+        name,
+        running,
+        config,
+        extra_networks,
+    }
+}
+
+/// Only the settings the user chose carry over; addresses and ids are handed out again
+fn endpoint_settings(endpoint: EndpointSettings, container_id: &str) -> EndpointSettings {
+    // The daemon aliases every container by its short id on user-defined networks
+    let aliases = endpoint.aliases.map(|aliases| {
+        aliases
+            .into_iter()
+            .filter(|alias| !container_id.starts_with(alias.as_str()))
+            .collect::<Vec<_>>()
+    });
+    EndpointSettings {
+        aliases: aliases.filter(|a| !a.is_empty()),
+        links: endpoint.links,
+        ipam_config: endpoint.ipam_config,
+        driver_opts: endpoint.driver_opts,
+        ..Default::default()
+    }
+}
+
+/// Mount the old container's anonymous volumes into the new one, so their data survives
+fn keep_anonymous_volumes(host_config: &mut HostConfig, mounts: &[MountPoint]) {
+    let mut taken: Vec<String> = host_config
+        .binds
+        .iter()
+        .flatten()
+        .filter_map(|bind| bind.split(':').nth(1).map(str::to_string))
+        .collect();
+    taken.extend(
+        host_config
+            .mounts
+            .iter()
+            .flatten()
+            .filter_map(|mount| mount.target.clone()),
+    );
+
+    for mount in mounts {
+        let (Some(name), Some(destination)) = (&mount.name, &mount.destination) else {
+            continue;
+        };
+        if mount.typ != Some(MountPointTypeEnum::VOLUME) || taken.contains(destination) {
+            continue;
+        }
+        host_config
+            .binds
+            .get_or_insert_with(Vec::new)
+            .push(format!("{}:{}", name, destination));
+    }
+}
+
+/// Wait for a started container to prove it's up: healthy if it has a healthcheck,
+/// otherwise still running once `settle_secs` have passed
+async fn wait_until_up(docker: &Docker, id: &str, options: &RecreateOptions) -> DockerResult<()> {
+    let started = Instant::now();
+    let settle = Duration::from_secs(options.settle_secs);
+    let timeout = Duration::from_secs(options.health_timeout_secs).max(settle);
+
+    loop {
+        let state = docker
+            .inspect_container(id, None)
+            .await?
+            .state
+            .unwrap_or_default();
+        if !state.running.unwrap_or(false) || state.restarting.unwrap_or(false) {
+            return Err(DockerError::OperationError(format!(
+                "New container exited with code {}",
+                state.exit_code.unwrap_or_default()
+            )));
+        }
+        match state.health.and_then(|h| h.status) {
+            Some(HealthStatusEnum::HEALTHY) => return Ok(()),
+            Some(HealthStatusEnum::UNHEALTHY) => {
+                return Err(DockerError::OperationError(
+                    "New container is unhealthy".to_string(),
+                ))
+            }
+            Some(HealthStatusEnum::STARTING) => {}
+            _ if started.elapsed() >= settle => return Ok(()),
+            _ => {}
+        }
+        if started.elapsed() >= timeout {
+            return Err(DockerError::OperationError(format!(
+                "New container did not become healthy within {}s",
+                options.health_timeout_secs
+            )));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Create, connect and start the replacement, returning its id even when it fails
+/// after creation so the caller can clean it up
+async fn replace(
+    docker: &Docker,
+    blueprint: &Blueprint,
+    options: &RecreateOptions,
+) -> (Option<String>, DockerResult<()>) {
+    let created = docker
+        .create_container(
+            Some(BollardCreateOptions {
+                name: blueprint.name.clone(),
+                platform: None,
+            }),
+            blueprint.config.clone(),
+        )
+        .await;
+    let id = match created {
+        Ok(created) => created.id,
+        Err(e) => return (None, Err(e.into())),
+    };
+
+    let result = async {
+        for (network, settings) in &blueprint.extra_networks {
+            docker
+                .connect_network(
+                    network,
+                    ConnectNetworkOptions {
+                        container: id.clone(),
+                        endpoint_config: settings.clone(),
+                    },
+                )
+                .await?;
+        }
+        if blueprint.running {
+            docker::start_container(docker, &id).await?;
+            wait_until_up(docker, &id, options).await?;
+        }
+        Ok(())
+    }
+    .await;
+    (Some(id), result)
+}
+
+/// Put the old container back under its name, running again if it was before
+async fn roll_back(
+    docker: &Docker,
+    old_id: &str,
+    new_id: Option<&str>,
+    blueprint: &Blueprint,
+) -> DockerResult<()> {
+    if let Some(new_id) = new_id {
+        docker
+            .remove_container(
+                new_id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await?;
+    }
+    docker
+        .rename_container(
+            old_id,
+            RenameContainerOptions {
+                name: blueprint.name.clone(),
+            },
+        )
+        .await?;
+    if blueprint.running {
+        docker::start_container(docker, old_id).await?;
+    }
+    Ok(())
+}
+
+/// Replace a container with a new one with the same name, config, host config and
+/// networks, returning the new container's id.
+///
+/// A container that was running is started and must come up (see
+/// [`RecreateOptions::settle_secs`]) before the old one is removed; otherwise the old
+/// container is restored and the error says why.
+pub async fn recreate_container<F>(
+    docker: &Docker,
+    container_id: &str,
+    options: &RecreateOptions,
+    on_progress: F,
+) -> DockerResult<String>
+where
+    F: FnMut(&CreateImageInfo),
+{
+    let details = docker.inspect_container(container_id, None).await?;
+    let old_id = details
+        .id
+        .clone()
+        .unwrap_or_else(|| container_id.to_string());
+    let image = match &options.image {
+        Some(image) => image.clone(),
+        None => details
+            .config
+            .as_ref()
+            .and_then(|c| c.image.clone())
+            .unwrap_or_default(),
+    };
+    if options.pull {
+        docker::pull_image(docker, &image, on_progress).await?;
+    }
+    // Without the old image there's no telling what was inherited, so all of it stays
+    let old_image = match &details.image {
+        Some(old_image) => match docker.inspect_image(old_image).await {
+            Ok(inspect) => inspect.config,
+            Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => None,
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+    let blueprint = blueprint(details, old_image.as_ref(), &image);
+
+    if blueprint.running {
+        docker::stop_container(docker, &old_id).await?;
+    }
+    let parked = format!("{}-old-{}", blueprint.name, &old_id[..old_id.len().min(12)]);
+    let renamed = docker
+        .rename_container(&old_id, RenameContainerOptions { name: parked })
+        .await;
+    if let Err(e) = renamed {
+        if blueprint.running {
+            docker::start_container(docker, &old_id).await?;
+        }
+        return Err(e.into());
+    }
+
+    let (new_id, result) = replace(docker, &blueprint, options).await;
+    if let Err(cause) = result {
+        return Err(
+            match roll_back(docker, &old_id, new_id.as_deref(), &blueprint).await {
+                Ok(()) => DockerError::OperationError(format!(
+                    "{}; restored the previous container",
+                    cause
+                )),
+                Err(e) => DockerError::OperationError(format!(
+                    "{}; restoring the previous container failed: {}",
+                    cause, e
+                )),
+            },
+        );
+    }
+
+    docker.remove_container(&old_id, None).await?;
+    Ok(new_id.unwrap_or_default())
+}
@@ -0,0 +1,261 @@
+//! Image reference parsing, normalized the way the docker CLI does it.
+//!
+//! `nginx` is `docker.io/library/nginx`, the first path component is only a registry
+//! when it looks like a host (`localhost`, a dot or a port), and a colon is a tag only
+//! after the last slash: `localhost:5000/app` has no tag, `host:5000/ns/app:1.0` does.
+use crate::docker::{DockerError, DockerResult};
+use serde::Serialize;
+
+/// Registry used for names without a host
+pub const DEFAULT_REGISTRY: &str = "docker.io";
+/// Namespace of official images on Docker Hub
+const OFFICIAL_NAMESPACE: &str = "library";
+const DEFAULT_TAG: &str = "latest";
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_TAG_LENGTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImageReference {
+    /// Registry host, with port if any
+    pub registry: String,
+    /// Every path component but the last; empty for `localhost:5000/app`
+    pub namespace: String,
+    /// Last path component
+    pub repository: String,
+    /// Tag as written; see [`ImageReference::tag_or_default`]
+    pub tag: Option<String>,
+    /// `algorithm:hex` content digest, such as `sha256:...`
+    pub digest: Option<String>,
+}
+
+fn invalid(reference: &str, reason: &str) -> DockerError {
+    DockerError::OperationError(format!(
+        "Invalid image reference '{}': {}",
+        reference, reason
+    ))
+}
+
+/// `[a-z0-9]+` separated by `.`, `_`, `__` or runs of `-`
+fn is_path_component(component: &str) -> bool {
+    let is_separator = |s: &str| matches!(s, "." | "_" | "__") || s.bytes().all(|b| b == b'-');
+    let mut parts = component.split(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit());
+    let (Some(first), Some(last)) = (parts.next(), parts.next_back()) else {
+        return false;
+    };
+    first.is_empty() && last.is_empty() && parts.all(is_separator)
+}
+
+/// Hostname labels, optionally with a port, or a bracketed IPv6 address
+fn is_registry(registry: &str) -> bool {
+    let (host, port) = match registry.rsplit_once(':') {
+        Some((host, port)) if !host.ends_with(':') => (host, Some(port)),
+        _ => (registry, None),
+    };
+    if port.is_some_and(|p| p.is_empty() || !p.bytes().all(|b| b.is_ascii_digit())) {
+        return false;
+    }
+    if let Some(ip) = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        return !ip.is_empty() && ip.bytes().all(|b| b.is_ascii_hexdigit() || b == b':');
+    }
+    !host.is_empty()
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+fn is_tag(tag: &str) -> bool {
+    let bytes = tag.as_bytes();
+    !bytes.is_empty()
+        && bytes.len() <= MAX_TAG_LENGTH
+        && (bytes[0].is_ascii_alphanumeric() || bytes[0] == b'_')
+        && bytes
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b'-'))
+}
+
+/// `algorithm:encoded`; sha256 and sha512 digests must be lowercase hex of the right length
+fn is_digest(digest: &str) -> bool {
+    let Some((algorithm, encoded)) = digest.split_once(':') else {
+        return false;
+    };
+    let is_hex = |len: usize| {
+        encoded.len() == len
+            && encoded
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    };
+    match algorithm {
+        "sha256" => is_hex(64),
+        "sha512" => is_hex(128),
+        _ => {
+            algorithm.split(['+', '.', '_', '-']).all(|part| {
+                !part.is_empty()
+                    && part
+                        .bytes()
+                        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+            }) && encoded.len() >= 32
+                && encoded
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'=' | b'_' | b'-'))
+        }
+    }
+}
+
+impl ImageReference {
+    /// Parse `[registry/][namespace/]repository[:tag][@digest]`
+    pub fn parse(reference: &str) -> DockerResult<Self> {
+        if reference.is_empty() {
+            return Err(invalid(reference, "reference is empty"));
+        }
+
+        let (name, digest) = match reference.split_once('@') {
+            Some((name, digest)) => {
+                if !is_digest(digest) {
+                    return Err(invalid(reference, "invalid digest"));
+                }
+                (name, Some(digest.to_string()))
+            }
+            None => (reference, None),
+        };
+
+        let last_segment = name.rfind('/').map(|i| i + 1).unwrap_or(0);
+        let (name, tag) = match name[last_segment..].find(':') {
+            Some(i) => {
+                let tag = &name[last_segment + i + 1..];
+                if !is_tag(tag) {
+                    return Err(invalid(reference, "invalid tag"));
+                }
+                (&name[..last_segment + i], Some(tag.to_string()))
+            }
+            None => (name, None),
+        };
+        if name.len() > MAX_NAME_LENGTH {
+            return Err(invalid(reference, "repository name is too long"));
+        }
+
+        let (registry, path) = match name.split_once('/') {
+            Some((first, rest))
+                if first.contains(['.', ':'])
+                    || first == "localhost"
+                    || first.bytes().any(|b| b.is_ascii_uppercase()) =>
+            {
+                if !is_registry(first) {
+                    return Err(invalid(reference, "invalid registry"));
+                }
+                (first, rest)
+            }
+            _ => (DEFAULT_REGISTRY, name),
+        };
+        // Hub's old index host names the same registry
+        let registry = match registry {
+            "index.docker.io" | "registry-1.docker.io" => DEFAULT_REGISTRY,
+            registry => registry,
+        };
+
+        let mut components: Vec<&str> = path.split('/').collect();
+        if let Some(bad) = components.iter().find(|c| !is_path_component(c)) {
+            let reason = if bad.bytes().any(|b| b.is_ascii_uppercase()) {
+                "repository name must be lowercase"
+            } else {
+                "invalid repository name"
+            };
+            return Err(invalid(reference, reason));
+        }
+        if registry == DEFAULT_REGISTRY && components.len() == 1 {
+            components.insert(0, OFFICIAL_NAMESPACE);
+        }
+        let repository = components.pop().unwrap_or_default().to_string();
+
+        Ok(Self {
+            registry: registry.to_string(),
+            namespace: components.join("/"),
+            repository,
+            tag,
+            digest,
+        })
+    }
+
+    /// `namespace/repository`, the path within the registry
+    pub fn path(&self) -> String {
+        if self.namespace.is_empty() {
+            self.repository.clone()
+        } else {
+            format!("{}/{}", self.namespace, self.repository)
+        }
+    }
+
+    /// Fully qualified repository, e.g. `docker.io/library/nginx`
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.registry, self.path())
+    }
+
+    /// Repository as the daemon shows it in `RepoTags` and `RepoDigests`: Hub's
+    /// registry and `library/` namespace are left out
+    pub fn familiar_name(&self) -> String {
+        if self.registry != DEFAULT_REGISTRY {
+            return self.name();
+        }
+        match self.namespace.as_str() {
+            OFFICIAL_NAMESPACE => self.repository.clone(),
+            _ => self.path(),
+        }
+    }
+
+    /// The tag, or `latest` when neither a tag nor a digest was given
+    pub fn tag_or_default(&self) -> Option<&str> {
+        match (&self.tag, &self.digest) {
+            (Some(tag), _) => Some(tag),
+            (None, None) => Some(DEFAULT_TAG),
+            (None, Some(_)) => None,
+        }
+    }
+
+    /// What to pull: the digest when pinned, so a moved tag can't change the image
+    pub fn pull_tag(&self) -> &str {
+        match &self.digest {
+            Some(digest) => digest,
+            None => self.tag_or_default().unwrap_or(DEFAULT_TAG),
+        }
+    }
+
+    /// Fully qualified reference with the default tag filled in
+    pub fn canonical(&self) -> String {
+        let mut out = self.name();
+        if let Some(tag) = self.tag_or_default() {
+            out.push(':');
+            out.push_str(tag);
+        }
+        if let Some(digest) = &self.digest {
+            out.push('@');
+            out.push_str(digest);
+        }
+        out
+    }
+}
+
+/// The reference in its short form, as written by users: `nginx:1.27`
+impl std::fmt::Display for ImageReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.familiar_name())?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for ImageReference {
+    type Err = DockerError;
+
+    fn from_str(reference: &str) -> DockerResult<Self> {
+        Self::parse(reference)
+    }
+}
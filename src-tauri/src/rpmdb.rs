@@ -0,0 +1,418 @@
+//! Installed package list from an RPM database (`rpmdb.sqlite`).
+//!
+//! Only what the SBOM needs: a read-only walk of the `Packages` table b-tree and a
+//! decoder for the header blobs it holds. rpm keeps the database in WAL mode, so
+//! transactions not yet checkpointed live in `rpmdb.sqlite-wal` and are read from
+//! there. Fedora 33+, RHEL 9 and openSUSE use the SQLite backend; older BerkeleyDB
+//! `Packages` files aren't supported.
+use crate::docker::{DockerError, DockerResult};
+use std::collections::HashMap;
+
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+// Write-ahead log magic; the last bit gives the byte order of the checksums
+const WAL_MAGIC_LE: u32 = 0x377f0682;
+const WAL_MAGIC_BE: u32 = 0x377f0683;
+const WAL_HEADER: usize = 32;
+const WAL_FRAME_HEADER: usize = 24;
+
+// RPM header tags
+const TAG_NAME: u32 = 1000;
+const TAG_VERSION: u32 = 1001;
+const TAG_RELEASE: u32 = 1002;
+const TAG_EPOCH: u32 = 1003;
+const TAG_LICENSE: u32 = 1014;
+const TAG_ARCH: u32 = 1022;
+const TAG_SOURCERPM: u32 = 1044;
+
+// RPM header data types
+const TYPE_INT32: u32 = 4;
+const TYPE_STRING: u32 = 6;
+const TYPE_STRING_ARRAY: u32 = 8;
+const TYPE_I18NSTRING: u32 = 9;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RpmPackage {
+    pub name: String,
+    pub version: String,
+    pub release: String,
+    pub epoch: Option<u32>,
+    pub arch: String,
+    pub license: String,
+    pub source_rpm: String,
+}
+
+impl RpmPackage {
+    /// `[epoch:]version-release`, the form RPM compares versions in
+    pub fn evr(&self) -> String {
+        let epoch = self.epoch.map(|e| format!("{}:", e)).unwrap_or_default();
+        if self.release.is_empty() {
+            format!("{}{}", epoch, self.version)
+        } else {
+            format!("{}{}-{}", epoch, self.version, self.release)
+        }
+    }
+}
+
+fn corrupt(what: &str) -> DockerError {
+    DockerError::OperationError(format!("Unreadable RPM database: {}", what))
+}
+
+/// Every package in an `rpmdb.sqlite` file, including the committed transactions in
+/// its write-ahead log when there is one
+pub fn read_rpmdb(db: &[u8], wal: Option<&[u8]>) -> DockerResult<Vec<RpmPackage>> {
+    let mut sqlite = Sqlite::open(db)?;
+    if let Some(wal) = wal {
+        sqlite.apply_wal(wal)?;
+    }
+    let root = sqlite
+        .table_root("Packages")?
+        .ok_or_else(|| corrupt("no Packages table"))?;
+
+    let mut packages = Vec::new();
+    sqlite.walk_table(root, 0, &mut |record| {
+        // (hnum INTEGER PRIMARY KEY, blob BLOB); hnum is stored as NULL
+        if let Some(Value::Blob(blob)) = record.get(1) {
+            if let Some(package) = parse_header(blob) {
+                packages.push(package);
+            }
+        }
+    })?;
+    Ok(packages)
+}
+
+/// Decode an RPM header blob as stored in the database, i.e. without the 8-byte
+/// magic that precedes headers in `.rpm` files
+pub fn parse_header(blob: &[u8]) -> Option<RpmPackage> {
+    let count = be32(blob, 0)? as usize;
+    let data_len = be32(blob, 4)? as usize;
+    let data_start = 8 + count.checked_mul(16)?;
+    let data = blob.get(data_start..data_start.checked_add(data_len)?)?;
+
+    let mut package = RpmPackage::default();
+    for i in 0..count {
+        let entry = 8 + i * 16;
+        let (tag, kind, offset) = (
+            be32(blob, entry)?,
+            be32(blob, entry + 4)?,
+            be32(blob, entry + 8)? as usize,
+        );
+        let string = || match kind {
+            TYPE_STRING | TYPE_STRING_ARRAY | TYPE_I18NSTRING => {
+                let bytes = data.get(offset..)?;
+                let end = bytes.iter().position(|&b| b == 0)?;
+                Some(String::from_utf8_lossy(&bytes[..end]).to_string())
+            }
+            _ => None,
+        };
+
+        match tag {
+            TAG_NAME => package.name = string()?,
+            TAG_VERSION => package.version = string()?,
+            TAG_RELEASE => package.release = string()?,
+            TAG_ARCH => package.arch = string().unwrap_or_default(),
+            TAG_LICENSE => package.license = string().unwrap_or_default(),
+            TAG_SOURCERPM => package.source_rpm = string().unwrap_or_default(),
+            TAG_EPOCH if kind == TYPE_INT32 => package.epoch = be32(data, offset),
+            _ => {}
+        }
+    }
+
+    // gpg-pubkey entries are keys, not packages
+    if package.name.is_empty() || package.name == "gpg-pubkey" {
+        return None;
+    }
+    Some(package)
+}
+
+fn be32(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn be16(bytes: &[u8], at: usize) -> Option<usize> {
+    let b = bytes.get(at..at + 2)?;
+    Some(u16::from_be_bytes([b[0], b[1]]) as usize)
+}
+
+/// SQLite varint: up to 9 bytes, big-endian 7-bit groups, the 9th byte uses all 8 bits
+fn varint(bytes: &[u8], at: usize) -> Option<(u64, usize)> {
+    let mut value: u64 = 0;
+    for i in 0..9 {
+        let b = *bytes.get(at + i)?;
+        if i == 8 {
+            return Some(((value << 8) | b as u64, 9));
+        }
+        value = (value << 7) | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+#[derive(Debug)]
+enum Value {
+    Null,
+    Integer(i64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+struct Sqlite<'a> {
+    db: &'a [u8],
+    page_size: usize,
+    usable: usize,
+    /// Pages committed to the write-ahead log, which win over the database file
+    wal_pages: HashMap<u32, &'a [u8]>,
+    /// Size in pages after the last commit in the log, if it had any
+    wal_size: Option<u32>,
+}
+
+/// SQLite's WAL checksum over `data`, continuing from `sum`
+fn wal_checksum(big_endian: bool, sum: (u32, u32), data: &[u8]) -> (u32, u32) {
+    let word = |b: &[u8]| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    };
+    let (mut s0, mut s1) = sum;
+    for chunk in data.chunks_exact(8) {
+        s0 = s0.wrapping_add(word(&chunk[..4])).wrapping_add(s1);
+        s1 = s1.wrapping_add(word(&chunk[4..])).wrapping_add(s0);
+    }
+    (s0, s1)
+}
+
+// Guards against cycles in a corrupt file
+const MAX_DEPTH: usize = 32;
+
+impl<'a> Sqlite<'a> {
+    fn open(db: &'a [u8]) -> DockerResult<Self> {
+        if db.len() < 100 || &db[..16] != SQLITE_MAGIC {
+            return Err(corrupt("not a SQLite database"));
+        }
+        let page_size = match be16(db, 16).unwrap_or(0) {
+            1 => 65536,
+            size if size >= 512 && size.is_power_of_two() => size,
+            _ => return Err(corrupt("bad page size")),
+        };
+        let usable = page_size - db[20] as usize;
+        Ok(Self {
+            db,
+            page_size,
+            usable,
+            wal_pages: HashMap::new(),
+            wal_size: None,
+        })
+    }
+
+    /// Overlay the pages of every committed transaction in a write-ahead log. The log
+    /// ends at the first frame with stale salts or a bad checksum, as it does for
+    /// SQLite, and frames after the last commit are ignored.
+    fn apply_wal(&mut self, wal: &'a [u8]) -> DockerResult<()> {
+        // A checkpoint leaves an empty log behind
+        if wal.len() < WAL_HEADER {
+            return Ok(());
+        }
+        let big_endian = match be32(wal, 0) {
+            Some(WAL_MAGIC_BE) => true,
+            Some(WAL_MAGIC_LE) => false,
+            _ => return Err(corrupt("not a write-ahead log")),
+        };
+        if be32(wal, 8) != Some(self.page_size as u32) {
+            return Err(corrupt("write-ahead log page size differs"));
+        }
+        let mut sum = wal_checksum(big_endian, (0, 0), &wal[..24]);
+        if (be32(wal, 24), be32(wal, 28)) != (Some(sum.0), Some(sum.1)) {
+            // SQLite ignores a log with a bad header too
+            return Ok(());
+        }
+
+        let salts = &wal[16..24];
+        let mut pending = HashMap::new();
+        let mut at = WAL_HEADER;
+        while let Some(frame) = wal.get(at..at + WAL_FRAME_HEADER + self.page_size) {
+            let field = |i: usize| be32(frame, i * 4).unwrap_or_default();
+            if &frame[8..16] != salts {
+                break;
+            }
+            sum = wal_checksum(big_endian, sum, &frame[..8]);
+            sum = wal_checksum(big_endian, sum, &frame[WAL_FRAME_HEADER..]);
+            if (field(4), field(5)) != sum {
+                break;
+            }
+            pending.insert(field(0), &frame[WAL_FRAME_HEADER..]);
+            // Commit frames record the database size in pages
+            if field(1) != 0 {
+                self.wal_pages.extend(pending.drain());
+                self.wal_size = Some(field(1));
+            }
+            at += frame.len();
+        }
+        Ok(())
+    }
+
+    fn page(&self, number: u32) -> DockerResult<&'a [u8]> {
+        if self.wal_size.is_some_and(|size| number > size) {
+            return Err(corrupt("page out of range"));
+        }
+        if let Some(page) = self.wal_pages.get(&number) {
+            return Ok(page);
+        }
+        let start = (number as usize)
+            .checked_sub(1)
+            .ok_or_else(|| corrupt("page 0"))?
+            * self.page_size;
+        self.db
+            .get(start..start + self.page_size)
+            .ok_or_else(|| corrupt("page out of range"))
+    }
+
+    /// Root page of a table, from the schema table on page 1
+    fn table_root(&self, name: &str) -> DockerResult<Option<u32>> {
+        let mut root = None;
+        self.walk_table(1, 0, &mut |record| {
+            // (type, name, tbl_name, rootpage, sql)
+            if let (Some(Value::Text(kind)), Some(Value::Text(table)), Some(Value::Integer(page))) =
+                (record.first(), record.get(1), record.get(3))
+            {
+                if kind == "table" && table == name {
+                    root = Some(*page as u32);
+                }
+            }
+        })?;
+        Ok(root)
+    }
+
+    fn walk_table(
+        &self,
+        number: u32,
+        depth: usize,
+        visit: &mut dyn FnMut(&[Value]),
+    ) -> DockerResult<()> {
+        if depth > MAX_DEPTH {
+            return Err(corrupt("b-tree too deep"));
+        }
+        let page = self.page(number)?;
+        // Page 1 starts with the 100-byte database header
+        let header = if number == 1 { 100 } else { 0 };
+        let kind = page[header];
+        let cells = be16(page, header + 3).ok_or_else(|| corrupt("short page"))?;
+
+        match kind {
+            // Interior table page: child pointers plus a right-most child
+            0x05 => {
+                for i in 0..cells {
+                    let cell = be16(page, header + 12 + i * 2).ok_or_else(|| corrupt("cell"))?;
+                    let child = be32(page, cell).ok_or_else(|| corrupt("cell"))?;
+                    self.walk_table(child, depth + 1, visit)?;
+                }
+                let right = be32(page, header + 8).ok_or_else(|| corrupt("short page"))?;
+                self.walk_table(right, depth + 1, visit)
+            }
+            // Leaf table page: records
+            0x0d => {
+                for i in 0..cells {
+                    let cell = be16(page, header + 8 + i * 2).ok_or_else(|| corrupt("cell"))?;
+                    let payload = self.payload(page, cell)?;
+                    visit(&record(&payload).ok_or_else(|| corrupt("bad record"))?);
+                }
+                Ok(())
+            }
+            _ => Err(corrupt("unexpected page type")),
+        }
+    }
+
+    /// A leaf cell's payload, following overflow pages when it doesn't fit the page
+    fn payload(&self, page: &'a [u8], cell: usize) -> DockerResult<Vec<u8>> {
+        let (size, n) = varint(page, cell).ok_or_else(|| corrupt("cell"))?;
+        let (_rowid, m) = varint(page, cell + n).ok_or_else(|| corrupt("cell"))?;
+        let start = cell + n + m;
+        let size = size as usize;
+
+        let u = self.usable;
+        let max_local = u - 35;
+        let local = if size <= max_local {
+            size
+        } else {
+            let min_local = (u - 12) * 32 / 255 - 23;
+            let k = min_local + (size - min_local) % (u - 4);
+            if k <= max_local {
+                k
+            } else {
+                min_local
+            }
+        };
+
+        let mut payload = page
+            .get(start..start + local)
+            .ok_or_else(|| corrupt("cell overflows page"))?
+            .to_vec();
+        let mut next = if local < size {
+            be32(page, start + local).ok_or_else(|| corrupt("overflow pointer"))?
+        } else {
+            0
+        };
+        while next != 0 && payload.len() < size {
+            let overflow = self.page(next)?;
+            let take = (size - payload.len()).min(u - 4);
+            payload.extend_from_slice(&overflow[4..4 + take]);
+            next = be32(overflow, 0).ok_or_else(|| corrupt("overflow page"))?;
+        }
+        if payload.len() < size {
+            return Err(corrupt("truncated overflow chain"));
+        }
+        Ok(payload)
+    }
+}
+
+fn record(payload: &[u8]) -> Option<Vec<Value>> {
+    let (header_len, mut at) = varint(payload, 0)?;
+    let mut types = Vec::new();
+    while at < header_len as usize {
+        let (serial, n) = varint(payload, at)?;
+        types.push(serial);
+        at += n;
+    }
+
+    let mut body = header_len as usize;
+    let mut values = Vec::with_capacity(types.len());
+    for serial in types {
+        let int = |len: usize| -> Option<i64> {
+            let bytes = payload.get(body..body + len)?;
+            // Sign-extend from the first byte
+            let mut value = if bytes[0] & 0x80 != 0 { -1i64 } else { 0 };
+            for &b in bytes {
+                value = (value << 8) | b as i64;
+            }
+            Some(value)
+        };
+        let (value, len) = match serial {
+            0 => (Value::Null, 0),
+            1..=4 => (Value::Integer(int(serial as usize)?), serial as usize),
+            5 => (Value::Integer(int(6)?), 6),
+            6 => (Value::Integer(int(8)?), 8),
+            7 => (Value::Null, 8),
+            8 => (Value::Integer(0), 0),
+            9 => (Value::Integer(1), 0),
+            n if n >= 12 && n % 2 == 0 => {
+                let len = (n as usize - 12) / 2;
+                let bytes = payload.get(body..body + len)?.to_vec();
+                (Value::Blob(bytes), len)
+            }
+            n if n >= 13 => {
+                let len = (n as usize - 13) / 2;
+                let text = String::from_utf8_lossy(payload.get(body..body + len)?).to_string();
+                (Value::Text(text), len)
+            }
+            _ => return None,
+        };
+        values.push(value);
+        body += len;
+    }
+    Some(values)
+}
@@ -0,0 +1,841 @@
+//! Software bill of materials for an image, built offline from its package databases.
+//!
+//! The image's filesystem is read by creating a throwaway container from it (never
+//! started, networking disabled) and streaming its export. Only package metadata is
+//! kept in memory: dpkg and apk databases, `rpmdb.sqlite` and its write-ahead log,
+//! `node_modules` manifests,
+//! `Cargo.lock` files and Python `dist-info`/`egg-info` metadata.
+use crate::archive::{EntryKind, TarStream};
+use crate::docker::{self, DockerError, DockerResult, ImageInfo};
+use crate::rpmdb;
+use crate::vulndb::{VulnerabilityDb, VulnerabilityMatch};
+use bollard::container::{Config, RemoveContainerOptions};
+use bollard::Docker;
+use chrono::{SecondsFormat, TimeZone, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Label on the temporary containers used to read an image's filesystem
+pub const SBOM_LABEL: &str = "rykard.sbom";
+
+// Package databases larger than this are skipped rather than held in memory
+const MAX_METADATA_SIZE: usize = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SbomFormat {
+    CycloneDx,
+    Spdx,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageKind {
+    Deb,
+    Apk,
+    Rpm,
+    Npm,
+    Cargo,
+    Pypi,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    pub kind: PackageKind,
+    /// Source package a distro package was built from, when it differs
+    pub source: Option<String>,
+    pub arch: Option<String>,
+    pub license: Option<String>,
+    pub purl: String,
+    /// Where in the image the package was found
+    pub location: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Distro {
+    /// `ID` from os-release, e.g. `debian` or `alpine`
+    pub id: String,
+    pub version_id: String,
+    pub pretty_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub purl: String,
+    #[serde(flatten)]
+    pub vulnerability: VulnerabilityMatch,
+}
+
+/// Everything found in one image
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageSbom {
+    pub image: ImageInfo,
+    pub distro: Option<Distro>,
+    pub packages: Vec<Package>,
+    /// Empty unless a vulnerability database has been imported
+    pub findings: Vec<Finding>,
+    /// Metadata that was found but couldn't be read
+    pub warnings: Vec<String>,
+    /// Unix timestamp of the scan
+    pub generated: i64,
+}
+
+/// A scan along with the document rendered from it
+#[derive(Debug, Clone, Serialize)]
+pub struct SbomReport {
+    pub sbom: ImageSbom,
+    pub document: Value,
+}
+
+/// Whether a file in an image holds package metadata [`Inventory`] understands
+pub fn is_package_metadata(path: &str) -> bool {
+    let segments: Vec<&str> = path.split('/').collect();
+    let n = segments.len();
+    let file = segments[n - 1];
+    let parent = if n >= 2 { segments[n - 2] } else { "" };
+
+    match path {
+        "etc/os-release" | "usr/lib/os-release" => return true,
+        "var/lib/dpkg/status" | "lib/apk/db/installed" => return true,
+        "var/lib/rpm/rpmdb.sqlite" | "usr/lib/sysimage/rpm/rpmdb.sqlite" => return true,
+        "var/lib/rpm/rpmdb.sqlite-wal" | "usr/lib/sysimage/rpm/rpmdb.sqlite-wal" => return true,
+        _ => {}
+    }
+
+    // Distroless images keep one dpkg status file per package
+    if path.starts_with("var/lib/dpkg/status.d/") && !file.ends_with(".md5sums") {
+        return n == 5;
+    }
+    if file == "package.json" && n >= 3 {
+        let scoped =
+            n >= 4 && segments[n - 4] == "node_modules" && segments[n - 3].starts_with('@');
+        return segments[n - 3] == "node_modules" || scoped;
+    }
+    if file == "Cargo.lock" {
+        return true;
+    }
+    let in_site_packages =
+        |i: usize| i < n && (segments[i] == "site-packages" || segments[i] == "dist-packages");
+    if file == "METADATA" && parent.ends_with(".dist-info") && n >= 3 {
+        return in_site_packages(n - 3);
+    }
+    if file == "PKG-INFO" && parent.ends_with(".egg-info") && n >= 3 {
+        return in_site_packages(n - 3);
+    }
+    // Single-file egg-info from distutils installs
+    file.ends_with(".egg-info") && n >= 2 && in_site_packages(n - 2)
+}
+
+/// An RPM database and its write-ahead log, which can come out of the image in
+/// either order
+#[derive(Debug, Default)]
+struct RpmFiles {
+    db: Option<Vec<u8>>,
+    wal: Option<Vec<u8>>,
+}
+
+/// Collects packages from metadata files as they come out of an image
+#[derive(Debug, Default)]
+pub struct Inventory {
+    os_release: HashMap<String, String>,
+    packages: Vec<Package>,
+    /// Keyed by the path of the database file
+    rpm_databases: BTreeMap<String, RpmFiles>,
+    warnings: Vec<String>,
+}
+
+impl Inventory {
+    /// Parse one file accepted by [`is_package_metadata`]
+    pub fn add_file(&mut self, path: &str, data: &[u8]) {
+        let text = || String::from_utf8_lossy(data);
+        let file = path.rsplit('/').next().unwrap_or(path);
+
+        if path == "etc/os-release" || path == "usr/lib/os-release" {
+            // etc/os-release wins; it's usually a symlink to the other one anyway
+            if self.os_release.is_empty() || path == "etc/os-release" {
+                self.os_release = parse_os_release(&text());
+            }
+        } else if path == "var/lib/dpkg/status" || path.starts_with("var/lib/dpkg/status.d/") {
+            self.packages.extend(parse_dpkg_status(&text(), path));
+        } else if path == "lib/apk/db/installed" {
+            self.packages.extend(parse_apk_installed(&text(), path));
+        } else if file == "rpmdb.sqlite" {
+            let files = self.rpm_databases.entry(path.to_string()).or_default();
+            files.db = Some(data.to_vec());
+        } else if let Some(db) = path
+            .strip_suffix("-wal")
+            .filter(|_| file == "rpmdb.sqlite-wal")
+        {
+            let files = self.rpm_databases.entry(db.to_string()).or_default();
+            files.wal = Some(data.to_vec());
+        } else if file == "package.json" {
+            match parse_package_json(data, path) {
+                Ok(package) => self.packages.extend(package),
+                Err(e) => self.warnings.push(format!("{}: {}", path, e)),
+            }
+        } else if file == "Cargo.lock" {
+            self.packages.extend(parse_cargo_lock(&text(), path));
+        } else {
+            self.packages.extend(parse_python_metadata(&text(), path));
+        }
+    }
+
+    /// The distro plus every package, deduplicated, sorted and with package URLs filled in
+    pub fn finish(mut self) -> (Option<Distro>, Vec<Package>, Vec<String>) {
+        // A log without its database has nothing to apply to
+        for (path, files) in std::mem::take(&mut self.rpm_databases) {
+            let Some(db) = files.db else {
+                continue;
+            };
+            match rpmdb::read_rpmdb(&db, files.wal.as_deref()) {
+                Ok(packages) => self.packages.extend(packages.into_iter().map(|p| Package {
+                    version: p.evr(),
+                    source: source_rpm_name(&p.source_rpm).filter(|s| *s != p.name),
+                    arch: Some(p.arch).filter(|a| !a.is_empty()),
+                    license: Some(p.license).filter(|l| !l.is_empty()),
+                    name: p.name,
+                    kind: PackageKind::Rpm,
+                    purl: String::new(),
+                    location: path.clone(),
+                })),
+                Err(e) => self.warnings.push(format!("{}: {}", path, e)),
+            }
+        }
+
+        let distro = self.os_release.get("ID").map(|id| Distro {
+            id: id.clone(),
+            version_id: self
+                .os_release
+                .get("VERSION_ID")
+                .cloned()
+                .unwrap_or_default(),
+            pretty_name: self
+                .os_release
+                .get("PRETTY_NAME")
+                .cloned()
+                .unwrap_or_else(|| id.clone()),
+        });
+
+        for package in &mut self.packages {
+            package.purl = purl(package, distro.as_ref());
+        }
+        self.packages.sort_by(|a, b| {
+            (a.kind as u8, &a.name, &a.version, &a.location).cmp(&(
+                b.kind as u8,
+                &b.name,
+                &b.version,
+                &b.location,
+            ))
+        });
+        self.packages
+            .dedup_by(|a, b| a.purl == b.purl && a.location == b.location);
+
+        (distro, self.packages, self.warnings)
+    }
+}
+
+/// OSV ecosystem a package's advisories are filed under. Distro packages get the
+/// release from os-release (`Debian:12`, `Alpine:v3.20`); without a `VERSION_ID`, as
+/// on Debian testing, only the distro is known and advisories for any release match.
+pub fn ecosystem(kind: PackageKind, distro: Option<&Distro>) -> String {
+    let id = distro.map(|d| d.id.as_str()).unwrap_or_default();
+    let version = distro.map(|d| d.version_id.as_str()).unwrap_or_default();
+    let mut parts = version.split('.');
+    let major = parts.next().unwrap_or_default();
+    let minor = parts.next();
+
+    let (name, release) = match kind {
+        PackageKind::Deb if id == "ubuntu" => ("Ubuntu", version.to_string()),
+        PackageKind::Deb => ("Debian", major.to_string()),
+        PackageKind::Apk => match minor {
+            Some(minor) => ("Alpine", format!("v{}.{}", major, minor)),
+            None => ("Alpine", String::new()),
+        },
+        PackageKind::Rpm => match id {
+            "rhel" | "centos" => ("Red Hat", format!("enterprise_linux:{}", major)),
+            "rocky" => ("Rocky Linux", major.to_string()),
+            "almalinux" => ("AlmaLinux", major.to_string()),
+            "opensuse-leap" => ("openSUSE", format!("Leap {}", version)),
+            "opensuse-tumbleweed" => ("openSUSE", "Tumbleweed".to_string()),
+            "sles" => ("SUSE", String::new()),
+            "mariner" | "azurelinux" => ("Mariner", version.to_string()),
+            _ => return id.to_string(),
+        },
+        PackageKind::Npm => return "npm".to_string(),
+        PackageKind::Cargo => return "crates.io".to_string(),
+        PackageKind::Pypi => return "PyPI".to_string(),
+    };
+    // No release to go on; `enterprise_linux:` alone would match nothing
+    if version.is_empty() || release.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}:{}", name, release)
+    }
+}
+
+fn parse_os_release(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim().trim_matches('"').trim_matches('\'');
+            (key.trim().to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// RFC 822 style stanzas separated by blank lines, with indented continuation lines
+fn stanzas(text: &str) -> Vec<HashMap<String, String>> {
+    let mut stanzas = Vec::new();
+    let mut current: HashMap<String, String> = HashMap::new();
+    let mut last_key = None;
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                stanzas.push(std::mem::take(&mut current));
+            }
+            last_key = None;
+        } else if line.starts_with([' ', '\t']) {
+            if let Some(value) = last_key.as_ref().and_then(|k| current.get_mut(k)) {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            current.insert(key.to_string(), value.trim().to_string());
+            last_key = Some(key.to_string());
+        }
+    }
+    if !current.is_empty() {
+        stanzas.push(current);
+    }
+    stanzas
+}
+
+fn parse_dpkg_status(text: &str, location: &str) -> Vec<Package> {
+    stanzas(text)
+        .into_iter()
+        .filter(|s| {
+            // Removed packages linger as `deinstall ok config-files`
+            s.get("Status")
+                .map(|status| status.ends_with(" installed"))
+                .unwrap_or(true)
+        })
+        .filter_map(|mut s| {
+            let name = s.remove("Package")?;
+            // `Source: openssl (3.0.11-1)`
+            let source = s
+                .get("Source")
+                .and_then(|source| source.split_whitespace().next())
+                .map(str::to_string)
+                .filter(|source| *source != name);
+            Some(Package {
+                version: s.remove("Version")?,
+                arch: s.remove("Architecture"),
+                source,
+                license: None,
+                name,
+                kind: PackageKind::Deb,
+                purl: String::new(),
+                location: location.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn parse_apk_installed(text: &str, location: &str) -> Vec<Package> {
+    let mut packages = Vec::new();
+
+    for stanza in text.split("\n\n") {
+        let field = |key: char| {
+            stanza
+                .lines()
+                .find(|line| line.starts_with(key) && line[1..].starts_with(':'))
+                .map(|line| line[2..].to_string())
+        };
+        let (Some(name), Some(version)) = (field('P'), field('V')) else {
+            continue;
+        };
+        packages.push(Package {
+            source: field('o').filter(|origin| *origin != name),
+            arch: field('A'),
+            license: field('L'),
+            name,
+            version,
+            kind: PackageKind::Apk,
+            purl: String::new(),
+            location: location.to_string(),
+        });
+    }
+    packages
+}
+
+// `openssl-3.0.7-27.el9.src.rpm` -> `openssl`
+fn source_rpm_name(source_rpm: &str) -> Option<String> {
+    let stem = source_rpm.strip_suffix(".src.rpm")?;
+    let mut parts = stem.rsplitn(3, '-');
+    let (_release, _version) = (parts.next()?, parts.next()?);
+    parts.next().map(str::to_string)
+}
+
+fn parse_package_json(data: &[u8], path: &str) -> DockerResult<Option<Package>> {
+    let manifest: Value = serde_json::from_slice(data)
+        .map_err(|e| DockerError::OperationError(format!("Invalid package.json: {}", e)))?;
+    let (Some(name), Some(version)) = (manifest["name"].as_str(), manifest["version"].as_str())
+    else {
+        return Ok(None);
+    };
+    // `"license": "MIT"` or the older `"license": { "type": "MIT" }`
+    let license = manifest["license"]
+        .as_str()
+        .or_else(|| manifest["license"]["type"].as_str())
+        .map(str::to_string);
+
+    Ok(Some(Package {
+        name: name.to_string(),
+        version: version.to_string(),
+        kind: PackageKind::Npm,
+        source: None,
+        arch: None,
+        license,
+        purl: String::new(),
+        location: path.trim_end_matches("/package.json").to_string(),
+    }))
+}
+
+fn parse_cargo_lock(text: &str, location: &str) -> Vec<Package> {
+    let mut packages = Vec::new();
+    let mut current: HashMap<&str, String> = HashMap::new();
+
+    let mut flush = |current: &mut HashMap<&str, String>| {
+        // Workspace members have no source; they're the application itself
+        if let (Some(name), Some(version), Some(_)) = (
+            current.remove("name"),
+            current.remove("version"),
+            current.remove("source"),
+        ) {
+            packages.push(Package {
+                name,
+                version,
+                kind: PackageKind::Cargo,
+                source: None,
+                arch: None,
+                license: None,
+                purl: String::new(),
+                location: location.to_string(),
+            });
+        }
+        current.clear();
+    };
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            flush(&mut current);
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let key = match key.trim() {
+                "name" => "name",
+                "version" => "version",
+                "source" => "source",
+                _ => continue,
+            };
+            current.insert(key, value.trim().trim_matches('"').to_string());
+        }
+    }
+    flush(&mut current);
+    packages
+}
+
+fn parse_python_metadata(text: &str, path: &str) -> Option<Package> {
+    // Headers end at the first blank line; the description follows
+    let headers = text.split("\n\n").next().unwrap_or_default();
+    let stanza = stanzas(headers).into_iter().next()?;
+    let license = stanza
+        .get("License-Expression")
+        .or_else(|| stanza.get("License"))
+        .filter(|l| !l.is_empty() && *l != "UNKNOWN")
+        .map(|l| l.lines().next().unwrap_or_default().to_string());
+
+    Some(Package {
+        name: stanza.get("Name")?.clone(),
+        version: stanza.get("Version")?.clone(),
+        kind: PackageKind::Pypi,
+        source: None,
+        arch: None,
+        license,
+        purl: String::new(),
+        location: path
+            .trim_end_matches("/METADATA")
+            .trim_end_matches("/PKG-INFO")
+            .to_string(),
+    })
+}
+
+// Percent-encode everything outside the purl-safe set
+fn purl_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Package URL (https://github.com/package-url/purl-spec)
+fn purl(package: &Package, distro: Option<&Distro>) -> String {
+    let version = purl_encode(&package.version);
+    let mut qualifiers = Vec::new();
+    if let Some(arch) = &package.arch {
+        qualifiers.push(format!("arch={}", purl_encode(arch)));
+    }
+
+    let base = match package.kind {
+        PackageKind::Deb | PackageKind::Apk | PackageKind::Rpm => {
+            let kind = match package.kind {
+                PackageKind::Deb => "deb",
+                PackageKind::Apk => "apk",
+                _ => "rpm",
+            };
+            let namespace = distro.map(|d| d.id.as_str()).unwrap_or(match package.kind {
+                PackageKind::Deb => "debian",
+                PackageKind::Apk => "alpine",
+                _ => "redhat",
+            });
+            if let Some(distro) = distro {
+                qualifiers.push(format!(
+                    "distro={}",
+                    purl_encode(&format!("{}-{}", distro.id, distro.version_id))
+                ));
+            }
+            format!(
+                "pkg:{}/{}/{}@{}",
+                kind,
+                namespace,
+                purl_encode(&package.name),
+                version
+            )
+        }
+        PackageKind::Npm => match package.name.split_once('/') {
+            Some((scope, name)) => format!(
+                "pkg:npm/{}/{}@{}",
+                purl_encode(scope),
+                purl_encode(name),
+                version
+            ),
+            None => format!("pkg:npm/{}@{}", purl_encode(&package.name), version),
+        },
+        PackageKind::Cargo => format!("pkg:cargo/{}@{}", purl_encode(&package.name), version),
+        PackageKind::Pypi => format!(
+            "pkg:pypi/{}@{}",
+            purl_encode(&package.name.to_ascii_lowercase().replace('_', "-")),
+            version
+        ),
+    };
+
+    if qualifiers.is_empty() {
+        base
+    } else {
+        format!("{}?{}", base, qualifiers.join("&"))
+    }
+}
+
+/// Match every package against an imported vulnerability database
+pub fn find_vulnerabilities(
+    db: &VulnerabilityDb,
+    distro: Option<&Distro>,
+    packages: &[Package],
+) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = packages
+        .iter()
+        .flat_map(|package| {
+            let mut names = vec![package.name.as_str()];
+            names.extend(package.source.as_deref());
+            db.matches(&ecosystem(package.kind, distro), &names, &package.version)
+                .into_iter()
+                .map(|vulnerability| Finding {
+                    purl: package.purl.clone(),
+                    vulnerability,
+                })
+        })
+        .collect();
+    // Packages are sorted, so copies of one package installed in several places are adjacent
+    findings.dedup_by(|a, b| a.purl == b.purl && a.vulnerability.id == b.vulnerability.id);
+    findings
+}
+
+/// Inventory an image's packages by streaming the filesystem of a temporary container
+pub async fn scan_image(docker: &Docker, image: &str) -> DockerResult<ImageSbom> {
+    let info = docker::inspect_image(docker, image).await?;
+
+    let config = Config {
+        image: Some(image.to_string()),
+        // Never started, but images without a CMD can't be created without one
+        cmd: Some(vec!["/rykard-sbom".to_string()]),
+        network_disabled: Some(true),
+        labels: Some(HashMap::from([(
+            SBOM_LABEL.to_string(),
+            "true".to_string(),
+        )])),
+        ..Default::default()
+    };
+    let container = docker
+        .create_container::<String, String>(None, config)
+        .await?
+        .id;
+
+    let inventory = read_inventory(docker, &container).await;
+    let removed = docker
+        .remove_container(
+            &container,
+            Some(RemoveContainerOptions {
+                force: true,
+                v: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+    let inventory = inventory?;
+    if let Err(e) = removed {
+        eprintln!("Failed to remove SBOM container {}: {}", container, e);
+    }
+
+    let (distro, packages, warnings) = inventory.finish();
+    Ok(ImageSbom {
+        image: info,
+        distro,
+        packages,
+        findings: Vec::new(),
+        warnings,
+        generated: Utc::now().timestamp(),
+    })
+}
+
+async fn read_inventory(docker: &Docker, container: &str) -> DockerResult<Inventory> {
+    let mut inventory = Inventory::default();
+    let mut tar = TarStream::new(
+        |path: &str, kind| kind == EntryKind::File && is_package_metadata(path),
+        MAX_METADATA_SIZE,
+    );
+
+    let stream = docker.export_container(container);
+    tokio::pin!(stream);
+    while let Some(chunk) = stream.next().await {
+        for entry in tar.push(&chunk?)? {
+            inventory.add_file(&entry.path, &entry.data);
+        }
+    }
+    Ok(inventory)
+}
+
+/// Scan an image and, when a database is given, flag vulnerable packages
+pub async fn generate_sbom(
+    docker: &Docker,
+    image: &str,
+    db: Option<&VulnerabilityDb>,
+) -> DockerResult<ImageSbom> {
+    let mut sbom = scan_image(docker, image).await?;
+    if let Some(db) = db {
+        sbom.findings = find_vulnerabilities(db, sbom.distro.as_ref(), &sbom.packages);
+    }
+    Ok(sbom)
+}
+
+impl ImageSbom {
+    fn image_name(&self) -> String {
+        self.image
+            .repo_tags
+            .first()
+            .cloned()
+            .unwrap_or_else(|| self.image.id.clone())
+    }
+
+    fn timestamp(&self) -> String {
+        Utc.timestamp_opt(self.generated, 0)
+            .single()
+            .unwrap_or_default()
+            .to_rfc3339_opts(SecondsFormat::Secs, true)
+    }
+
+    /// Serialize as a CycloneDX or SPDX JSON document
+    pub fn document(&self, format: SbomFormat) -> Value {
+        match format {
+            SbomFormat::CycloneDx => self.cyclonedx(),
+            SbomFormat::Spdx => self.spdx(),
+        }
+    }
+
+    /// CycloneDX 1.5, with findings in its `vulnerabilities` section
+    fn cyclonedx(&self) -> Value {
+        let bom_ref = |i: usize| format!("pkg-{}", i + 1);
+        // The same package can be installed in several places
+        let refs_for = |purl: &str| -> Vec<Value> {
+            self.packages
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| p.purl == purl)
+                .map(|(i, _)| json!({ "ref": bom_ref(i) }))
+                .collect()
+        };
+
+        let components: Vec<Value> = self
+            .packages
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let mut component = json!({
+                    "type": "library",
+                    "bom-ref": bom_ref(i),
+                    "name": p.name,
+                    "version": p.version,
+                    "purl": p.purl,
+                    "properties": [{ "name": "rykard:location", "value": p.location }],
+                });
+                if let Some(license) = &p.license {
+                    component["licenses"] = json!([{ "license": { "name": license } }]);
+                }
+                component
+            })
+            .collect();
+
+        let vulnerabilities: Vec<Value> = self
+            .findings
+            .iter()
+            .map(|f| {
+                let v = &f.vulnerability;
+                let severity = match v.severity.as_deref().map(str::to_ascii_lowercase) {
+                    Some(s)
+                        if ["critical", "high", "medium", "low", "info", "none"]
+                            .contains(&s.as_str()) =>
+                    {
+                        s
+                    }
+                    Some(s) if s == "moderate" => "medium".to_string(),
+                    _ => "unknown".to_string(),
+                };
+                let mut vulnerability = json!({
+                    "id": v.id,
+                    "source": { "name": "OSV" },
+                    "description": v.summary,
+                    "ratings": [{ "severity": severity }],
+                    "affects": refs_for(&f.purl),
+                });
+                if let Some(fixed) = &v.fixed_version {
+                    vulnerability["recommendation"] = json!(format!("Upgrade to {}", fixed));
+                }
+                if !v.aliases.is_empty() {
+                    vulnerability["references"] = v
+                        .aliases
+                        .iter()
+                        .map(|alias| json!({ "id": alias, "source": { "name": "OSV" } }))
+                        .collect();
+                }
+                vulnerability
+            })
+            .collect();
+
+        json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "version": 1,
+            "metadata": {
+                "timestamp": self.timestamp(),
+                "tools": { "components": [{
+                    "type": "application",
+                    "name": "rykard",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }] },
+                "component": {
+                    "type": "container",
+                    "bom-ref": "image",
+                    "name": self.image_name(),
+                    "version": format!("sha256:{}", self.image.id),
+                },
+            },
+            "components": components,
+            "vulnerabilities": vulnerabilities,
+        })
+    }
+
+    /// SPDX 2.3; findings become advisory references on the affected packages
+    fn spdx(&self) -> Value {
+        let mut packages = vec![json!({
+            "SPDXID": "SPDXRef-Image",
+            "name": self.image_name(),
+            "versionInfo": format!("sha256:{}", self.image.id),
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "primaryPackagePurpose": "CONTAINER",
+        })];
+        let mut relationships = vec![json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": "SPDXRef-Image",
+        })];
+
+        for (i, p) in self.packages.iter().enumerate() {
+            let id = format!("SPDXRef-Package-{}", i + 1);
+            let mut refs = vec![json!({
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": p.purl,
+            })];
+            refs.extend(self.findings.iter().filter(|f| f.purl == p.purl).map(|f| {
+                json!({
+                    "referenceCategory": "SECURITY",
+                    "referenceType": "advisory",
+                    "referenceLocator": format!(
+                        "https://osv.dev/vulnerability/{}",
+                        f.vulnerability.id
+                    ),
+                })
+            }));
+
+            let mut package = json!({
+                "SPDXID": id,
+                "name": p.name,
+                "versionInfo": p.version,
+                "downloadLocation": "NOASSERTION",
+                "filesAnalyzed": false,
+                // Package metadata licenses are free text, not always valid SPDX expressions
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": "NOASSERTION",
+                "sourceInfo": format!("found in {}", p.location),
+                "externalRefs": refs,
+            });
+            if let Some(license) = &p.license {
+                package["licenseComments"] = json!(license);
+            }
+            packages.push(package);
+            relationships.push(json!({
+                "spdxElementId": "SPDXRef-Image",
+                "relationshipType": "CONTAINS",
+                "relatedSpdxElement": id,
+            }));
+        }
+
+        json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": self.image_name(),
+            "documentNamespace": format!(
+                "https://rykard.local/spdx/{}-{}",
+                self.image.id, self.generated
+            ),
+            "creationInfo": {
+                "created": self.timestamp(),
+                "creators": [format!("Tool: rykard-{}", env!("CARGO_PKG_VERSION"))],
+            },
+            "packages": packages,
+            "relationships": relationships,
+        })
+    }
+}
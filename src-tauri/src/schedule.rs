@@ -0,0 +1,580 @@
+//! Scheduled container and compose-project actions, driven by cron expressions.
+//!
+//! Expressions have the usual five fields (minute, hour, day of month, month, day of
+//! week) with `*`, lists, ranges, `/` steps, month and weekday names, and the `@daily`
+//! style shorthands. As in Vixie cron, when both day fields are restricted a day
+//! matching either of them matches. Times are wall-clock times in the local zone: a
+//! time skipped by a DST change runs when the clock jumps past it, and a repeated hour
+//! runs once.
+use crate::docker::{self, DockerError, DockerResult, DockerState, COMPOSE_PROJECT_LABEL};
+use crate::guards;
+use crate::store;
+use bollard::Docker;
+use chrono::{
+    DateTime, Datelike, Local, LocalResult, NaiveDate, NaiveDateTime, TimeDelta, TimeZone,
+    Timelike, Utc,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+pub const SCHEDULES_FILE: &str = "schedules.json";
+/// Action history entries kept, oldest dropped first
+pub const HISTORY_RETENTION: usize = 500;
+/// How far back the scheduler catches up on runs it missed, e.g. across a DST jump
+/// or a slow tick. Runs missed while the machine was asleep longer are skipped.
+const MAX_CATCH_UP: TimeDelta = TimeDelta::hours(1);
+/// `0 0 29 2 *` can be eight years away around 2100
+const SEARCH_YEARS: i64 = 8;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+fn invalid(expression: &str, reason: &str) -> DockerError {
+    DockerError::OperationError(format!(
+        "Invalid cron expression '{}': {}",
+        expression, reason
+    ))
+}
+
+/// The values one field allows, as a bit set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    bits: u64,
+    /// Written as `*` or `*/n`, which matters for the day-of-month/day-of-week rule
+    any: bool,
+}
+
+impl Field {
+    fn has(self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+
+    fn parse(
+        expression: &str,
+        field: &str,
+        (min, max): (u32, u32),
+        names: &[&str],
+    ) -> DockerResult<Self> {
+        let value = |s: &str| -> DockerResult<u32> {
+            let lower = s.to_ascii_lowercase();
+            let parsed = match names.iter().position(|name| *name == lower) {
+                Some(i) => Some(min + i as u32),
+                None => s.parse().ok(),
+            };
+            parsed
+                .filter(|v| (min..=max).contains(v))
+                .ok_or_else(|| invalid(expression, &format!("'{}' is out of range", s)))
+        };
+
+        let mut bits = 0;
+        for item in field.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => match step.parse::<u32>() {
+                    Ok(step) if step > 0 => (range, Some(step)),
+                    _ => return Err(invalid(expression, &format!("bad step in '{}'", item))),
+                },
+                None => (item, None),
+            };
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else if let Some((start, end)) = range.split_once('-') {
+                (value(start)?, value(end)?)
+            } else {
+                // `5/15` runs from 5 to the end of the range
+                let start = value(range)?;
+                (start, if step.is_some() { max } else { start })
+            };
+            if start > end {
+                return Err(invalid(
+                    expression,
+                    &format!("range '{}' runs backwards", range),
+                ));
+            }
+            for v in (start..=end).step_by(step.unwrap_or(1) as usize) {
+                bits |= 1 << v;
+            }
+        }
+        Ok(Self {
+            bits,
+            any: field.starts_with('*'),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+impl CronExpr {
+    pub fn parse(expression: &str) -> DockerResult<Self> {
+        let trimmed = expression.trim();
+        let expanded = match trimmed.to_ascii_lowercase().as_str() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            shorthand if shorthand.starts_with('@') => {
+                return Err(invalid(expression, "unknown shorthand"))
+            }
+            _ => trimmed,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid(expression, "expected five fields"));
+        };
+
+        let mut weekdays = Field::parse(expression, weekday, (0, 7), &WEEKDAYS)?;
+        // Both 0 and 7 are Sunday
+        if weekdays.has(7) {
+            weekdays.bits = (weekdays.bits & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            minutes: Field::parse(expression, minute, (0, 59), &[])?,
+            hours: Field::parse(expression, hour, (0, 23), &[])?,
+            days: Field::parse(expression, day, (1, 31), &[])?,
+            months: Field::parse(expression, month, (1, 12), &MONTHS)?,
+            weekdays,
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days.has(date.day());
+        let weekday = self.weekdays.has(date.weekday().num_days_from_sunday());
+        if self.days.any || self.weekdays.any {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+
+    pub fn matches(&self, time: &NaiveDateTime) -> bool {
+        self.months.has(time.month())
+            && self.matches_day(time.date())
+            && self.hours.has(time.hour())
+            && self.minutes.has(time.minute())
+    }
+
+    /// The first matching wall-clock minute strictly after `after`; `None` when the
+    /// expression can't match, like `0 0 31 2 *`
+    pub fn next_match(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        let end = time + TimeDelta::days(366 * SEARCH_YEARS);
+        while time < end {
+            let date = time.date();
+            if !self.months.has(time.month()) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.matches_day(date) {
+                time = date.succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !self.hours.has(time.hour()) {
+                time = date.and_hms_opt(time.hour(), 0, 0)? + TimeDelta::hours(1);
+            } else if !self.minutes.has(time.minute()) {
+                time += TimeDelta::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    /// The next run after `after` in its time zone, skipping times a DST change removes
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let zone = after.timezone();
+        let mut time = after.naive_local();
+        loop {
+            time = self.next_match(time)?;
+            match zone.from_local_datetime(&time) {
+                LocalResult::Single(run) | LocalResult::Ambiguous(run, _) if run > *after => {
+                    return Some(run)
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for CronExpr {
+    type Err = DockerError;
+
+    fn from_str(expression: &str) -> DockerResult<Self> {
+        Self::parse(expression)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ScheduleTarget {
+    /// A container by name or id
+    Container { container: String },
+    /// Every container of a compose project
+    Project { project: String },
+}
+
+impl std::fmt::Display for ScheduleTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Container { container } => write!(f, "container {}", container),
+            Self::Project { project } => write!(f, "project {}", project),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleAction {
+    Start,
+    Stop,
+    Restart,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub name: String,
+    /// Five-field cron expression or shorthand such as `@daily`, in local time
+    pub cron: String,
+    pub target: ScheduleTarget,
+    pub action: ScheduleAction,
+    pub enabled: bool,
+}
+
+impl Schedule {
+    pub fn cron_expr(&self) -> DockerResult<CronExpr> {
+        CronExpr::parse(&self.cron)
+    }
+}
+
+/// A schedule as listed: with its next run and how the last one went
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScheduleInfo {
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    /// Unix time of the next run; `None` when disabled
+    pub next_run: Option<i64>,
+    pub last_run: Option<ActionRecord>,
+}
+
+/// One run of a schedule, kept in the action history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActionRecord {
+    pub schedule: String,
+    pub action: ScheduleAction,
+    pub target: ScheduleTarget,
+    /// Unix time the run started
+    pub time: i64,
+    /// Started by hand rather than by the clock
+    pub manual: bool,
+    /// Containers the action was applied to
+    pub containers: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl ActionRecord {
+    fn new(schedule: &Schedule, manual: bool) -> Self {
+        Self {
+            schedule: schedule.name.clone(),
+            action: schedule.action,
+            target: schedule.target.clone(),
+            time: Utc::now().timestamp(),
+            manual,
+            containers: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// A run that couldn't start, e.g. because Docker isn't reachable
+    pub fn failed(schedule: &Schedule, manual: bool, error: &DockerError) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            ..Self::new(schedule, manual)
+        }
+    }
+}
+
+/// Containers to act on, as (id, name). Project containers already in the wanted
+/// state are left alone.
+async fn resolve(
+    docker: &Docker,
+    target: &ScheduleTarget,
+    action: ScheduleAction,
+) -> DockerResult<Vec<(String, String)>> {
+    let project = match target {
+        ScheduleTarget::Container { container } => {
+            return Ok(vec![(container.clone(), container.clone())])
+        }
+        ScheduleTarget::Project { project } => project,
+    };
+
+    let containers: Vec<_> = docker::list_containers(docker, true)
+        .await?
+        .into_iter()
+        .filter(|c| c.labels.get(COMPOSE_PROJECT_LABEL) == Some(project))
+        .filter(|c| !guards::is_trashed(&c.names))
+        .collect();
+    if containers.is_empty() {
+        return Err(DockerError::NotFound(format!(
+            "No containers found for project {}",
+            project
+        )));
+    }
+    Ok(containers
+        .into_iter()
+        .filter(|c| match action {
+            ScheduleAction::Start => c.state != "running",
+            ScheduleAction::Stop => c.state == "running",
+            ScheduleAction::Restart => true,
+        })
+        .map(|c| {
+            let name = c
+                .names
+                .first()
+                .cloned()
+                .unwrap_or_else(|| c.id.chars().take(12).collect());
+            (c.id, name)
+        })
+        .collect())
+}
+
+/// Run a schedule's action now. Failures end up in the record rather than an error,
+/// and one failing container doesn't stop the rest of a project.
+pub async fn run(docker: &Docker, schedule: &Schedule, manual: bool) -> ActionRecord {
+    let mut record = ActionRecord::new(schedule, manual);
+    let containers = match resolve(docker, &schedule.target, schedule.action).await {
+        Ok(containers) => containers,
+        Err(e) => {
+            record.error = Some(e.to_string());
+            return record;
+        }
+    };
+
+    let mut errors = Vec::new();
+    for (id, name) in containers {
+        let result = match schedule.action {
+            ScheduleAction::Start => docker::start_container(docker, &id).await,
+            ScheduleAction::Stop => docker::stop_container(docker, &id).await,
+            ScheduleAction::Restart => docker::restart_container(docker, &id).await,
+        };
+        match result {
+            Ok(()) => record.containers.push(name),
+            Err(e) => errors.push(format!("{}: {}", name, e)),
+        }
+    }
+    if !errors.is_empty() {
+        record.error = Some(errors.join("; "));
+    }
+    record
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct ScheduleFile {
+    schedules: Vec<Schedule>,
+    history: Vec<ActionRecord>,
+}
+
+/// Schedules and their action history, persisted in the app config directory
+pub struct ScheduleStore {
+    path: PathBuf,
+    file: ScheduleFile,
+    writable: bool,
+}
+
+impl ScheduleStore {
+    pub fn load(path: PathBuf) -> DockerResult<Self> {
+        let file = store::read_json(&path)?.unwrap_or_default();
+        Ok(Self {
+            path,
+            file,
+            writable: true,
+        })
+    }
+
+    /// A store with no schedules, for when [`load`](Self::load) fails. The file is moved
+    /// to `schedules.json.bak` first; if that fails, nothing is written back.
+    pub fn recover(path: PathBuf) -> Self {
+        let writable = store::set_aside(&path);
+        Self {
+            path,
+            file: ScheduleFile::default(),
+            writable,
+        }
+    }
+
+    pub fn list<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Vec<ScheduleInfo> {
+        self.file
+            .schedules
+            .iter()
+            .map(|schedule| ScheduleInfo {
+                next_run: schedule
+                    .cron_expr()
+                    .ok()
+                    .filter(|_| schedule.enabled)
+                    .and_then(|cron| cron.next_after(now))
+                    .map(|run| run.timestamp()),
+                last_run: self
+                    .file
+                    .history
+                    .iter()
+                    .rev()
+                    .find(|r| r.schedule == schedule.name)
+                    .cloned(),
+                schedule: schedule.clone(),
+            })
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> DockerResult<Schedule> {
+        self.file
+            .schedules
+            .iter()
+            .find(|s| s.name == name)
+            .cloned()
+            .ok_or_else(|| DockerError::NotFound(format!("No such schedule: {}", name)))
+    }
+
+    /// Add or replace a schedule by name
+    pub fn save(&mut self, schedule: Schedule) -> DockerResult<Schedule> {
+        if schedule.name.trim().is_empty() {
+            return Err(DockerError::OperationError(
+                "Schedule name must not be empty".to_string(),
+            ));
+        }
+        let target = match &schedule.target {
+            ScheduleTarget::Container { container } => container,
+            ScheduleTarget::Project { project } => project,
+        };
+        if target.trim().is_empty() {
+            return Err(DockerError::OperationError(
+                "Schedule target must not be empty".to_string(),
+            ));
+        }
+        schedule.cron_expr()?;
+
+        let mut schedules = self.file.schedules.clone();
+        match schedules.iter_mut().find(|s| s.name == schedule.name) {
+            Some(existing) => *existing = schedule.clone(),
+            None => schedules.push(schedule.clone()),
+        }
+        self.write(schedules, self.file.history.clone())?;
+        Ok(schedule)
+    }
+
+    /// Delete a schedule; its history is kept
+    pub fn delete(&mut self, name: &str) -> DockerResult<()> {
+        let mut schedules = self.file.schedules.clone();
+        let before = schedules.len();
+        schedules.retain(|s| s.name != name);
+        if schedules.len() == before {
+            return Err(DockerError::NotFound(format!("No such schedule: {}", name)));
+        }
+        self.write(schedules, self.file.history.clone())
+    }
+
+    /// Enabled schedules with a run in the wall-clock interval `(since, until]`
+    pub fn due(&self, since: NaiveDateTime, until: NaiveDateTime) -> Vec<Schedule> {
+        self.file
+            .schedules
+            .iter()
+            .filter(|s| s.enabled)
+            .filter(|s| {
+                s.cron_expr()
+                    .ok()
+                    .and_then(|cron| cron.next_match(since))
+                    .is_some_and(|run| run <= until)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Append a run to the history. It stays in memory even if writing fails.
+    pub fn record(&mut self, record: ActionRecord) -> DockerResult<()> {
+        let history = &mut self.file.history;
+        history.push(record);
+        let excess = history.len().saturating_sub(HISTORY_RETENTION);
+        history.drain(..excess);
+        if !self.writable {
+            return Err(store::not_writable(&self.path));
+        }
+        store::write_json(&self.path, &self.file)
+    }
+
+    /// Past runs, newest first, optionally of one schedule only
+    pub fn history(&self, schedule: Option<&str>) -> Vec<ActionRecord> {
+        self.file
+            .history
+            .iter()
+            .rev()
+            .filter(|r| schedule.is_none_or(|name| r.schedule == name))
+            .cloned()
+            .collect()
+    }
+
+    fn write(&mut self, schedules: Vec<Schedule>, history: Vec<ActionRecord>) -> DockerResult<()> {
+        if !self.writable {
+            return Err(store::not_writable(&self.path));
+        }
+        let file = ScheduleFile { schedules, history };
+        store::write_json(&self.path, &file)?;
+        self.file = file;
+        Ok(())
+    }
+}
+
+fn until_next_minute() -> Duration {
+    let now = Utc::now();
+    let into_minute = now.second() as u64 * 1000 + now.timestamp_subsec_millis() as u64;
+    Duration::from_millis(60_000 - into_minute.min(59_999))
+}
+
+/// Run due schedules at the top of every minute, forever. `on_run` sees every record,
+/// with the endpoint it ran against, before it is added to the history.
+pub async fn run_scheduler<F>(
+    state: Arc<Mutex<DockerState>>,
+    schedules: Arc<Mutex<ScheduleStore>>,
+    mut on_run: F,
+) where
+    F: FnMut(&str, &ActionRecord),
+{
+    let mut since = Local::now().naive_local();
+    loop {
+        tokio::time::sleep(until_next_minute()).await;
+        let now = Local::now().naive_local();
+        let due = schedules
+            .lock()
+            .await
+            .due(since.max(now - MAX_CATCH_UP), now);
+        // The clock going back an hour must not run that hour's schedules again
+        since = since.max(now);
+        if due.is_empty() {
+            continue;
+        }
+
+        let (docker, endpoint) = {
+            let state = state.lock().await;
+            (state.get_client(), state.endpoint())
+        };
+        for schedule in due {
+            let record = match &docker {
+                Ok(docker) => run(docker, &schedule, false).await,
+                Err(e) => ActionRecord::failed(&schedule, false, e),
+            };
+            on_run(&endpoint, &record);
+            if let Err(e) = schedules.lock().await.record(record) {
+                eprintln!("Failed to save schedule history: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Typed, versioned user settings persisted in the app config directory.
+use crate::docker::{DockerError, DockerResult, DockerState};
+use crate::dockerfile;
+use crate::jobs::JobKind;
+use crate::store;
+pub use crate::store::backup_path;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Current schema version; bump it and add a step to `migrate` when the layout changes
+pub const SETTINGS_VERSION: u64 = 1;
+
+pub const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u64,
+    /// Docker socket or named pipe to connect to; `None` uses the platform default
+    pub default_endpoint: Option<String>,
+    /// Lines fetched when a log view doesn't ask for a specific tail size
+    pub log_tail_lines: u64,
+    /// How often stats are sampled, in milliseconds
+    pub stats_interval_ms: u64,
+    /// Number of Docker events kept for the event history
+    pub event_retention: usize,
+    pub notification_rules: Vec<NotificationRule>,
+    pub confirm_on_delete: bool,
+    pub theme: Theme,
+    pub dockerfile_lint: DockerfileLint,
+    pub job_limits: JobLimits,
+    pub crash_alerts: CrashAlerts,
+    pub delete_guards: DeleteGuards,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            default_endpoint: None,
+            log_tail_lines: 100,
+            stats_interval_ms: 2000,
+            event_retention: 500,
+            notification_rules: vec![
+                NotificationRule::new("container", "die"),
+                NotificationRule::new("container", "oom"),
+            ],
+            confirm_on_delete: true,
+            theme: Theme::System,
+            dockerfile_lint: DockerfileLint::default(),
+            job_limits: JobLimits::default(),
+            crash_alerts: CrashAlerts::default(),
+            delete_guards: DeleteGuards::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DockerfileLint {
+    /// Lint the Dockerfile before every image build
+    pub before_build: bool,
+    /// Refuse to start a build while the linter reports errors. Off by default, so
+    /// diagnostics are only warnings.
+    pub block_on_errors: bool,
+    /// Rule ids that are never reported
+    pub disabled_rules: Vec<String>,
+}
+
+impl Default for DockerfileLint {
+    fn default() -> Self {
+        Self {
+            before_build: true,
+            block_on_errors: false,
+            disabled_rules: Vec::new(),
+        }
+    }
+}
+
+/// How many jobs of each kind may run at once; more wait in the queue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobLimits {
+    pub pulls: usize,
+    pub builds: usize,
+    pub pushes: usize,
+    pub prunes: usize,
+}
+
+impl Default for JobLimits {
+    fn default() -> Self {
+        Self {
+            pulls: 3,
+            builds: 1,
+            pushes: 2,
+            prunes: 1,
+        }
+    }
+}
+
+impl JobLimits {
+    pub fn limit(&self, kind: JobKind) -> usize {
+        match kind {
+            JobKind::Pull => self.pulls,
+            JobKind::Build => self.builds,
+            JobKind::Push => self.pushes,
+            JobKind::Prune => self.prunes,
+        }
+    }
+}
+
+/// When the crash monitor calls a container crash-looping, and what it attaches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrashAlerts {
+    /// Restarts within the window that make a crash loop
+    pub restart_threshold: usize,
+    pub window_minutes: u64,
+    /// Log lines captured when a container dies
+    pub log_lines: u64,
+}
+
+impl Default for CrashAlerts {
+    fn default() -> Self {
+        Self {
+            restart_threshold: 3,
+            window_minutes: 5,
+            log_lines: 50,
+        }
+    }
+}
+
+/// Which containers and images refuse removal unless forced, and whether removed
+/// containers go to the trash first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeleteGuards {
+    /// `*` and `?` patterns matched against container names and image tags
+    pub protected_names: Vec<String>,
+    /// `key` or `key=value` labels that protect whatever carries them
+    pub protected_labels: Vec<String>,
+    /// Stop and rename removed containers, deleting them once the grace period is over
+    pub trash: bool,
+    pub trash_grace_minutes: u64,
+}
+
+impl Default for DeleteGuards {
+    fn default() -> Self {
+        Self {
+            protected_names: Vec::new(),
+            protected_labels: vec!["rykard.protected=true".to_string()],
+            trash: false,
+            trash_grace_minutes: 24 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+    System,
+}
+
+/// Notify the user when a Docker event of this type and action arrives
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationRule {
+    /// Event type, e.g. `container` or `image`
+    pub event_type: String,
+    /// Event action, e.g. `die` or `oom`
+    pub action: String,
+    pub enabled: bool,
+}
+
+impl NotificationRule {
+    fn new(event_type: &str, action: &str) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            action: action.to_string(),
+            enabled: true,
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> DockerResult<()> {
+        if self.log_tail_lines == 0 {
+            return Err(invalid("log_tail_lines must be at least 1"));
+        }
+        if self.stats_interval_ms < 250 {
+            return Err(invalid("stats_interval_ms must be at least 250"));
+        }
+        if let Some(endpoint) = &self.default_endpoint {
+            if endpoint.trim().is_empty() {
+                return Err(invalid("default_endpoint must not be empty"));
+            }
+        }
+        let limits = &self.job_limits;
+        if [limits.pulls, limits.builds, limits.pushes, limits.prunes].contains(&0) {
+            return Err(invalid("job limits must be at least 1"));
+        }
+        let alerts = &self.crash_alerts;
+        if alerts.restart_threshold == 0 || alerts.window_minutes == 0 || alerts.log_lines == 0 {
+            return Err(invalid(
+                "crash alert threshold, window and log lines must be at least 1",
+            ));
+        }
+        let guards = &self.delete_guards;
+        if guards.trash_grace_minutes == 0 {
+            return Err(invalid("trash_grace_minutes must be at least 1"));
+        }
+        if guards
+            .protected_names
+            .iter()
+            .chain(&guards.protected_labels)
+            .any(|rule| rule.trim().is_empty())
+        {
+            return Err(invalid("protected names and labels must not be empty"));
+        }
+        for id in &self.dockerfile_lint.disabled_rules {
+            if !dockerfile::RULES.iter().any(|rule| rule.id == id) {
+                return Err(invalid(&format!("unknown Dockerfile lint rule {}", id)));
+            }
+        }
+        Ok(())
+    }
+
+    /// A Docker state for the configured endpoint
+    pub fn docker_state(&self) -> DockerState {
+        match &self.default_endpoint {
+            Some(endpoint) => DockerState::with_socket(endpoint.clone()),
+            None => DockerState::default(),
+        }
+    }
+}
+
+fn invalid(message: &str) -> DockerError {
+    DockerError::OperationError(format!("Invalid settings: {}", message))
+}
+
+/// Upgrade a raw settings document to `SETTINGS_VERSION`.
+///
+/// Returns the upgraded document and whether anything had to change.
+fn migrate(mut value: Value) -> DockerResult<(Value, bool)> {
+    let object = value
+        .as_object_mut()
+        .ok_or_else(|| invalid("settings file is not a JSON object"))?;
+
+    let version = object.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > SETTINGS_VERSION {
+        return Err(invalid(&format!(
+            "settings version {} is newer than this build supports ({})",
+            version, SETTINGS_VERSION
+        )));
+    }
+
+    // Step N upgrades a version N document to N + 1
+    for step in version..SETTINGS_VERSION {
+        match step {
+            // Unversioned files predate the schema; missing fields fall back to defaults
+            0 => {}
+            _ => unreachable!("no migration from settings version {}", step),
+        }
+    }
+
+    object.insert("version".to_string(), SETTINGS_VERSION.into());
+    Ok((value, version < SETTINGS_VERSION))
+}
+
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: Settings,
+    /// Off when an unusable file couldn't be moved out of the way
+    writable: bool,
+}
+
+impl SettingsStore {
+    /// Load settings from `path`, migrating older files in place.
+    ///
+    /// A missing file yields the defaults without creating it. A file that can't be
+    /// parsed, is from a newer version or holds invalid values is an error.
+    pub fn load(path: PathBuf) -> DockerResult<Self> {
+        let settings = match store::read_json::<Value>(&path)? {
+            Some(raw) => {
+                let (value, migrated) = migrate(raw)?;
+                let settings: Settings =
+                    serde_json::from_value(value).map_err(|e| invalid(&e.to_string()))?;
+                settings.validate()?;
+                if migrated {
+                    store::write_json(&path, &settings)?;
+                }
+                settings
+            }
+            None => Settings::default(),
+        };
+
+        Ok(Self {
+            path,
+            settings,
+            writable: true,
+        })
+    }
+
+    /// Load settings from the app config directory (used by the CLI)
+    pub fn load_default() -> DockerResult<Self> {
+        Self::load(store::app_config_dir()?.join(SETTINGS_FILE))
+    }
+
+    /// A store holding the defaults, for when [`load`](Self::load) fails. The file at
+    /// `path` is moved to `settings.json.bak` first so saving can't overwrite it; if it
+    /// can't be moved, the defaults stay in memory and updates are refused.
+    pub fn recover(path: PathBuf) -> Self {
+        let writable = store::set_aside(&path);
+        Self {
+            path,
+            settings: Settings::default(),
+            writable,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Apply a partial update: top-level keys in `changes` replace the current values
+    pub fn update(&mut self, changes: Value) -> DockerResult<Settings> {
+        let changes = match changes {
+            Value::Object(changes) => changes,
+            _ => return Err(invalid("update must be a JSON object")),
+        };
+
+        let mut merged = serde_json::to_value(&self.settings)
+            .map_err(|e| DockerError::Unknown(e.to_string()))?;
+        if let Value::Object(current) = &mut merged {
+            current.extend(changes);
+            current.insert("version".to_string(), SETTINGS_VERSION.into());
+        }
+
+        let settings: Settings =
+            serde_json::from_value(merged).map_err(|e| invalid(&e.to_string()))?;
+        settings.validate()?;
+
+        if !self.writable {
+            return Err(store::not_writable(&self.path));
+        }
+        store::write_json(&self.path, &settings)?;
+        self.settings = settings.clone();
+        Ok(settings)
+    }
+}
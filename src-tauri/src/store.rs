@@ -0,0 +1,70 @@
+//! JSON files kept in the app config directory.
+use crate::docker::{DockerError, DockerResult};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Bundle identifier from `tauri.conf.json`
+pub const APP_IDENTIFIER: &str = "com.fosslife.rykard";
+
+/// The app config directory, resolved the same way Tauri's `app_config_dir` does.
+///
+/// The GUI asks Tauri directly; this is for the CLI, which runs without an app handle.
+pub fn app_config_dir() -> DockerResult<PathBuf> {
+    dirs::config_dir()
+        .map(|dir| dir.join(APP_IDENTIFIER))
+        .ok_or_else(|| DockerError::OperationError("Unable to locate config directory".to_string()))
+}
+
+/// Read a JSON file, returning `None` if it doesn't exist yet
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> DockerResult<Option<T>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    serde_json::from_str(&contents).map(Some).map_err(|e| {
+        DockerError::OperationError(format!("Failed to parse {}: {}", path.display(), e))
+    })
+}
+
+/// Write a JSON file atomically so a crash never leaves a half-written file behind
+pub fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> DockerResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let json = serde_json::to_string_pretty(value).map_err(|e| {
+        DockerError::OperationError(format!("Failed to serialize {}: {}", path.display(), e))
+    })?;
+
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Where [`set_aside`] moves an unusable file
+pub fn backup_path(path: &Path) -> PathBuf {
+    path.with_extension("json.bak")
+}
+
+/// Move a file that failed to load to its [`backup_path`] so a fresh store can't
+/// overwrite it. Returns whether the path is now safe to write.
+pub fn set_aside(path: &Path) -> bool {
+    match fs::rename(path, backup_path(path)) {
+        Ok(()) => true,
+        Err(e) => e.kind() == ErrorKind::NotFound,
+    }
+}
+
+/// The error a store returns when it wasn't able to [`set_aside`] its file
+pub fn not_writable(path: &Path) -> DockerError {
+    DockerError::OperationError(format!(
+        "{} couldn't be loaded or moved aside, so it won't be overwritten",
+        path.display()
+    ))
+}
@@ -0,0 +1,221 @@
+//! Image update checks: is the digest a tag resolves to in its registry still the one
+//! the local image was pulled at?
+//!
+//! The remote digest comes from the daemon's distribution endpoint, which resolves the
+//! manifest over the registry HTTP API with the daemon's own credentials, TLS and
+//! insecure-registry settings. A plain-HTTP `registry:2` on localhost works the same as
+//! Docker Hub.
+use crate::docker::{self, DockerError, DockerResult, ImageInfo};
+use crate::recreate::{self, RecreateOptions};
+use crate::reference::ImageReference;
+use bollard::container::ListContainersOptions;
+use bollard::models::CreateImageInfo;
+use bollard::Docker;
+use futures_util::{stream, StreamExt};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateStatus {
+    /// The registry still serves the digest the image was pulled at
+    Current,
+    /// The tag has moved on in the registry
+    Outdated,
+    /// Built or loaded locally; there's no pulled digest to compare
+    Local,
+    /// The registry couldn't be asked, see `error`
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContainerUsage {
+    pub id: String,
+    pub name: String,
+    pub state: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageUpdate {
+    pub image_id: String,
+    pub tag: String,
+    pub local_digest: Option<String>,
+    pub remote_digest: Option<String>,
+    pub status: UpdateStatus,
+    pub error: Option<String>,
+    /// Containers created from the image, running or not
+    pub containers: Vec<ContainerUsage>,
+}
+
+/// Digest the image was pulled at from the repository of `tag`
+fn local_digest(image: &ImageInfo, tag: &str) -> Option<String> {
+    let repository = ImageReference::parse(tag).ok()?.name();
+    image
+        .repo_digests
+        .iter()
+        .filter_map(|d| ImageReference::parse(d).ok())
+        .find(|reference| reference.name() == repository)
+        .and_then(|reference| reference.digest)
+}
+
+/// Digest `tag` currently resolves to in its registry
+pub async fn remote_digest(docker: &Docker, tag: &str) -> DockerResult<String> {
+    let inspect = docker.inspect_registry_image(tag, None).await?;
+    inspect.descriptor.digest.ok_or_else(|| {
+        DockerError::OperationError(format!("Registry returned no digest for {}", tag))
+    })
+}
+
+/// Containers created from each image, keyed by full image id
+async fn containers_by_image(docker: &Docker) -> DockerResult<Vec<(String, ContainerUsage)>> {
+    let containers = docker
+        .list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            ..Default::default()
+        }))
+        .await?;
+
+    Ok(containers
+        .into_iter()
+        .map(|c| {
+            let image_id = c
+                .image_id
+                .unwrap_or_default()
+                .trim_start_matches("sha256:")
+                .to_string();
+            let usage = ContainerUsage {
+                id: c.id.unwrap_or_default(),
+                name: c
+                    .names
+                    .unwrap_or_default()
+                    .first()
+                    .map(|n| n.trim_start_matches('/').to_string())
+                    .unwrap_or_default(),
+                state: c.state.unwrap_or_default(),
+            };
+            (image_id, usage)
+        })
+        .collect())
+}
+
+async fn check_tag(
+    docker: &Docker,
+    image: &ImageInfo,
+    tag: &str,
+    containers: Vec<ContainerUsage>,
+) -> ImageUpdate {
+    let mut update = ImageUpdate {
+        image_id: image.id.clone(),
+        tag: tag.to_string(),
+        local_digest: local_digest(image, tag),
+        remote_digest: None,
+        status: UpdateStatus::Local,
+        error: None,
+        containers,
+    };
+    if update.local_digest.is_none() {
+        return update;
+    }
+
+    match remote_digest(docker, tag).await {
+        Ok(remote) => {
+            update.status = if update.local_digest.as_deref() == Some(remote.as_str()) {
+                UpdateStatus::Current
+            } else {
+                UpdateStatus::Outdated
+            };
+            update.remote_digest = Some(remote);
+        }
+        Err(e) => {
+            update.status = UpdateStatus::Unknown;
+            update.error = Some(e.to_string());
+        }
+    }
+    update
+}
+
+// Registry lookups in flight at once while checking
+const UPDATE_CHECK_CONCURRENCY: usize = 4;
+
+/// Check every tag of one image, or of all local images, against its registry
+pub async fn check_updates(docker: &Docker, image: Option<&str>) -> DockerResult<Vec<ImageUpdate>> {
+    let images = match image {
+        Some(image) => vec![docker::inspect_image(docker, image).await?],
+        None => docker::list_images(docker).await?,
+    };
+    let containers = containers_by_image(docker).await?;
+
+    // Owned copies, so the buffered futures don't borrow from this function
+    let checks: Vec<(ImageInfo, String, Vec<ContainerUsage>)> = images
+        .iter()
+        .flat_map(|image| {
+            let users: Vec<ContainerUsage> = containers
+                .iter()
+                .filter(|(image_id, _)| *image_id == image.id)
+                .map(|(_, usage)| usage.clone())
+                .collect();
+            image
+                .repo_tags
+                .iter()
+                .filter(|tag| tag.as_str() != "<none>:<none>")
+                .map(move |tag| (image.clone(), tag.clone(), users.clone()))
+        })
+        .collect();
+    Ok(stream::iter(checks)
+        .map(|(image, tag, users)| {
+            let docker = docker.clone();
+            async move { check_tag(&docker, &image, &tag, users).await }
+        })
+        .buffered(UPDATE_CHECK_CONCURRENCY)
+        .collect()
+        .await)
+}
+
+/// What became of one container that ran the image a pull replaced
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecreateOutcome {
+    pub container: ContainerUsage,
+    /// The replacement's id, when recreating worked
+    pub recreated_id: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Pull `tag` and recreate the containers that ran its previous image on the new one.
+///
+/// A container that fails to recreate doesn't stop the others; each one's outcome is
+/// returned. Nothing is recreated when the pull didn't change the image.
+/// `options.image` and `options.pull` are ignored.
+pub async fn pull_and_recreate<F>(
+    docker: &Docker,
+    tag: &str,
+    options: &RecreateOptions,
+    on_progress: F,
+) -> DockerResult<Vec<RecreateOutcome>>
+where
+    F: FnMut(&CreateImageInfo),
+{
+    let previous = docker::inspect_image(docker, tag).await?;
+    docker::pull_image(docker, tag, on_progress).await?;
+    let current = docker::inspect_image(docker, tag).await?;
+    if current.id == previous.id {
+        return Ok(Vec::new());
+    }
+
+    let options = RecreateOptions {
+        image: Some(tag.to_string()),
+        pull: false,
+        ..options.clone()
+    };
+    let mut outcomes = Vec::new();
+    for (image_id, container) in containers_by_image(docker).await? {
+        if image_id != previous.id {
+            continue;
+        }
+        let result = recreate::recreate_container(docker, &container.id, &options, |_| {}).await;
+        outcomes.push(RecreateOutcome {
+            container,
+            recreated_id: result.as_ref().ok().cloned(),
+            error: result.err().map(|e| e.to_string()),
+        });
+    }
+    Ok(outcomes)
+}
@@ -0,0 +1,559 @@
+//! Offline vulnerability matching against an imported OSV database file.
+//!
+//! The file is a JSON array of [OSV](https://ossf.github.io/osv-schema/) records, or an
+//! object with a `vulns` array, as produced by concatenating the per-ecosystem exports.
+//! Nothing here talks to the network.
+use crate::docker::{DockerError, DockerResult};
+use crate::store;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// File the imported database is kept in, inside the app config directory
+pub const VULNDB_FILE: &str = "vulndb.json";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OsvRecord {
+    pub id: String,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub affected: Vec<OsvAffected>,
+    #[serde(default)]
+    pub database_specific: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OsvAffected {
+    pub package: OsvPackage,
+    #[serde(default)]
+    pub ranges: Vec<OsvRange>,
+    /// Explicitly listed affected versions
+    #[serde(default)]
+    pub versions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OsvPackage {
+    /// e.g. `Debian:12`, `Alpine:v3.20`, `npm`, `PyPI`, `crates.io`
+    pub ecosystem: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OsvRange {
+    #[serde(rename = "type")]
+    pub type_: String,
+    #[serde(default)]
+    pub events: Vec<OsvEvent>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OsvEvent {
+    pub introduced: Option<String>,
+    pub fixed: Option<String>,
+    pub last_affected: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VulnerabilityMatch {
+    pub id: String,
+    pub aliases: Vec<String>,
+    pub summary: String,
+    pub package: String,
+    pub installed_version: String,
+    /// Earliest version of the matching range that fixes it, if any
+    pub fixed_version: Option<String>,
+    pub severity: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DbFile {
+    Records(Vec<OsvRecord>),
+    Wrapped { vulns: Vec<OsvRecord> },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VulnerabilityDb {
+    pub records: Vec<OsvRecord>,
+}
+
+impl From<DbFile> for VulnerabilityDb {
+    fn from(file: DbFile) -> Self {
+        match file {
+            DbFile::Records(records) | DbFile::Wrapped { vulns: records } => Self { records },
+        }
+    }
+}
+
+impl VulnerabilityDb {
+    pub fn from_json(json: &str) -> DockerResult<Self> {
+        let file: DbFile = serde_json::from_str(json).map_err(|e| {
+            DockerError::OperationError(format!("Invalid vulnerability database: {}", e))
+        })?;
+        Ok(file.into())
+    }
+
+    /// The imported database, or `None` if nothing was imported yet
+    pub fn load(path: &Path) -> DockerResult<Option<Self>> {
+        Ok(store::read_json::<DbFile>(path)?.map(Self::from))
+    }
+
+    /// Validate `source` and copy it over the database at `dest`, returning the record count
+    pub fn import(source: &Path, dest: &Path) -> DockerResult<usize> {
+        Ok(Self::replace(source, dest)?.records.len())
+    }
+
+    fn replace(source: &Path, dest: &Path) -> DockerResult<Self> {
+        let contents = std::fs::read_to_string(source)?;
+        let db = Self::from_json(&contents)?;
+        // Keep the records as they were, including fields we don't read
+        let raw: serde_json::Value = serde_json::from_str(&contents).map_err(|e| {
+            DockerError::OperationError(format!("Invalid vulnerability database: {}", e))
+        })?;
+        store::write_json(dest, &raw)?;
+        Ok(db)
+    }
+
+    /// Advisories affecting one installed package.
+    ///
+    /// `ecosystem` carries the distro release (`Debian:12`, `Alpine:v3.20`) and also
+    /// matches the more specific ecosystems OSV files some advisories under, such as
+    /// `Ubuntu:22.04:LTS` for `Ubuntu:22.04`. `names` lets distro packages match on
+    /// their source package too.
+    pub fn matches(
+        &self,
+        ecosystem: &str,
+        names: &[&str],
+        version: &str,
+    ) -> Vec<VulnerabilityMatch> {
+        let mut found = Vec::new();
+        for record in &self.records {
+            for affected in &record.affected {
+                if !ecosystem_matches(ecosystem, &affected.package.ecosystem)
+                    || !names
+                        .iter()
+                        .any(|n| package_name_eq(ecosystem, n, &affected.package.name))
+                {
+                    continue;
+                }
+                if let Some(fixed_version) = affected_by(affected, version) {
+                    found.push(VulnerabilityMatch {
+                        id: record.id.clone(),
+                        aliases: record.aliases.clone(),
+                        summary: record.summary.clone(),
+                        package: affected.package.name.clone(),
+                        installed_version: version.to_string(),
+                        fixed_version,
+                        severity: severity(record),
+                    });
+                    break;
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Ecosystem name without the release, e.g. `Debian` for `Debian:12`
+fn ecosystem_name(ecosystem: &str) -> &str {
+    ecosystem.split(':').next().unwrap_or_default()
+}
+
+fn ecosystem_matches(ecosystem: &str, advisory: &str) -> bool {
+    let (ecosystem, advisory) = (
+        ecosystem.to_ascii_lowercase(),
+        advisory.to_ascii_lowercase(),
+    );
+    advisory
+        .strip_prefix(&ecosystem)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+}
+
+/// The imported database, read from disk on first use and then kept in memory, since
+/// it can run to hundreds of megabytes and every scan needs it
+#[derive(Debug)]
+pub struct VulnerabilityDbStore {
+    path: PathBuf,
+    /// `None` until the file has been read
+    db: Option<Option<Arc<VulnerabilityDb>>>,
+}
+
+impl VulnerabilityDbStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, db: None }
+    }
+
+    /// The imported database, or `None` if nothing was imported yet
+    pub fn get(&mut self) -> DockerResult<Option<Arc<VulnerabilityDb>>> {
+        if let Some(db) = &self.db {
+            return Ok(db.clone());
+        }
+        let db = VulnerabilityDb::load(&self.path)?.map(Arc::new);
+        self.db = Some(db.clone());
+        Ok(db)
+    }
+
+    /// Replace the database with an OSV JSON file, returning its record count
+    pub fn import(&mut self, source: &Path) -> DockerResult<usize> {
+        let db = VulnerabilityDb::replace(source, &self.path)?;
+        let count = db.records.len();
+        self.db = Some(Some(Arc::new(db)));
+        Ok(count)
+    }
+}
+
+// PyPI names are case-insensitive and treat `-`, `_` and `.` alike
+fn package_name_eq(ecosystem: &str, a: &str, b: &str) -> bool {
+    if ecosystem_name(ecosystem).eq_ignore_ascii_case("pypi") {
+        let normalize = |s: &str| s.to_ascii_lowercase().replace(['_', '.'], "-");
+        normalize(a) == normalize(b)
+    } else {
+        a == b
+    }
+}
+
+fn severity(record: &OsvRecord) -> Option<String> {
+    record
+        .database_specific
+        .as_ref()?
+        .get("severity")?
+        .as_str()
+        .map(str::to_string)
+}
+
+type Comparator = fn(&str, &str) -> Ordering;
+
+/// How versions of an ecosystem are ordered, if we know. PyPI's PEP 440 ordering
+/// isn't implemented; its advisories list every affected version explicitly anyway.
+fn ecosystem_comparator(ecosystem: &str) -> Option<Comparator> {
+    match ecosystem_name(ecosystem) {
+        "Debian" | "Ubuntu" => Some(compare_versions),
+        "Alpine" => Some(compare_apk),
+        "Red Hat" | "Rocky Linux" | "AlmaLinux" | "openSUSE" | "SUSE" | "Mariner" => {
+            Some(compare_rpm)
+        }
+        "npm" | "crates.io" => Some(compare_semver),
+        _ => None,
+    }
+}
+
+/// `Some(fixed)` if `version` is affected, with the fixing version of that range
+fn affected_by(affected: &OsvAffected, version: &str) -> Option<Option<String>> {
+    if affected.versions.iter().any(|v| v == version) {
+        return Some(None);
+    }
+
+    for range in &affected.ranges {
+        // GIT ranges are commit hashes, which an installed package can't be compared
+        // to, and ECOSYSTEM ranges of an ecosystem we can't order are skipped
+        let cmp = match range.type_.as_str() {
+            "SEMVER" => compare_semver,
+            "ECOSYSTEM" => match ecosystem_comparator(&affected.package.ecosystem) {
+                Some(cmp) => cmp,
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        // Events are sorted; walk them tracking whether we're inside an affected span
+        let mut inside = false;
+        let mut fixed = None;
+        for event in &range.events {
+            if let Some(introduced) = &event.introduced {
+                if introduced == "0" || cmp(version, introduced) != Ordering::Less {
+                    inside = true;
+                    fixed = None;
+                }
+            }
+            if let Some(f) = &event.fixed {
+                if inside && cmp(version, f) != Ordering::Less {
+                    inside = false;
+                } else if inside && fixed.is_none() {
+                    fixed = Some(f.clone());
+                }
+            }
+            if let Some(last) = &event.last_affected {
+                if inside && cmp(version, last) == Ordering::Greater {
+                    inside = false;
+                }
+            }
+        }
+        if inside {
+            return Some(fixed);
+        }
+    }
+    None
+}
+
+/// Compare SemVer-style versions: like [`compare_versions`], but a `-` introduces a
+/// pre-release that sorts before the release
+pub fn compare_semver(a: &str, b: &str) -> Ordering {
+    let prerelease = |v: &str| {
+        let v = v.trim_start_matches('v');
+        let v = v.split('+').next().unwrap_or(v);
+        v.replacen('-', "~", 1)
+    };
+    compare_versions(&prerelease(a), &prerelease(b))
+}
+
+/// Compare Debian package versions the way dpkg does: `[epoch:]upstream[-revision]`,
+/// digit runs compared numerically, `~` sorting before anything, even the end.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let split = |v: &str| -> (u64, String, String) {
+        let (epoch, rest) = match v.split_once(':') {
+            Some((epoch, rest)) if epoch.chars().all(|c| c.is_ascii_digit()) => {
+                (epoch.parse().unwrap_or(0), rest)
+            }
+            _ => (0, v),
+        };
+        match rest.rsplit_once('-') {
+            Some((upstream, revision)) => (epoch, upstream.to_string(), revision.to_string()),
+            None => (epoch, rest.to_string(), String::new()),
+        }
+    };
+
+    let (ea, ua, ra) = split(a);
+    let (eb, ub, rb) = split(b);
+    ea.cmp(&eb)
+        .then_with(|| verrevcmp(&ua, &ub))
+        .then_with(|| verrevcmp(&ra, &rb))
+}
+
+fn order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(b'~') => -1,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => c as i32,
+        Some(c) => c as i32 + 256,
+    }
+}
+
+fn verrevcmp(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        // Non-digit prefix, character by character
+        while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+            let ac = order(a.get(i).copied().filter(|c| !c.is_ascii_digit()));
+            let bc = order(b.get(j).copied().filter(|c| !c.is_ascii_digit()));
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            if i < a.len() && !a[i].is_ascii_digit() {
+                i += 1;
+            }
+            if j < b.len() && !b[j].is_ascii_digit() {
+                j += 1;
+            }
+        }
+
+        // Digit run, numerically
+        while i < a.len() && a[i] == b'0' {
+            i += 1;
+        }
+        while j < b.len() && b[j] == b'0' {
+            j += 1;
+        }
+        let (si, sj) = (i, j);
+        while i < a.len() && a[i].is_ascii_digit() {
+            i += 1;
+        }
+        while j < b.len() && b[j].is_ascii_digit() {
+            j += 1;
+        }
+        let (na, nb) = (&a[si..i], &b[sj..j]);
+        let cmp = na.len().cmp(&nb.len()).then_with(|| na.cmp(nb));
+        if cmp != Ordering::Equal {
+            return cmp;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Compare Alpine package versions the way apk does: dotted numbers, an optional
+/// letter, `_suffix[N]` parts where `_alpha`, `_beta`, `_pre` and `_rc` sort before
+/// the release and the rest after it, then the `-rN` package release.
+pub fn compare_apk(a: &str, b: &str) -> Ordering {
+    struct ApkVersion {
+        numbers: Vec<u64>,
+        letter: Option<char>,
+        /// Rank of each suffix against the release, and its number
+        suffixes: Vec<(i32, u64)>,
+        release: u64,
+    }
+
+    fn parse(version: &str) -> ApkVersion {
+        let (version, release) = match version.rsplit_once("-r") {
+            Some((version, release)) if release.chars().all(|c| c.is_ascii_digit()) => {
+                (version, release.parse().unwrap_or(0))
+            }
+            _ => (version, 0),
+        };
+        let mut parts = version.split('_');
+        let base = parts.next().unwrap_or_default();
+        let letter = base.chars().last().filter(char::is_ascii_alphabetic);
+        let numbers = base
+            .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+            .split('.')
+            .map(|n| n.parse().unwrap_or(0))
+            .collect();
+        let suffixes = parts
+            .map(|suffix| {
+                let digits = suffix.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+                let rank = match &suffix[..suffix.len() - digits.len()] {
+                    "alpha" => -4,
+                    "beta" => -3,
+                    "pre" => -2,
+                    "rc" => -1,
+                    "cvs" => 1,
+                    "svn" => 2,
+                    "git" => 3,
+                    "hg" => 4,
+                    _ => 5,
+                };
+                (rank, digits.parse().unwrap_or(0))
+            })
+            .collect();
+        ApkVersion {
+            numbers,
+            letter,
+            suffixes,
+            release,
+        }
+    }
+
+    let (a, b) = (parse(a), parse(b));
+    // A missing suffix ranks like the release itself
+    let suffixes = || {
+        let pad = |s: &[(i32, u64)], i: usize| s.get(i).copied().unwrap_or((0, 0));
+        (0..a.suffixes.len().max(b.suffixes.len()))
+            .map(|i| pad(&a.suffixes, i).cmp(&pad(&b.suffixes, i)))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    };
+    a.numbers
+        .cmp(&b.numbers)
+        .then_with(|| a.letter.cmp(&b.letter))
+        .then_with(suffixes)
+        .then_with(|| a.release.cmp(&b.release))
+}
+
+/// Compare RPM versions the way rpm does: `[epoch:]version-release`, each compared
+/// by alternating digit and letter segments, with `~` sorting before anything and
+/// `^` after the end but before anything else.
+pub fn compare_rpm(a: &str, b: &str) -> Ordering {
+    let split = |v: &str| -> (u64, String, String) {
+        let (epoch, rest) = match v.split_once(':') {
+            Some((epoch, rest)) if epoch.chars().all(|c| c.is_ascii_digit()) => {
+                (epoch.parse().unwrap_or(0), rest)
+            }
+            _ => (0, v),
+        };
+        match rest.rsplit_once('-') {
+            Some((version, release)) => (epoch, version.to_string(), release.to_string()),
+            None => (epoch, rest.to_string(), String::new()),
+        }
+    };
+
+    let (ea, va, ra) = split(a);
+    let (eb, vb, rb) = split(b);
+    ea.cmp(&eb)
+        .then_with(|| rpmvercmp(&va, &vb))
+        .then_with(|| rpmvercmp(&ra, &rb))
+}
+
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    let separator = |c: u8| !c.is_ascii_alphanumeric() && c != b'~' && c != b'^';
+
+    loop {
+        while i < a.len() && separator(a[i]) {
+            i += 1;
+        }
+        while j < b.len() && separator(b[j]) {
+            j += 1;
+        }
+        let (ca, cb) = (a.get(i).copied(), b.get(j).copied());
+
+        if ca == Some(b'~') || cb == Some(b'~') {
+            if ca != Some(b'~') {
+                return Ordering::Greater;
+            }
+            if cb != Some(b'~') {
+                return Ordering::Less;
+            }
+            i += 1;
+            j += 1;
+            continue;
+        }
+        if ca == Some(b'^') || cb == Some(b'^') {
+            match (ca, cb) {
+                (None, _) => return Ordering::Less,
+                (_, None) => return Ordering::Greater,
+                (Some(b'^'), Some(b'^')) => {}
+                (Some(b'^'), _) => return Ordering::Less,
+                _ => return Ordering::Greater,
+            }
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let (Some(ca), Some(_)) = (ca, cb) else {
+            break;
+        };
+
+        // Take a segment of the same class from both
+        let numeric = ca.is_ascii_digit();
+        let class = |c: &u8| {
+            if numeric {
+                c.is_ascii_digit()
+            } else {
+                c.is_ascii_alphabetic()
+            }
+        };
+        let (si, sj) = (i, j);
+        while i < a.len() && class(&a[i]) {
+            i += 1;
+        }
+        while j < b.len() && class(&b[j]) {
+            j += 1;
+        }
+        let (sa, sb) = (&a[si..i], &b[sj..j]);
+        // A number is newer than letters
+        if sb.is_empty() {
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+        let cmp = if numeric {
+            let trim = |s: &[u8]| {
+                let zeros = s.iter().take_while(|&&c| c == b'0').count();
+                s[zeros..].to_vec()
+            };
+            let (na, nb) = (trim(sa), trim(sb));
+            na.len().cmp(&nb.len()).then_with(|| na.cmp(&nb))
+        } else {
+            sa.cmp(sb)
+        };
+        if cmp != Ordering::Equal {
+            return cmp;
+        }
+    }
+
+    // Whichever has segments left is newer
+    match (i < a.len(), j < b.len()) {
+        (false, false) => Ordering::Equal,
+        (true, _) => Ordering::Greater,
+        _ => Ordering::Less,
+    }
+}
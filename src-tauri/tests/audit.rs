@@ -0,0 +1,307 @@
+//! The audit log: recording outcomes, filtering, export and secret masking.
+mod common;
+
+use common::MockDocker;
+use rykard_lib::audit::{
+    redact, AuditExportFormat, AuditFilter, AuditLog, AuditOutcome, AuditRecord,
+};
+use rykard_lib::docker::{DockerError, DockerResult, DockerState};
+use rykard_lib::jobs::{JobManager, JobRequest};
+use rykard_lib::schedule::{Schedule, ScheduleAction, ScheduleTarget};
+use serde_json::json;
+use std::io::Write;
+
+const ENDPOINT: &str = "unix:///var/run/docker.sock";
+
+fn log() -> (tempfile::TempDir, AuditLog) {
+    let dir = tempfile::tempdir().unwrap();
+    let log = AuditLog::new(dir.path().join("audit.jsonl"));
+    (dir, log)
+}
+
+fn record(time: i64, action: &str, target: &str, outcome: AuditOutcome) -> AuditRecord {
+    AuditRecord {
+        time,
+        endpoint: ENDPOINT.to_string(),
+        action: action.to_string(),
+        target: target.to_string(),
+        parameters: json!({}),
+        outcome,
+        error: None,
+    }
+}
+
+#[test]
+fn records_outcomes_newest_first() {
+    let (_dir, log) = log();
+    assert!(log.read(&AuditFilter::default()).unwrap().is_empty());
+
+    let ok: DockerResult<()> = Ok(());
+    let failed: DockerResult<()> = Err(DockerError::NotFound("No such container: x".into()));
+    log.record(ENDPOINT, "stop_container", "web", json!({}), &ok);
+    log.record(ENDPOINT, "remove_container", "x", json!({}), &failed);
+
+    let records = log.read(&AuditFilter::default()).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].action, "remove_container");
+    assert_eq!(records[0].outcome, AuditOutcome::Failure);
+    assert_eq!(
+        records[0].error.as_deref(),
+        Some("Not found: No such container: x")
+    );
+    assert_eq!(records[1].action, "stop_container");
+    assert_eq!(records[1].outcome, AuditOutcome::Success);
+    assert_eq!(records[1].endpoint, ENDPOINT);
+    assert!(records[1].time > 1_700_000_000);
+}
+
+#[test]
+fn filters_by_every_field() {
+    let (_dir, log) = log();
+    log.append(&record(
+        100,
+        "start_container",
+        "web",
+        AuditOutcome::Success,
+    ))
+    .unwrap();
+    log.append(&record(
+        200,
+        "remove_image",
+        "nginx:latest",
+        AuditOutcome::Failure,
+    ))
+    .unwrap();
+    let mut remote = record(300, "remove_container", "Shop-Web-1", AuditOutcome::Success);
+    remote.endpoint = "tcp://build-host:2375".to_string();
+    log.append(&remote).unwrap();
+
+    let targets = |filter: AuditFilter| -> Vec<String> {
+        log.read(&filter)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.target)
+            .collect()
+    };
+    assert_eq!(
+        targets(AuditFilter::default()),
+        ["Shop-Web-1", "nginx:latest", "web"]
+    );
+    assert_eq!(
+        targets(AuditFilter {
+            actions: vec!["remove_image".into(), "remove_container".into()],
+            ..Default::default()
+        }),
+        ["Shop-Web-1", "nginx:latest"]
+    );
+    assert_eq!(
+        targets(AuditFilter {
+            target: Some("WEB".into()),
+            ..Default::default()
+        }),
+        ["Shop-Web-1", "web"]
+    );
+    assert_eq!(
+        targets(AuditFilter {
+            endpoint: Some(ENDPOINT.into()),
+            outcome: Some(AuditOutcome::Success),
+            ..Default::default()
+        }),
+        ["web"]
+    );
+    assert_eq!(
+        targets(AuditFilter {
+            since: Some(200),
+            until: Some(300),
+            limit: Some(1),
+            ..Default::default()
+        }),
+        ["Shop-Web-1"]
+    );
+
+    let filter: AuditFilter =
+        serde_json::from_value(json!({ "outcome": "failure", "since": 150 })).unwrap();
+    assert_eq!(targets(filter), ["nginx:latest"]);
+}
+
+#[test]
+fn torn_lines_are_skipped() {
+    let (dir, log) = log();
+    log.append(&record(
+        100,
+        "start_container",
+        "web",
+        AuditOutcome::Success,
+    ))
+    .unwrap();
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(dir.path().join("audit.jsonl"))
+        .unwrap();
+    write!(file, "{{\"time\":200,\"endpo").unwrap();
+
+    assert_eq!(log.read(&AuditFilter::default()).unwrap().len(), 1);
+}
+
+#[test]
+fn environment_values_are_masked() {
+    let mut parameters = json!({
+        "image": "postgres:16",
+        "env_vars": ["POSTGRES_PASSWORD=hunter2", "PGDATA=/data", "BARE"],
+        "options": { "env": ["TOKEN=abc"] },
+    });
+    redact(&mut parameters);
+    assert_eq!(
+        parameters,
+        json!({
+            "image": "postgres:16",
+            "env_vars": ["POSTGRES_PASSWORD=***", "PGDATA=***", "BARE"],
+            "options": { "env": ["TOKEN=***"] },
+        })
+    );
+
+    let (dir, log) = log();
+    let ok: DockerResult<()> = Ok(());
+    log.record(
+        ENDPOINT,
+        "create_container",
+        "db",
+        json!({ "env_vars": ["POSTGRES_PASSWORD=hunter2"] }),
+        &ok,
+    );
+    let written = std::fs::read_to_string(dir.path().join("audit.jsonl")).unwrap();
+    assert!(!written.contains("hunter2"));
+    assert!(written.contains("POSTGRES_PASSWORD=***"));
+}
+
+#[test]
+fn build_arg_values_are_masked() {
+    let mut parameters = json!({
+        "tag": "shop/api:dev",
+        "build_args": { "NPM_TOKEN": "npm_secret", "VERSION": "1.2" },
+        "request": { "build": { "build_args": { "GITHUB_TOKEN": "ghp_secret" } } },
+    });
+    redact(&mut parameters);
+    assert_eq!(
+        parameters,
+        json!({
+            "tag": "shop/api:dev",
+            "build_args": { "NPM_TOKEN": "***", "VERSION": "***" },
+            "request": { "build": { "build_args": { "GITHUB_TOKEN": "***" } } },
+        })
+    );
+}
+
+#[test]
+fn exports_json_lines_and_csv() {
+    let (dir, log) = log();
+    log.append(&record(0, "start_container", "web", AuditOutcome::Success))
+        .unwrap();
+    let mut failed = record(60, "remove_image", "nginx:latest", AuditOutcome::Failure);
+    failed.parameters = json!({ "force": true, "note": "a,b" });
+    failed.error = Some("Operation error: image is \"in use\"".to_string());
+    log.append(&failed).unwrap();
+
+    let json_path = dir.path().join("audit.ndjson");
+    let count = log
+        .export(&AuditFilter::default(), &json_path, AuditExportFormat::Json)
+        .unwrap();
+    assert_eq!(count, 2);
+    let lines: Vec<AuditRecord> = std::fs::read_to_string(&json_path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    // Oldest first, like the file itself
+    assert_eq!(lines[0].action, "start_container");
+    assert_eq!(lines[1], failed);
+
+    let csv_path = dir.path().join("audit.csv");
+    log.export(&AuditFilter::default(), &csv_path, AuditExportFormat::Csv)
+        .unwrap();
+    let csv = std::fs::read_to_string(&csv_path).unwrap();
+    let rows: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        rows[0],
+        "time,endpoint,action,target,parameters,outcome,error"
+    );
+    assert_eq!(
+        rows[1],
+        format!(
+            "1970-01-01T00:00:00Z,{},start_container,web,{{}},success,",
+            ENDPOINT
+        )
+    );
+    assert_eq!(
+        rows[2],
+        format!(
+            "1970-01-01T00:01:00Z,{},remove_image,nginx:latest,\"{{\"\"force\"\":true,\"\"note\"\":\"\"a,b\"\"}}\",failure,\"Operation error: image is \"\"in use\"\"\"",
+            ENDPOINT
+        )
+    );
+
+    let filtered = AuditFilter {
+        outcome: Some(AuditOutcome::Success),
+        ..Default::default()
+    };
+    assert_eq!(
+        log.export(&filtered, &csv_path, AuditExportFormat::Csv)
+            .unwrap(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn jobs_and_schedules_are_recorded() {
+    let mock = MockDocker::start().await;
+    let (_dir, log) = log();
+
+    let jobs = JobManager::default();
+    let request = JobRequest::Pull {
+        image: common::MISSING_IMAGE.to_string(),
+        platform: None,
+    };
+    let job = jobs.submit(mock.client(), request.clone());
+    let done = jobs.wait(&job.id).await.unwrap();
+    log.record_job(ENDPOINT, &request, &done);
+
+    let schedule = Schedule {
+        name: "evening".to_string(),
+        cron: "0 19 * * *".to_string(),
+        target: ScheduleTarget::Project {
+            project: "shop".to_string(),
+        },
+        action: ScheduleAction::Stop,
+        enabled: true,
+    };
+    let run = rykard_lib::schedule::run(&mock.client(), &schedule, false).await;
+    log.record_schedule(ENDPOINT, &run);
+
+    let records = log.read(&AuditFilter::default()).unwrap();
+    let scheduled = &records[0];
+    assert_eq!(scheduled.action, "stop_container");
+    assert_eq!(scheduled.target, "project shop");
+    assert_eq!(scheduled.outcome, AuditOutcome::Success);
+    assert_eq!(
+        scheduled.parameters,
+        json!({ "schedule": "evening", "manual": false, "containers": ["web"] })
+    );
+
+    let pull = &records[1];
+    assert_eq!(pull.action, "pull_image");
+    assert_eq!(pull.target, common::MISSING_IMAGE);
+    assert_eq!(pull.outcome, AuditOutcome::Failure);
+    assert!(pull
+        .error
+        .as_deref()
+        .unwrap()
+        .contains("pull access denied"));
+    assert_eq!(pull.parameters["job"], json!(job.id));
+    assert_eq!(pull.parameters["kind"], "pull");
+}
+
+#[test]
+fn endpoint_names_the_configured_socket() {
+    let state = DockerState::with_socket("unix:///run/user/1000/docker.sock");
+    assert_eq!(state.endpoint(), "unix:///run/user/1000/docker.sock");
+}
@@ -0,0 +1,251 @@
+//! Volume backups: streaming through a helper container, checksums and restores.
+mod common;
+
+use common::{MockDocker, CREATED_ID, VOLUME};
+use flate2::read::GzDecoder;
+use hyper::Method;
+use rykard_lib::backup::{
+    backup_volume, checksum_path, restore_volume, RestoreOptions, VolumeOperation, BACKUP_LABEL,
+    HELPER_IMAGE,
+};
+use rykard_lib::docker::DockerError;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+
+fn volume_tar() -> Vec<u8> {
+    common::tar_archive(&common::volume_files())
+}
+
+#[tokio::test]
+async fn backup_compresses_the_volume_and_records_its_checksum() {
+    let mock = MockDocker::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("pgdata.tar.gz");
+
+    let mut progress = Vec::new();
+    let backup = backup_volume(&mock.client(), VOLUME, &path, |p| progress.push(p.clone()))
+        .await
+        .unwrap();
+
+    let archive = std::fs::read(&path).unwrap();
+    assert_eq!(backup.size, archive.len() as u64);
+    let mut tar = Vec::new();
+    GzDecoder::new(archive.as_slice())
+        .read_to_end(&mut tar)
+        .unwrap();
+    assert_eq!(tar, volume_tar());
+    assert!(archive.len() < tar.len());
+
+    assert_eq!(backup.sha256.len(), 64);
+    assert_eq!(
+        std::fs::read_to_string(checksum_path(&path)).unwrap(),
+        format!("{}  pgdata.tar.gz\n", backup.sha256)
+    );
+    assert!(!dir.path().join("pgdata.tar.gz.part").exists());
+
+    let last = progress.last().unwrap();
+    assert!(last.done);
+    assert_eq!(last.operation, VolumeOperation::Backup);
+    assert_eq!(last.bytes, backup.size);
+
+    // The helper mounts the volume read-only, is never started and is cleaned up
+    let create = mock.request(Method::POST, "/containers/create").unwrap();
+    let config: Value = serde_json::from_str(&create.body).unwrap();
+    assert_eq!(config["Image"], HELPER_IMAGE);
+    assert_eq!(config["HostConfig"]["Binds"][0], "pgdata:/volume:ro");
+    assert_eq!(config["Labels"][BACKUP_LABEL], VOLUME);
+    let download = mock
+        .request(Method::GET, &format!("/containers/{}/archive", CREATED_ID))
+        .unwrap();
+    assert_eq!(download.query["path"], "/volume");
+    assert!(mock
+        .request(Method::POST, &format!("/containers/{}/start", CREATED_ID))
+        .is_none());
+    assert!(mock
+        .request(Method::DELETE, &format!("/containers/{}", CREATED_ID))
+        .is_some());
+    // The helper image wasn't there yet
+    assert!(mock.request(Method::POST, "/images/create").is_some());
+}
+
+#[tokio::test]
+async fn backup_of_a_missing_volume_fails() {
+    let mock = MockDocker::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("ghost.tar.gz");
+
+    let err = backup_volume(&mock.client(), "ghost", &path, |_| {})
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DockerError::NotFound(_)));
+    assert!(!path.exists());
+    // Binding a missing volume would have created it
+    assert!(mock.request(Method::POST, "/containers/create").is_none());
+}
+
+#[tokio::test]
+async fn restore_verifies_then_streams_into_a_new_volume() {
+    let mock = MockDocker::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("pgdata.tar.gz");
+    let backup = backup_volume(&mock.client(), VOLUME, &path, |_| {})
+        .await
+        .unwrap();
+
+    let mut progress = Vec::new();
+    let restored = restore_volume(
+        &mock.client(),
+        &path,
+        "pgdata-copy",
+        &RestoreOptions::default(),
+        |p| progress.push(p.clone()),
+    )
+    .await
+    .unwrap();
+    assert!(restored.created);
+    assert_eq!(restored.sha256, backup.sha256);
+
+    let create = mock.request(Method::POST, "/volumes/create").unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(&create.body).unwrap()["Name"],
+        "pgdata-copy"
+    );
+    let upload = mock
+        .request(Method::PUT, &format!("/containers/{}/archive", CREATED_ID))
+        .unwrap();
+    assert_eq!(upload.query["path"], "/");
+    assert_eq!(upload.body.as_bytes(), volume_tar().as_slice());
+    let helper = mock
+        .requests()
+        .into_iter()
+        .rev()
+        .find(|r| r.path == "/containers/create")
+        .unwrap();
+    let config: Value = serde_json::from_str(&helper.body).unwrap();
+    assert_eq!(config["HostConfig"]["Binds"][0], "pgdata-copy:/volume:rw");
+
+    let last = progress.last().unwrap();
+    assert!(last.done);
+    assert_eq!(last.operation, VolumeOperation::Restore);
+    assert_eq!(last.total, Some(backup.size));
+    assert_eq!(last.bytes, backup.size);
+}
+
+#[tokio::test]
+async fn restore_refuses_existing_volumes_and_bad_checksums() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("pgdata.tar.gz");
+    let backup = backup_volume(&docker, VOLUME, &path, |_| {}).await.unwrap();
+
+    let err = restore_volume(&docker, &path, VOLUME, &RestoreOptions::default(), |_| {})
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Volume pgdata already exists"));
+    assert!(mock
+        .request(Method::PUT, &format!("/containers/{}/archive", CREATED_ID))
+        .is_none());
+
+    let overwrite = RestoreOptions {
+        overwrite: true,
+        ..Default::default()
+    };
+    let restored = restore_volume(&docker, &path, VOLUME, &overwrite, |_| {})
+        .await
+        .unwrap();
+    assert!(!restored.created);
+    assert!(mock.request(Method::POST, "/volumes/create").is_none());
+
+    // A checksum given explicitly wins over the file
+    let wrong = RestoreOptions {
+        sha256: Some("0".repeat(64)),
+        ..Default::default()
+    };
+    let err = restore_volume(&docker, &path, "other", &wrong, |_| {})
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Checksum mismatch"), "{}", err);
+    let given = RestoreOptions {
+        sha256: Some(backup.sha256.to_uppercase()),
+        ..Default::default()
+    };
+    assert!(restore_volume(&docker, &path, "other", &given, |_| {})
+        .await
+        .is_ok());
+
+    // A damaged archive is caught before any volume is created
+    let mut archive = std::fs::read(&path).unwrap();
+    let middle = archive.len() / 2;
+    archive[middle] ^= 0xff;
+    std::fs::write(&path, archive).unwrap();
+    let err = restore_volume(
+        &docker,
+        &path,
+        "damaged",
+        &RestoreOptions::default(),
+        |_| {},
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("Checksum mismatch"), "{}", err);
+
+    std::fs::remove_file(checksum_path(&path)).unwrap();
+    let err = restore_volume(
+        &docker,
+        &path,
+        "damaged",
+        &RestoreOptions::default(),
+        |_| {},
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("No checksum to verify"), "{}", err);
+
+    let volumes: Vec<String> = mock
+        .requests()
+        .into_iter()
+        .filter(|r| r.path == "/volumes/create")
+        .map(|r| serde_json::from_str::<Value>(&r.body).unwrap()["Name"].to_string())
+        .collect();
+    assert_eq!(volumes, ["\"other\""]);
+}
+
+#[tokio::test]
+async fn failed_restore_removes_the_volume_it_created() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("pgdata.tar.gz");
+    backup_volume(&docker, VOLUME, &path, |_| {}).await.unwrap();
+
+    // Cut off before the gzip trailer, with a checksum that matches what's left
+    let mut archive = std::fs::read(&path).unwrap();
+    archive.truncate(archive.len() - 4);
+    std::fs::write(&path, &archive).unwrap();
+    let options = RestoreOptions {
+        sha256: Some(format!("{:x}", Sha256::digest(&archive))),
+        ..Default::default()
+    };
+
+    assert!(
+        restore_volume(&docker, &path, "truncated", &options, |_| {})
+            .await
+            .is_err()
+    );
+    assert!(mock.request(Method::POST, "/volumes/create").is_some());
+    assert!(mock.request(Method::DELETE, "/volumes/truncated").is_some());
+
+    // A volume that was there before is left alone
+    let overwrite = RestoreOptions {
+        overwrite: true,
+        ..options
+    };
+    assert!(restore_volume(&docker, &path, VOLUME, &overwrite, |_| {})
+        .await
+        .is_err());
+    assert!(mock
+        .request(Method::DELETE, &format!("/volumes/{}", VOLUME))
+        .is_none());
+}
@@ -0,0 +1,20 @@
+//! Telling CLI invocations apart from GUI launches.
+use rykard_lib::cli;
+
+#[test]
+fn launches_without_a_subcommand_start_the_gui() {
+    assert_eq!(cli::run_with(["rykard"]), None);
+    // macOS passes a process serial number to apps started from Finder
+    assert_eq!(cli::run_with(["rykard", "-psn_0_1234567"]), None);
+    assert_eq!(cli::run_with(["rykard", "--unknown-flag"]), None);
+}
+
+#[test]
+fn bad_arguments_to_a_subcommand_are_cli_errors() {
+    assert_eq!(cli::run_with(["rykard", "ps", "--bogus"]), Some(2));
+    assert_eq!(
+        cli::run_with(["rykard", "--format", "yaml", "images"]),
+        Some(2)
+    );
+    assert_eq!(cli::run_with(["rykard", "--version"]), Some(0));
+}
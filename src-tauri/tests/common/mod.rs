@@ -0,0 +1,1037 @@
+//! In-process fake Docker Engine API served on a temporary unix socket.
+//!
+//! It answers the handful of endpoints rykard talks to with canned responses and
+//! records every request so tests can assert on what the backend sent.
+#![allow(dead_code)]
+
+use bollard::Docker;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use rykard_lib::docker::DockerState;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tokio::net::UnixListener;
+use tokio::task::JoinHandle;
+
+pub const WEB_ID: &str = "4f66ad9a0b2e2c1f7b1d6a2e8c3b5d7f9e1a3c5b7d9f1e3a5c7b9d1f3e5a7c9b";
+pub const DB_ID: &str = "9b7c5a3e1f3d5b7a9c1e3f5d7b9a1c3e5f7d9b1a3c5e7f9d1b3a5c7e9f1d3b5a";
+pub const IMAGE_ID: &str =
+    "sha256:2b0ab8f6e9a1c4d7e0f3a6b9c2d5e8f1a4b7c0d3e6f9a2b5c8d1e4f7a0b3c6d9";
+/// What the db container runs: `postgres:16`, labelled `rykard.protected=true`
+pub const DB_IMAGE_ID: &str = "sha256:feed";
+pub const CREATED_ID: &str = "c0ffee00c0ffee00c0ffee00c0ffee00c0ffee00c0ffee00c0ffee00c0ffee00";
+pub const IMPORTED_IMAGE_ID: &str =
+    "sha256:1a2b3c4d1a2b3c4d1a2b3c4d1a2b3c4d1a2b3c4d1a2b3c4d1a2b3c4d1a2b3c4d";
+/// Following this container's logs floods the reader with `FLOOD_LINES` new lines
+pub const CHATTY_ID: &str = "chatty";
+pub const FLOOD_LINES: usize = 2000;
+/// Size of the tar `/containers/{id}/export` returns: a megabyte and a half
+pub const EXPORT_SIZE: usize = 1024 * 1024 * 3 / 2;
+
+/// Image ID the mock reports at the end of every build
+pub const BUILT_IMAGE_ID: &str =
+    "sha256:b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0";
+
+/// Digest `nginx` was pulled at, still what `nginx:1.27` resolves to
+pub const PULLED_DIGEST: &str =
+    "sha256:0f0e0d0c0b0a09080706050403020100ffeeddccbbaa99887766554433221100";
+/// What `nginx:latest` resolves to in the registry since a newer release
+pub const LATEST_DIGEST: &str =
+    "sha256:1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a";
+/// Image `nginx:latest` points at once it has been pulled again
+pub const UPDATED_IMAGE_ID: &str =
+    "sha256:2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c2c";
+
+/// An image pulled from a local `registry:2` on port 5000
+pub const REGISTRY_TAG: &str = "localhost:5000/shop/api:1.0";
+pub const REGISTRY_IMAGE_ID: &str =
+    "sha256:3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d3d";
+pub const REGISTRY_DIGEST: &str =
+    "sha256:4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e4e";
+
+/// Pulling `slow:<ms>` takes `<ms>` milliseconds
+pub const SLOW_IMAGE: &str = "slow";
+
+/// Containers created from this image exit right after starting
+pub const CRASHING_IMAGE: &str = "nginx:broken";
+
+/// Repository the mock files committed images under
+pub const COMMITTED_REPO: &str = "debug/web";
+pub const COMMITTED_IMAGE_ID: &str =
+    "sha256:5eed5eed5eed5eed5eed5eed5eed5eed5eed5eed5eed5eed5eed5eed5eed5eed";
+
+/// Image reference the fake registry refuses to pull
+pub const MISSING_IMAGE: &str = "does-not-exist";
+/// A 32-bit ARM image, as pulled with `--platform linux/arm/v7` on an x86 host
+pub const ARM_IMAGE: &str = "arm32v7/nginx:latest";
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub body: String,
+    /// The body as sent, for binary payloads such as compressed build contexts
+    pub raw: Bytes,
+}
+
+pub struct MockDocker {
+    socket: PathBuf,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    server: JoinHandle<()>,
+    // Keeps the socket directory alive for as long as the server runs
+    _dir: TempDir,
+}
+
+impl MockDocker {
+    pub async fn start() -> Self {
+        let dir = tempfile::tempdir().expect("failed to create socket dir");
+        let socket = dir.path().join("docker.sock");
+        let listener = UnixListener::bind(&socket).expect("failed to bind mock socket");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let log = requests.clone();
+        let server = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let log = log.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req| handle(req, log.clone()));
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        Self {
+            socket,
+            requests,
+            server,
+            _dir: dir,
+        }
+    }
+
+    pub fn socket_path(&self) -> String {
+        self.socket.to_string_lossy().to_string()
+    }
+
+    /// A `DockerState` pointed at this daemon, not yet initialized
+    pub fn state(&self) -> DockerState {
+        DockerState::with_socket(self.socket_path())
+    }
+
+    pub fn client(&self) -> Docker {
+        let mut state = self.state();
+        state.initialize();
+        state.get_client().expect("mock client should connect")
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The first recorded request matching `method` and `path`
+    pub fn request(&self, method: Method, path: &str) -> Option<RecordedRequest> {
+        self.requests()
+            .into_iter()
+            .find(|r| r.method == method && r.path == path)
+    }
+}
+
+impl Drop for MockDocker {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn handle(
+    req: Request<Incoming>,
+    log: Arc<Mutex<Vec<RecordedRequest>>>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let method = req.method().clone();
+    let path = strip_version(req.uri().path()).to_string();
+    let query = parse_query(req.uri().query().unwrap_or_default());
+    let raw = req
+        .into_body()
+        .collect()
+        .await
+        .map(|b| b.to_bytes())
+        .unwrap_or_default();
+    let body = String::from_utf8_lossy(&raw).to_string();
+
+    // Pulls of `slow:<ms>` take that long to answer, to keep jobs busy
+    if method == Method::POST && path == "/images/create" {
+        if let (Some(SLOW_IMAGE), Some(ms)) = (
+            query.get("fromImage").map(String::as_str),
+            query.get("tag").and_then(|t| t.parse().ok()),
+        ) {
+            tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+        }
+    }
+
+    let history = log.lock().unwrap().clone();
+    let response = route(&method, &path, &query, &body, &history);
+    log.lock().unwrap().push(RecordedRequest {
+        method,
+        path,
+        query,
+        body,
+        raw,
+    });
+
+    Ok(response)
+}
+
+/// Answer a request; `history` holds the requests before it, for the few routes whose
+/// answer depends on what happened earlier
+fn route(
+    method: &Method,
+    path: &str,
+    query: &HashMap<String, String>,
+    body: &str,
+    history: &[RecordedRequest],
+) -> Response<Full<Bytes>> {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    // Pulling `nginx` again moves its tags to a newer image
+    let pulled_nginx = history.iter().any(|r| {
+        r.method == Method::POST
+            && r.path == "/images/create"
+            && r.query.get("fromImage").map(String::as_str) == Some("nginx")
+    });
+    // The last container created runs this image
+    let created_image = history
+        .iter()
+        .rev()
+        .find(|r| r.method == Method::POST && r.path == "/containers/create")
+        .and_then(|r| serde_json::from_str::<Value>(&r.body).ok())
+        .and_then(|body| body["Image"].as_str().map(str::to_string));
+
+    match (method.as_str(), segments.as_slice()) {
+        ("GET", ["_ping"]) | ("HEAD", ["_ping"]) => text(StatusCode::OK, "OK"),
+        ("GET", ["containers", "json"]) => {
+            let all = query.get("all").map(|v| v == "true").unwrap_or(false);
+            let filters: HashMap<String, Vec<String>> = query
+                .get("filters")
+                .and_then(|f| serde_json::from_str(f).ok())
+                .unwrap_or_default();
+            let containers: Vec<Value> = current_containers(history)
+                .into_iter()
+                .filter(|c| all || c["State"] == "running")
+                .filter(|c| {
+                    filters.get("ancestor").is_none_or(|images| {
+                        images
+                            .iter()
+                            .any(|image| c["Image"] == *image || c["ImageID"] == *image)
+                    })
+                })
+                .collect();
+            json_response(StatusCode::OK, &Value::Array(containers))
+        }
+        ("POST", ["containers", "create"]) => json_response(
+            StatusCode::CREATED,
+            &json!({ "Id": CREATED_ID, "Warnings": [] }),
+        ),
+        ("POST", ["commit"]) => match query.get("container") {
+            Some(id) if is_known_container(id) => {
+                json_response(StatusCode::CREATED, &json!({ "Id": COMMITTED_IMAGE_ID }))
+            }
+            Some(id) => not_found(id),
+            None => not_found(""),
+        },
+        // Only its follow stream has anything in it
+        ("GET", ["containers", CHATTY_ID, "logs"]) => {
+            let follow = query.get("follow").map(String::as_str) == Some("true");
+            raw_stream(
+                (0..FLOOD_LINES)
+                    .filter(|_| follow)
+                    .map(|i| (1, format!("2024-01-01T00:01:00.000000000Z flood {}\n", i)))
+                    .collect(),
+            )
+        }
+        (_, ["containers", id, ..]) if !is_known_container(id) => not_found(id),
+        ("POST", ["containers", _, "start" | "stop" | "restart"]) => empty(StatusCode::NO_CONTENT),
+        ("POST", ["containers", _, "rename"]) => empty(StatusCode::NO_CONTENT),
+        ("DELETE", ["containers", _]) => empty(StatusCode::NO_CONTENT),
+        ("GET", ["containers", id, "json"])
+            if history
+                .iter()
+                .any(|r| r.method == Method::DELETE && r.path == format!("/containers/{}", id)) =>
+        {
+            not_found(id)
+        }
+        ("GET", ["containers", id, "json"])
+            if CREATED_ID.starts_with(id) && created_image.as_deref() == Some(CRASHING_IMAGE) =>
+        {
+            let mut container = inspect_json(id);
+            container["State"] = json!({ "Status": "exited", "Running": false, "ExitCode": 137 });
+            json_response(StatusCode::OK, &container)
+        }
+        ("GET", ["containers", id, "json"]) => {
+            // Live updates land in the host config
+            let mut container = inspect_json(id);
+            let updates = history
+                .iter()
+                .filter(|r| r.method == Method::POST && r.path.ends_with("/update"))
+                .filter_map(|r| serde_json::from_str::<Value>(&r.body).ok());
+            for update in updates {
+                for (key, value) in update.as_object().into_iter().flatten() {
+                    container["HostConfig"][key] = value.clone();
+                }
+            }
+            json_response(StatusCode::OK, &container)
+        }
+        ("POST", ["containers", _, "update"]) => {
+            json_response(StatusCode::OK, &json!({ "Warnings": [] }))
+        }
+        ("POST", ["networks", _, "connect"]) => empty(StatusCode::OK),
+        // Containers created from an image for scanning export a small root filesystem
+        ("GET", ["containers", id, "export"]) if CREATED_ID.starts_with(id) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/x-tar")
+            .body(Full::new(Bytes::from(tar_archive(&image_filesystem()))))
+            .unwrap(),
+        ("GET", ["containers", _, "export"]) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/x-tar")
+            .body(Full::new(Bytes::from(vec![b'x'; EXPORT_SIZE])))
+            .unwrap(),
+        // Volume helpers read the volume's files, and take them back
+        ("GET", ["containers", _, "archive"]) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/x-tar")
+            .body(Full::new(Bytes::from(tar_archive(&volume_files()))))
+            .unwrap(),
+        ("PUT", ["containers", _, "archive"]) => empty(StatusCode::OK),
+        ("GET", ["containers", _, "changes"]) => json_response(StatusCode::OK, &changes()),
+        ("GET", ["containers", _, "stats"]) => json_response(StatusCode::OK, &stats_json()),
+        ("GET", ["containers", _, "logs"]) => {
+            let tail: usize = query
+                .get("tail")
+                .and_then(|t| t.parse().ok())
+                .unwrap_or(usize::MAX);
+            let timestamps = query
+                .get("timestamps")
+                .map(|v| v == "true")
+                .unwrap_or(false);
+            let frames = log_lines(tail)
+                .into_iter()
+                .map(|(stream, line)| {
+                    if timestamps {
+                        let n = line.trim_end().trim_start_matches("line ").to_string();
+                        (
+                            stream,
+                            format!("2024-01-01T00:00:0{}.000000000Z {}", n, line),
+                        )
+                    } else {
+                        (stream, line)
+                    }
+                })
+                .collect();
+            raw_stream(frames)
+        }
+        ("GET", ["images", "json"]) => json_response(StatusCode::OK, &json!([image_json()])),
+        // Image names may contain slashes, so match everything between the prefix and `/json`
+        ("GET", ["images", name @ .., "json"]) if !name.is_empty() => {
+            let name = name.join("/");
+            if name == REGISTRY_TAG {
+                let mut image = image_inspect_json();
+                image["Id"] = json!(REGISTRY_IMAGE_ID);
+                image["RepoTags"] = json!([REGISTRY_TAG]);
+                image["RepoDigests"] =
+                    json!([format!("localhost:5000/shop/api@{}", REGISTRY_DIGEST)]);
+                json_response(StatusCode::OK, &image)
+            } else if name == ARM_IMAGE {
+                let mut image = image_inspect_json();
+                image["RepoTags"] = json!([ARM_IMAGE]);
+                image["Architecture"] = json!("arm");
+                image["Variant"] = json!("v7");
+                json_response(StatusCode::OK, &image)
+            } else if name.starts_with(COMMITTED_REPO) {
+                let mut image = image_inspect_json();
+                image["Id"] = json!(COMMITTED_IMAGE_ID);
+                image["RepoTags"] = json!([name]);
+                json_response(StatusCode::OK, &image)
+            } else if name == "postgres:16" || name == DB_IMAGE_ID {
+                let mut image = image_inspect_json();
+                image["Id"] = json!(DB_IMAGE_ID);
+                image["RepoTags"] = json!(["postgres:16"]);
+                image["Config"]["Labels"] = json!({ "rykard.protected": "true" });
+                json_response(StatusCode::OK, &image)
+            } else if is_known_image(&name) {
+                let mut image = image_inspect_json();
+                if name.starts_with("nginx") && pulled_nginx {
+                    image["Id"] = json!(UPDATED_IMAGE_ID);
+                }
+                json_response(StatusCode::OK, &image)
+            } else {
+                json_response(
+                    StatusCode::NOT_FOUND,
+                    &json!({ "message": format!("No such image: {}", name) }),
+                )
+            }
+        }
+        // What a tag resolves to in its registry
+        ("GET", ["distribution", name @ .., "json"]) if !name.is_empty() => {
+            let name = name.join("/");
+            let digest = match name.as_str() {
+                "nginx" | "nginx:latest" => LATEST_DIGEST,
+                "nginx:1.27" => PULLED_DIGEST,
+                REGISTRY_TAG => REGISTRY_DIGEST,
+                _ => {
+                    return json_response(
+                        StatusCode::UNAUTHORIZED,
+                        &json!({ "message": format!("pull access denied for {}", name) }),
+                    )
+                }
+            };
+            json_response(
+                StatusCode::OK,
+                &json!({
+                    "Descriptor": {
+                        "mediaType": "application/vnd.oci.image.index.v1+json",
+                        "digest": digest,
+                        "size": 10_229
+                    },
+                    "Platforms": [
+                        { "architecture": "amd64", "os": "linux" },
+                        { "architecture": "arm64", "os": "linux", "variant": "v8" },
+                        { "architecture": "arm", "os": "linux", "variant": "v7" }
+                    ]
+                }),
+            )
+        }
+        ("POST", ["images", "create"]) if query.get("fromSrc").map(String::as_str) == Some("-") => {
+            match query.get("changes").filter(|c| c.contains("BOGUS")) {
+                Some(_) => json_response(
+                    StatusCode::BAD_REQUEST,
+                    &json!({ "message": "bogus is not a valid change command" }),
+                ),
+                None => json_lines(vec![json!({ "status": IMPORTED_IMAGE_ID })]),
+            }
+        }
+        ("POST", ["images", "create"]) => {
+            let image = query.get("fromImage").cloned().unwrap_or_default();
+            json_lines(pull_progress(&image))
+        }
+        ("POST", ["images", name @ .., "push"]) if !name.is_empty() => {
+            if name.join("/") == "localhost:5000/shop/api" {
+                json_lines(vec![
+                    json!({ "status": "The push refers to repository [localhost:5000/shop/api]" }),
+                    json!({ "status": "Pushing", "id": "a1b2c3d4e5f6", "progressDetail": { "current": 512, "total": 1024 } }),
+                    json!({ "status": "Pushed", "id": "a1b2c3d4e5f6" }),
+                    json!({ "status": format!("1.0: digest: {} size: 528", REGISTRY_DIGEST) }),
+                ])
+            } else {
+                json_lines(vec![json!({
+                    "error": "denied: requested access to the resource is denied",
+                    "errorDetail": { "message": "denied: requested access to the resource is denied" }
+                })])
+            }
+        }
+        ("POST", ["images", "prune"]) => json_response(
+            StatusCode::OK,
+            &json!({
+                "ImagesDeleted": [{ "Untagged": "old:1" }, { "Deleted": "sha256:0ld" }],
+                "SpaceReclaimed": 52_428_800
+            }),
+        ),
+        ("POST", ["volumes", "create"]) => {
+            let name = serde_json::from_str::<Value>(body)
+                .ok()
+                .and_then(|body| body["Name"].as_str().map(str::to_string))
+                .unwrap_or_default();
+            json_response(
+                StatusCode::CREATED,
+                &json!({ "Name": name, "Driver": "local", "Mountpoint": format!("/var/lib/docker/volumes/{}/_data", name), "Scope": "local", "Labels": {}, "Options": {} }),
+            )
+        }
+        ("GET", ["volumes", name]) if *name == VOLUME => json_response(
+            StatusCode::OK,
+            &json!({ "Name": VOLUME, "Driver": "local", "Mountpoint": format!("/var/lib/docker/volumes/{}/_data", VOLUME), "Scope": "local", "Labels": {}, "Options": {} }),
+        ),
+        ("GET", ["volumes", name]) => json_response(
+            StatusCode::NOT_FOUND,
+            &json!({ "message": format!("get {}: no such volume", name) }),
+        ),
+        ("DELETE", ["volumes", _]) => empty(StatusCode::NO_CONTENT),
+        ("POST", ["volumes", "prune"]) => json_response(
+            StatusCode::OK,
+            &json!({ "VolumesDeleted": [], "SpaceReclaimed": 0 }),
+        ),
+        ("POST", ["networks", "prune"]) => json_response(
+            StatusCode::OK,
+            &json!({ "NetworksDeleted": ["shop_default"] }),
+        ),
+        // The stopped db container still uses postgres, so only forcing untags it
+        ("DELETE", ["images", "postgres:16"])
+            if query.get("force").map(String::as_str) != Some("true") =>
+        {
+            json_response(
+                StatusCode::CONFLICT,
+                &json!({ "message": "conflict: unable to remove repository reference \"postgres:16\" (must force) - container 9b7c5a3e1f3d is using its referenced image feed" }),
+            )
+        }
+        ("DELETE", ["images", "postgres:16"]) => {
+            json_response(StatusCode::OK, &json!([{ "Untagged": "postgres:16" }]))
+        }
+        ("DELETE", ["images", id]) => {
+            if IMAGE_ID.trim_start_matches("sha256:").starts_with(*id) || *id == "nginx:latest" {
+                json_response(StatusCode::OK, &json!([{ "Deleted": IMAGE_ID }]))
+            } else {
+                not_found(id)
+            }
+        }
+        ("POST", ["build"]) => json_lines(vec![
+            json!({ "stream": "Step 1/2 : FROM alpine:3.20\n" }),
+            json!({ "stream": "Step 2/2 : COPY . /app\n" }),
+            json!({ "aux": { "ID": BUILT_IMAGE_ID } }),
+            json!({ "stream": format!("Successfully built {}\n", &BUILT_IMAGE_ID[7..19]) }),
+        ]),
+        ("GET", ["events"]) => json_lines(events()),
+        _ => json_response(
+            StatusCode::NOT_FOUND,
+            &json!({ "message": format!("page not found: {} {}", method, path) }),
+        ),
+    }
+}
+
+fn is_known_container(id: &str) -> bool {
+    [WEB_ID, DB_ID, CREATED_ID]
+        .iter()
+        .any(|known| known.starts_with(id))
+        || id == "web"
+        || id == "db"
+}
+
+fn is_known_image(name: &str) -> bool {
+    let tags = ["nginx", "nginx:latest", "nginx:1.27", CRASHING_IMAGE];
+    tags.contains(&name)
+        || (name.len() >= 4
+            && IMAGE_ID
+                .trim_start_matches("sha256:")
+                .starts_with(name.trim_start_matches("sha256:")))
+}
+
+fn strip_version(path: &str) -> &str {
+    match path.strip_prefix("/v1.") {
+        Some(rest) => rest.find('/').map(|i| &rest[i..]).unwrap_or("/"),
+        None => path,
+    }
+}
+
+/// Decode a query string; a repeated key's values are joined with newlines
+fn parse_query(query: &str) -> HashMap<String, String> {
+    let mut parsed: HashMap<String, String> = HashMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        parsed
+            .entry(percent_decode(key))
+            .and_modify(|values| {
+                values.push('\n');
+                values.push_str(&percent_decode(value));
+            })
+            .or_insert_with(|| percent_decode(value));
+    }
+    parsed
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(b) => out.push(b),
+                    Err(_) => out.extend_from_slice(&bytes[i..i + 3]),
+                }
+                i += 3;
+            }
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+fn empty(status: StatusCode) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .body(Full::new(Bytes::new()))
+        .unwrap()
+}
+
+fn text(status: StatusCode, body: &str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap()
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap()
+}
+
+/// Newline-delimited JSON, the way the daemon streams pulls and events
+fn json_lines(items: Vec<Value>) -> Response<Full<Bytes>> {
+    let body: String = items.iter().map(|item| format!("{}\n", item)).collect();
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+/// Multiplexed stdout/stderr frames as returned by `/containers/{id}/logs`
+fn raw_stream(frames: Vec<(u8, String)>) -> Response<Full<Bytes>> {
+    let mut body = Vec::new();
+    for (stream, line) in frames {
+        body.extend_from_slice(&[stream, 0, 0, 0]);
+        body.extend_from_slice(&(line.len() as u32).to_be_bytes());
+        body.extend_from_slice(line.as_bytes());
+    }
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/vnd.docker.multiplexed-stream")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+fn not_found(id: &str) -> Response<Full<Bytes>> {
+    json_response(
+        StatusCode::NOT_FOUND,
+        &json!({ "message": format!("No such container: {}", id) }),
+    )
+}
+
+/// A tar archive of regular files. Names over 100 bytes get a GNU long-name entry,
+/// like GNU tar and the daemon write them.
+pub fn tar_archive(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    fn header(name: &[u8], size: usize, typeflag: u8) -> [u8; 512] {
+        let mut header = [0u8; 512];
+        header[..name.len().min(100)].copy_from_slice(&name[..name.len().min(100)]);
+        header[100..107].copy_from_slice(b"0000644");
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[136..147].copy_from_slice(b"14612345670");
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar ");
+        header[263..265].copy_from_slice(b" \0");
+        header[148..156].fill(b' ');
+        let sum: u32 = header.iter().map(|&b| b as u32).sum();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        header
+    }
+    fn data(out: &mut Vec<u8>, bytes: &[u8]) {
+        out.extend_from_slice(bytes);
+        out.resize(out.len().div_ceil(512) * 512, 0);
+    }
+
+    let mut out = Vec::new();
+    for (name, contents) in files {
+        if name.len() > 100 {
+            let long = format!("{}\0", name);
+            out.extend_from_slice(&header(b"././@LongLink", long.len(), b'L'));
+            data(&mut out, long.as_bytes());
+        }
+        out.extend_from_slice(&header(name.as_bytes(), contents.len(), b'0'));
+        data(&mut out, contents);
+    }
+    out.resize(out.len() + 1024, 0);
+    out
+}
+
+pub const DPKG_STATUS: &str = "\
+Package: libssl3
+Status: install ok installed
+Priority: optional
+Architecture: amd64
+Source: openssl (3.0.11-1~deb12u1)
+Version: 3.0.11-1~deb12u1
+Description: Secure Sockets Layer toolkit
+ This package is part of the OpenSSL project.
+
+Package: old-tool
+Status: deinstall ok config-files
+Architecture: amd64
+Version: 1.0-1
+
+Package: base-files
+Status: install ok installed
+Architecture: amd64
+Version: 12.4+deb12u5
+";
+
+/// Files `image_filesystem` puts under a deeply nested `node_modules`
+pub const NESTED_MODULE: &str = "app/node_modules/@scope/toolkit/node_modules/deeply-nested-dependency-with-a-long-name/node_modules/left-pad";
+
+/// The named volume the backup tests copy
+pub const VOLUME: &str = "pgdata";
+
+/// What the daemon's archive endpoint returns for the volume mounted at `/volume`
+pub fn volume_files() -> Vec<(String, Vec<u8>)> {
+    vec![
+        ("volume/PG_VERSION".to_string(), b"16\n".to_vec()),
+        (
+            "volume/postgresql.conf".to_string(),
+            "listen_addresses = '*'\n".repeat(200).into_bytes(),
+        ),
+        ("volume/base/1/112".to_string(), b"page".repeat(512)),
+    ]
+}
+
+/// Root filesystem of the image the scan tests read
+pub fn image_filesystem() -> Vec<(String, Vec<u8>)> {
+    let file = |name: &str, contents: &str| (name.to_string(), contents.as_bytes().to_vec());
+    vec![
+        file("etc/hostname", ""),
+        file(
+            "etc/os-release",
+            "PRETTY_NAME=\"Debian GNU/Linux 12 (bookworm)\"\nID=debian\nVERSION_ID=\"12\"\n",
+        ),
+        file("var/lib/dpkg/status", DPKG_STATUS),
+        file(
+            "app/node_modules/express/package.json",
+            r#"{"name":"express","version":"4.17.1","license":"MIT"}"#,
+        ),
+        file(
+            "app/node_modules/@scope/toolkit/package.json",
+            r#"{"name":"@scope/toolkit","version":"2.0.0-beta.1","license":{"type":"ISC"}}"#,
+        ),
+        file(
+            &format!("{}/package.json", NESTED_MODULE),
+            r#"{"name":"left-pad","version":"1.3.0"}"#,
+        ),
+        // Not a dependency manifest
+        file("app/package.json", r#"{"name":"app","version":"1.0.0"}"#),
+        file(
+            "usr/lib/python3/dist-packages/PyYAML-6.0.1.dist-info/METADATA",
+            "Metadata-Version: 2.1\nName: PyYAML\nVersion: 6.0.1\nLicense: MIT\n\nYAML parser\n",
+        ),
+        file(
+            "srv/Cargo.lock",
+            "version = 3\n\n[[package]]\nname = \"srv\"\nversion = \"0.1.0\"\ndependencies = [\n \"serde\",\n]\n\n\
+             [[package]]\nname = \"serde\"\nversion = \"1.0.200\"\n\
+             source = \"registry+https://github.com/rust-lang/crates.io-index\"\n",
+        ),
+    ]
+}
+
+/// The containers as earlier requests left them: renamed ones under their new name and
+/// removed ones gone
+fn current_containers(history: &[RecordedRequest]) -> Vec<Value> {
+    let mut containers = containers_json();
+    for request in history {
+        let segments: Vec<&str> = request.path.trim_start_matches('/').split('/').collect();
+        let (id, renamed) = match (request.method.as_str(), segments.as_slice()) {
+            ("POST", ["containers", id, "rename"]) => (*id, request.query.get("name")),
+            ("DELETE", ["containers", id]) => (*id, None),
+            _ => continue,
+        };
+        let position = containers.iter().position(|c| {
+            c["Id"].as_str().is_some_and(|known| known.starts_with(id))
+                || c["Names"][0] == format!("/{}", id)
+        });
+        match (position, renamed) {
+            (Some(i), Some(name)) => containers[i]["Names"] = json!([format!("/{}", name)]),
+            (Some(i), None) => {
+                containers.remove(i);
+            }
+            (None, _) => {}
+        }
+    }
+    containers
+}
+
+pub fn containers_json() -> Vec<Value> {
+    vec![
+        json!({
+            "Id": WEB_ID,
+            "Names": ["/web"],
+            "Image": "nginx:latest",
+            "ImageID": IMAGE_ID,
+            "Command": "nginx -g 'daemon off;'",
+            "Created": 1_700_000_000,
+            "State": "running",
+            "Status": "Up 2 hours",
+            "Ports": [
+                { "IP": "0.0.0.0", "PrivatePort": 80, "PublicPort": 8080, "Type": "tcp" },
+                { "PrivatePort": 443, "Type": "tcp" }
+            ],
+            "Labels": {
+                "com.docker.compose.project": "shop",
+                "com.docker.compose.service": "web"
+            }
+        }),
+        json!({
+            "Id": DB_ID,
+            "Names": ["/db"],
+            "Image": "postgres:16",
+            "ImageID": DB_IMAGE_ID,
+            "Command": "docker-entrypoint.sh postgres",
+            "Created": 1_690_000_000,
+            "State": "exited",
+            "Status": "Exited (0) 3 days ago",
+            "Ports": [],
+            "Labels": {}
+        }),
+    ]
+}
+
+pub fn image_json() -> Value {
+    json!({
+        "Id": IMAGE_ID,
+        "ParentId": "",
+        "RepoTags": ["nginx:latest", "nginx:1.27"],
+        "RepoDigests": [format!("nginx@{}", PULLED_DIGEST)],
+        "Created": 1_699_000_000,
+        "Size": 187_654_321,
+        "SharedSize": -1,
+        "Labels": {},
+        "Containers": 1
+    })
+}
+
+/// `/images/{name}/json`, which unlike the listing has a string `Created`
+pub fn image_inspect_json() -> Value {
+    let summary = image_json();
+    json!({
+        "Id": IMAGE_ID,
+        "RepoTags": summary["RepoTags"],
+        "RepoDigests": summary["RepoDigests"],
+        "Created": "2023-11-03T08:26:40.000000000Z",
+        "Size": summary["Size"],
+        "Architecture": "amd64",
+        "Os": "linux",
+        "Config": {
+            "Cmd": ["nginx", "-g", "daemon off;"],
+            "Env": ["PATH=/usr/local/sbin:/usr/local/bin"],
+            "ExposedPorts": { "80/tcp": {} }
+        },
+        "RootFS": { "Type": "layers", "Layers": [] }
+    })
+}
+
+pub fn inspect_json(id: &str) -> Value {
+    json!({
+        "Id": if WEB_ID.starts_with(id) || id == "web" { WEB_ID } else { id },
+        "Name": "/web",
+        "Created": "2023-11-14T22:13:20.000000000Z",
+        "Path": "nginx",
+        "Args": ["-g", "daemon off;"],
+        "State": {
+            "Status": "running",
+            "Running": true,
+            "Pid": 4242,
+            "ExitCode": 0,
+            "OOMKilled": false,
+            "StartedAt": "2024-01-01T00:00:00Z",
+            "Health": {
+                "Status": "healthy",
+                "FailingStreak": 0,
+                "Log": [
+                    { "ExitCode": 1, "Output": "connection refused" },
+                    { "ExitCode": 0, "Output": "ok" }
+                ]
+            }
+        },
+        "RestartCount": 2,
+        "SizeRootFs": 1_500_000,
+        "Image": IMAGE_ID,
+        "Config": {
+            "Hostname": "web-host",
+            "User": "nginx",
+            "WorkingDir": "/usr/share/nginx",
+            "Image": "nginx:latest",
+            "Entrypoint": ["/docker-entrypoint.sh"],
+            "Cmd": ["nginx", "-g", "daemon off;"],
+            "Env": ["PATH=/usr/local/sbin:/usr/local/bin", "NGINX_VERSION=1.27.0"],
+            "Labels": { "com.docker.compose.project": "shop" },
+            "Healthcheck": {
+                "Test": ["CMD-SHELL", "curl -f http://localhost/ || exit 1"],
+                "Interval": 30_000_000_000u64,
+                "Timeout": 5_000_000_000u64,
+                "StartPeriod": 0,
+                "Retries": 3
+            }
+        },
+        "HostConfig": {
+            "NetworkMode": "bridge",
+            "Privileged": false,
+            "CapAdd": ["NET_ADMIN"],
+            "CapDrop": ["MKNOD"],
+            "SecurityOpt": ["no-new-privileges"],
+            "Devices": [{
+                "PathOnHost": "/dev/fuse",
+                "PathInContainer": "/dev/fuse",
+                "CgroupPermissions": "rwm"
+            }],
+            "Tmpfs": { "/run": "rw,size=64m" },
+            "Ulimits": [{ "Name": "nofile", "Soft": 1024, "Hard": 4096 }],
+            "LogConfig": { "Type": "json-file", "Config": { "max-size": "10m" } },
+            "Dns": ["1.1.1.1"],
+            "DnsSearch": [],
+            "ExtraHosts": ["db.internal:10.0.0.5"],
+            "RestartPolicy": { "Name": "always", "MaximumRetryCount": 0 },
+            "Memory": 268_435_456,
+            "MemorySwap": 536_870_912,
+            "CpuShares": 0,
+            "NanoCpus": 1_500_000_000,
+            "CpusetCpus": "",
+            "PidsLimit": 0,
+            "BlkioWeight": 0
+        },
+        "NetworkSettings": {
+            // Loopback so tests can reach "container" ports with a local listener
+            "IPAddress": "127.0.0.1",
+            "Networks": {
+                "bridge": { "IPAddress": "127.0.0.1", "Gateway": "127.0.0.1" },
+                "shop_default": {
+                    "Aliases": ["web", &WEB_ID[..12]],
+                    "NetworkID": "5hop",
+                    "IPAddress": "127.0.0.1",
+                    "Gateway": "127.0.0.1",
+                    "MacAddress": "02:42:ac:12:00:02"
+                }
+            },
+            "Ports": {
+                "80/tcp": [{ "HostIp": "0.0.0.0", "HostPort": "8080" }],
+                "443/tcp": null
+            }
+        },
+        "Mounts": [{
+            "Type": "bind",
+            "Source": "/srv/www",
+            "Destination": "/usr/share/nginx/html",
+            "Mode": "ro",
+            "RW": false
+        }]
+    })
+}
+
+/// A stats sample worth 20% CPU over two cores and 25% memory
+pub fn stats_json() -> Value {
+    json!({
+        "read": "2024-01-01T00:00:01Z",
+        "preread": "2024-01-01T00:00:00Z",
+        "num_procs": 0,
+        "pids_stats": { "current": 3 },
+        "networks": {
+            "eth0": {
+                "rx_bytes": 1000, "rx_packets": 10, "rx_errors": 0, "rx_dropped": 0,
+                "tx_bytes": 2000, "tx_packets": 20, "tx_errors": 0, "tx_dropped": 0
+            },
+            "eth1": {
+                "rx_bytes": 24, "rx_packets": 1, "rx_errors": 0, "rx_dropped": 0,
+                "tx_bytes": 48, "tx_packets": 1, "tx_errors": 0, "tx_dropped": 0
+            }
+        },
+        "memory_stats": { "usage": 52_428_800u64, "limit": 209_715_200u64 },
+        "blkio_stats": {
+            "io_service_bytes_recursive": [
+                { "major": 8, "minor": 0, "op": "Read", "value": 4096 },
+                { "major": 8, "minor": 0, "op": "Write", "value": 8192 },
+                { "major": 8, "minor": 0, "op": "Sync", "value": 12288 }
+            ]
+        },
+        "cpu_stats": {
+            "cpu_usage": { "total_usage": 200_000_000u64, "usage_in_usermode": 0, "usage_in_kernelmode": 0 },
+            "system_cpu_usage": 2_000_000_000u64,
+            "online_cpus": 2,
+            "throttling_data": { "periods": 0, "throttled_periods": 0, "throttled_time": 0 }
+        },
+        "precpu_stats": {
+            "cpu_usage": { "total_usage": 100_000_000u64, "usage_in_usermode": 0, "usage_in_kernelmode": 0 },
+            "system_cpu_usage": 1_000_000_000u64,
+            "online_cpus": 2,
+            "throttling_data": { "periods": 0, "throttled_periods": 0, "throttled_time": 0 }
+        },
+        "storage_stats": {}
+    })
+}
+
+/// The last `tail` of five log lines, alternating stdout and stderr
+pub fn log_lines(tail: usize) -> Vec<(u8, String)> {
+    let lines: Vec<(u8, String)> = (1..=5)
+        .map(|i| {
+            let stream = if i % 2 == 0 { 2 } else { 1 };
+            (stream, format!("line {}\n", i))
+        })
+        .collect();
+    let skip = lines.len().saturating_sub(tail);
+    lines.into_iter().skip(skip).collect()
+}
+
+pub fn pull_progress(image: &str) -> Vec<Value> {
+    if image == MISSING_IMAGE {
+        return vec![json!({
+            "error": "pull access denied for does-not-exist",
+            "errorDetail": { "message": "pull access denied for does-not-exist" }
+        })];
+    }
+
+    vec![
+        json!({ "status": format!("Pulling from library/{}", image), "id": "latest" }),
+        json!({ "status": "Pulling fs layer", "id": "a1b2c3d4e5f6" }),
+        json!({
+            "status": "Downloading",
+            "id": "a1b2c3d4e5f6",
+            "progress": "[=====>      ]  1.5MB/3MB",
+            "progressDetail": { "current": 1_500_000, "total": 3_000_000 }
+        }),
+        json!({ "status": "Pull complete", "id": "a1b2c3d4e5f6" }),
+        json!({ "status": format!("Status: Downloaded newer image for {}:latest", image) }),
+    ]
+}
+
+/// Filesystem changes as reported by `/containers/{id}/changes`, unsorted
+pub fn changes() -> Value {
+    json!([
+        { "Path": "/var/cache/nginx/proxy", "Kind": 1 },
+        { "Path": "/etc/nginx/nginx.conf", "Kind": 0 },
+        { "Path": "/etc/nginx/conf.d/default.conf", "Kind": 2 },
+        { "Path": "/etc/nginx", "Kind": 0 }
+    ])
+}
+
+pub fn events() -> Vec<Value> {
+    vec![
+        json!({
+            "Type": "container",
+            "Action": "start",
+            "Actor": { "ID": WEB_ID, "Attributes": { "name": "web", "image": "nginx:latest" } },
+            "scope": "local",
+            "time": 1_700_000_100,
+            "timeNano": 1_700_000_100_000_000_000u64
+        }),
+        json!({
+            "Type": "container",
+            "Action": "die",
+            "Actor": { "ID": DB_ID, "Attributes": { "name": "db", "exitCode": "1" } },
+            "scope": "local",
+            "time": 1_700_000_200,
+            "timeNano": 1_700_000_200_000_000_000u64
+        }),
+    ]
+}
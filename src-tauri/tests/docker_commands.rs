@@ -0,0 +1,854 @@
+//! Backend commands exercised against the in-process mock daemon.
+mod common;
+
+use common::{
+    MockDocker, ARM_IMAGE, COMMITTED_IMAGE_ID, CREATED_ID, DB_ID, EXPORT_SIZE, IMPORTED_IMAGE_ID,
+    MISSING_IMAGE, WEB_ID,
+};
+use futures_util::StreamExt;
+use hyper::Method;
+use rykard_lib::docker::{
+    self, ChangeKind, CommitOptions, ContainerResources, CreateContainerOptions, DockerError,
+    DockerState, DockerStatus, ImagePlatform, ImportOptions, RestartPolicySetting,
+};
+use std::fs;
+
+#[tokio::test]
+async fn state_connects_to_socket_and_pings() {
+    let mock = MockDocker::start().await;
+    let mut state = mock.state();
+
+    assert!(matches!(state.initialize(), DockerStatus::Connected));
+    assert!(matches!(
+        state.check_status().await,
+        DockerStatus::Connected
+    ));
+    assert!(mock.request(Method::GET, "/_ping").is_some());
+}
+
+#[tokio::test]
+async fn state_reports_missing_socket() {
+    let mut state = DockerState::with_socket("/nonexistent/rykard-test.sock");
+
+    assert!(matches!(state.initialize(), DockerStatus::Error(_)));
+    assert!(matches!(
+        state.get_client(),
+        Err(DockerError::ConnectionError(_))
+    ));
+}
+
+#[tokio::test]
+async fn list_containers_maps_summary_fields() {
+    let mock = MockDocker::start().await;
+    let containers = docker::list_containers(&mock.client(), true).await.unwrap();
+
+    assert_eq!(containers.len(), 2);
+    let web = &containers[0];
+    assert_eq!(web.id, WEB_ID);
+    assert_eq!(web.names, vec!["web"]);
+    assert_eq!(web.image, "nginx:latest");
+    assert_eq!(web.state, "running");
+    assert_eq!(web.created, 1_700_000_000);
+    assert_eq!(web.labels["com.docker.compose.project"], "shop");
+
+    assert_eq!(web.ports.len(), 2);
+    assert_eq!(web.ports[0].ip, "0.0.0.0");
+    assert_eq!(web.ports[0].private_port, 80);
+    assert_eq!(web.ports[0].public_port, 8080);
+    assert_eq!(web.ports[0].type_, "tcp");
+    // Unpublished ports come back without an IP or public port
+    assert_eq!(web.ports[1].ip, "");
+    assert_eq!(web.ports[1].public_port, 0);
+}
+
+#[tokio::test]
+async fn list_containers_passes_all_flag() {
+    let mock = MockDocker::start().await;
+    let running = docker::list_containers(&mock.client(), false)
+        .await
+        .unwrap();
+
+    assert_eq!(running.len(), 1);
+    assert_eq!(running[0].id, WEB_ID);
+}
+
+#[tokio::test]
+async fn list_images_strips_digest_prefix() {
+    let mock = MockDocker::start().await;
+    let images = docker::list_images(&mock.client()).await.unwrap();
+
+    assert_eq!(images.len(), 1);
+    assert!(!images[0].id.starts_with("sha256:"));
+    assert_eq!(images[0].repo_tags, vec!["nginx:latest", "nginx:1.27"]);
+    assert_eq!(images[0].size, 187_654_321);
+    assert_eq!(images[0].created, 1_699_000_000);
+}
+
+#[tokio::test]
+async fn images_report_their_platform() {
+    let mock = MockDocker::start().await;
+    let images = docker::list_images(&mock.client()).await.unwrap();
+    assert_eq!(images[0].platform.to_string(), "linux/amd64");
+
+    let arm = docker::inspect_image(&mock.client(), ARM_IMAGE)
+        .await
+        .unwrap();
+    assert_eq!(arm.platform.architecture, "arm");
+    assert_eq!(arm.platform.variant.as_deref(), Some("v7"));
+
+    // Flattened so the frontend sees `architecture` next to `id`
+    let json = serde_json::to_value(&arm).unwrap();
+    assert_eq!(json["architecture"], "arm");
+    assert_eq!(json["variant"], "v7");
+}
+
+#[test]
+fn platforms_parse_with_optional_variant() {
+    let arm = ImagePlatform::parse("linux/arm/v7").unwrap();
+    assert_eq!(arm.os, "linux");
+    assert_eq!(arm.architecture, "arm");
+    assert_eq!(arm.variant.as_deref(), Some("v7"));
+    assert_eq!(arm.to_string(), "linux/arm/v7");
+
+    assert_eq!(
+        ImagePlatform::parse("linux/arm64").unwrap().to_string(),
+        "linux/arm64"
+    );
+    for invalid in ["", "arm64", "linux/", "/amd64", "linux/arm/v7/extra"] {
+        assert!(ImagePlatform::parse(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[tokio::test]
+async fn manifest_list_shows_every_platform() {
+    let mock = MockDocker::start().await;
+    let list = docker::manifest_list(&mock.client(), "nginx:latest")
+        .await
+        .unwrap();
+
+    assert_eq!(list.digest, common::LATEST_DIGEST);
+    assert_eq!(
+        list.media_type.as_deref(),
+        Some("application/vnd.oci.image.index.v1+json")
+    );
+    let platforms: Vec<String> = list.platforms.iter().map(|p| p.to_string()).collect();
+    assert_eq!(platforms, ["linux/amd64", "linux/arm64/v8", "linux/arm/v7"]);
+}
+
+#[tokio::test]
+async fn start_stop_and_remove_container() {
+    let mock = MockDocker::start().await;
+    let client = mock.client();
+
+    docker::start_container(&client, WEB_ID).await.unwrap();
+    docker::stop_container(&client, WEB_ID).await.unwrap();
+    docker::remove_container(&client, DB_ID).await.unwrap();
+
+    assert!(mock
+        .request(Method::POST, &format!("/containers/{}/start", WEB_ID))
+        .is_some());
+    assert!(mock
+        .request(Method::POST, &format!("/containers/{}/stop", WEB_ID))
+        .is_some());
+    assert!(mock
+        .request(Method::DELETE, &format!("/containers/{}", DB_ID))
+        .is_some());
+}
+
+#[tokio::test]
+async fn unknown_container_is_not_found() {
+    let mock = MockDocker::start().await;
+    let err = docker::start_container(&mock.client(), "missing")
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, DockerError::NotFound(msg) if msg.contains("missing")));
+}
+
+#[tokio::test]
+async fn pull_image_reports_progress() {
+    let mock = MockDocker::start().await;
+    let mut statuses = Vec::new();
+
+    docker::pull_image(&mock.client(), "alpine:3.20", |progress| {
+        statuses.push(progress.status.clone().unwrap_or_default());
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(statuses.len(), 5);
+    assert_eq!(statuses[2], "Downloading");
+
+    let request = mock.request(Method::POST, "/images/create").unwrap();
+    assert_eq!(request.query["fromImage"], "alpine");
+    assert_eq!(request.query["tag"], "3.20");
+}
+
+#[tokio::test]
+async fn pull_image_defaults_to_latest() {
+    let mock = MockDocker::start().await;
+    docker::pull_image(&mock.client(), "alpine", |_| {})
+        .await
+        .unwrap();
+
+    let request = mock.request(Method::POST, "/images/create").unwrap();
+    assert_eq!(request.query["tag"], "latest");
+}
+
+#[tokio::test]
+async fn pull_image_by_digest() {
+    let mock = MockDocker::start().await;
+    let reference = format!("docker.io/library/nginx:1.27@{}", common::PULLED_DIGEST);
+    docker::pull_image(&mock.client(), &reference, |_| {})
+        .await
+        .unwrap();
+
+    let request = mock.request(Method::POST, "/images/create").unwrap();
+    assert_eq!(request.query["fromImage"], "nginx");
+    assert_eq!(request.query["tag"], common::PULLED_DIGEST);
+
+    let err = docker::pull_image(&mock.client(), "Nginx", |_| {})
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("must be lowercase"));
+}
+
+#[tokio::test]
+async fn pull_image_for_another_platform() {
+    let mock = MockDocker::start().await;
+    docker::pull_image_for_platform(&mock.client(), "nginx", Some("linux/arm64"), |_| {})
+        .await
+        .unwrap();
+
+    let request = mock.request(Method::POST, "/images/create").unwrap();
+    assert_eq!(request.query["fromImage"], "nginx");
+    assert_eq!(request.query["platform"], "linux/arm64");
+
+    let err = docker::pull_image_for_platform(&mock.client(), "nginx", Some("arm64"), |_| {})
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Invalid platform"));
+    assert_eq!(mock.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn pull_image_surfaces_stream_errors() {
+    let mock = MockDocker::start().await;
+    let err = docker::pull_image(&mock.client(), MISSING_IMAGE, |_| {})
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, DockerError::OperationError(msg) if msg.contains("pull access denied")));
+}
+
+#[tokio::test]
+async fn ensure_image_pulls_only_missing_images() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+
+    assert!(!docker::ensure_image(&docker, "nginx:1.27", |_| {})
+        .await
+        .unwrap());
+    assert!(mock.request(Method::POST, "/images/create").is_none());
+
+    let mut progress = 0;
+    assert!(docker::ensure_image(&docker, "redis:7", |_| progress += 1)
+        .await
+        .unwrap());
+    assert!(progress > 0);
+    let pull = mock.request(Method::POST, "/images/create").unwrap();
+    assert_eq!(pull.query["fromImage"], "redis");
+    assert_eq!(pull.query["tag"], "7");
+
+    assert!(docker::ensure_image(&docker, MISSING_IMAGE, |_| {})
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn remove_image_by_id() {
+    let mock = MockDocker::start().await;
+    let client = mock.client();
+
+    docker::remove_image(&client, "2b0ab8f6e9a1").await.unwrap();
+    assert!(matches!(
+        docker::remove_image(&client, "ffffffffffff").await,
+        Err(DockerError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn container_logs_respects_tail() {
+    let mock = MockDocker::start().await;
+    let logs = docker::container_logs(&mock.client(), WEB_ID, 2)
+        .await
+        .unwrap();
+
+    assert_eq!(logs, "line 4\nline 5\n");
+
+    let request = mock
+        .request(Method::GET, &format!("/containers/{}/logs", WEB_ID))
+        .unwrap();
+    assert_eq!(request.query["tail"], "2");
+    assert_eq!(request.query["stdout"], "true");
+    assert_eq!(request.query["stderr"], "true");
+}
+
+#[tokio::test]
+async fn container_logs_stream_yields_each_frame() {
+    let mock = MockDocker::start().await;
+    let chunks: Vec<String> = docker::container_logs_stream(&mock.client(), WEB_ID, 100, true)
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+
+    assert_eq!(chunks.len(), 5);
+    assert_eq!(chunks[0], "line 1\n");
+
+    let request = mock
+        .request(Method::GET, &format!("/containers/{}/logs", WEB_ID))
+        .unwrap();
+    assert_eq!(request.query["follow"], "true");
+}
+
+#[tokio::test]
+async fn create_container_creates_and_starts() {
+    let mock = MockDocker::start().await;
+    let options = CreateContainerOptions {
+        image: "nginx:latest".to_string(),
+        name: "fresh".to_string(),
+        ..Default::default()
+    };
+
+    let id = docker::create_container(&mock.client(), &options)
+        .await
+        .unwrap();
+    assert_eq!(id, CREATED_ID);
+
+    let create = mock.request(Method::POST, "/containers/create").unwrap();
+    assert_eq!(create.query["name"], "fresh");
+    assert!(create.body.contains("\"Image\":\"nginx:latest\""));
+    assert!(mock
+        .request(Method::POST, &format!("/containers/{}/start", CREATED_ID))
+        .is_some());
+}
+
+#[tokio::test]
+async fn create_container_for_another_platform() {
+    let mock = MockDocker::start().await;
+    let options = CreateContainerOptions {
+        image: "nginx:latest".to_string(),
+        name: "emulated".to_string(),
+        platform: Some("linux/arm64".to_string()),
+        ..Default::default()
+    };
+    docker::create_container(&mock.client(), &options)
+        .await
+        .unwrap();
+
+    let create = mock.request(Method::POST, "/containers/create").unwrap();
+    assert_eq!(create.query["platform"], "linux/arm64");
+
+    let invalid = CreateContainerOptions {
+        platform: Some("linux".to_string()),
+        ..options
+    };
+    assert!(docker::create_container(&mock.client(), &invalid)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn container_stats_summarizes_sample() {
+    let mock = MockDocker::start().await;
+    let stats = docker::container_stats(&mock.client(), WEB_ID)
+        .await
+        .unwrap();
+
+    assert!((stats.cpu_usage_percent - 20.0).abs() < 1e-9);
+    assert_eq!(stats.memory_usage, 52_428_800);
+    assert_eq!(stats.memory_limit, 209_715_200);
+    assert!((stats.memory_usage_percent - 25.0).abs() < 1e-9);
+    assert_eq!(stats.network_rx_bytes, 1024);
+    assert_eq!(stats.network_tx_bytes, 2048);
+    assert_eq!(stats.block_read_bytes, 4096);
+    assert_eq!(stats.block_write_bytes, 8192);
+
+    let request = mock
+        .request(Method::GET, &format!("/containers/{}/stats", WEB_ID))
+        .unwrap();
+    assert_eq!(request.query["stream"], "false");
+}
+
+#[tokio::test]
+async fn container_config_reads_inspect() {
+    let mock = MockDocker::start().await;
+    let config = docker::container_config(&mock.client(), "web")
+        .await
+        .unwrap();
+
+    assert_eq!(config.id, "web");
+    assert_eq!(config.name, "web");
+    assert_eq!(config.image, "nginx:latest");
+    assert_eq!(config.command, "nginx -g daemon off;");
+    assert_eq!(config.status, "running");
+    assert_eq!(config.network_mode, "bridge");
+    assert_eq!(config.restart_policy, "always");
+    assert_eq!(config.resources.memory, Some(268_435_456));
+    assert_eq!(config.resources.memory_swap, Some(536_870_912));
+    assert_eq!(config.resources.cpus, Some(1.5));
+    // Zero means no limit
+    assert_eq!(config.resources.cpu_shares, None);
+    assert_eq!(config.resources.pids_limit, None);
+    assert_eq!(config.resources.cpuset_cpus, None);
+    assert_eq!(
+        config.resources.restart_policy.as_ref().unwrap().name,
+        "always"
+    );
+
+    assert_eq!(config.entrypoint, ["/docker-entrypoint.sh"]);
+    assert_eq!(config.working_dir, "/usr/share/nginx");
+    assert_eq!(config.user, "nginx");
+    assert_eq!(config.hostname, "web-host");
+    assert_eq!(config.restart_count, 2);
+    assert_eq!(config.pid, 4242);
+    assert!(!config.oom_killed);
+    assert_eq!(config.env_vars.len(), 2);
+    assert_eq!(config.labels["com.docker.compose.project"], "shop");
+
+    // Exposed-but-unpublished ports have no bindings and are skipped
+    assert_eq!(config.ports.len(), 1);
+    assert_eq!(config.ports[0].host_port, "8080");
+    assert_eq!(config.ports[0].container_port, "80");
+    assert_eq!(config.ports[0].protocol, "tcp");
+
+    assert_eq!(config.volumes.len(), 1);
+    assert_eq!(config.volumes[0].host_path, "/srv/www");
+    assert_eq!(config.volumes[0].mode, "ro");
+}
+
+#[tokio::test]
+async fn events_stream_decodes_messages() {
+    let mock = MockDocker::start().await;
+    let events: Vec<_> = docker::events_stream(&mock.client())
+        .map(|event| event.unwrap())
+        .collect()
+        .await;
+
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].action.as_deref(), Some("start"));
+    assert_eq!(events[1].actor.as_ref().unwrap().id.as_deref(), Some(DB_ID));
+}
+
+#[tokio::test]
+async fn commit_container_sends_changes() {
+    let mock = MockDocker::start().await;
+    let options = CommitOptions {
+        repo: "debug/web".to_string(),
+        tag: "tuned".to_string(),
+        author: "ops <ops@example.com>".to_string(),
+        message: "raise worker count".to_string(),
+        cmd: Some(vec![
+            "nginx".to_string(),
+            "-g".to_string(),
+            "daemon off;".to_string(),
+        ]),
+        env: vec!["WORKERS=8".to_string(), "GREETING=hello world".to_string()],
+        ..Default::default()
+    };
+
+    let id = docker::commit_container(&mock.client(), WEB_ID, &options)
+        .await
+        .unwrap();
+    assert_eq!(id, COMMITTED_IMAGE_ID);
+
+    let commit = mock.request(Method::POST, "/commit").unwrap();
+    assert_eq!(commit.query["container"], WEB_ID);
+    assert_eq!(commit.query["repo"], "debug/web");
+    assert_eq!(commit.query["tag"], "tuned");
+    assert_eq!(commit.query["comment"], "raise worker count");
+    assert_eq!(commit.query["pause"], "true");
+    assert_eq!(
+        commit.query["changes"],
+        "CMD [\"nginx\",\"-g\",\"daemon off;\"]\nENV WORKERS=\"8\"\nENV GREETING=\"hello world\""
+    );
+}
+
+#[tokio::test]
+async fn commit_container_validates_options() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+
+    let tag_only = CommitOptions {
+        tag: "v1".to_string(),
+        ..Default::default()
+    };
+    assert!(docker::commit_container(&docker, WEB_ID, &tag_only)
+        .await
+        .is_err());
+
+    let bad_env = CommitOptions {
+        env: vec!["NOVALUE".to_string()],
+        ..Default::default()
+    };
+    assert!(docker::commit_container(&docker, WEB_ID, &bad_env)
+        .await
+        .is_err());
+    assert!(mock.request(Method::POST, "/commit").is_none());
+
+    let missing = docker::commit_container(&docker, "ghost", &CommitOptions::default()).await;
+    assert!(matches!(missing, Err(DockerError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn container_diff_maps_kinds_and_sorts() {
+    let mock = MockDocker::start().await;
+    let changes = docker::container_diff(&mock.client(), WEB_ID)
+        .await
+        .unwrap();
+
+    let paths: Vec<_> = changes.iter().map(|c| c.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        [
+            "/etc/nginx",
+            "/etc/nginx/conf.d/default.conf",
+            "/etc/nginx/nginx.conf",
+            "/var/cache/nginx/proxy"
+        ]
+    );
+    assert_eq!(changes[1].kind, ChangeKind::Deleted);
+    assert_eq!(changes[2].kind, ChangeKind::Modified);
+    assert_eq!(changes[3].kind, ChangeKind::Added);
+}
+
+#[tokio::test]
+async fn export_container_streams_tar_with_progress() {
+    let mock = MockDocker::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("web.tar");
+
+    let mut updates = Vec::new();
+    let written =
+        docker::export_container(&mock.client(), WEB_ID, &path, |p| updates.push(p.clone()))
+            .await
+            .unwrap();
+
+    assert_eq!(written, EXPORT_SIZE as u64);
+    assert_eq!(fs::metadata(&path).unwrap().len(), EXPORT_SIZE as u64);
+    assert!(!dir.path().join("web.tar.part").exists());
+
+    let last = updates.last().unwrap();
+    assert!(last.done);
+    assert_eq!(last.bytes_written, EXPORT_SIZE as u64);
+    assert_eq!(last.estimated_total, Some(1_500_000));
+    assert!(updates.len() >= 2, "expected intermediate progress");
+
+    let inspect = mock
+        .request(Method::GET, &format!("/containers/{}/json", WEB_ID))
+        .unwrap();
+    assert_eq!(inspect.query["size"], "true");
+}
+
+#[tokio::test]
+async fn export_of_missing_container_leaves_no_file() {
+    let mock = MockDocker::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("ghost.tar");
+
+    let result = docker::export_container(&mock.client(), "ghost", &path, |_| {}).await;
+
+    assert!(matches!(result, Err(DockerError::NotFound(_))));
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn import_image_streams_the_tarball_to_the_daemon() {
+    let mock = MockDocker::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rootfs.tar");
+    let rootfs = vec![7u8; 3 * 1024 * 1024];
+    fs::write(&path, &rootfs).unwrap();
+
+    let options = ImportOptions {
+        repo: "forensics/web".to_string(),
+        tag: "incident-42".to_string(),
+        cmd: Some(vec!["/bin/sh".to_string()]),
+        env: vec!["CASE=42".to_string()],
+        changes: vec!["WORKDIR /evidence".to_string()],
+    };
+    let mut progress = Vec::new();
+    let id = docker::import_image(&mock.client(), &mock.socket_path(), &path, &options, |p| {
+        progress.push(p.clone())
+    })
+    .await
+    .unwrap();
+
+    // The ID is whatever the daemon reports
+    assert_eq!(id, IMPORTED_IMAGE_ID);
+    assert!(progress.len() > 2);
+    let last = progress.last().unwrap();
+    assert!(last.done);
+    assert_eq!(last.bytes_sent, rootfs.len() as u64);
+    assert_eq!(last.total, rootfs.len() as u64);
+
+    let import = mock.request(Method::POST, "/images/create").unwrap();
+    assert_eq!(import.query["fromSrc"], "-");
+    assert_eq!(import.query["repo"], "forensics/web");
+    assert_eq!(import.query["tag"], "incident-42");
+    assert_eq!(
+        import.query["changes"],
+        "CMD [\"/bin/sh\"]\nENV CASE=\"42\"\nWORKDIR /evidence"
+    );
+    assert_eq!(import.raw, rootfs);
+
+    let refused = docker::import_image(
+        &mock.client(),
+        &mock.socket_path(),
+        &path,
+        &ImportOptions {
+            changes: vec!["BOGUS x".to_string()],
+            ..Default::default()
+        },
+        |_| {},
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        refused.to_string().contains("not a valid change"),
+        "{}",
+        refused
+    );
+
+    let missing = docker::import_image(
+        &mock.client(),
+        &mock.socket_path(),
+        &dir.path().join("absent.tar"),
+        &ImportOptions::default(),
+        |_| {},
+    )
+    .await;
+    assert!(missing.is_err());
+}
+
+#[tokio::test]
+async fn update_container_resources_applies_live() {
+    let mock = MockDocker::start().await;
+    let resources = ContainerResources {
+        memory: Some(512 * 1024 * 1024),
+        memory_swap: Some(-1),
+        cpu_shares: Some(512),
+        cpus: None,
+        cpu_period: Some(100_000),
+        cpu_quota: Some(50_000),
+        cpuset_cpus: Some("0-1,3".to_string()),
+        pids_limit: Some(200),
+        blkio_weight: Some(300),
+        restart_policy: Some(RestartPolicySetting {
+            name: "on-failure".to_string(),
+            maximum_retry_count: 5,
+        }),
+    };
+    let applied = docker::update_container_resources(&mock.client(), WEB_ID, &resources)
+        .await
+        .unwrap();
+
+    let update = mock
+        .request(Method::POST, &format!("/containers/{}/update", WEB_ID))
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_str(&update.body).unwrap();
+    assert_eq!(body["Memory"], 536_870_912);
+    assert_eq!(body["MemorySwap"], -1);
+    assert_eq!(body["CpuShares"], 512);
+    assert!(body["NanoCpus"].is_null());
+    assert_eq!(body["CpuQuota"], 50_000);
+    assert_eq!(body["CpusetCpus"], "0-1,3");
+    assert_eq!(body["PidsLimit"], 200);
+    assert_eq!(body["BlkioWeight"], 300);
+    assert_eq!(body["RestartPolicy"]["Name"], "on-failure");
+    assert_eq!(body["RestartPolicy"]["MaximumRetryCount"], 5);
+
+    // What comes back is read from the container afterwards
+    assert_eq!(applied.memory, Some(536_870_912));
+    assert_eq!(applied.cpus, Some(1.5));
+    assert_eq!(applied.cpu_quota, Some(50_000));
+    assert_eq!(applied.pids_limit, Some(200));
+    assert_eq!(applied.restart_policy, resources.restart_policy);
+}
+
+#[tokio::test]
+async fn update_container_resources_leaves_unset_fields_alone() {
+    let mock = MockDocker::start().await;
+    let only_memory = ContainerResources {
+        memory: Some(128 * 1024 * 1024),
+        ..Default::default()
+    };
+    docker::update_container_resources(&mock.client(), WEB_ID, &only_memory)
+        .await
+        .unwrap();
+
+    let update = mock
+        .request(Method::POST, &format!("/containers/{}/update", WEB_ID))
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_str(&update.body).unwrap();
+    // A null restart policy is the daemon's "unchanged"
+    let set: Vec<&String> = body
+        .as_object()
+        .unwrap()
+        .iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(set, ["Memory"]);
+}
+
+#[tokio::test]
+async fn update_container_resources_rejects_bad_limits() {
+    let mock = MockDocker::start().await;
+    let invalid = [
+        ContainerResources {
+            memory: Some(1024),
+            ..Default::default()
+        },
+        ContainerResources {
+            memory: Some(512 * 1024 * 1024),
+            memory_swap: Some(256 * 1024 * 1024),
+            ..Default::default()
+        },
+        ContainerResources {
+            cpus: Some(2.0),
+            cpu_quota: Some(50_000),
+            ..Default::default()
+        },
+        ContainerResources {
+            cpu_period: Some(10),
+            ..Default::default()
+        },
+        ContainerResources {
+            cpuset_cpus: Some("3-1".to_string()),
+            ..Default::default()
+        },
+        ContainerResources {
+            blkio_weight: Some(5),
+            ..Default::default()
+        },
+        ContainerResources {
+            restart_policy: Some(RestartPolicySetting {
+                name: "always".to_string(),
+                maximum_retry_count: 3,
+            }),
+            ..Default::default()
+        },
+        ContainerResources {
+            restart_policy: Some(RestartPolicySetting {
+                name: "sometimes".to_string(),
+                maximum_retry_count: 0,
+            }),
+            ..Default::default()
+        },
+    ];
+    for resources in invalid {
+        assert!(
+            docker::update_container_resources(&mock.client(), WEB_ID, &resources)
+                .await
+                .is_err(),
+            "{:?} should be rejected",
+            resources
+        );
+    }
+    assert!(mock.requests().is_empty());
+
+    let missing = docker::update_container_resources(
+        &mock.client(),
+        "missing",
+        &ContainerResources::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(missing, DockerError::NotFound(_)));
+}
+
+#[tokio::test]
+async fn update_container_resources_only_lifts_what_the_daemon_can() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+
+    // The daemon would silently ignore these zeros
+    let lift_memory = ContainerResources {
+        memory: Some(0),
+        ..Default::default()
+    };
+    let err = docker::update_container_resources(&docker, WEB_ID, &lift_memory)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("can't lift memory"), "{}", err);
+    let lift_cpus = ContainerResources {
+        cpus: Some(0.0),
+        ..Default::default()
+    };
+    assert!(
+        docker::update_container_resources(&docker, WEB_ID, &lift_cpus)
+            .await
+            .is_err()
+    );
+    assert!(mock.requests().is_empty());
+
+    let lift = ContainerResources {
+        pids_limit: Some(0),
+        memory_swap: Some(-1),
+        ..Default::default()
+    };
+    docker::update_container_resources(&docker, WEB_ID, &lift)
+        .await
+        .unwrap();
+    let update = mock
+        .request(Method::POST, &format!("/containers/{}/update", WEB_ID))
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_str(&update.body).unwrap();
+    assert_eq!(body["PidsLimit"], 0);
+    assert_eq!(body["MemorySwap"], -1);
+}
+
+#[tokio::test]
+async fn container_config_shows_runtime_details() {
+    let mock = MockDocker::start().await;
+    let config = docker::container_config(&mock.client(), WEB_ID)
+        .await
+        .unwrap();
+
+    let check = config.healthcheck.unwrap();
+    assert_eq!(check.test[0], "CMD-SHELL");
+    assert_eq!(check.interval_secs, 30.0);
+    assert_eq!(check.timeout_secs, 5.0);
+    assert_eq!(check.retries, 3);
+    let health = config.health.unwrap();
+    assert_eq!(health.status, "healthy");
+    assert_eq!(health.last_output, "ok");
+
+    assert!(!config.privileged);
+    assert_eq!(config.cap_add, ["NET_ADMIN"]);
+    assert_eq!(config.cap_drop, ["MKNOD"]);
+    assert_eq!(config.security_opts, ["no-new-privileges"]);
+    assert_eq!(config.devices[0].host_path, "/dev/fuse");
+    assert_eq!(config.devices[0].permissions, "rwm");
+    assert_eq!(config.tmpfs["/run"], "rw,size=64m");
+    assert_eq!(config.ulimits[0].name, "nofile");
+    assert_eq!(
+        (config.ulimits[0].soft, config.ulimits[0].hard),
+        (1024, 4096)
+    );
+    assert_eq!(config.log_driver, "json-file");
+    assert_eq!(config.log_options["max-size"], "10m");
+    assert_eq!(config.dns, ["1.1.1.1"]);
+    assert_eq!(config.extra_hosts, ["db.internal:10.0.0.5"]);
+    assert_eq!(config.started_at, "2024-01-01T00:00:00Z");
+
+    // Every network, sorted by name
+    let names: Vec<&str> = config.networks.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(names, ["bridge", "shop_default"]);
+    let shop = &config.networks[1];
+    assert_eq!(shop.ip_address, "127.0.0.1");
+    assert_eq!(shop.mac_address, "02:42:ac:12:00:02");
+    assert_eq!(shop.network_id, "5hop");
+    assert!(shop.aliases.contains(&"web".to_string()));
+}
@@ -0,0 +1,419 @@
+//! Dockerfile parsing and linting, and image builds from a local context.
+mod common;
+
+use common::{MockDocker, BUILT_IMAGE_ID};
+use flate2::read::GzDecoder;
+use hyper::Method;
+use rykard_lib::archive::{EntryKind, TarStream};
+use rykard_lib::build::{self, BuildOptions, DockerIgnore};
+use rykard_lib::dockerfile::{self, Severity, RULES};
+use rykard_lib::settings::DockerfileLint;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// Rule ids reported for `text`, in line order
+fn rules(text: &str) -> Vec<String> {
+    dockerfile::lint(text, &[])
+        .into_iter()
+        .map(|d| d.rule)
+        .collect()
+}
+
+const CLEAN: &str = r#"# syntax=docker/dockerfile:1
+FROM node:20-alpine AS build
+WORKDIR /src
+COPY package.json package-lock.json ./
+RUN npm ci
+COPY . .
+RUN npm run build
+
+FROM nginx:1.27-alpine
+RUN apk add --no-cache curl
+COPY --from=build /src/dist /usr/share/nginx/html
+ENV NGINX_PORT=8080 \
+    API_TOKEN_FILE=/run/secrets/api_token
+USER nginx
+CMD ["nginx", "-g", "daemon off;"]
+"#;
+
+#[test]
+fn parses_continuations_flags_and_exec_form() {
+    let instructions = dockerfile::parse(CLEAN);
+    let keywords: Vec<&str> = instructions.iter().map(|i| i.keyword.as_str()).collect();
+    assert_eq!(
+        keywords,
+        [
+            "FROM", "WORKDIR", "COPY", "RUN", "COPY", "RUN", "FROM", "RUN", "COPY", "ENV", "USER",
+            "CMD"
+        ]
+    );
+
+    let copy = &instructions[8];
+    assert_eq!(copy.line, 11);
+    assert_eq!(copy.flags, ["--from=build"]);
+    assert_eq!(copy.args, "/src/dist /usr/share/nginx/html");
+
+    // The continuation is joined and the instruction keeps its first line
+    let env = &instructions[9];
+    assert_eq!(env.line, 12);
+    assert!(env.args.contains("NGINX_PORT=8080"));
+    assert!(env.args.contains("API_TOKEN_FILE=/run/secrets/api_token"));
+
+    let cmd = &instructions[11];
+    assert_eq!(
+        cmd.json.as_deref(),
+        Some(&["nginx".to_string(), "-g".into(), "daemon off;".into()][..])
+    );
+    assert!(instructions[3].json.is_none());
+}
+
+#[test]
+fn parses_escape_directive_and_heredocs() {
+    let text = "# escape=`\nFROM mcr.microsoft.com/windows/servercore:ltsc2022\nRUN echo one `\n  # dropped\n  && echo two\nWORKDIR C:\\app\n";
+    let instructions = dockerfile::parse(text);
+    assert_eq!(instructions.len(), 3);
+    assert_eq!(instructions[1].args, "echo one    && echo two");
+    assert_eq!(instructions[2].line, 6);
+    assert_eq!(instructions[2].args, "C:\\app");
+
+    let text = "FROM debian:12\nRUN <<EOF\napt-get update\nFROM is not an instruction here\nEOF\nUSER app\n";
+    let instructions = dockerfile::parse(text);
+    assert_eq!(instructions.len(), 3);
+    assert!(instructions[1]
+        .args
+        .contains("FROM is not an instruction here"));
+    assert_eq!(instructions[2].keyword, "USER");
+    assert_eq!(instructions[2].line, 6);
+}
+
+#[test]
+fn clean_dockerfile_has_no_diagnostics() {
+    assert_eq!(dockerfile::lint(CLEAN, &[]), Vec::new());
+}
+
+#[test]
+fn flags_base_image_tags() {
+    assert_eq!(rules("FROM ubuntu\nUSER app\n"), ["unpinned-base-image"]);
+    assert_eq!(rules("FROM ubuntu:latest\nUSER app\n"), ["latest-tag"]);
+    // A registry port is not a tag
+    assert_eq!(
+        rules("FROM registry.local:5000/app\nUSER app\n"),
+        ["unpinned-base-image"]
+    );
+    assert!(rules("FROM registry.local:5000/app:1.2\nUSER app\n").is_empty());
+    assert!(rules("FROM alpine@sha256:0123\nUSER app\n").is_empty());
+    assert!(rules("ARG BASE=alpine:3.20\nFROM $BASE\nUSER app\n").is_empty());
+    // Earlier stages are referenced without tags
+    assert!(rules("FROM golang:1.23 AS builder\nFROM builder\nUSER app\n").is_empty());
+    assert!(rules("FROM scratch\nUSER 65534\n").is_empty());
+}
+
+#[test]
+fn flags_package_manager_hygiene() {
+    let diagnostics = dockerfile::lint(
+        "FROM debian:12\nRUN apt-get update && apt-get install curl\nUSER app\n",
+        &[],
+    );
+    let ids: Vec<&str> = diagnostics.iter().map(|d| d.rule.as_str()).collect();
+    assert_eq!(ids, ["apt-get-no-cleanup", "apt-get-missing-yes"]);
+    assert!(diagnostics.iter().all(|d| d.line == 2));
+
+    assert!(rules(
+        "FROM debian:12\nRUN apt-get update \\\n && apt-get install -y --no-install-recommends curl \\\n && rm -rf /var/lib/apt/lists/*\nUSER app\n"
+    )
+    .is_empty());
+    assert!(rules(
+        "FROM debian:12\nRUN --mount=type=cache,target=/var/lib/apt apt-get update && apt-get -qy install curl\nUSER app\n"
+    )
+    .is_empty());
+
+    assert_eq!(
+        rules("FROM alpine:3.20\nRUN apk update && apk add curl\nUSER app\n"),
+        ["apk-no-cache"]
+    );
+}
+
+#[test]
+fn flags_add_for_local_files_only() {
+    assert_eq!(
+        rules("FROM alpine:3.20\nADD app.conf /etc/app.conf\nUSER app\n"),
+        ["add-instead-of-copy"]
+    );
+    assert!(rules("FROM alpine:3.20\nADD rootfs.tar.gz /\nUSER app\n").is_empty());
+    assert!(rules(
+        "FROM alpine:3.20\nADD [\"https://example.com/app.tgz\", \"/opt/\"]\nUSER app\n"
+    )
+    .is_empty());
+}
+
+#[test]
+fn flags_user_in_the_final_stage() {
+    // Only the last stage's USER matters, and a missing one points at its FROM
+    let diagnostics = dockerfile::lint(
+        "FROM golang:1.23 AS build\nUSER builder\nFROM alpine:3.20\nCOPY --from=build /app /app\n",
+        &[],
+    );
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].rule, "missing-user");
+    assert_eq!(diagnostics[0].line, 3);
+
+    assert_eq!(rules("FROM alpine:3.20\nUSER root\n"), ["root-user"]);
+    assert_eq!(rules("FROM alpine:3.20\nUSER 0:0\n"), ["root-user"]);
+    assert!(rules("FROM alpine:3.20\nUSER root\nRUN true\nUSER 1000\n").is_empty());
+}
+
+#[test]
+fn flags_secrets_in_env_and_arg() {
+    assert_eq!(
+        rules("FROM alpine:3.20\nENV DB_PASSWORD=hunter2\nUSER app\n"),
+        ["secret-in-env"]
+    );
+    assert_eq!(
+        rules("FROM alpine:3.20\nENV GITHUB_TOKEN ghp_abc\nUSER app\n"),
+        ["secret-in-env"]
+    );
+    assert_eq!(
+        rules("ARG NPM_TOKEN=abc\nFROM node:20\nUSER node\n"),
+        ["secret-in-env"]
+    );
+    // Declarations, file pointers and references to build args are fine
+    assert!(rules("ARG NPM_TOKEN\nFROM node:20\nUSER node\n").is_empty());
+    assert!(rules("FROM alpine:3.20\nENV DB_PASSWORD_FILE=/run/secrets/db\nUSER app\n").is_empty());
+    assert!(rules("FROM alpine:3.20\nENV API_KEY=$API_KEY\nUSER app\n").is_empty());
+    assert!(rules("FROM alpine:3.20\nENV LOG_LEVEL=debug\nUSER app\n").is_empty());
+}
+
+#[test]
+fn flags_shell_and_structure_problems() {
+    let text = "\
+RUN echo too early
+FROM alpine:3.20
+MAINTAINER someone@example.com
+WORKDIR app
+RUN cd /tmp && make
+RUN curl -fsSL https://example.com/install.sh | sh
+RUN sudo make install
+HEALTHCHEK CMD true
+CMD [\"one\"]
+CMD two
+USER app
+";
+    let diagnostics = dockerfile::lint(text, &[]);
+    let found: Vec<(usize, &str)> = diagnostics
+        .iter()
+        .map(|d| (d.line, d.rule.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (1, "from-first"),
+            (3, "maintainer-deprecated"),
+            (4, "relative-workdir"),
+            (5, "cd-in-run"),
+            (6, "curl-pipe-shell"),
+            (7, "sudo-in-run"),
+            (8, "unknown-instruction"),
+            (10, "multiple-cmd"),
+            (10, "shell-form-cmd"),
+        ]
+    );
+    let severity = |rule: &str| {
+        diagnostics
+            .iter()
+            .find(|d| d.rule == rule)
+            .unwrap()
+            .severity
+    };
+    assert_eq!(severity("unknown-instruction"), Severity::Error);
+    assert_eq!(severity("cd-in-run"), Severity::Info);
+}
+
+#[test]
+fn disabled_rules_are_not_reported() {
+    let text = "FROM ubuntu:latest\nADD app.conf /etc/\n";
+    assert_eq!(
+        rules(text),
+        ["latest-tag", "missing-user", "add-instead-of-copy"]
+    );
+
+    let disabled = vec!["missing-user".to_string(), "latest-tag".to_string()];
+    let diagnostics = dockerfile::lint(text, &disabled);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].rule, "add-instead-of-copy");
+}
+
+#[test]
+fn rule_ids_are_unique() {
+    let mut ids: Vec<&str> = RULES.iter().map(|r| r.id).collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), RULES.len());
+}
+
+#[test]
+fn dockerignore_matches_like_the_cli() {
+    let ignore = DockerIgnore::parse(
+        "# build output\ntarget\n*.log\n**/node_modules\ndocs/**/*.md\n!docs/README.md\n./.git\n",
+    );
+    assert!(ignore.is_ignored("target"));
+    assert!(ignore.is_ignored("target/debug/app"));
+    assert!(ignore.is_ignored("build.log"));
+    assert!(!ignore.is_ignored("logs/build.log"));
+    assert!(ignore.is_ignored("node_modules/left-pad/index.js"));
+    assert!(ignore.is_ignored("web/node_modules"));
+    assert!(ignore.is_ignored("docs/guide/intro.md"));
+    assert!(ignore.is_ignored("docs/intro.md"));
+    assert!(!ignore.is_ignored("docs/README.md"));
+    assert!(ignore.is_ignored(".git/HEAD"));
+    assert!(!ignore.is_ignored("src/main.rs"));
+}
+
+fn write(root: &Path, path: &str, contents: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+/// Paths of the regular files in a gzipped context tar
+fn context_files(context: &[u8]) -> Vec<String> {
+    let mut tar = Vec::new();
+    GzDecoder::new(context).read_to_end(&mut tar).unwrap();
+    let mut stream = TarStream::new(|_: &str, kind| kind == EntryKind::File, 1024 * 1024);
+    let mut files: Vec<String> = stream
+        .push(&tar)
+        .unwrap()
+        .into_iter()
+        .map(|e| e.path)
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn context_honours_dockerignore() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write(root, "build/Dockerfile", "FROM alpine:3.20\nUSER app\n");
+    write(
+        root,
+        ".dockerignore",
+        "build\n*.log\nsecrets\n!secrets/public.pem\n",
+    );
+    write(root, "src/main.rs", "fn main() {}\n");
+    write(root, "debug.log", "noise\n");
+    write(root, "secrets/private.pem", "private\n");
+    write(root, "secrets/public.pem", "public\n");
+    // A deep path needs the long-name extension
+    let deep = format!("{}/file.txt", ["nested"; 20].join("/"));
+    write(root, &deep, "deep\n");
+
+    let options = BuildOptions {
+        context: root.to_path_buf(),
+        dockerfile: "build/Dockerfile".to_string(),
+        ..Default::default()
+    };
+    let files = context_files(&build::build_context(&options).unwrap());
+    let mut expected = vec![
+        ".dockerignore".to_string(),
+        "build/Dockerfile".to_string(),
+        deep,
+        "secrets/public.pem".to_string(),
+        "src/main.rs".to_string(),
+    ];
+    expected.sort();
+    assert_eq!(files, expected);
+}
+
+#[test]
+fn context_rejects_dockerfile_outside_it() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "app/Dockerfile", "FROM alpine:3.20\n");
+    let options = BuildOptions {
+        context: dir.path().join("app"),
+        dockerfile: "../Dockerfile".to_string(),
+        ..Default::default()
+    };
+    assert!(build::build_context(&options).is_err());
+
+    let options = BuildOptions {
+        context: dir.path().to_path_buf(),
+        dockerfile: "Dockerfile".to_string(),
+        ..Default::default()
+    };
+    assert!(build::build_context(&options).is_err());
+}
+
+#[test]
+fn preflight_blocks_on_errors_when_configured() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "Dockerfile", "FROM ubuntu\nRUNN make\n");
+    let options = BuildOptions {
+        context: dir.path().to_path_buf(),
+        dockerfile: "Dockerfile".to_string(),
+        ..Default::default()
+    };
+
+    let strict = DockerfileLint {
+        block_on_errors: true,
+        ..Default::default()
+    };
+    let err = build::preflight(&options, &strict).unwrap_err();
+    assert!(err.to_string().contains("unknown-instruction"));
+
+    // By default diagnostics are only warnings
+    let diagnostics = build::preflight(&options, &DockerfileLint::default()).unwrap();
+    assert_eq!(diagnostics.len(), 3);
+
+    let disabled = DockerfileLint {
+        disabled_rules: vec!["unknown-instruction".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(build::preflight(&options, &disabled).unwrap().len(), 2);
+
+    let off = DockerfileLint {
+        before_build: false,
+        ..Default::default()
+    };
+    assert!(build::preflight(&options, &off).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn builds_image_and_reports_progress() {
+    let mock = MockDocker::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        "docker/app.Dockerfile",
+        "FROM alpine:3.20\nCOPY . /app\n",
+    );
+    write(dir.path(), "main.sh", "echo hi\n");
+
+    let options = BuildOptions {
+        context: dir.path().to_path_buf(),
+        dockerfile: "docker/app.Dockerfile".to_string(),
+        tag: "demo/app:dev".to_string(),
+        build_args: [("VERSION".to_string(), "1.0".to_string())].into(),
+        no_cache: true,
+        ..Default::default()
+    };
+    let mut steps = Vec::new();
+    let id = build::build_image(&mock.client(), &options, |info| {
+        if let Some(stream) = &info.stream {
+            steps.push(stream.clone());
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(id, BUILT_IMAGE_ID);
+    assert_eq!(steps.len(), 3);
+    assert!(steps[0].starts_with("Step 1/2"));
+
+    let build = mock.request(Method::POST, "/build").unwrap();
+    assert_eq!(build.query["dockerfile"], "docker/app.Dockerfile");
+    assert_eq!(build.query["t"], "demo/app:dev");
+    assert_eq!(build.query["nocache"], "true");
+    assert!(build.query["buildargs"].contains("VERSION"));
+    assert!(context_files(&build.raw).contains(&"main.sh".to_string()));
+}
@@ -0,0 +1,124 @@
+//! Service URLs for published ports and the reachability probe.
+mod common;
+
+use common::MockDocker;
+use rykard_lib::docker::{ContainerInfo, PortInfo};
+use rykard_lib::endpoints::{self, container_endpoints, probe, Scheme};
+use std::collections::HashMap;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+fn port(ip: &str, private_port: u16, public_port: u16, type_: &str) -> PortInfo {
+    PortInfo {
+        ip: ip.to_string(),
+        private_port,
+        public_port,
+        type_: type_.to_string(),
+    }
+}
+
+fn container(ports: Vec<PortInfo>, labels: &[(&str, &str)]) -> ContainerInfo {
+    ContainerInfo {
+        id: "abc".to_string(),
+        names: vec!["app".to_string()],
+        image: "app:latest".to_string(),
+        state: "running".to_string(),
+        status: "Up".to_string(),
+        labels: labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>(),
+        ports,
+        created: 0,
+    }
+}
+
+#[test]
+fn builds_urls_from_published_ports() {
+    let app = container(
+        vec![
+            port("0.0.0.0", 80, 8080, "tcp"),
+            port("::", 80, 8080, "tcp"),
+            port("127.0.0.1", 443, 8443, "tcp"),
+            port("0.0.0.0", 5432, 15432, "tcp"),
+            port("0.0.0.0", 53, 5353, "udp"),
+            // Exposed but not published
+            port("", 9000, 0, "tcp"),
+        ],
+        &[],
+    );
+
+    let endpoints = container_endpoints(&app);
+    let urls: Vec<_> = endpoints.iter().map(|e| e.url.as_deref()).collect();
+    assert_eq!(
+        urls,
+        [
+            Some("http://localhost:8080"),
+            Some("https://127.0.0.1:8443"),
+            None,
+            None
+        ]
+    );
+    assert_eq!(endpoints[2].scheme, Scheme::Tcp);
+}
+
+#[test]
+fn labels_override_the_guess() {
+    let app = container(
+        vec![
+            port("0.0.0.0", 8000, 8000, "tcp"),
+            port("0.0.0.0", 9000, 9000, "tcp"),
+        ],
+        &[("rykard.scheme", "https"), ("rykard.scheme.9000", "tcp")],
+    );
+
+    let endpoints = container_endpoints(&app);
+    assert_eq!(endpoints[0].url.as_deref(), Some("https://localhost:8000"));
+    assert_eq!(endpoints[1].url, None);
+}
+
+#[tokio::test]
+async fn probe_reports_http_status_and_closed_ports() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 256];
+        let _ = socket.read(&mut buf).await;
+        socket
+            .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+            .await
+            .unwrap();
+    });
+
+    let open = probe("127.0.0.1", port, Scheme::Http).await;
+    assert!(open.reachable);
+    assert_eq!(open.http_status, Some(204));
+    assert_eq!(open.error, None);
+
+    // Grab a port and release it so nothing is listening there
+    let closed_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let closed = probe("127.0.0.1", closed_port, Scheme::Http).await;
+    assert!(!closed.reachable);
+    assert!(closed.error.is_some());
+}
+
+#[tokio::test]
+async fn resolves_url_for_container_port() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+
+    assert_eq!(
+        endpoints::endpoint_url(&docker, "web", None).await.unwrap(),
+        "http://localhost:8080"
+    );
+    assert!(endpoints::endpoint_url(&docker, "web", Some(9999))
+        .await
+        .is_err());
+    // db publishes nothing
+    assert!(endpoints::endpoint_url(&docker, "db", None).await.is_err());
+}
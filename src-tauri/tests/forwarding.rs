@@ -0,0 +1,132 @@
+//! Local port forwards to unpublished container ports.
+mod common;
+
+use common::{MockDocker, DB_ID, WEB_ID};
+use rykard_lib::forwarding::{ForwardMode, PortForwards};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A "container port": echoes back whatever each connection sends
+async fn echo_server() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = socket.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn proxies_connections_until_stopped() {
+    let mock = MockDocker::start().await;
+    let target = echo_server().await;
+    let forwards = PortForwards::default();
+
+    let forward = forwards
+        .start(&mock.client(), WEB_ID, target, None)
+        .await
+        .unwrap();
+    assert_eq!(forward.mode, ForwardMode::Direct);
+    assert_eq!(forward.container_name, "web");
+    assert_eq!(forwards.list().len(), 1);
+    assert_eq!(forwards.list()[0].local_port, forward.local_port);
+
+    let mut client = TcpStream::connect(("127.0.0.1", forward.local_port))
+        .await
+        .unwrap();
+    client.write_all(b"ping").await.unwrap();
+    let mut reply = [0u8; 4];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(&reply, b"ping");
+
+    forwards.stop(&forward.id).unwrap();
+    assert!(forwards.list().is_empty());
+    assert!(forwards.stop(&forward.id).is_err());
+
+    // The listener goes away with the forward
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(TcpStream::connect(("127.0.0.1", forward.local_port))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn forward_is_removed_when_container_dies() {
+    let mock = MockDocker::start().await;
+    let target = echo_server().await;
+    let forwards = PortForwards::default();
+
+    // The mock's event stream reports db dying
+    let forward = forwards
+        .start(&mock.client(), DB_ID, target, None)
+        .await
+        .unwrap();
+    assert_eq!(forward.container_id, DB_ID);
+
+    for _ in 0..50 {
+        if forwards.list().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(forwards.list().is_empty());
+
+    let events = mock
+        .requests()
+        .into_iter()
+        .find(|r| r.path == "/events")
+        .unwrap();
+    assert!(events.query["filters"].contains(DB_ID));
+}
+
+#[tokio::test]
+async fn forward_outlives_a_dropped_event_stream() {
+    let mock = MockDocker::start().await;
+    let target = echo_server().await;
+    let forwards = PortForwards::default();
+
+    // The mock's event stream ends without mentioning web, so the forward stays up
+    let forward = forwards
+        .start(&mock.client(), WEB_ID, target, None)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(forwards.list().len(), 1);
+
+    // Removed while nobody was listening: the next check notices
+    mock.client().remove_container(WEB_ID, None).await.unwrap();
+    for _ in 0..100 {
+        if forwards.list().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(forwards.list().is_empty());
+    assert!(forwards.stop(&forward.id).is_err());
+
+    let subscriptions = mock
+        .requests()
+        .into_iter()
+        .filter(|r| r.path == "/events")
+        .count();
+    assert!(subscriptions >= 2, "{}", subscriptions);
+}
+
+#[tokio::test]
+async fn taken_local_port_is_an_error() {
+    let mock = MockDocker::start().await;
+    let target = echo_server().await;
+    let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let taken_port = taken.local_addr().unwrap().port();
+
+    let result = PortForwards::default()
+        .start(&mock.client(), WEB_ID, target, Some(taken_port))
+        .await;
+    assert!(result.is_err());
+}
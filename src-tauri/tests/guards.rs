@@ -0,0 +1,285 @@
+//! Protected resources, images in use and the container trash.
+mod common;
+
+use common::{MockDocker, DB_ID, DB_IMAGE_ID, IMAGE_ID, WEB_ID};
+use hyper::Method;
+use rykard_lib::docker::DockerError;
+use rykard_lib::guards::{self, ContainerRemoval, Dependent, ImageRemoval};
+use rykard_lib::settings::DeleteGuards;
+use serde_json::Value;
+use std::collections::HashMap;
+
+fn protect(names: &[&str], labels: &[&str]) -> DeleteGuards {
+    DeleteGuards {
+        protected_names: names.iter().map(|n| n.to_string()).collect(),
+        protected_labels: labels.iter().map(|l| l.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+fn trash() -> DeleteGuards {
+    DeleteGuards {
+        trash: true,
+        trash_grace_minutes: 60,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn protection_matches_names_and_labels() {
+    let labels = HashMap::from([("tier".to_string(), "prod".to_string())]);
+    let names = ["shop-db-1".to_string()];
+
+    let rule = |guards: DeleteGuards| guards::protection(&guards, &names, &labels);
+    assert_eq!(
+        rule(protect(&["*-db-?"], &[])),
+        Some("name pattern *-db-?".to_string())
+    );
+    assert_eq!(
+        rule(protect(&[], &["tier=prod"])),
+        Some("label tier=prod".to_string())
+    );
+    assert_eq!(
+        rule(protect(&[], &["tier"])),
+        Some("label tier".to_string())
+    );
+    assert_eq!(rule(protect(&["db"], &["tier=dev", "owner"])), None);
+    assert_eq!(rule(DeleteGuards::default()), None);
+}
+
+#[tokio::test]
+async fn protected_containers_need_force() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+    let protected = protect(&["w?b"], &[]);
+
+    let err = guards::remove_container(&docker, &protected, "web", false)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DockerError::PermissionDenied(_)));
+    assert!(err
+        .to_string()
+        .contains("web is protected by name pattern w?b"));
+    assert!(mock
+        .request(Method::DELETE, &format!("/containers/{}", WEB_ID))
+        .is_none());
+
+    let removed = guards::remove_container(&docker, &protected, "web", true)
+        .await
+        .unwrap();
+    assert_eq!(removed, ContainerRemoval::Removed);
+    let request = mock
+        .request(Method::DELETE, &format!("/containers/{}", WEB_ID))
+        .unwrap();
+    assert_eq!(request.query.get("force").map(String::as_str), Some("true"));
+
+    // Unprotected ones go straight away, by name or id prefix
+    let removed = guards::remove_container(&docker, &protected, &DB_ID[..12], false)
+        .await
+        .unwrap();
+    assert_eq!(removed, ContainerRemoval::Removed);
+
+    let err = guards::remove_container(&docker, &protected, "ghost", false)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DockerError::NotFound(_)));
+}
+
+#[tokio::test]
+async fn images_in_use_report_their_containers() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+
+    // postgres:16 carries the default protection label
+    let err = guards::remove_image(&docker, &DeleteGuards::default(), "postgres:16", false)
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("postgres:16 is protected by label rykard.protected=true"));
+    assert!(mock
+        .request(Method::DELETE, "/images/postgres:16")
+        .is_none());
+
+    let removal = guards::remove_image(&docker, &protect(&[], &[]), "postgres:16", false)
+        .await
+        .unwrap();
+    assert_eq!(
+        removal,
+        ImageRemoval::InUse {
+            dependents: vec![Dependent {
+                id: DB_ID.to_string(),
+                name: "db".to_string(),
+                state: "exited".to_string(),
+            }]
+        }
+    );
+    let lookup = mock
+        .requests()
+        .into_iter()
+        .rev()
+        .find(|r| r.path == "/containers/json")
+        .unwrap();
+    assert!(lookup.query["filters"].contains(DB_IMAGE_ID));
+
+    let removal = guards::remove_image(&docker, &DeleteGuards::default(), "postgres:16", true)
+        .await
+        .unwrap();
+    assert_eq!(removal, ImageRemoval::Removed);
+
+    let removal = guards::remove_image(&docker, &DeleteGuards::default(), &IMAGE_ID[7..19], false)
+        .await
+        .unwrap();
+    assert_eq!(removal, ImageRemoval::Removed);
+}
+
+#[tokio::test]
+async fn trash_stops_renames_and_restores() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+
+    let removal = guards::remove_container(&docker, &trash(), "web", false)
+        .await
+        .unwrap();
+    let ContainerRemoval::Trashed { name, purge_at } = removal else {
+        panic!("expected the container to be trashed, got {:?}", removal);
+    };
+    assert!(name.starts_with("rykard-trash."));
+    // Its restart policy is kept in the name and switched off before it's stopped
+    assert!(name.ends_with(".always.web"), "{}", name);
+    let position = |path: String| mock.requests().iter().position(|r| r.path == path);
+    let update = position(format!("/containers/{}/update", WEB_ID)).unwrap();
+    let stop = position(format!("/containers/{}/stop", WEB_ID)).unwrap();
+    assert!(update < stop);
+    let body: Value = serde_json::from_str(&mock.requests()[update].body).unwrap();
+    assert_eq!(body["RestartPolicy"]["Name"], "no");
+    let rename = mock
+        .request(Method::POST, &format!("/containers/{}/rename", WEB_ID))
+        .unwrap();
+    assert_eq!(rename.query["name"], name);
+    assert!(mock
+        .request(Method::DELETE, &format!("/containers/{}", WEB_ID))
+        .is_none());
+
+    let trashed = guards::list_trash(&docker, 60).await.unwrap();
+    assert_eq!(trashed.len(), 1);
+    assert_eq!(trashed[0].name, "web");
+    assert_eq!(trashed[0].trash_name, name);
+    assert_eq!(trashed[0].purge_at, purge_at);
+    assert_eq!(trashed[0].purge_at - trashed[0].trashed_at, 3600);
+
+    // A stopped container goes to the trash without being stopped again
+    guards::remove_container(&docker, &trash(), "db", false)
+        .await
+        .unwrap();
+    assert!(mock
+        .request(Method::POST, &format!("/containers/{}/stop", DB_ID))
+        .is_none());
+
+    assert_eq!(
+        guards::restore_container(&docker, &name).await.unwrap(),
+        "web"
+    );
+    let restored = mock
+        .requests()
+        .into_iter()
+        .rev()
+        .find(|r| r.path == format!("/containers/{}/update", WEB_ID))
+        .unwrap();
+    let body: Value = serde_json::from_str(&restored.body).unwrap();
+    assert_eq!(body["RestartPolicy"]["Name"], "always");
+    let names: Vec<String> = guards::list_trash(&docker, 60)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|c| c.name)
+        .collect();
+    assert_eq!(names, ["db"]);
+
+    let err = guards::restore_container(&docker, "web").await.unwrap_err();
+    assert_eq!(err.to_string(), "Operation error: web is not in the trash");
+}
+
+#[tokio::test]
+async fn prune_leaves_trashed_and_protected_containers() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+    let db_removed = || {
+        mock.requests()
+            .iter()
+            .any(|r| r.method == Method::DELETE && r.path == format!("/containers/{}", DB_ID))
+    };
+
+    // The stopped db container sits in the trash
+    let ContainerRemoval::Trashed { name, .. } =
+        guards::remove_container(&docker, &trash(), "db", false)
+            .await
+            .unwrap()
+    else {
+        panic!("expected the container to be trashed");
+    };
+    let report = guards::prune_containers(&docker, &trash()).await.unwrap();
+    assert!(report.deleted.is_empty());
+    assert!(!db_removed());
+
+    // Back out of the trash but protected
+    guards::restore_container(&docker, &name).await.unwrap();
+    let report = guards::prune_containers(&docker, &protect(&["d?"], &[]))
+        .await
+        .unwrap();
+    assert!(report.deleted.is_empty());
+    assert!(!db_removed());
+
+    // Unguarded, only the stopped container goes; web is running
+    let report = guards::prune_containers(&docker, &protect(&[], &[]))
+        .await
+        .unwrap();
+    assert_eq!(report.deleted, [DB_ID]);
+    assert!(db_removed());
+    assert!(mock
+        .request(Method::DELETE, &format!("/containers/{}", WEB_ID))
+        .is_none());
+}
+
+#[tokio::test]
+async fn purge_removes_only_expired_containers() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+
+    guards::remove_container(&docker, &trash(), "web", false)
+        .await
+        .unwrap();
+    let trashed_at = guards::list_trash(&docker, 60).await.unwrap()[0].trashed_at;
+
+    // Still within its grace period
+    assert!(guards::purge_trash(&docker, trashed_at - 1)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(mock
+        .request(Method::DELETE, &format!("/containers/{}", WEB_ID))
+        .is_none());
+
+    assert_eq!(
+        guards::purge_trash(&docker, trashed_at).await.unwrap(),
+        ["web"]
+    );
+    assert!(mock
+        .request(Method::DELETE, &format!("/containers/{}", WEB_ID))
+        .is_some());
+    assert!(guards::list_trash(&docker, 60).await.unwrap().is_empty());
+
+    // Removing a container that's already in the trash deletes it
+    let removal = guards::remove_container(&docker, &trash(), "db", false)
+        .await
+        .unwrap();
+    let ContainerRemoval::Trashed { name, .. } = removal else {
+        panic!("expected the container to be trashed");
+    };
+    assert_eq!(
+        guards::remove_container(&docker, &trash(), &name, false)
+            .await
+            .unwrap(),
+        ContainerRemoval::Removed
+    );
+}
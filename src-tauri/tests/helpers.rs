@@ -0,0 +1,97 @@
+//! Pure helpers that don't need a daemon.
+mod common;
+
+use bollard::container::Stats;
+use chrono::{TimeZone, Utc};
+use rykard_lib::docker::{
+    calculate_cpu_percentage, get_block_io_stats, get_network_stats, parse_size, parse_timestamp,
+};
+use serde_json::json;
+
+fn sample() -> Stats {
+    serde_json::from_value(common::stats_json()).unwrap()
+}
+
+#[test]
+fn cpu_percentage_scales_by_online_cpus() {
+    let stats = sample();
+    assert!((calculate_cpu_percentage(&stats) - 20.0).abs() < 1e-9);
+}
+
+#[test]
+fn cpu_percentage_defaults_to_one_cpu() {
+    let mut value = common::stats_json();
+    value["cpu_stats"]["online_cpus"] = json!(null);
+    let stats: Stats = serde_json::from_value(value).unwrap();
+
+    assert!((calculate_cpu_percentage(&stats) - 10.0).abs() < 1e-9);
+}
+
+#[test]
+fn cpu_percentage_is_zero_without_deltas() {
+    let mut value = common::stats_json();
+    // First sample after start: precpu is empty, usage counters went "backwards"
+    value["cpu_stats"]["cpu_usage"]["total_usage"] = json!(50);
+    value["precpu_stats"]["system_cpu_usage"] = json!(null);
+    let stats: Stats = serde_json::from_value(value).unwrap();
+    assert_eq!(calculate_cpu_percentage(&stats), 0.0);
+
+    let mut value = common::stats_json();
+    value["cpu_stats"]["system_cpu_usage"] = json!(null);
+    let stats: Stats = serde_json::from_value(value).unwrap();
+    assert_eq!(calculate_cpu_percentage(&stats), 0.0);
+}
+
+#[test]
+fn network_stats_sum_all_interfaces() {
+    assert_eq!(get_network_stats(&sample()), (1024, 2048));
+
+    let mut value = common::stats_json();
+    value["networks"] = json!(null);
+    let stats: Stats = serde_json::from_value(value).unwrap();
+    assert_eq!(get_network_stats(&stats), (0, 0));
+}
+
+#[test]
+fn block_io_stats_only_count_reads_and_writes() {
+    assert_eq!(get_block_io_stats(&sample()), (4096, 8192));
+
+    let mut value = common::stats_json();
+    value["blkio_stats"] = json!({});
+    let stats: Stats = serde_json::from_value(value).unwrap();
+    assert_eq!(get_block_io_stats(&stats), (0, 0));
+}
+
+#[test]
+fn parse_size_handles_units() {
+    assert_eq!(parse_size(""), 0);
+    assert_eq!(parse_size("512B"), 512);
+    assert_eq!(parse_size("2KB"), 2048);
+    assert_eq!(parse_size("1.5 MB"), 1_572_864);
+    assert_eq!(parse_size("3g"), 3 * 1024 * 1024 * 1024);
+    assert_eq!(parse_size("1T"), 1024u64.pow(4));
+    // Unknown units fall back to plain bytes
+    assert_eq!(parse_size("42 parsecs"), 42);
+    assert_eq!(parse_size("garbage"), 0);
+}
+
+#[test]
+fn parse_timestamp_accepts_docker_formats() {
+    let expected = Utc
+        .with_ymd_and_hms(2024, 3, 5, 14, 30, 0)
+        .unwrap()
+        .timestamp() as u64;
+
+    assert_eq!(parse_timestamp("2024-03-05 14:30:00"), expected);
+    assert_eq!(parse_timestamp("2024-03-05T14:30:00"), expected);
+    assert_eq!(parse_timestamp("Tue Mar 05 14:30:00 2024"), expected);
+}
+
+#[test]
+fn parse_timestamp_falls_back_to_now() {
+    let before = Utc::now().timestamp() as u64;
+    let parsed = parse_timestamp("not a timestamp");
+    let after = Utc::now().timestamp() as u64;
+
+    assert!(parsed >= before && parsed <= after);
+}
@@ -0,0 +1,265 @@
+//! The background job queue: progress, limits, cancellation and each kind of job.
+mod common;
+
+use common::{MockDocker, BUILT_IMAGE_ID, REGISTRY_TAG, SLOW_IMAGE};
+use rykard_lib::build::BuildOptions;
+use rykard_lib::docker::PruneTarget;
+use rykard_lib::jobs::{JobKind, JobManager, JobRequest, JobState, FINISHED_JOB_RETENTION};
+use rykard_lib::settings::JobLimits;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn pull(image: &str) -> JobRequest {
+    JobRequest::Pull {
+        image: image.to_string(),
+        platform: None,
+    }
+}
+
+fn slow_pull(ms: u64) -> JobRequest {
+    pull(&format!("{}:{}", SLOW_IMAGE, ms))
+}
+
+/// Poll until the job reaches `state`
+async fn reach(jobs: &JobManager, id: &str, state: JobState) {
+    for _ in 0..100 {
+        if jobs.get(id).map(|j| j.state) == Some(state) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("job {} never became {:?}", id, state);
+}
+
+#[tokio::test]
+async fn pull_aggregates_layer_progress() {
+    let mock = MockDocker::start().await;
+    let jobs = JobManager::default();
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let seen = updates.clone();
+    jobs.on_update(move |job| seen.lock().unwrap().push(job.state));
+
+    let job = jobs.submit(mock.client(), pull("nginx"));
+    assert_eq!(job.kind, JobKind::Pull);
+    assert_eq!(job.target, "nginx");
+    assert!(job.id.starts_with("job-"));
+
+    let done = jobs.wait(&job.id).await.unwrap();
+    assert_eq!(done.state, JobState::Completed);
+    assert!(done.error.is_none());
+    assert!(done.started.is_some() && done.finished.is_some());
+
+    let progress = &done.progress;
+    assert_eq!(progress.layers.len(), 1);
+    assert_eq!(progress.layers[0].id, "a1b2c3d4e5f6");
+    assert_eq!(progress.layers[0].status, "Pull complete");
+    assert_eq!(progress.total, 3_000_000);
+    assert_eq!(progress.downloaded, 3_000_000);
+    assert_eq!(progress.extracted, 3_000_000);
+    assert_eq!(
+        progress.message,
+        "Status: Downloaded newer image for nginx:latest"
+    );
+
+    let states = updates.lock().unwrap().clone();
+    assert_eq!(states.first(), Some(&JobState::Queued));
+    assert!(states.contains(&JobState::Running));
+    assert_eq!(states.last(), Some(&JobState::Completed));
+}
+
+#[tokio::test]
+async fn failed_pull_keeps_the_error() {
+    let mock = MockDocker::start().await;
+    let jobs = JobManager::default();
+    let job = jobs.submit(mock.client(), pull(common::MISSING_IMAGE));
+
+    let done = jobs.wait(&job.id).await.unwrap();
+    assert_eq!(done.state, JobState::Failed);
+    assert!(done.error.unwrap().contains("pull access denied"));
+}
+
+#[tokio::test]
+async fn jobs_queue_behind_the_limit_in_order() {
+    let mock = MockDocker::start().await;
+    let jobs = JobManager::new(JobLimits {
+        pulls: 1,
+        ..Default::default()
+    });
+
+    let first = jobs.submit(mock.client(), slow_pull(300));
+    let second = jobs.submit(mock.client(), slow_pull(10));
+    let third = jobs.submit(mock.client(), slow_pull(10));
+    // Other kinds have their own limit
+    let prune = jobs.submit(
+        mock.client(),
+        JobRequest::Prune {
+            target: PruneTarget::Volumes,
+        },
+    );
+
+    reach(&jobs, &first.id, JobState::Running).await;
+    assert_eq!(
+        jobs.wait(&prune.id).await.unwrap().state,
+        JobState::Completed
+    );
+    assert_eq!(jobs.get(&second.id).unwrap().state, JobState::Queued);
+    assert_eq!(jobs.get(&third.id).unwrap().state, JobState::Queued);
+
+    let listed: Vec<String> = jobs.list().into_iter().map(|j| j.id).collect();
+    assert_eq!(
+        listed,
+        [&first.id, &second.id, &third.id, &prune.id].map(String::clone)
+    );
+
+    assert_eq!(
+        jobs.wait(&first.id).await.unwrap().state,
+        JobState::Completed
+    );
+    assert_eq!(
+        jobs.wait(&third.id).await.unwrap().state,
+        JobState::Completed
+    );
+    let (second, third) = (jobs.get(&second.id).unwrap(), jobs.get(&third.id).unwrap());
+    assert_eq!(second.state, JobState::Completed);
+    assert!(second.started <= third.started);
+}
+
+#[tokio::test]
+async fn raising_the_limit_starts_queued_jobs() {
+    let mock = MockDocker::start().await;
+    let jobs = JobManager::new(JobLimits {
+        pulls: 1,
+        ..Default::default()
+    });
+    let first = jobs.submit(mock.client(), slow_pull(2_000));
+    let second = jobs.submit(mock.client(), slow_pull(2_000));
+    reach(&jobs, &first.id, JobState::Running).await;
+    assert_eq!(jobs.get(&second.id).unwrap().state, JobState::Queued);
+
+    jobs.set_limits(JobLimits::default());
+    reach(&jobs, &second.id, JobState::Running).await;
+
+    jobs.cancel(&first.id).unwrap();
+    jobs.cancel(&second.id).unwrap();
+}
+
+#[tokio::test]
+async fn cancel_stops_running_and_queued_jobs() {
+    let mock = MockDocker::start().await;
+    let jobs = JobManager::new(JobLimits {
+        pulls: 1,
+        ..Default::default()
+    });
+    let running = jobs.submit(mock.client(), slow_pull(10_000));
+    let queued = jobs.submit(mock.client(), slow_pull(10));
+    let next = jobs.submit(mock.client(), slow_pull(10));
+    reach(&jobs, &running.id, JobState::Running).await;
+
+    // A cancelled queued job gives up its place in line
+    assert_eq!(jobs.cancel(&queued.id).unwrap().state, JobState::Cancelled);
+    let cancelled = jobs.cancel(&running.id).unwrap();
+    assert_eq!(cancelled.state, JobState::Cancelled);
+    assert!(cancelled.finished.is_some());
+
+    assert_eq!(
+        jobs.wait(&next.id).await.unwrap().state,
+        JobState::Completed
+    );
+    assert_eq!(jobs.get(&running.id).unwrap().state, JobState::Cancelled);
+    assert_eq!(jobs.get(&queued.id).unwrap().state, JobState::Cancelled);
+
+    // Finished and unknown jobs can't be cancelled
+    assert!(jobs.cancel(&next.id).is_err());
+    assert!(jobs.cancel("job-0").is_err());
+}
+
+#[tokio::test]
+async fn cancelled_jobs_count_towards_retention() {
+    let mock = MockDocker::start().await;
+    let jobs = JobManager::new(JobLimits {
+        pulls: 1,
+        ..Default::default()
+    });
+    let queued: Vec<_> = (0..FINISHED_JOB_RETENTION + 5)
+        .map(|_| jobs.submit(mock.client(), slow_pull(10_000)))
+        .collect();
+    for job in &queued {
+        jobs.cancel(&job.id).unwrap();
+    }
+
+    assert_eq!(jobs.list().len(), FINISHED_JOB_RETENTION);
+    // The oldest went first
+    assert!(jobs.get(&queued[0].id).is_none());
+    assert!(jobs.get(&queued.last().unwrap().id).is_some());
+}
+
+#[tokio::test]
+async fn build_push_and_prune_jobs_report_results() {
+    let mock = MockDocker::start().await;
+    let jobs = JobManager::default();
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("Dockerfile"), "FROM alpine:3.20\n").unwrap();
+    let build = jobs.submit(
+        mock.client(),
+        JobRequest::Build(BuildOptions {
+            context: dir.path().to_path_buf(),
+            dockerfile: "Dockerfile".to_string(),
+            tag: "demo/app:dev".to_string(),
+            ..Default::default()
+        }),
+    );
+    assert_eq!(build.target, "demo/app:dev");
+    let build = jobs.wait(&build.id).await.unwrap();
+    assert_eq!(build.state, JobState::Completed);
+    assert_eq!(build.result.as_deref(), Some(BUILT_IMAGE_ID));
+    assert!(build.progress.message.starts_with("Successfully built"));
+
+    let push = jobs.submit(
+        mock.client(),
+        JobRequest::Push {
+            image: REGISTRY_TAG.to_string(),
+        },
+    );
+    let push = jobs.wait(&push.id).await.unwrap();
+    assert_eq!(push.state, JobState::Completed);
+    assert!(push.progress.message.starts_with("1.0: digest:"));
+
+    let denied = jobs.submit(
+        mock.client(),
+        JobRequest::Push {
+            image: "library/nginx:latest".to_string(),
+        },
+    );
+    let denied = jobs.wait(&denied.id).await.unwrap();
+    assert_eq!(denied.state, JobState::Failed);
+    assert!(denied.error.unwrap().contains("requested access"));
+
+    let prune = jobs.submit(
+        mock.client(),
+        JobRequest::Prune {
+            target: PruneTarget::Images,
+        },
+    );
+    assert_eq!(prune.target, "images");
+    let prune = jobs.wait(&prune.id).await.unwrap();
+    assert_eq!(
+        prune.result.as_deref(),
+        Some("Removed 2 items and reclaimed 52428800 bytes")
+    );
+}
+
+#[test]
+fn requests_deserialize_with_a_kind_tag() {
+    let request: JobRequest =
+        serde_json::from_str(r#"{ "kind": "prune", "target": "containers" }"#).unwrap();
+    assert_eq!(
+        request,
+        JobRequest::Prune {
+            target: PruneTarget::Containers
+        }
+    );
+    let request: JobRequest =
+        serde_json::from_str(r#"{ "kind": "build", "context": "/src", "tag": "app" }"#).unwrap();
+    assert_eq!(request.kind(), JobKind::Build);
+}
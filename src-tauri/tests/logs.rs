@@ -0,0 +1,316 @@
+//! Log level detection, filtering and search against the mock daemon.
+mod common;
+
+use common::{MockDocker, CHATTY_ID, DB_ID, FLOOD_LINES, WEB_ID};
+use hyper::Method;
+use rykard_lib::guards;
+use rykard_lib::logs::{
+    self, detect_level, AggregatedLogLine, LogExportFormat, LogFilter, LogLevel, LogLine, LogQuery,
+    LogSource, LogStream,
+};
+use rykard_lib::settings::DeleteGuards;
+use std::fs;
+
+fn line(message: &str) -> LogLine {
+    LogLine {
+        timestamp: None,
+        stream: LogStream::Stdout,
+        level: detect_level(message),
+        message: message.to_string(),
+    }
+}
+
+#[test]
+fn detects_bare_level_tokens() {
+    assert_eq!(
+        detect_level("2024-01-01 12:00:00 ERROR connection refused"),
+        Some(LogLevel::Error)
+    );
+    assert_eq!(detect_level("[WARN] disk 91% full"), Some(LogLevel::Warn));
+    assert_eq!(
+        detect_level("WARNING: deprecated flag"),
+        Some(LogLevel::Warn)
+    );
+    assert_eq!(detect_level("INFO listening on :80"), Some(LogLevel::Info));
+    assert_eq!(detect_level("FATAL out of memory"), Some(LogLevel::Error));
+    // Lowercase prose isn't mistaken for a level
+    assert_eq!(detect_level("for more info see the docs"), None);
+}
+
+#[test]
+fn untimed_lines_stay_with_the_line_before_them() {
+    let tagged = |container: &str, timestamp: Option<&str>, message: &str| AggregatedLogLine {
+        service: container.to_string(),
+        container_id: container.to_string(),
+        line: LogLine {
+            timestamp: timestamp.map(str::to_string),
+            ..line(message)
+        },
+    };
+    let mut lines = vec![
+        tagged("web", Some("2024-05-01T10:00:01Z"), "panic: boom"),
+        tagged("web", None, "  at main.go:12"),
+        tagged("web", Some("2024-05-01T10:00:03Z"), "restarting"),
+        tagged("db", Some("2024-05-01T10:00:02Z"), "checkpoint"),
+    ];
+    logs::merge_by_timestamp(&mut lines);
+
+    let messages: Vec<&str> = lines.iter().map(|l| l.line.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        ["panic: boom", "  at main.go:12", "checkpoint", "restarting"]
+    );
+}
+
+#[test]
+fn detects_json_and_logfmt_levels() {
+    assert_eq!(
+        detect_level(r#"{"level":"warn","msg":"slow query"}"#),
+        Some(LogLevel::Warn)
+    );
+    assert_eq!(
+        detect_level(r#"{"severity":"ERROR","message":"boom"}"#),
+        Some(LogLevel::Error)
+    );
+    // pino numeric levels
+    assert_eq!(
+        detect_level(r#"{"level":30,"msg":"ready"}"#),
+        Some(LogLevel::Info)
+    );
+    assert_eq!(detect_level(r#"{"msg":"no level here"}"#), None);
+    assert_eq!(
+        detect_level(r#"time=2024-01-01T00:00:00Z level=debug msg="cache miss""#),
+        Some(LogLevel::Debug)
+    );
+}
+
+#[test]
+fn filters_by_substring_regex_and_level() {
+    let lines = [
+        line("INFO GET /health 200"),
+        line("ERROR GET /orders 500"),
+        line("WARN GET /orders took 2.1s"),
+        line("plain output"),
+    ];
+
+    let substring = LogFilter::new(&LogQuery {
+        pattern: Some("/ORDERS".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(lines.iter().filter(|l| substring.matches(l)).count(), 2);
+
+    let case_sensitive = LogFilter::new(&LogQuery {
+        pattern: Some("/ORDERS".to_string()),
+        case_sensitive: true,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(
+        lines.iter().filter(|l| case_sensitive.matches(l)).count(),
+        0
+    );
+
+    let regex = LogFilter::new(&LogQuery {
+        pattern: Some(r"\s5\d\d$".to_string()),
+        regex: true,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(lines.iter().filter(|l| regex.matches(l)).count(), 1);
+
+    let levels = LogFilter::new(&LogQuery {
+        levels: vec![LogLevel::Warn, LogLevel::Error],
+        ..Default::default()
+    })
+    .unwrap();
+    let matched: Vec<_> = lines.iter().filter(|l| levels.matches(l)).collect();
+    assert_eq!(matched.len(), 2);
+    assert!(matched.iter().all(|l| l.message.contains("/orders")));
+}
+
+#[test]
+fn rejects_invalid_regex() {
+    let query = LogQuery {
+        pattern: Some("(unclosed".to_string()),
+        regex: true,
+        ..Default::default()
+    };
+    assert!(LogFilter::new(&query).is_err());
+}
+
+#[tokio::test]
+async fn search_fetches_window_and_splits_timestamps() {
+    let mock = MockDocker::start().await;
+    let query = LogQuery {
+        since: Some(1_704_067_200),
+        until: Some(1_704_067_260),
+        pattern: Some("line [24]".to_string()),
+        regex: true,
+        ..Default::default()
+    };
+
+    let result = logs::search_logs(&mock.client(), WEB_ID, &query)
+        .await
+        .unwrap();
+
+    assert_eq!(result.scanned, 5);
+    assert_eq!(result.lines.len(), 2);
+    assert_eq!(result.lines[0].message, "line 2");
+    assert_eq!(result.lines[0].stream, LogStream::Stderr);
+    assert_eq!(
+        result.lines[0].timestamp.as_deref(),
+        Some("2024-01-01T00:00:02.000000000Z")
+    );
+
+    let request = mock
+        .request(Method::GET, &format!("/containers/{}/logs", WEB_ID))
+        .unwrap();
+    assert_eq!(request.query["since"], "1704067200");
+    assert_eq!(request.query["until"], "1704067260");
+    assert_eq!(request.query["timestamps"], "true");
+    assert_eq!(request.query["tail"], "all");
+}
+
+#[tokio::test]
+async fn export_writes_filtered_lines() {
+    let mock = MockDocker::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let query = LogQuery {
+        tail: Some(2),
+        ..Default::default()
+    };
+
+    let text_path = dir.path().join("web.log");
+    let written = logs::export_logs(
+        &mock.client(),
+        WEB_ID,
+        &query,
+        &text_path,
+        LogExportFormat::Text,
+    )
+    .await
+    .unwrap();
+    assert_eq!(written, 2);
+    assert_eq!(
+        fs::read_to_string(&text_path).unwrap(),
+        "2024-01-01T00:00:04.000000000Z line 4\n2024-01-01T00:00:05.000000000Z line 5\n"
+    );
+
+    let json_path = dir.path().join("web.ndjson");
+    logs::export_logs(
+        &mock.client(),
+        WEB_ID,
+        &query,
+        &json_path,
+        LogExportFormat::Json,
+    )
+    .await
+    .unwrap();
+    let exported: Vec<LogLine> = fs::read_to_string(&json_path)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(exported.len(), 2);
+    assert_eq!(exported[1].message, "line 5");
+}
+
+#[tokio::test]
+async fn resolves_compose_project_and_named_sources() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+
+    let project = logs::log_sources(&docker, Some("shop"), &[]).await.unwrap();
+    assert_eq!(project.len(), 1);
+    assert_eq!(project[0].container_id, WEB_ID);
+    assert_eq!(project[0].service, "web");
+
+    // Containers outside compose are tagged with their name
+    let named = logs::log_sources(&docker, Some("shop"), &["db".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(named.len(), 2);
+    assert_eq!(named[1].service, "db");
+
+    assert!(logs::log_sources(&docker, Some("missing"), &[])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn trashed_containers_leave_their_project() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+    let trash = DeleteGuards {
+        trash: true,
+        ..Default::default()
+    };
+    guards::remove_container(&docker, &trash, "web", false)
+        .await
+        .unwrap();
+
+    let err = logs::log_sources(&docker, Some("shop"), &[])
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("No containers found for project shop"));
+}
+
+#[tokio::test]
+async fn aggregates_containers_in_timestamp_order() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+    let sources = logs::log_sources(&docker, Some("shop"), &["db".to_string()])
+        .await
+        .unwrap();
+
+    let mut batches = Vec::new();
+    logs::aggregate_logs(&docker, &sources, 2, true, |batch| batches.push(batch))
+        .await
+        .unwrap();
+
+    // The follow streams only repeat lines already in the backlog, so nothing else arrives
+    assert!(batches.iter().all(|batch| batch.skipped == 0));
+    let lines: Vec<_> = batches.into_iter().flat_map(|batch| batch.lines).collect();
+    let tagged: Vec<_> = lines
+        .iter()
+        .map(|l| format!("{} | {}", l.service, l.line.message))
+        .collect();
+    assert_eq!(
+        tagged,
+        ["web | line 4", "db | line 4", "web | line 5", "db | line 5"]
+    );
+    assert_eq!(lines[1].container_id, DB_ID);
+
+    let follows = mock
+        .requests()
+        .into_iter()
+        .filter(|r| r.query.get("follow").map(String::as_str) == Some("true"))
+        .count();
+    assert_eq!(follows, 2);
+}
+
+#[tokio::test]
+async fn floods_are_cut_down_to_paced_batches() {
+    let mock = MockDocker::start().await;
+    let sources = [LogSource {
+        container_id: CHATTY_ID.to_string(),
+        service: "chatty".to_string(),
+    }];
+
+    let mut batches = Vec::new();
+    logs::aggregate_logs(&mock.client(), &sources, 0, true, |batch| {
+        batches.push(batch)
+    })
+    .await
+    .unwrap();
+
+    // The whole flood lands within one interval: the first batch keeps what fits and
+    // counts the rest
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].lines.len(), 500);
+    assert_eq!(batches[0].lines[0].line.message, "flood 0");
+    assert_eq!(batches[0].lines.len() + batches[0].skipped, FLOOD_LINES);
+}
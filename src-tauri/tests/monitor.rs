@@ -0,0 +1,203 @@
+//! Crash classification from container events, and alerts with the logs at death.
+mod common;
+
+use bollard::models::EventMessage;
+use common::{MockDocker, DB_ID};
+use hyper::Method;
+use rykard_lib::monitor::{AlertKind, ContainerAlert, CrashMonitor, CrashTracker};
+use rykard_lib::settings::CrashAlerts;
+use serde_json::json;
+use std::sync::Mutex;
+
+const ID: &str = "c1";
+const START: i64 = 1_700_000_000;
+
+fn event(action: &str, time: i64, attributes: serde_json::Value) -> EventMessage {
+    serde_json::from_value(json!({
+        "Type": "container",
+        "Action": action,
+        "Actor": { "ID": ID, "Attributes": attributes },
+        "time": time
+    }))
+    .unwrap()
+}
+
+fn start(time: i64) -> EventMessage {
+    event("start", time, json!({ "name": "api" }))
+}
+
+fn die(time: i64, exit_code: i64) -> EventMessage {
+    event(
+        "die",
+        time,
+        json!({ "name": "api", "exitCode": exit_code.to_string() }),
+    )
+}
+
+fn tracker() -> CrashTracker {
+    CrashTracker::new(CrashAlerts::default())
+}
+
+#[test]
+fn non_zero_exit_is_reported_unless_the_container_was_stopped() {
+    let mut tracker = tracker();
+    tracker.observe(&start(START));
+    let crash = tracker.observe(&die(START + 10, 2)).unwrap();
+    assert_eq!(crash.kind, AlertKind::NonZeroExit);
+    assert_eq!(crash.container_id, ID);
+    assert_eq!(crash.container_name, "api");
+    assert_eq!(crash.exit_code, 2);
+    assert_eq!(crash.time, START + 10);
+
+    // Clean exits and `docker stop`, which kills with SIGTERM, are not crashes
+    tracker.observe(&start(START + 20));
+    assert!(tracker.observe(&die(START + 30, 0)).is_none());
+    tracker.observe(&start(START + 40));
+    tracker.observe(&event("kill", START + 50, json!({ "signal": "15" })));
+    assert!(tracker.observe(&die(START + 51, 143)).is_none());
+}
+
+#[test]
+fn oom_kill_takes_precedence() {
+    let mut tracker = tracker();
+    tracker.observe(&start(START));
+    tracker.observe(&event("oom", START + 5, json!({ "name": "api" })));
+    let crash = tracker.observe(&die(START + 5, 137)).unwrap();
+    assert_eq!(crash.kind, AlertKind::OomKilled);
+    assert_eq!(crash.exit_code, 137);
+
+    // The flag belongs to that death only
+    tracker.observe(&start(START + 10));
+    let crash = tracker.observe(&die(START + 20, 137)).unwrap();
+    assert_eq!(crash.kind, AlertKind::NonZeroExit);
+}
+
+#[test]
+fn restarts_within_the_window_are_a_crash_loop() {
+    let mut tracker = tracker();
+    tracker.observe(&start(START));
+    let mut kinds = Vec::new();
+    for i in 1..=6 {
+        let time = START + i * 10;
+        kinds.push(tracker.observe(&die(time, 1)).map(|c| (c.kind, c.restarts)));
+        tracker.observe(&start(time + 1));
+    }
+    assert_eq!(
+        kinds,
+        [
+            Some((AlertKind::NonZeroExit, 0)),
+            Some((AlertKind::NonZeroExit, 1)),
+            Some((AlertKind::NonZeroExit, 2)),
+            Some((AlertKind::CrashLoop, 3)),
+            // One alert per loop, not one per death
+            None,
+            None,
+        ]
+    );
+
+    // Once the window has passed, a still-looping container is reported again
+    let later = START + 6 * 60;
+    for i in 0..3 {
+        tracker.observe(&die(later + i * 10, 1));
+        tracker.observe(&start(later + i * 10 + 1));
+    }
+    let crash = tracker.observe(&die(later + 30, 1)).unwrap();
+    assert_eq!(crash.kind, AlertKind::CrashLoop);
+}
+
+#[test]
+fn old_restarts_fall_out_of_the_window() {
+    let mut tracker = CrashTracker::new(CrashAlerts {
+        restart_threshold: 2,
+        window_minutes: 1,
+        ..Default::default()
+    });
+    tracker.observe(&start(START));
+    tracker.observe(&die(START + 1, 1));
+    tracker.observe(&start(START + 2));
+    tracker.observe(&die(START + 3, 1));
+    tracker.observe(&start(START + 4));
+
+    let crash = tracker.observe(&die(START + 100, 1)).unwrap();
+    assert_eq!(crash.kind, AlertKind::NonZeroExit);
+    assert_eq!(crash.restarts, 0);
+}
+
+#[test]
+fn requested_restarts_and_other_objects_are_ignored() {
+    let mut tracker = CrashTracker::new(CrashAlerts {
+        restart_threshold: 1,
+        ..Default::default()
+    });
+    tracker.observe(&start(START));
+    tracker.observe(&event("kill", START + 1, json!({ "signal": "15" })));
+    tracker.observe(&die(START + 2, 0));
+    tracker.observe(&start(START + 3));
+    let crash = tracker.observe(&die(START + 10, 1)).unwrap();
+    assert_eq!(crash.kind, AlertKind::NonZeroExit);
+
+    let image: EventMessage = serde_json::from_value(json!({
+        "Type": "image",
+        "Action": "die",
+        "Actor": { "ID": ID, "Attributes": { "exitCode": "1" } },
+        "time": START
+    }))
+    .unwrap();
+    assert!(tracker.observe(&image).is_none());
+}
+
+#[tokio::test]
+async fn watch_alerts_with_the_logs_at_death() {
+    let mock = MockDocker::start().await;
+    let tracker = Mutex::new(CrashTracker::new(CrashAlerts {
+        log_lines: 3,
+        ..Default::default()
+    }));
+    let mut alerts: Vec<ContainerAlert> = Vec::new();
+    rykard_lib::monitor::watch(&mock.client(), &tracker, |alert| alerts.push(alert))
+        .await
+        .unwrap();
+
+    assert_eq!(alerts.len(), 1);
+    let alert = &alerts[0];
+    assert_eq!(alert.crash.container_id, DB_ID);
+    assert_eq!(alert.crash.kind, AlertKind::NonZeroExit);
+    assert_eq!(alert.message, "db exited with code 1");
+    assert_eq!(alert.last_logs, ["line 3", "line 4", "line 5"]);
+
+    let logs = mock
+        .request(Method::GET, &format!("/containers/{}/logs", DB_ID))
+        .unwrap();
+    assert_eq!(logs.query.get("tail").map(String::as_str), Some("3"));
+
+    let payload = serde_json::to_value(alert).unwrap();
+    assert_eq!(payload["kind"], "non_zero_exit");
+    assert_eq!(payload["container_name"], "db");
+}
+
+#[tokio::test]
+async fn monitor_stays_enabled_across_a_lost_connection() {
+    let mock = MockDocker::start().await;
+    let monitor = CrashMonitor::new(CrashAlerts::default());
+    assert!(!monitor.is_enabled());
+
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    monitor.start(mock.client(), move |alert| {
+        let _ = sender.send(alert);
+    });
+    assert!(monitor.is_enabled());
+    let alert = receiver.recv().await.unwrap();
+    assert_eq!(alert.crash.container_id, DB_ID);
+
+    // The mock's event stream ends, like a daemon going away
+    while monitor.is_running() {
+        tokio::task::yield_now().await;
+    }
+    assert!(monitor.is_enabled());
+    monitor.suspend();
+    assert!(monitor.is_enabled());
+
+    monitor.stop();
+    assert!(!monitor.is_enabled());
+    assert!(!monitor.is_running());
+}
@@ -0,0 +1,101 @@
+//! Host port conflict detection before container creation.
+mod common;
+
+use common::{MockDocker, WEB_ID};
+use hyper::Method;
+use rykard_lib::docker::{self, CreateContainerOptions, PortMapping};
+use rykard_lib::ports::{self, PortOwner};
+use std::net::TcpListener;
+
+fn mapping(host_ip: &str, host_port: &str) -> PortMapping {
+    PortMapping {
+        host_ip: host_ip.to_string(),
+        host_port: host_port.to_string(),
+        container_port: "80".to_string(),
+        protocol: "tcp".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn reports_port_published_by_container() {
+    let mock = MockDocker::start().await;
+
+    let conflicts = ports::check_port_conflicts(&mock.client(), &[mapping("", "8080")])
+        .await
+        .unwrap();
+
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(
+        conflicts[0].owner,
+        PortOwner::Container {
+            id: WEB_ID.to_string(),
+            name: "web".to_string()
+        }
+    );
+    let suggested = conflicts[0].suggested_port.unwrap();
+    assert_ne!(suggested, 8080);
+    assert!(suggested.abs_diff(8080) <= 100);
+    assert!(conflicts[0].describe().contains("container web"));
+}
+
+#[tokio::test]
+async fn reports_port_held_by_local_listener() {
+    let mock = MockDocker::start().await;
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let conflicts =
+        ports::check_port_conflicts(&mock.client(), &[mapping("127.0.0.1", &port.to_string())])
+            .await
+            .unwrap();
+
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].owner, PortOwner::HostProcess);
+    assert!(ports::is_port_free(
+        "127.0.0.1",
+        conflicts[0].suggested_port.unwrap(),
+        "tcp"
+    ));
+
+    drop(listener);
+    let conflicts =
+        ports::check_port_conflicts(&mock.client(), &[mapping("127.0.0.1", &port.to_string())])
+            .await
+            .unwrap();
+    assert!(conflicts.is_empty());
+}
+
+#[tokio::test]
+async fn skips_ephemeral_ports_and_rejects_garbage() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+
+    let conflicts = ports::check_port_conflicts(&docker, &[mapping("", ""), mapping("", "0")])
+        .await
+        .unwrap();
+    assert!(conflicts.is_empty());
+    // Nothing to check, so the daemon isn't asked for its containers
+    assert!(mock.request(Method::GET, "/containers/json").is_none());
+
+    assert!(ports::check_port_conflicts(&docker, &[mapping("", "http")])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn create_refuses_taken_port_before_calling_daemon() {
+    let mock = MockDocker::start().await;
+    let options = CreateContainerOptions {
+        image: "nginx:latest".to_string(),
+        name: "second-web".to_string(),
+        ports: vec![mapping("0.0.0.0", "8080")],
+        ..Default::default()
+    };
+
+    let err = docker::create_container(&mock.client(), &options)
+        .await
+        .unwrap_err();
+
+    assert!(err.to_string().contains("8080/tcp"), "{}", err);
+    assert!(mock.request(Method::POST, "/containers/create").is_none());
+}
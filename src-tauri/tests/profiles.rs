@@ -0,0 +1,228 @@
+//! Run profiles: placeholder substitution, persistence and launching.
+mod common;
+
+use common::{MockDocker, CREATED_ID};
+use hyper::Method;
+use rykard_lib::docker::{self, CreateContainerOptions};
+use rykard_lib::profiles::{builtin_profiles, ContainerProfile, ProfileStore, PROFILES_FILE};
+use rykard_lib::store;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::TcpListener;
+
+fn profile(container: CreateContainerOptions) -> ContainerProfile {
+    ContainerProfile {
+        name: "api".to_string(),
+        description: String::new(),
+        container,
+        variables: BTreeMap::new(),
+        builtin: false,
+    }
+}
+
+fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn resolves_placeholders_with_defaults_and_fallbacks() {
+    let mut api = profile(CreateContainerOptions {
+        image: "registry.local/api:${TAG:-latest}".to_string(),
+        name: "api-${ENV}".to_string(),
+        env_vars: vec![
+            "DATABASE_URL=postgres://${DB_HOST}/app".to_string(),
+            "PRICE=$$5 and $HOME".to_string(),
+        ],
+        ..Default::default()
+    });
+    api.variables
+        .insert("DB_HOST".to_string(), "db".to_string());
+
+    let resolved = api.resolve(&values(&[("ENV", "staging")])).unwrap();
+    assert_eq!(resolved.image, "registry.local/api:latest");
+    assert_eq!(resolved.name, "api-staging");
+    assert_eq!(resolved.env_vars[0], "DATABASE_URL=postgres://db/app");
+    assert_eq!(resolved.env_vars[1], "PRICE=$5 and $HOME");
+
+    // Launch values win over profile defaults
+    let resolved = api
+        .resolve(&values(&[("ENV", "dev"), ("DB_HOST", "localhost")]))
+        .unwrap();
+    assert_eq!(
+        resolved.env_vars[0],
+        "DATABASE_URL=postgres://localhost/app"
+    );
+
+    assert_eq!(
+        api.placeholders().unwrap().into_iter().collect::<Vec<_>>(),
+        ["DB_HOST", "ENV", "TAG"]
+    );
+}
+
+#[test]
+fn reports_missing_and_malformed_placeholders() {
+    let api = profile(CreateContainerOptions {
+        image: "api:${TAG}".to_string(),
+        name: "${NAME}".to_string(),
+        ..Default::default()
+    });
+    let err = api.resolve(&HashMap::new()).unwrap_err().to_string();
+    assert!(err.contains("NAME, TAG"), "{}", err);
+
+    let unclosed = profile(CreateContainerOptions {
+        image: "api:${TAG".to_string(),
+        ..Default::default()
+    });
+    assert!(unclosed.placeholders().is_err());
+
+    let invalid = profile(CreateContainerOptions {
+        image: "api:${1TAG}".to_string(),
+        ..Default::default()
+    });
+    assert!(invalid.resolve(&HashMap::new()).is_err());
+}
+
+#[test]
+fn builtins_resolve_with_their_defaults() {
+    for builtin in builtin_profiles() {
+        let resolved = builtin.resolve(&HashMap::new()).unwrap();
+        assert!(!resolved.image.contains('$'), "{}", builtin.name);
+        assert!(resolved.limits.memory.is_some());
+    }
+
+    let postgres = builtin_profiles()
+        .into_iter()
+        .find(|p| p.name == "postgres")
+        .unwrap();
+    let resolved = postgres
+        .resolve(&values(&[("NAME", "orders-db"), ("PORT", "15432")]))
+        .unwrap();
+    assert_eq!(resolved.image, "postgres:16");
+    assert_eq!(resolved.ports[0].host_port, "15432");
+    assert_eq!(resolved.volumes[0].host_path, "orders-db-data");
+}
+
+#[test]
+fn store_persists_and_shadows_builtins() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(PROFILES_FILE);
+
+    let mut store = ProfileStore::load(path.clone()).unwrap();
+    let builtin_count = store.list().len();
+    assert!(store.get("redis").unwrap().builtin);
+
+    let mut redis = store.get("redis").unwrap();
+    redis
+        .variables
+        .insert("VERSION".to_string(), "6".to_string());
+    store.save(redis).unwrap();
+    store
+        .save(profile(CreateContainerOptions {
+            image: "api:1".to_string(),
+            ..Default::default()
+        }))
+        .unwrap();
+
+    let reloaded = ProfileStore::load(path).unwrap();
+    assert_eq!(reloaded.list().len(), builtin_count + 1);
+    let redis = reloaded.get("redis").unwrap();
+    assert!(!redis.builtin);
+    assert_eq!(redis.variables["VERSION"], "6");
+
+    assert!(store.delete("postgres").is_err());
+    store.delete("redis").unwrap();
+    assert!(store.get("redis").unwrap().builtin);
+}
+
+#[test]
+fn corrupt_store_is_moved_aside_not_overwritten() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(PROFILES_FILE);
+    let corrupt = "[{\"name\": \"api\",";
+    fs::write(&path, corrupt).unwrap();
+    assert!(ProfileStore::load(path.clone()).is_err());
+
+    let mut store = ProfileStore::recover(path.clone());
+    assert!(store.list().iter().all(|p| p.builtin));
+    assert_eq!(
+        fs::read_to_string(store::backup_path(&path)).unwrap(),
+        corrupt
+    );
+
+    store
+        .save(profile(CreateContainerOptions {
+            image: "api:1".to_string(),
+            ..Default::default()
+        }))
+        .unwrap();
+    assert!(
+        !ProfileStore::load(path.clone())
+            .unwrap()
+            .get("api")
+            .unwrap()
+            .builtin
+    );
+    assert_eq!(
+        fs::read_to_string(store::backup_path(&path)).unwrap(),
+        corrupt
+    );
+
+    // If the file can't be moved aside, the store refuses to replace it
+    fs::write(&path, corrupt).unwrap();
+    fs::remove_file(store::backup_path(&path)).unwrap();
+    fs::create_dir(store::backup_path(&path)).unwrap();
+    fs::write(store::backup_path(&path).join("keep"), "").unwrap();
+    let mut store = ProfileStore::recover(path.clone());
+    let err = store
+        .save(profile(CreateContainerOptions::default()))
+        .unwrap_err();
+    assert!(err.to_string().contains("won't be overwritten"), "{}", err);
+    assert_eq!(fs::read_to_string(&path).unwrap(), corrupt);
+}
+
+#[tokio::test]
+async fn resolved_profile_feeds_create() {
+    let mock = MockDocker::start().await;
+    let postgres = builtin_profiles()
+        .into_iter()
+        .find(|p| p.name == "postgres")
+        .unwrap();
+    // Any free port, so a local Postgres on 5432 doesn't trip the conflict check
+    let port = TcpListener::bind("0.0.0.0:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+        .to_string();
+    let options = postgres.resolve(&values(&[("PORT", &port)])).unwrap();
+
+    let id = docker::create_container(&mock.client(), &options)
+        .await
+        .unwrap();
+    assert_eq!(id, CREATED_ID);
+
+    let create = mock.request(Method::POST, "/containers/create").unwrap();
+    assert_eq!(create.query["name"], "postgres");
+    let body: Value = serde_json::from_str(&create.body).unwrap();
+    assert_eq!(body["Image"], "postgres:16");
+    assert!(body["Env"]
+        .as_array()
+        .unwrap()
+        .contains(&Value::from("POSTGRES_PASSWORD=postgres")));
+    assert!(body["ExposedPorts"].get("5432/tcp").is_some());
+
+    let host = &body["HostConfig"];
+    assert_eq!(
+        host["PortBindings"]["5432/tcp"][0]["HostPort"],
+        port.as_str()
+    );
+    assert_eq!(
+        host["Binds"][0],
+        "postgres-data:/var/lib/postgresql/data:rw"
+    );
+    assert_eq!(host["Memory"], 1024 * 1024 * 1024);
+}
@@ -0,0 +1,153 @@
+//! Recreating containers in place, with rollback when the replacement fails.
+mod common;
+
+use common::{MockDocker, CRASHING_IMAGE, CREATED_ID, WEB_ID};
+use hyper::Method;
+use rykard_lib::recreate::{self, RecreateOptions};
+use serde_json::Value;
+
+fn options() -> RecreateOptions {
+    RecreateOptions {
+        settle_secs: 0,
+        ..Default::default()
+    }
+}
+
+/// Non-GET requests in the order the mock saw them
+fn mutations(mock: &MockDocker) -> Vec<String> {
+    mock.requests()
+        .into_iter()
+        .filter(|r| r.method != Method::GET)
+        .map(|r| format!("{} {}", r.method, r.path))
+        .collect()
+}
+
+#[tokio::test]
+async fn recreates_with_the_same_configuration() {
+    let mock = MockDocker::start().await;
+    let id = recreate::recreate_container(&mock.client(), "web", &options(), |_| {})
+        .await
+        .unwrap();
+    assert_eq!(id, CREATED_ID);
+
+    // The old container is parked under another name until the new one is up
+    let rename = mock
+        .request(Method::POST, &format!("/containers/{}/rename", WEB_ID))
+        .unwrap();
+    assert_eq!(rename.query["name"], format!("web-old-{}", &WEB_ID[..12]));
+
+    let create = mock.request(Method::POST, "/containers/create").unwrap();
+    assert_eq!(create.query["name"], "web");
+    let body: Value = serde_json::from_str(&create.body).unwrap();
+    assert_eq!(body["Image"], "nginx:latest");
+    // What came from the image is left for the image to supply
+    assert!(body["Cmd"].is_null());
+    assert!(body["ExposedPorts"].is_null());
+    assert_eq!(body["Env"], serde_json::json!(["NGINX_VERSION=1.27.0"]));
+    assert_eq!(
+        body["Entrypoint"],
+        serde_json::json!(["/docker-entrypoint.sh"])
+    );
+    assert_eq!(body["Hostname"], "web-host");
+    assert_eq!(body["Labels"]["com.docker.compose.project"], "shop");
+    assert_eq!(body["HostConfig"]["NetworkMode"], "bridge");
+    assert_eq!(body["HostConfig"]["RestartPolicy"]["Name"], "always");
+
+    // Only the primary network is given at create time, the rest are joined after
+    let endpoints = body["NetworkingConfig"]["EndpointsConfig"]
+        .as_object()
+        .unwrap();
+    assert_eq!(endpoints.len(), 1);
+    assert!(endpoints["bridge"]["IPAddress"].is_null());
+    let connect = mock
+        .request(Method::POST, "/networks/shop_default/connect")
+        .unwrap();
+    let connect: Value = serde_json::from_str(&connect.body).unwrap();
+    assert_eq!(connect["Container"], CREATED_ID);
+    // The old container's short-id alias doesn't carry over
+    assert_eq!(
+        connect["EndpointConfig"]["Aliases"],
+        serde_json::json!(["web"])
+    );
+
+    assert_eq!(
+        mutations(&mock).last().unwrap(),
+        &format!("DELETE /containers/{}", WEB_ID)
+    );
+}
+
+#[tokio::test]
+async fn pulls_the_requested_image_first() {
+    let mock = MockDocker::start().await;
+    let options = RecreateOptions {
+        image: Some("nginx:1.27".to_string()),
+        pull: true,
+        ..options()
+    };
+    recreate::recreate_container(&mock.client(), WEB_ID, &options, |_| {})
+        .await
+        .unwrap();
+
+    let order = mutations(&mock);
+    assert_eq!(order[0], "POST /images/create");
+    assert_eq!(order[1], format!("POST /containers/{}/stop", WEB_ID));
+
+    let pull = mock.request(Method::POST, "/images/create").unwrap();
+    assert_eq!(pull.query["fromImage"], "nginx");
+    assert_eq!(pull.query["tag"], "1.27");
+    let create = mock.request(Method::POST, "/containers/create").unwrap();
+    let body: Value = serde_json::from_str(&create.body).unwrap();
+    assert_eq!(body["Image"], "nginx:1.27");
+}
+
+#[tokio::test]
+async fn rolls_back_when_the_new_container_exits() {
+    let mock = MockDocker::start().await;
+    let options = RecreateOptions {
+        image: Some(CRASHING_IMAGE.to_string()),
+        ..options()
+    };
+    let err = recreate::recreate_container(&mock.client(), "web", &options, |_| {})
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("exited with code 137"), "{}", err);
+    assert!(err.contains("restored the previous container"), "{}", err);
+
+    assert_eq!(
+        mutations(&mock),
+        [
+            format!("POST /containers/{}/stop", WEB_ID),
+            format!("POST /containers/{}/rename", WEB_ID),
+            "POST /containers/create".to_string(),
+            "POST /networks/shop_default/connect".to_string(),
+            format!("POST /containers/{}/start", CREATED_ID),
+            format!("DELETE /containers/{}", CREATED_ID),
+            format!("POST /containers/{}/rename", WEB_ID),
+            format!("POST /containers/{}/start", WEB_ID),
+        ]
+    );
+
+    let renames: Vec<String> = mock
+        .requests()
+        .into_iter()
+        .filter(|r| r.path.ends_with("/rename"))
+        .map(|r| r.query["name"].clone())
+        .collect();
+    assert_eq!(renames[1], "web");
+    let removal = mock
+        .request(Method::DELETE, &format!("/containers/{}", CREATED_ID))
+        .unwrap();
+    assert_eq!(removal.query["force"], "true");
+}
+
+#[tokio::test]
+async fn missing_container_is_left_alone() {
+    let mock = MockDocker::start().await;
+    assert!(
+        recreate::recreate_container(&mock.client(), "nope", &options(), |_| {})
+            .await
+            .is_err()
+    );
+    assert!(mutations(&mock).is_empty());
+}
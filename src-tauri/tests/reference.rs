@@ -0,0 +1,190 @@
+//! Image reference parsing and normalization.
+use rykard_lib::reference::ImageReference;
+
+const DIGEST: &str = "sha256:4c0fdaa8b6341bfdeca5f18f7837462c80cff90527ee35ef185571e1c327beac";
+
+fn parse(reference: &str) -> ImageReference {
+    ImageReference::parse(reference).unwrap()
+}
+
+/// registry, namespace, repository, tag, digest
+fn parts(reference: &str) -> (String, String, String, Option<String>, Option<String>) {
+    let r = parse(reference);
+    (r.registry, r.namespace, r.repository, r.tag, r.digest)
+}
+
+fn some(s: &str) -> Option<String> {
+    Some(s.to_string())
+}
+
+#[test]
+fn official_images_live_in_library_on_docker_hub() {
+    assert_eq!(
+        parts("nginx"),
+        (
+            "docker.io".into(),
+            "library".into(),
+            "nginx".into(),
+            None,
+            None
+        )
+    );
+    assert_eq!(
+        parts("nginx:1.27-alpine"),
+        (
+            "docker.io".into(),
+            "library".into(),
+            "nginx".into(),
+            some("1.27-alpine"),
+            None
+        )
+    );
+
+    let nginx = parse("nginx");
+    assert_eq!(nginx.name(), "docker.io/library/nginx");
+    assert_eq!(nginx.familiar_name(), "nginx");
+    assert_eq!(nginx.canonical(), "docker.io/library/nginx:latest");
+    assert_eq!(nginx.to_string(), "nginx");
+}
+
+#[test]
+fn user_repositories_keep_their_namespace() {
+    assert_eq!(
+        parts("grafana/grafana:11.0.0"),
+        (
+            "docker.io".into(),
+            "grafana".into(),
+            "grafana".into(),
+            some("11.0.0"),
+            None
+        )
+    );
+    assert_eq!(parse("grafana/grafana").familiar_name(), "grafana/grafana");
+
+    // Fully qualified Hub names shorten to what the daemon shows
+    let qualified = parse("docker.io/library/redis:7");
+    assert_eq!(qualified.familiar_name(), "redis");
+    assert_eq!(qualified.to_string(), "redis:7");
+    assert_eq!(parse("index.docker.io/library/redis").registry, "docker.io");
+}
+
+#[test]
+fn registry_ports_are_not_tags() {
+    assert_eq!(
+        parts("localhost:5000/app"),
+        ("localhost:5000".into(), "".into(), "app".into(), None, None)
+    );
+    assert_eq!(
+        parts("host:5000/ns/app:tag"),
+        (
+            "host:5000".into(),
+            "ns".into(),
+            "app".into(),
+            some("tag"),
+            None
+        )
+    );
+
+    let local = parse("localhost:5000/shop/api:1.0");
+    assert_eq!(local.familiar_name(), "localhost:5000/shop/api");
+    assert_eq!(local.pull_tag(), "1.0");
+    assert_eq!(local.to_string(), "localhost:5000/shop/api:1.0");
+}
+
+#[test]
+fn first_component_is_a_registry_only_when_it_looks_like_a_host() {
+    assert_eq!(parse("localhost/app").registry, "localhost");
+    assert_eq!(parse("ghcr.io/owner/tool").registry, "ghcr.io");
+    assert_eq!(parse("ghcr.io/owner/tool").path(), "owner/tool");
+    assert_eq!(parse("myhost/app").registry, "docker.io");
+    assert_eq!(parse("myhost/app").namespace, "myhost");
+    assert_eq!(parse("[::1]:5000/app").registry, "[::1]:5000");
+
+    let deep = parse("registry.example.com:443/team/sub/app:v2");
+    assert_eq!(deep.namespace, "team/sub");
+    assert_eq!(deep.repository, "app");
+    assert_eq!(deep.name(), "registry.example.com:443/team/sub/app");
+}
+
+#[test]
+fn digests_pin_the_image() {
+    let pinned = parse(&format!("nginx@{}", DIGEST));
+    assert_eq!(pinned.digest.as_deref(), Some(DIGEST));
+    assert_eq!(pinned.tag, None);
+    assert_eq!(pinned.tag_or_default(), None);
+    assert_eq!(pinned.pull_tag(), DIGEST);
+    assert_eq!(
+        pinned.canonical(),
+        format!("docker.io/library/nginx@{}", DIGEST)
+    );
+
+    // A tag next to a digest is kept for display, but the digest is what gets pulled
+    let both = parse(&format!("localhost:5000/app:1.0@{}", DIGEST));
+    assert_eq!(both.registry, "localhost:5000");
+    assert_eq!(both.tag.as_deref(), Some("1.0"));
+    assert_eq!(both.pull_tag(), DIGEST);
+    assert_eq!(
+        both.to_string(),
+        format!("localhost:5000/app:1.0@{}", DIGEST)
+    );
+}
+
+#[test]
+fn untagged_references_default_to_latest() {
+    let untagged = parse("alpine");
+    assert_eq!(untagged.tag, None);
+    assert_eq!(untagged.tag_or_default(), Some("latest"));
+    assert_eq!(untagged.pull_tag(), "latest");
+}
+
+#[test]
+fn rejects_malformed_references() {
+    let invalid = [
+        "",
+        "Nginx",
+        "nginx:",
+        "nginx:-bad",
+        "nginx@sha256:abc",
+        "nginx@md5",
+        "a//b",
+        "/nginx",
+        "nginx/",
+        "ns/-app",
+        "ns/app..x",
+        "host:port/app",
+        "nginx:tag:extra",
+    ];
+    for reference in invalid {
+        assert!(
+            ImageReference::parse(reference).is_err(),
+            "{:?} should be rejected",
+            reference
+        );
+    }
+
+    let err = ImageReference::parse("MyApp").unwrap_err().to_string();
+    assert!(err.contains("must be lowercase"), "{}", err);
+}
+
+#[test]
+fn accepts_the_separators_docker_allows() {
+    for reference in [
+        "my_app",
+        "my__app",
+        "my-app",
+        "my---app",
+        "my.app",
+        "ns/app_1.2-x:v1.0_rc-2",
+        "UPPER.example.com/app",
+    ] {
+        assert!(
+            ImageReference::parse(reference).is_ok(),
+            "{:?} should parse",
+            reference
+        );
+    }
+    assert!(ImageReference::parse("my___app").is_err());
+
+    let reference: ImageReference = "redis:7".parse().unwrap();
+    assert_eq!(reference.repository, "redis");
+}
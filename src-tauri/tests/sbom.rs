@@ -0,0 +1,558 @@
+//! SBOM generation: tar streaming, package database parsers and vulnerability matching.
+mod common;
+
+use common::{image_filesystem, tar_archive, MockDocker, CREATED_ID, IMAGE_ID, NESTED_MODULE};
+use hyper::Method;
+use rykard_lib::archive::TarStream;
+use rykard_lib::rpmdb;
+use rykard_lib::sbom::{self, is_package_metadata, Distro, Inventory, PackageKind, SbomFormat};
+use rykard_lib::vulndb::{
+    compare_apk, compare_rpm, compare_semver, compare_versions, VulnerabilityDb,
+    VulnerabilityDbStore,
+};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+const OSV_DB: &str = r#"[
+  {
+    "id": "DSA-5532-1",
+    "summary": "openssl security update",
+    "aliases": ["CVE-2023-5363"],
+    "affected": [{
+      "package": { "ecosystem": "Debian:12", "name": "openssl" },
+      "ranges": [{ "type": "ECOSYSTEM", "events": [
+        { "introduced": "0" }, { "fixed": "3.0.11-1~deb12u2" }
+      ] }]
+    }],
+    "database_specific": { "severity": "High" }
+  },
+  {
+    "id": "GHSA-rv95-896h-c2vc",
+    "summary": "Open redirect in express",
+    "affected": [{
+      "package": { "ecosystem": "npm", "name": "express" },
+      "ranges": [{ "type": "SEMVER", "events": [
+        { "introduced": "0" }, { "fixed": "4.19.2" },
+        { "introduced": "5.0.0-alpha.1" }, { "fixed": "5.0.0-beta.3" }
+      ] }]
+    }]
+  },
+  {
+    "id": "PYSEC-2021-142",
+    "summary": "Arbitrary code execution in full_load",
+    "affected": [{
+      "package": { "ecosystem": "PyPI", "name": "pyyaml" },
+      "ranges": [{ "type": "ECOSYSTEM", "events": [
+        { "introduced": "5.1" }, { "last_affected": "5.3.1" }
+      ] }]
+    }]
+  }
+]"#;
+
+fn inventory() -> Inventory {
+    let mut inventory = Inventory::default();
+    for (path, data) in image_filesystem() {
+        if is_package_metadata(&path) {
+            inventory.add_file(&path, &data);
+        }
+    }
+    inventory
+}
+
+#[test]
+fn tar_stream_survives_any_chunking() {
+    let files = image_filesystem();
+    let archive = tar_archive(&files);
+
+    for chunk_size in [1, 7, 511, 512, 4096, archive.len()] {
+        let mut tar = TarStream::new(|_: &str, _| true, 1024 * 1024);
+        let mut entries = Vec::new();
+        for chunk in archive.chunks(chunk_size) {
+            entries.extend(tar.push(chunk).unwrap());
+        }
+
+        assert_eq!(entries.len(), files.len(), "chunk size {}", chunk_size);
+        for (entry, (path, data)) in entries.iter().zip(&files) {
+            assert_eq!(&entry.path, path);
+            assert_eq!(&entry.data, data);
+        }
+    }
+
+    // Only wanted entries are kept
+    let mut tar = TarStream::new(|path: &str, _| path.ends_with("os-release"), 1024);
+    let entries = tar.push(&archive).unwrap();
+    assert_eq!(entries.len(), 1);
+
+    let mut corrupt = archive.clone();
+    corrupt[148] = b'9';
+    let mut tar = TarStream::new(|_: &str, _| true, 1024);
+    assert!(tar.push(&corrupt).is_err());
+}
+
+#[test]
+fn inventory_reads_every_package_database() {
+    let (distro, packages, warnings) = inventory().finish();
+    assert!(warnings.is_empty(), "{:?}", warnings);
+
+    let distro = distro.unwrap();
+    assert_eq!(distro.id, "debian");
+    assert_eq!(distro.version_id, "12");
+
+    let purls: Vec<&str> = packages.iter().map(|p| p.purl.as_str()).collect();
+    assert_eq!(
+        purls,
+        [
+            "pkg:deb/debian/base-files@12.4%2Bdeb12u5?arch=amd64&distro=debian-12",
+            "pkg:deb/debian/libssl3@3.0.11-1~deb12u1?arch=amd64&distro=debian-12",
+            "pkg:npm/%40scope/toolkit@2.0.0-beta.1",
+            "pkg:npm/express@4.17.1",
+            "pkg:npm/left-pad@1.3.0",
+            "pkg:cargo/serde@1.0.200",
+            "pkg:pypi/pyyaml@6.0.1",
+        ]
+    );
+
+    let libssl = &packages[1];
+    assert_eq!(libssl.source.as_deref(), Some("openssl"));
+    assert_eq!(libssl.location, "var/lib/dpkg/status");
+
+    let toolkit = &packages[2];
+    assert_eq!(toolkit.kind, PackageKind::Npm);
+    assert_eq!(toolkit.license.as_deref(), Some("ISC"));
+    assert_eq!(packages[4].location, NESTED_MODULE);
+    assert_eq!(packages[6].license.as_deref(), Some("MIT"));
+}
+
+/// An rpmdb header blob for an x86_64 package with epoch 1: index count, data length,
+/// index entries, data
+fn rpm_header(name: &str, version: &str, release: &str) -> Vec<u8> {
+    let strings = [name, version, release, "x86_64"];
+    let mut data = Vec::new();
+    let mut entries = Vec::new();
+    for (tag, value) in [1000u32, 1001, 1002, 1022].into_iter().zip(strings) {
+        entries.push((tag, 6u32, data.len() as u32));
+        data.extend_from_slice(value.as_bytes());
+        data.push(0);
+    }
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+    entries.push((1003, 4, data.len() as u32));
+    data.extend_from_slice(&1u32.to_be_bytes());
+
+    let mut blob = Vec::new();
+    blob.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    blob.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    for (tag, kind, offset) in entries {
+        for field in [tag, kind, offset, 1] {
+            blob.extend_from_slice(&field.to_be_bytes());
+        }
+    }
+    blob.extend_from_slice(&data);
+    blob
+}
+
+#[test]
+fn reads_apk_and_rpm_databases() {
+    let mut inventory = Inventory::default();
+    inventory.add_file(
+        "etc/os-release",
+        b"ID=alpine\nVERSION_ID=3.20.1\nPRETTY_NAME=\"Alpine Linux v3.20\"\n",
+    );
+    inventory.add_file(
+        "lib/apk/db/installed",
+        b"C:Q1abc=\nP:musl\nV:1.2.5-r0\nA:x86_64\nL:MIT\no:musl\n\nC:Q1def=\nP:libcrypto3\nV:3.3.1-r0\nA:x86_64\nL:Apache-2.0\no:openssl\n",
+    );
+    inventory.add_file("var/lib/rpm/rpmdb.sqlite", b"not a database");
+    let (_, packages, warnings) = inventory.finish();
+
+    assert_eq!(packages.len(), 2);
+    assert_eq!(
+        packages[0].purl,
+        "pkg:apk/alpine/libcrypto3@3.3.1-r0?arch=x86_64&distro=alpine-3.20.1"
+    );
+    assert_eq!(packages[0].source.as_deref(), Some("openssl"));
+    assert_eq!(packages[1].source, None);
+    assert_eq!(warnings.len(), 1);
+
+    let blob = rpm_header("bash", "5.2.26", "3.fc40");
+    let bash = rpmdb::parse_header(&blob).unwrap();
+    assert_eq!(bash.name, "bash");
+    assert_eq!(bash.arch, "x86_64");
+    assert_eq!(bash.evr(), "1:5.2.26-3.fc40");
+    assert!(rpmdb::parse_header(&blob[..20]).is_none());
+}
+
+#[test]
+fn orders_versions() {
+    let less = [
+        ("1.0~rc1", "1.0"),
+        ("1.2.9", "1.2.10"),
+        ("2.0", "1:0.9"),
+        ("3.0.11-1~deb12u1", "3.0.11-1~deb12u2"),
+        ("3.0.11-1~deb12u2", "3.0.11-1"),
+        ("1.2.3-r4", "1.2.3-r10"),
+        ("1.0", "1.0a"),
+    ];
+    for (a, b) in less {
+        assert_eq!(compare_versions(a, b), Ordering::Less, "{} < {}", a, b);
+        assert_eq!(compare_versions(b, a), Ordering::Greater, "{} > {}", b, a);
+    }
+    assert_eq!(compare_versions("1.01", "1.1"), Ordering::Equal);
+
+    assert_eq!(compare_semver("2.0.0-beta.1", "2.0.0"), Ordering::Less);
+    assert_eq!(compare_semver("v4.17.1", "4.19.2"), Ordering::Less);
+    assert_eq!(compare_semver("1.0.0+build.5", "1.0.0"), Ordering::Equal);
+
+    let apk = [
+        ("3.3.1_rc2-r5", "3.3.1-r0"),
+        ("3.3.1-r0", "3.3.1_p1-r0"),
+        ("1.2.3_alpha1", "1.2.3_beta"),
+        ("1.2.3", "1.2.3a"),
+        ("1.2.9-r10", "1.2.10-r0"),
+        ("1.36.1-r28", "1.36.1-r29"),
+    ];
+    for (a, b) in apk {
+        assert_eq!(compare_apk(a, b), Ordering::Less, "{} < {}", a, b);
+        assert_eq!(compare_apk(b, a), Ordering::Greater, "{} > {}", b, a);
+    }
+
+    let rpm = [
+        ("1.0~rc1-1", "1.0-1"),
+        ("1.0-1", "1.0^git1-1"),
+        ("1.0^git1-1", "1.0.1-1"),
+        ("1.0a-1", "1.0.1-1"),
+        ("5.2.26-3.fc40", "1:5.2.15-1.fc40"),
+        ("3.0.7-27.el9", "3.0.7-28.el9"),
+    ];
+    for (a, b) in rpm {
+        assert_eq!(compare_rpm(a, b), Ordering::Less, "{} < {}", a, b);
+        assert_eq!(compare_rpm(b, a), Ordering::Greater, "{} > {}", b, a);
+    }
+    assert_eq!(compare_rpm("1.01-1", "1.1-1"), Ordering::Equal);
+}
+
+#[test]
+fn matches_only_the_installed_release() {
+    let db = VulnerabilityDb::from_json(
+        r#"[
+      { "id": "DLA-3530-1", "affected": [{
+        "package": { "ecosystem": "Debian:11", "name": "openssl" },
+        "ranges": [{ "type": "ECOSYSTEM", "events": [
+          { "introduced": "0" }, { "fixed": "3.0.13-1~deb11u1" }
+        ] }]
+      }] },
+      { "id": "USN-6188-1", "affected": [{
+        "package": { "ecosystem": "Ubuntu:22.04:LTS", "name": "openssl" },
+        "ranges": [{ "type": "ECOSYSTEM", "events": [
+          { "introduced": "0" }, { "fixed": "3.0.2-0ubuntu1.10" }
+        ] }]
+      }] },
+      { "id": "ALPINE-CVE-2024-5535", "affected": [{
+        "package": { "ecosystem": "Alpine:v3.20", "name": "openssl" },
+        "ranges": [{ "type": "ECOSYSTEM", "events": [
+          { "introduced": "0" }, { "fixed": "3.3.1-r1" }
+        ] }]
+      }] },
+      { "id": "PYSEC-2024-1", "affected": [{
+        "package": { "ecosystem": "PyPI", "name": "PyYAML" },
+        "ranges": [{ "type": "ECOSYSTEM", "events": [
+          { "introduced": "6.0" }, { "fixed": "6.0.2" }
+        ] }],
+        "versions": ["6.0"]
+      }] }
+    ]"#,
+    )
+    .unwrap();
+    let ids = |ecosystem: &str, name: &str, version: &str| -> Vec<String> {
+        db.matches(ecosystem, &[name], version)
+            .into_iter()
+            .map(|m| m.id)
+            .collect()
+    };
+
+    assert!(ids("Debian:12", "openssl", "3.0.11-1~deb12u1").is_empty());
+    assert_eq!(
+        ids("Debian:11", "openssl", "3.0.11-1~deb11u1"),
+        ["DLA-3530-1"]
+    );
+    // Without a release, advisories for any release apply
+    assert_eq!(ids("Debian", "openssl", "3.0.11-1"), ["DLA-3530-1"]);
+    assert_eq!(
+        ids("Ubuntu:22.04", "openssl", "3.0.2-0ubuntu1.9"),
+        ["USN-6188-1"]
+    );
+    assert!(ids("Ubuntu:22.0", "openssl", "3.0.2-0ubuntu1.9").is_empty());
+    // apk puts a release candidate before the release, dpkg would not
+    assert_eq!(
+        ids("Alpine:v3.20", "openssl", "3.3.1_rc2-r5"),
+        ["ALPINE-CVE-2024-5535"]
+    );
+    assert!(ids("Alpine:v3.19", "openssl", "3.3.1-r0").is_empty());
+    // PyPI ranges can't be ordered, but listed versions still match
+    assert_eq!(ids("PyPI", "pyyaml", "6.0"), ["PYSEC-2024-1"]);
+    assert!(ids("PyPI", "pyyaml", "6.0.1").is_empty());
+
+    let distro = |id: &str, version_id: &str| Distro {
+        id: id.to_string(),
+        version_id: version_id.to_string(),
+        pretty_name: String::new(),
+    };
+    let ecosystem = |kind, id, version_id| sbom::ecosystem(kind, Some(&distro(id, version_id)));
+    assert_eq!(ecosystem(PackageKind::Deb, "debian", "12"), "Debian:12");
+    assert_eq!(ecosystem(PackageKind::Deb, "debian", ""), "Debian");
+    assert_eq!(
+        ecosystem(PackageKind::Deb, "ubuntu", "22.04"),
+        "Ubuntu:22.04"
+    );
+    assert_eq!(
+        ecosystem(PackageKind::Apk, "alpine", "3.20.1"),
+        "Alpine:v3.20"
+    );
+    assert_eq!(
+        ecosystem(PackageKind::Rpm, "rhel", "9.4"),
+        "Red Hat:enterprise_linux:9"
+    );
+    assert_eq!(ecosystem(PackageKind::Rpm, "rocky", "9.4"), "Rocky Linux:9");
+    assert_eq!(ecosystem(PackageKind::Npm, "alpine", "3.20.1"), "npm");
+}
+
+const PAGE_SIZE: usize = 512;
+
+fn varint(n: usize) -> Vec<u8> {
+    if n < 0x80 {
+        vec![n as u8]
+    } else {
+        vec![0x80 | (n >> 7) as u8, (n & 0x7f) as u8]
+    }
+}
+
+/// A SQLite record from (serial type, body) columns
+fn sqlite_record(columns: &[(usize, &[u8])]) -> Vec<u8> {
+    let types: Vec<u8> = columns.iter().flat_map(|(t, _)| varint(*t)).collect();
+    let mut record = varint(types.len() + 1);
+    record.extend(types);
+    for (_, body) in columns {
+        record.extend_from_slice(body);
+    }
+    record
+}
+
+/// A leaf table page; page 1 starts with the database header
+fn leaf_page(records: &[Vec<u8>], first: bool) -> Vec<u8> {
+    let mut page = vec![0u8; PAGE_SIZE];
+    let header = if first {
+        page[..16].copy_from_slice(b"SQLite format 3\0");
+        page[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+        100
+    } else {
+        0
+    };
+    page[header] = 0x0d;
+    page[header + 3..header + 5].copy_from_slice(&(records.len() as u16).to_be_bytes());
+    let mut end = PAGE_SIZE;
+    for (i, record) in records.iter().enumerate() {
+        let mut cell = varint(record.len());
+        cell.extend(varint(i + 1));
+        cell.extend_from_slice(record);
+        end -= cell.len();
+        page[end..end + cell.len()].copy_from_slice(&cell);
+        let pointer = header + 8 + i * 2;
+        page[pointer..pointer + 2].copy_from_slice(&(end as u16).to_be_bytes());
+    }
+    page
+}
+
+/// A `Packages` table row: `hnum` is the rowid, so it's stored as NULL
+fn package_row(name: &str) -> Vec<u8> {
+    let blob = rpm_header(name, "1.0", "1.fc40");
+    sqlite_record(&[(0, &[]), (12 + 2 * blob.len(), &blob)])
+}
+
+/// A big-endian write-ahead log of (page, commit size, contents) frames
+fn write_ahead_log(frames: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+    let checksum = |(mut s0, mut s1): (u32, u32), data: &[u8]| {
+        for chunk in data.chunks_exact(8) {
+            let word = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+            s0 = s0.wrapping_add(word(&chunk[..4])).wrapping_add(s1);
+            s1 = s1.wrapping_add(word(&chunk[4..])).wrapping_add(s0);
+        }
+        (s0, s1)
+    };
+    let mut wal = Vec::new();
+    for field in [0x377f0683u32, 3007000, PAGE_SIZE as u32, 0, 7, 9] {
+        wal.extend_from_slice(&field.to_be_bytes());
+    }
+    let mut sum = checksum((0, 0), &wal);
+    wal.extend_from_slice(&sum.0.to_be_bytes());
+    wal.extend_from_slice(&sum.1.to_be_bytes());
+    for (page, commit, data) in frames {
+        let mut header = Vec::new();
+        for field in [*page, *commit, 7, 9] {
+            header.extend_from_slice(&field.to_be_bytes());
+        }
+        sum = checksum(sum, &header[..8]);
+        sum = checksum(sum, data);
+        header.extend_from_slice(&sum.0.to_be_bytes());
+        header.extend_from_slice(&sum.1.to_be_bytes());
+        wal.extend(header);
+        wal.extend_from_slice(data);
+    }
+    wal
+}
+
+#[test]
+fn reads_rpm_transactions_from_the_write_ahead_log() {
+    let sql = b"CREATE TABLE Packages (hnum INTEGER PRIMARY KEY, blob BLOB NOT NULL)";
+    let schema = sqlite_record(&[
+        (13 + 2 * 5, b"table"),
+        (13 + 2 * 8, b"Packages"),
+        (13 + 2 * 8, b"Packages"),
+        (1, &[2]),
+        (13 + 2 * sql.len(), sql),
+    ]);
+    let db = [
+        leaf_page(&[schema], true),
+        leaf_page(&[package_row("bash")], false),
+    ]
+    .concat();
+    let names = |wal: Option<&[u8]>| -> Vec<String> {
+        rpmdb::read_rpmdb(&db, wal)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect()
+    };
+    assert_eq!(names(None), ["bash"]);
+
+    // The second transaction was never committed
+    let wal = write_ahead_log(&[
+        (
+            2,
+            2,
+            leaf_page(&[package_row("bash"), package_row("zlib")], false),
+        ),
+        (2, 0, leaf_page(&[package_row("curl")], false)),
+    ]);
+    assert_eq!(names(Some(&wal)), ["bash", "zlib"]);
+    // A frame with a bad checksum ends the log
+    let mut damaged = wal.clone();
+    damaged[32 + 24 + 100] ^= 0xff;
+    assert_eq!(names(Some(&damaged)), ["bash"]);
+    // A checkpointed log is empty
+    assert_eq!(names(Some(&[])), ["bash"]);
+
+    // The log can come out of the image before its database
+    assert!(is_package_metadata("usr/lib/sysimage/rpm/rpmdb.sqlite-wal"));
+    let mut inventory = Inventory::default();
+    inventory.add_file("var/lib/rpm/rpmdb.sqlite-wal", &wal);
+    inventory.add_file("var/lib/rpm/rpmdb.sqlite", &db);
+    let (_, packages, warnings) = inventory.finish();
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    let names: Vec<&str> = packages.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["bash", "zlib"]);
+    assert_eq!(packages[1].location, "var/lib/rpm/rpmdb.sqlite");
+}
+
+#[test]
+fn matches_packages_against_imported_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("osv.json");
+    std::fs::write(&source, OSV_DB).unwrap();
+    let dest = dir.path().join("config").join("vulndb.json");
+
+    assert!(VulnerabilityDb::load(&dest).unwrap().is_none());
+    assert_eq!(VulnerabilityDb::import(&source, &dest).unwrap(), 3);
+    let db = VulnerabilityDb::load(&dest).unwrap().unwrap();
+
+    let (distro, packages, _) = inventory().finish();
+    let findings = sbom::find_vulnerabilities(&db, distro.as_ref(), &packages);
+    let ids: Vec<&str> = findings
+        .iter()
+        .map(|f| f.vulnerability.id.as_str())
+        .collect();
+    // pyyaml 6.0.1 is past the last affected version
+    assert_eq!(ids, ["DSA-5532-1", "GHSA-rv95-896h-c2vc"]);
+
+    let openssl = &findings[0];
+    assert!(openssl.purl.starts_with("pkg:deb/debian/libssl3@"));
+    assert_eq!(openssl.vulnerability.package, "openssl");
+    assert_eq!(
+        openssl.vulnerability.fixed_version.as_deref(),
+        Some("3.0.11-1~deb12u2")
+    );
+    assert_eq!(openssl.vulnerability.severity.as_deref(), Some("High"));
+
+    // The store reads the file once and keeps what it imports
+    let mut store = VulnerabilityDbStore::new(dest.clone());
+    assert_eq!(store.get().unwrap().unwrap().records.len(), 3);
+    std::fs::remove_file(&dest).unwrap();
+    assert_eq!(store.get().unwrap().unwrap().records.len(), 3);
+    assert_eq!(store.import(&source).unwrap(), 3);
+    assert!(dest.exists());
+
+    let bad = dir.path().join("bad.json");
+    std::fs::write(&bad, r#"{"records": 1}"#).unwrap();
+    assert!(VulnerabilityDb::import(&bad, &dest).is_err());
+    assert_eq!(
+        VulnerabilityDb::load(&dest).unwrap().unwrap().records.len(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn scans_image_through_a_temporary_container() {
+    let mock = MockDocker::start().await;
+    let db = VulnerabilityDb::from_json(OSV_DB).unwrap();
+
+    let sbom = sbom::generate_sbom(&mock.client(), "nginx", Some(&db))
+        .await
+        .unwrap();
+    assert_eq!(sbom.image.id, IMAGE_ID.trim_start_matches("sha256:"));
+    assert_eq!(sbom.packages.len(), 7);
+    assert_eq!(sbom.findings.len(), 2);
+
+    let create = mock.request(Method::POST, "/containers/create").unwrap();
+    let body: Value = serde_json::from_str(&create.body).unwrap();
+    assert_eq!(body["Image"], "nginx");
+    assert_eq!(body["NetworkDisabled"], true);
+    assert_eq!(body["Labels"][sbom::SBOM_LABEL], "true");
+    // The container is only ever exported, never started
+    assert!(mock
+        .request(Method::POST, &format!("/containers/{}/start", CREATED_ID))
+        .is_none());
+    let remove = mock
+        .request(Method::DELETE, &format!("/containers/{}", CREATED_ID))
+        .unwrap();
+    assert_eq!(remove.query["force"], "true");
+    assert_eq!(remove.query["v"], "true");
+
+    let cyclonedx = sbom.document(SbomFormat::CycloneDx);
+    assert_eq!(cyclonedx["bomFormat"], "CycloneDX");
+    assert_eq!(cyclonedx["metadata"]["component"]["name"], "nginx:latest");
+    assert_eq!(cyclonedx["components"].as_array().unwrap().len(), 7);
+    let vulnerability = &cyclonedx["vulnerabilities"][0];
+    assert_eq!(vulnerability["ratings"][0]["severity"], "high");
+    assert_eq!(vulnerability["affects"][0]["ref"], "pkg-2");
+    assert_eq!(cyclonedx["components"][1]["name"], "libssl3");
+
+    let spdx = sbom.document(SbomFormat::Spdx);
+    assert_eq!(spdx["spdxVersion"], "SPDX-2.3");
+    let packages = spdx["packages"].as_array().unwrap();
+    assert_eq!(packages.len(), 8);
+    assert_eq!(packages[0]["primaryPackagePurpose"], "CONTAINER");
+    assert_eq!(
+        packages[2]["externalRefs"][1]["referenceLocator"],
+        "https://osv.dev/vulnerability/DSA-5532-1"
+    );
+    assert_eq!(spdx["relationships"].as_array().unwrap().len(), 8);
+}
+
+#[tokio::test]
+async fn scan_of_missing_image_creates_nothing() {
+    let mock = MockDocker::start().await;
+    assert!(sbom::scan_image(&mock.client(), "does-not-exist")
+        .await
+        .is_err());
+    assert!(mock.request(Method::POST, "/containers/create").is_none());
+}
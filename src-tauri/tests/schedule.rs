@@ -0,0 +1,444 @@
+//! Cron parsing, schedule persistence and running scheduled actions.
+mod common;
+
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
+use common::{MockDocker, DB_ID, WEB_ID};
+use hyper::Method;
+use rykard_lib::guards;
+use rykard_lib::schedule::{
+    CronExpr, Schedule, ScheduleAction, ScheduleStore, ScheduleTarget, HISTORY_RETENTION,
+};
+use rykard_lib::settings::DeleteGuards;
+use rykard_lib::store;
+use std::fs;
+
+fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(y, mo, d)
+        .unwrap()
+        .and_hms_opt(h, mi, 0)
+        .unwrap()
+}
+
+fn cron(expression: &str) -> CronExpr {
+    CronExpr::parse(expression).unwrap()
+}
+
+fn next(expression: &str, after: NaiveDateTime) -> NaiveDateTime {
+    cron(expression).next_match(after).unwrap()
+}
+
+fn schedule(name: &str, cron: &str, target: ScheduleTarget, action: ScheduleAction) -> Schedule {
+    Schedule {
+        name: name.to_string(),
+        cron: cron.to_string(),
+        target,
+        action,
+        enabled: true,
+    }
+}
+
+fn container(name: &str) -> ScheduleTarget {
+    ScheduleTarget::Container {
+        container: name.to_string(),
+    }
+}
+
+fn project(name: &str) -> ScheduleTarget {
+    ScheduleTarget::Project {
+        project: name.to_string(),
+    }
+}
+
+#[test]
+fn next_match_walks_every_field() {
+    // Monday 2024-01-15 10:20
+    let now = at(2024, 1, 15, 10, 20);
+    assert_eq!(next("0 19 * * *", now), at(2024, 1, 15, 19, 0));
+    assert_eq!(
+        next("30 8 * * mon-fri", at(2024, 1, 19, 9, 0)),
+        at(2024, 1, 22, 8, 30)
+    );
+    assert_eq!(next("*/15 * * * *", now), at(2024, 1, 15, 10, 30));
+    assert_eq!(next("5,50 10 * * *", now), at(2024, 1, 15, 10, 50));
+    assert_eq!(next("0 3 1 * *", now), at(2024, 2, 1, 3, 0));
+    assert_eq!(next("0 0 1 jan *", now), at(2025, 1, 1, 0, 0));
+    assert_eq!(next("0 0 29 2 *", now), at(2024, 2, 29, 0, 0));
+    assert_eq!(
+        next("0 0 29 2 *", at(2024, 3, 1, 0, 0)),
+        at(2028, 2, 29, 0, 0)
+    );
+    assert_eq!(next("0 12 * * 7", now), at(2024, 1, 21, 12, 0));
+    assert_eq!(next("10/20 * * * *", now), at(2024, 1, 15, 10, 30));
+
+    // Strictly after, at minute granularity
+    assert_eq!(next("20 10 * * *", now), at(2024, 1, 16, 10, 20));
+    let mid_minute = at(2024, 1, 15, 10, 20) + chrono::TimeDelta::seconds(30);
+    assert_eq!(next("* * * * *", mid_minute), at(2024, 1, 15, 10, 21));
+
+    assert!(cron("0 0 31 2 *").next_match(now).is_none());
+}
+
+#[test]
+fn restricted_day_fields_match_either() {
+    // The 13th or any Friday
+    let expr = cron("0 0 13 * fri");
+    assert_eq!(
+        expr.next_match(at(2024, 1, 15, 0, 0)),
+        Some(at(2024, 1, 19, 0, 0))
+    );
+    assert_eq!(
+        expr.next_match(at(2024, 2, 10, 0, 0)),
+        Some(at(2024, 2, 13, 0, 0))
+    );
+
+    // A `*` in either one makes them both apply
+    let expr = cron("0 0 */2 * fri");
+    assert!(expr.matches(&at(2024, 1, 19, 0, 0)));
+    assert!(!expr.matches(&at(2024, 1, 26, 0, 0)));
+    assert!(!expr.matches(&at(2024, 1, 17, 0, 0)));
+}
+
+#[test]
+fn shorthands_expand() {
+    assert_eq!(cron("@daily"), cron("0 0 * * *"));
+    assert_eq!(cron("@midnight"), cron("0 0 * * *"));
+    assert_eq!(cron("@hourly"), cron("0 * * * *"));
+    assert_eq!(cron("@weekly"), cron("0 0 * * sun"));
+    assert_eq!(cron("@monthly"), cron("0 0 1 * *"));
+    assert_eq!(cron("@Yearly"), cron("0 0 1 JAN *"));
+}
+
+#[test]
+fn rejects_malformed_expressions() {
+    for expression in [
+        "",
+        "* * * *",
+        "* * * * * *",
+        "60 * * * *",
+        "* 24 * * *",
+        "* * 0 * *",
+        "* * * 13 *",
+        "* * * * 8",
+        "*/0 * * * *",
+        "5-1 * * * *",
+        "a * * * *",
+        "1,,2 * * * *",
+        "* * * foo *",
+        "@reboot",
+    ] {
+        assert!(
+            CronExpr::parse(expression).is_err(),
+            "{:?} should be rejected",
+            expression
+        );
+    }
+    let err = CronExpr::parse("61 * * * *").unwrap_err().to_string();
+    assert!(err.contains("'61' is out of range"), "{}", err);
+}
+
+#[test]
+fn next_after_skips_times_lost_to_dst() {
+    let expr = cron("30 2 * * *");
+    let utc = chrono::Utc.with_ymd_and_hms(2024, 3, 10, 0, 0, 0).unwrap();
+    assert_eq!(
+        expr.next_after(&utc).unwrap(),
+        chrono::Utc.with_ymd_and_hms(2024, 3, 10, 2, 30, 0).unwrap()
+    );
+
+    let offset = FixedOffset::east_opt(2 * 3600).unwrap();
+    let now = offset.with_ymd_and_hms(2024, 1, 15, 18, 59, 30).unwrap();
+    let run = cron("0 19 * * *").next_after(&now).unwrap();
+    assert_eq!(run, offset.with_ymd_and_hms(2024, 1, 15, 19, 0, 0).unwrap());
+    assert_eq!(run.timestamp() - now.timestamp(), 30);
+}
+
+#[test]
+fn store_persists_schedules_and_validates_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("schedules.json");
+    let mut store = ScheduleStore::load(path.clone()).unwrap();
+
+    let stop = schedule(
+        "evening",
+        "0 19 * * 1-5",
+        project("shop"),
+        ScheduleAction::Stop,
+    );
+    store.save(stop.clone()).unwrap();
+    store
+        .save(schedule(
+            "morning",
+            "30 8 * * 1-5",
+            project("shop"),
+            ScheduleAction::Start,
+        ))
+        .unwrap();
+
+    let reloaded = ScheduleStore::load(path.clone()).unwrap();
+    assert_eq!(reloaded.get("evening").unwrap(), stop);
+
+    // Saving under an existing name replaces it
+    let mut disabled = stop.clone();
+    disabled.enabled = false;
+    store.save(disabled).unwrap();
+    let now = FixedOffset::east_opt(0)
+        .unwrap()
+        .with_ymd_and_hms(2024, 1, 15, 12, 0, 0)
+        .unwrap();
+    let listed = store.list(&now);
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0].next_run, None);
+    assert_eq!(
+        listed[1].next_run,
+        Some(
+            FixedOffset::east_opt(0)
+                .unwrap()
+                .with_ymd_and_hms(2024, 1, 16, 8, 30, 0)
+                .unwrap()
+                .timestamp()
+        )
+    );
+
+    assert!(store
+        .save(schedule(
+            " ",
+            "@daily",
+            container("web"),
+            ScheduleAction::Restart
+        ))
+        .is_err());
+    assert!(store
+        .save(schedule(
+            "bad",
+            "0 25 * * *",
+            container("web"),
+            ScheduleAction::Restart
+        ))
+        .is_err());
+    assert!(store
+        .save(schedule(
+            "empty",
+            "@daily",
+            container(""),
+            ScheduleAction::Restart
+        ))
+        .is_err());
+
+    store.delete("morning").unwrap();
+    assert!(store.delete("morning").is_err());
+    assert!(store.get("morning").is_err());
+    assert_eq!(ScheduleStore::load(path).unwrap().list(&now).len(), 1);
+}
+
+#[test]
+fn corrupt_store_is_moved_aside_not_overwritten() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("schedules.json");
+    let corrupt = "{\"schedules\": [";
+    fs::write(&path, corrupt).unwrap();
+    assert!(ScheduleStore::load(path.clone()).is_err());
+
+    let nightly = schedule(
+        "nightly",
+        "@daily",
+        container("web"),
+        ScheduleAction::Restart,
+    );
+    let mut store = ScheduleStore::recover(path.clone());
+    assert_eq!(
+        fs::read_to_string(store::backup_path(&path)).unwrap(),
+        corrupt
+    );
+    store.save(nightly.clone()).unwrap();
+    assert_eq!(
+        ScheduleStore::load(path.clone())
+            .unwrap()
+            .get("nightly")
+            .unwrap(),
+        nightly
+    );
+    assert_eq!(
+        fs::read_to_string(store::backup_path(&path)).unwrap(),
+        corrupt
+    );
+
+    // If the file can't be moved aside, the store refuses to replace it
+    fs::write(&path, corrupt).unwrap();
+    fs::remove_file(store::backup_path(&path)).unwrap();
+    fs::create_dir(store::backup_path(&path)).unwrap();
+    fs::write(store::backup_path(&path).join("keep"), "").unwrap();
+    let mut store = ScheduleStore::recover(path.clone());
+    let err = store.save(nightly).unwrap_err();
+    assert!(err.to_string().contains("won't be overwritten"), "{}", err);
+    assert_eq!(fs::read_to_string(&path).unwrap(), corrupt);
+}
+
+#[test]
+fn due_covers_the_interval_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = ScheduleStore::load(dir.path().join("schedules.json")).unwrap();
+    store
+        .save(schedule(
+            "stop",
+            "0 19 * * *",
+            project("shop"),
+            ScheduleAction::Stop,
+        ))
+        .unwrap();
+    store
+        .save(schedule(
+            "nightly",
+            "0 3 * * *",
+            container("web"),
+            ScheduleAction::Restart,
+        ))
+        .unwrap();
+    let mut off = schedule("off", "* * * * *", container("web"), ScheduleAction::Stop);
+    off.enabled = false;
+    store.save(off).unwrap();
+
+    let names = |since, until| -> Vec<String> {
+        store
+            .due(since, until)
+            .into_iter()
+            .map(|s| s.name)
+            .collect()
+    };
+    assert_eq!(
+        names(at(2024, 1, 15, 18, 59), at(2024, 1, 15, 19, 0)),
+        ["stop"]
+    );
+    assert!(names(at(2024, 1, 15, 19, 0), at(2024, 1, 15, 19, 1)).is_empty());
+    // A late tick still catches the run it skipped over
+    assert_eq!(
+        names(at(2024, 1, 15, 2, 30), at(2024, 1, 15, 3, 2)),
+        ["nightly"]
+    );
+}
+
+#[tokio::test]
+async fn runs_container_and_project_actions() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+
+    let record = rykard_lib::schedule::run(
+        &docker,
+        &schedule(
+            "nightly",
+            "0 3 * * *",
+            container("web"),
+            ScheduleAction::Restart,
+        ),
+        false,
+    )
+    .await;
+    assert_eq!(record.containers, ["web"]);
+    assert_eq!(record.error, None);
+    assert!(!record.manual);
+    assert!(mock
+        .request(Method::POST, "/containers/web/restart")
+        .is_some());
+
+    // Only the running containers of the project are stopped
+    let record = rykard_lib::schedule::run(
+        &docker,
+        &schedule(
+            "evening",
+            "0 19 * * *",
+            project("shop"),
+            ScheduleAction::Stop,
+        ),
+        true,
+    )
+    .await;
+    assert_eq!(record.containers, ["web"]);
+    assert!(record.manual);
+    assert!(mock
+        .request(Method::POST, &format!("/containers/{}/stop", WEB_ID))
+        .is_some());
+    assert!(mock
+        .request(Method::POST, &format!("/containers/{}/stop", DB_ID))
+        .is_none());
+
+    // Already running: nothing to start, and that's not a failure
+    let record = rykard_lib::schedule::run(
+        &docker,
+        &schedule(
+            "morning",
+            "30 8 * * *",
+            project("shop"),
+            ScheduleAction::Start,
+        ),
+        false,
+    )
+    .await;
+    assert!(record.containers.is_empty());
+    assert_eq!(record.error, None);
+
+    // A trashed container keeps its compose labels but no longer belongs to the project
+    let trash = DeleteGuards {
+        trash: true,
+        ..Default::default()
+    };
+    guards::remove_container(&docker, &trash, "web", false)
+        .await
+        .unwrap();
+    let record = rykard_lib::schedule::run(
+        &docker,
+        &schedule(
+            "morning",
+            "30 8 * * *",
+            project("shop"),
+            ScheduleAction::Restart,
+        ),
+        false,
+    )
+    .await;
+    assert!(record.containers.is_empty());
+    assert_eq!(
+        record.error.as_deref(),
+        Some("Not found: No containers found for project shop")
+    );
+}
+
+#[tokio::test]
+async fn failures_are_recorded_in_the_history() {
+    let mock = MockDocker::start().await;
+    let docker = mock.client();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("schedules.json");
+    let mut store = ScheduleStore::load(path.clone()).unwrap();
+
+    let missing = rykard_lib::schedule::run(
+        &docker,
+        &schedule("gone", "@daily", container("ghost"), ScheduleAction::Stop),
+        false,
+    )
+    .await;
+    assert!(missing.containers.is_empty());
+    assert!(missing.error.as_deref().unwrap().contains("ghost"));
+
+    let no_project = rykard_lib::schedule::run(
+        &docker,
+        &schedule("other", "@daily", project("blog"), ScheduleAction::Restart),
+        false,
+    )
+    .await;
+    assert_eq!(
+        no_project.error.as_deref(),
+        Some("Not found: No containers found for project blog")
+    );
+
+    store.record(missing.clone()).unwrap();
+    store.record(no_project.clone()).unwrap();
+    assert_eq!(store.history(None), [no_project.clone(), missing.clone()]);
+    assert_eq!(store.history(Some("gone")), vec![missing.clone()]);
+    assert_eq!(ScheduleStore::load(path).unwrap().history(None).len(), 2);
+
+    for _ in 0..HISTORY_RETENTION {
+        store.record(missing.clone()).unwrap();
+    }
+    let history = store.history(None);
+    assert_eq!(history.len(), HISTORY_RETENTION);
+    assert!(history.iter().all(|r| r.schedule == "gone"));
+}
@@ -0,0 +1,136 @@
+//! Settings persistence and schema migration.
+use rykard_lib::settings::{
+    backup_path, Settings, SettingsStore, Theme, SETTINGS_FILE, SETTINGS_VERSION,
+};
+use serde_json::json;
+use std::fs;
+
+#[test]
+fn missing_file_yields_defaults_without_writing() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(SETTINGS_FILE);
+
+    let store = SettingsStore::load(path.clone()).unwrap();
+
+    assert_eq!(store.settings(), &Settings::default());
+    assert!(!path.exists());
+}
+
+#[test]
+fn update_merges_and_persists() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nested").join(SETTINGS_FILE);
+
+    let mut store = SettingsStore::load(path.clone()).unwrap();
+    let updated = store
+        .update(json!({ "log_tail_lines": 500, "theme": "dark" }))
+        .unwrap();
+
+    assert_eq!(updated.log_tail_lines, 500);
+    assert_eq!(updated.theme, Theme::Dark);
+    // Untouched fields keep their current values
+    assert_eq!(updated.stats_interval_ms, 2000);
+    assert!(updated.confirm_on_delete);
+
+    let reloaded = SettingsStore::load(path).unwrap();
+    assert_eq!(reloaded.settings(), &updated);
+}
+
+#[test]
+fn update_rejects_invalid_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(SETTINGS_FILE);
+    let mut store = SettingsStore::load(path.clone()).unwrap();
+
+    assert!(store.update(json!({ "log_tail_lines": 0 })).is_err());
+    assert!(store.update(json!({ "stats_interval_ms": 10 })).is_err());
+    assert!(store.update(json!({ "theme": "neon" })).is_err());
+    assert!(store.update(json!(["not", "an", "object"])).is_err());
+    assert!(store
+        .update(json!({ "dockerfile_lint": { "disabled_rules": ["no-such-rule"] } }))
+        .is_err());
+    assert!(store
+        .update(json!({ "job_limits": { "builds": 0 } }))
+        .is_err());
+    assert!(store
+        .update(json!({ "crash_alerts": { "window_minutes": 0 } }))
+        .is_err());
+    assert!(store
+        .update(json!({ "delete_guards": { "trash_grace_minutes": 0 } }))
+        .is_err());
+    assert!(store
+        .update(json!({ "delete_guards": { "protected_labels": [""] } }))
+        .is_err());
+
+    // Nothing was written and the in-memory settings are unchanged
+    assert!(!path.exists());
+    assert_eq!(store.settings(), &Settings::default());
+}
+
+#[test]
+fn unversioned_file_is_migrated_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(SETTINGS_FILE);
+    fs::write(&path, r#"{ "log_tail_lines": 250 }"#).unwrap();
+
+    let store = SettingsStore::load(path.clone()).unwrap();
+    assert_eq!(store.settings().version, SETTINGS_VERSION);
+    assert_eq!(store.settings().log_tail_lines, 250);
+    assert_eq!(store.settings().event_retention, 500);
+
+    let on_disk: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(on_disk["version"], json!(SETTINGS_VERSION));
+}
+
+#[test]
+fn newer_schema_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(SETTINGS_FILE);
+    fs::write(
+        &path,
+        json!({ "version": SETTINGS_VERSION + 1 }).to_string(),
+    )
+    .unwrap();
+
+    assert!(SettingsStore::load(path).is_err());
+}
+
+#[test]
+fn invalid_values_on_disk_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(SETTINGS_FILE);
+    fs::write(
+        &path,
+        json!({ "version": SETTINGS_VERSION, "log_tail_lines": 0 }).to_string(),
+    )
+    .unwrap();
+
+    let err = SettingsStore::load(path).err().unwrap();
+    assert!(err.to_string().contains("log_tail_lines"), "{}", err);
+}
+
+#[test]
+fn recovering_moves_the_unusable_file_aside() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(SETTINGS_FILE);
+    let newer = json!({ "version": SETTINGS_VERSION + 1, "log_tail_lines": 42 }).to_string();
+    fs::write(&path, &newer).unwrap();
+    assert!(SettingsStore::load(path.clone()).is_err());
+
+    let mut store = SettingsStore::recover(path.clone());
+    assert_eq!(store.settings(), &Settings::default());
+    assert_eq!(backup_path(&path), dir.path().join("settings.json.bak"));
+    assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), newer);
+
+    // Saving now starts a fresh file and leaves the backup alone
+    store.update(json!({ "log_tail_lines": 300 })).unwrap();
+    assert_eq!(
+        SettingsStore::load(path.clone())
+            .unwrap()
+            .settings()
+            .log_tail_lines,
+        300
+    );
+    assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), newer);
+}
@@ -0,0 +1,168 @@
+//! Image update checks against registry digests, and pulling and recreating.
+mod common;
+
+use common::{
+    MockDocker, CRASHING_IMAGE, CREATED_ID, IMAGE_ID, LATEST_DIGEST, PULLED_DIGEST,
+    REGISTRY_DIGEST, REGISTRY_IMAGE_ID, REGISTRY_TAG, WEB_ID,
+};
+use hyper::Method;
+use rykard_lib::docker;
+use rykard_lib::recreate::RecreateOptions;
+use rykard_lib::updates::{self, UpdateStatus};
+use serde_json::Value;
+
+#[tokio::test]
+async fn flags_tags_that_moved_in_the_registry() {
+    let mock = MockDocker::start().await;
+    let checks = updates::check_updates(&mock.client(), None).await.unwrap();
+    assert_eq!(checks.len(), 2);
+
+    let latest = checks.iter().find(|u| u.tag == "nginx:latest").unwrap();
+    assert_eq!(latest.status, UpdateStatus::Outdated);
+    assert_eq!(latest.local_digest.as_deref(), Some(PULLED_DIGEST));
+    assert_eq!(latest.remote_digest.as_deref(), Some(LATEST_DIGEST));
+    assert_eq!(latest.image_id, IMAGE_ID.trim_start_matches("sha256:"));
+
+    // Only the container running this image is listed
+    assert_eq!(latest.containers.len(), 1);
+    assert_eq!(latest.containers[0].id, WEB_ID);
+    assert_eq!(latest.containers[0].name, "web");
+    assert_eq!(latest.containers[0].state, "running");
+
+    let pinned = checks.iter().find(|u| u.tag == "nginx:1.27").unwrap();
+    assert_eq!(pinned.status, UpdateStatus::Current);
+    assert!(pinned.error.is_none());
+}
+
+#[tokio::test]
+async fn checks_images_from_a_local_registry() {
+    let mock = MockDocker::start().await;
+    let checks = updates::check_updates(&mock.client(), Some(REGISTRY_TAG))
+        .await
+        .unwrap();
+
+    assert_eq!(checks.len(), 1);
+    assert_eq!(checks[0].status, UpdateStatus::Current);
+    assert_eq!(checks[0].local_digest.as_deref(), Some(REGISTRY_DIGEST));
+    assert_eq!(
+        checks[0].image_id,
+        REGISTRY_IMAGE_ID.trim_start_matches("sha256:")
+    );
+    assert!(checks[0].containers.is_empty());
+
+    // The registry port stays part of the name the daemon looks up
+    assert!(mock
+        .request(Method::GET, &format!("/distribution/{}/json", REGISTRY_TAG))
+        .is_some());
+}
+
+#[tokio::test]
+async fn registry_errors_leave_the_status_unknown() {
+    let mock = MockDocker::start().await;
+    let checks = updates::check_updates(&mock.client(), Some(common::COMMITTED_REPO))
+        .await
+        .unwrap();
+
+    // Committed images have no repo digest, so the registry isn't asked
+    assert_eq!(checks[0].status, UpdateStatus::Local);
+    assert!(mock
+        .requests()
+        .iter()
+        .all(|r| !r.path.starts_with("/distribution/")));
+
+    assert!(updates::remote_digest(&mock.client(), "private/app:1")
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("pull access denied"));
+}
+
+#[tokio::test]
+async fn pull_splits_registry_port_from_tag() {
+    let mock = MockDocker::start().await;
+    docker::pull_image(&mock.client(), REGISTRY_TAG, |_| {})
+        .await
+        .unwrap();
+
+    let pull = mock.request(Method::POST, "/images/create").unwrap();
+    assert_eq!(pull.query["fromImage"], "localhost:5000/shop/api");
+    assert_eq!(pull.query["tag"], "1.0");
+}
+
+fn options() -> RecreateOptions {
+    RecreateOptions {
+        settle_secs: 0,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn pull_and_recreate_moves_containers_to_the_new_image() {
+    let mock = MockDocker::start().await;
+    let outcomes = updates::pull_and_recreate(&mock.client(), "nginx:latest", &options(), |_| {})
+        .await
+        .unwrap();
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].container.id, WEB_ID);
+    assert_eq!(outcomes[0].recreated_id.as_deref(), Some(CREATED_ID));
+    assert_eq!(outcomes[0].error, None);
+
+    let order: Vec<String> = mock
+        .requests()
+        .into_iter()
+        .filter(|r| r.method != Method::GET)
+        .map(|r| format!("{} {}", r.method, r.path))
+        .collect();
+    assert_eq!(
+        order,
+        [
+            "POST /images/create".to_string(),
+            format!("POST /containers/{}/stop", WEB_ID),
+            format!("POST /containers/{}/rename", WEB_ID),
+            "POST /containers/create".to_string(),
+            "POST /networks/shop_default/connect".to_string(),
+            format!("POST /containers/{}/start", CREATED_ID),
+            format!("DELETE /containers/{}", WEB_ID),
+        ]
+    );
+
+    let create = mock.request(Method::POST, "/containers/create").unwrap();
+    assert_eq!(create.query["name"], "web");
+    let body: Value = serde_json::from_str(&create.body).unwrap();
+    assert_eq!(body["Image"], "nginx:latest");
+    assert_eq!(body["HostConfig"]["RestartPolicy"]["Name"], "always");
+    assert!(body["NetworkingConfig"]["EndpointsConfig"]["bridge"].is_object());
+    assert!(body["Env"]
+        .as_array()
+        .unwrap()
+        .contains(&Value::from("NGINX_VERSION=1.27.0")));
+}
+
+#[tokio::test]
+async fn pull_without_changes_recreates_nothing() {
+    let mock = MockDocker::start().await;
+    let outcomes = updates::pull_and_recreate(&mock.client(), REGISTRY_TAG, &options(), |_| {})
+        .await
+        .unwrap();
+    assert!(outcomes.is_empty());
+    assert!(mock.request(Method::POST, "/containers/create").is_none());
+}
+
+#[tokio::test]
+async fn failed_recreates_are_reported_per_container() {
+    let mock = MockDocker::start().await;
+    // The new image's containers exit at once, so web is rolled back
+    let outcomes = updates::pull_and_recreate(&mock.client(), CRASHING_IMAGE, &options(), |_| {})
+        .await
+        .unwrap();
+
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].container.name, "web");
+    assert_eq!(outcomes[0].recreated_id, None);
+    let error = outcomes[0].error.as_deref().unwrap();
+    assert!(
+        error.contains("restored the previous container"),
+        "{}",
+        error
+    );
+}